@@ -0,0 +1,152 @@
+// Memory held by a loaded library, the baseline for changes to how tracks
+// are stored. Prints the heap each way of loading keeps, then times them.
+// Run with cargo bench --bench library
+
+use chrono::NaiveDateTime;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use diesel::prelude::*;
+use diesel_migrations::MigrationHarness;
+use fml9000::models::{NewTrack, Track};
+use fml9000::schema::tracks;
+use fml9000::MIGRATIONS;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const TRACKS: usize = 100_000;
+
+// Counts the bytes and allocations not yet freed. Each allocation also costs
+// the allocator's own bookkeeping, which the byte count leaves out
+struct Counting;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static LIVE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+    LIVE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+    LIVE_ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+    System.dealloc(ptr, layout)
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// A track row the way Track was before tags were interned, every field its
+// own copy
+type PlainTrack = (
+  String,
+  Option<String>,
+  Option<String>,
+  Option<String>,
+  Option<String>,
+  Option<String>,
+  Option<String>,
+  Option<NaiveDateTime>,
+  Option<i32>,
+  Option<i32>,
+  Option<i32>,
+);
+
+// Ten tracks an album, ten albums an artist, twenty genres
+fn library() -> SqliteConnection {
+  let mut conn = SqliteConnection::establish(":memory:").unwrap();
+  conn.run_pending_migrations(MIGRATIONS).unwrap();
+  let rows: Vec<(String, String, String, String, String, String)> = (0..TRACKS)
+    .map(|i| {
+      let artist = format!("Artist {}", i / 100);
+      let album = format!("Album {}", i / 10);
+      (
+        format!(
+          "/home/user/Music/{}/{}/{:02} Track {}.flac",
+          artist,
+          album,
+          i % 10 + 1,
+          i
+        ),
+        artist,
+        album,
+        format!("Track {}", i),
+        format!("Genre {}", i % 20),
+        format!("{:02}", i % 10 + 1),
+      )
+    })
+    .collect();
+  conn
+    .transaction(|conn| {
+      for chunk in rows.chunks(1000) {
+        let values: Vec<NewTrack> = chunk
+          .iter()
+          .map(|(filename, artist, album, title, genre, track)| NewTrack {
+            filename,
+            artist: Some(artist),
+            title: Some(title),
+            album: Some(album),
+            genre: Some(genre),
+            track: Some(track),
+            album_artist: None,
+            year: Some(1999),
+            duration_ms: Some(180_000),
+            scan_session_id: None,
+          })
+          .collect();
+        diesel::insert_into(tracks::table)
+          .values(values)
+          .execute(conn)?;
+      }
+      QueryResult::Ok(())
+    })
+    .unwrap();
+  conn
+}
+
+// Heap kept by what load returns, in bytes and allocations
+fn held<T>(load: impl FnOnce() -> T) -> (T, String) {
+  let bytes = LIVE_BYTES.load(Ordering::Relaxed);
+  let allocations = LIVE_ALLOCATIONS.load(Ordering::Relaxed);
+  let loaded = load();
+  let held = format!(
+    "{} MB in {}k allocations",
+    LIVE_BYTES.load(Ordering::Relaxed).saturating_sub(bytes) / 1_000_000,
+    LIVE_ALLOCATIONS
+      .load(Ordering::Relaxed)
+      .saturating_sub(allocations)
+      / 1000
+  );
+  (loaded, held)
+}
+
+fn load(c: &mut Criterion) {
+  let mut conn = library();
+
+  // prepares the statement both loads share, so neither pays for it
+  drop(tracks::table.load::<PlainTrack>(&mut conn).unwrap());
+  // interned first, so the pool's own copies are counted too
+  let (interned, interned_held) = held(|| tracks::table.load::<Track>(&mut conn).unwrap());
+  let (plain, plain_held) = held(|| tracks::table.load::<PlainTrack>(&mut conn).unwrap());
+  println!(
+    "{} tracks: {} interned, {} as plain strings",
+    TRACKS, interned_held, plain_held
+  );
+  drop((interned, plain));
+
+  let mut group = c.benchmark_group("load");
+  group.throughput(Throughput::Elements(TRACKS as u64));
+  group.sample_size(10);
+  group.bench_function("interned", |b| {
+    b.iter(|| tracks::table.load::<Track>(&mut conn).unwrap())
+  });
+  group.bench_function("plain strings", |b| {
+    b.iter(|| tracks::table.load::<PlainTrack>(&mut conn).unwrap())
+  });
+  group.finish();
+}
+
+criterion_group!(benches, load);
+criterion_main!(benches);
@@ -0,0 +1,124 @@
+// Scanner throughput, the baseline for changes to how folders are scanned.
+// Run with cargo bench --bench scan
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use diesel::prelude::*;
+use diesel_migrations::MigrationHarness;
+use fml9000::folders::last_scan;
+use fml9000::metadata::default_providers;
+use fml9000::models::Track;
+use fml9000::scanner::{scan_folder, ScanOptions};
+use fml9000::schema::tracks;
+use fml9000::writer::Writer;
+use fml9000::MIGRATIONS;
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::{Accessor, Tag};
+use std::path::Path;
+use tempfile::TempDir;
+
+const FILES: usize = 500;
+
+// A second of 8 kHz mono silence
+fn write_wav(path: &Path) {
+  let samples = 8000u32;
+  let data_len = samples * 2;
+  let mut wav = Vec::new();
+  wav.extend_from_slice(b"RIFF");
+  wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+  wav.extend_from_slice(b"WAVEfmt ");
+  wav.extend_from_slice(&16u32.to_le_bytes());
+  wav.extend_from_slice(&1u16.to_le_bytes());
+  wav.extend_from_slice(&1u16.to_le_bytes());
+  wav.extend_from_slice(&8000u32.to_le_bytes());
+  wav.extend_from_slice(&16000u32.to_le_bytes());
+  wav.extend_from_slice(&2u16.to_le_bytes());
+  wav.extend_from_slice(&16u16.to_le_bytes());
+  wav.extend_from_slice(b"data");
+  wav.extend_from_slice(&data_len.to_le_bytes());
+  wav.resize(wav.len() + data_len as usize, 0);
+  std::fs::write(path, wav).unwrap();
+}
+
+// Albums of ten tracks, tagged the way the scanner expects
+fn fixtures() -> TempDir {
+  let dir = TempDir::new().unwrap();
+  for i in 0..FILES {
+    let album = dir.path().join(format!("Album {}", i / 10));
+    std::fs::create_dir_all(&album).unwrap();
+    let path = album.join(format!("{:02} Track.wav", i % 10 + 1));
+    write_wav(&path);
+    let mut tagged_file = Probe::open(&path).unwrap().read().unwrap();
+    let mut tag = Tag::new(tagged_file.primary_tag_type());
+    tag.set_artist(format!("Artist {}", i / 50));
+    tag.set_album(format!("Album {}", i / 10));
+    tag.set_title(format!("Track {}", i));
+    tagged_file.insert_tag(tag);
+    tagged_file
+      .save_to_path(&path, WriteOptions::default())
+      .unwrap();
+  }
+  dir
+}
+
+fn empty_library() -> Writer {
+  let mut conn = SqliteConnection::establish(":memory:").unwrap();
+  conn.run_pending_migrations(MIGRATIONS).unwrap();
+  Writer::new(conn)
+}
+
+fn scan(c: &mut Criterion) {
+  let dir = fixtures();
+  let folder = dir.path().to_str().unwrap();
+  let providers = default_providers(&[]);
+  let mut group = c.benchmark_group("scan");
+  group.throughput(Throughput::Elements(FILES as u64));
+  group.sample_size(10);
+
+  group.bench_function("new library", |b| {
+    b.iter_batched(
+      empty_library,
+      |writer| {
+        scan_folder(
+          &writer,
+          folder,
+          &[],
+          None,
+          &providers,
+          ScanOptions::default(),
+        )
+      },
+      BatchSize::PerIteration,
+    )
+  });
+
+  // what every startup does when nothing changed
+  let writer = empty_library();
+  scan_folder(
+    &writer,
+    folder,
+    &[],
+    None,
+    &providers,
+    ScanOptions::default(),
+  );
+  let rows: Vec<Track> = writer.write(|conn| tracks::table.load(conn)).unwrap();
+  let scanned = writer.write(|conn| last_scan(conn, folder)).unwrap();
+  group.bench_function("unchanged library", |b| {
+    b.iter(|| {
+      scan_folder(
+        &writer,
+        folder,
+        &rows,
+        scanned,
+        &providers,
+        ScanOptions::default(),
+      )
+    })
+  });
+  group.finish();
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS playlists (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  name VARCHAR NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS playlist_tracks (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  playlist_id INTEGER NOT NULL REFERENCES playlists(id) ON DELETE CASCADE,
+  filename VARCHAR NOT NULL,
+  position INTEGER NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE tracks ADD COLUMN duration_ms INTEGER;
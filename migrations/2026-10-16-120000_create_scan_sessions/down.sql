@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE tracks DROP COLUMN scan_session_id;
+DROP TABLE scan_sessions;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS scan_sessions (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  folder VARCHAR NOT NULL,
+  started DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE tracks ADD COLUMN scan_session_id INTEGER REFERENCES scan_sessions(id);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS folder_scans (
+  folder VARCHAR NOT NULL PRIMARY KEY,
+  scanned DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
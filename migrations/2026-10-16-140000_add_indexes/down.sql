@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS playlist_tracks_playlist_position;
+DROP INDEX IF EXISTS playlist_tracks_filename;
+DROP INDEX IF EXISTS tracks_scan_session;
+DROP INDEX IF EXISTS recently_played_timestamp;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE INDEX IF NOT EXISTS playlist_tracks_playlist_position ON playlist_tracks (playlist_id, position);
+CREATE INDEX IF NOT EXISTS playlist_tracks_filename ON playlist_tracks (filename);
+CREATE INDEX IF NOT EXISTS tracks_scan_session ON tracks (scan_session_id);
+CREATE INDEX IF NOT EXISTS recently_played_timestamp ON recently_played (timestamp);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS track_offsets (
+  filename VARCHAR NOT NULL PRIMARY KEY,
+  start_ms INTEGER NOT NULL DEFAULT 0,
+  end_ms INTEGER
+);
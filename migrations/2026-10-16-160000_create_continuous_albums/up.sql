@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS continuous_albums (
+  artist VARCHAR NOT NULL,
+  album VARCHAR NOT NULL,
+  PRIMARY KEY (artist, album)
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS file_checksums (
+  filename VARCHAR NOT NULL PRIMARY KEY,
+  sha256 VARCHAR NOT NULL,
+  modified_ms BIGINT NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS track_bpm (
+  filename VARCHAR NOT NULL PRIMARY KEY,
+  bpm FLOAT NOT NULL
+);
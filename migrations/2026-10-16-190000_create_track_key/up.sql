@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS track_key (
+  filename VARCHAR NOT NULL PRIMARY KEY,
+  musical_key INTEGER NOT NULL
+);
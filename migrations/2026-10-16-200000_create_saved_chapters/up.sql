@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS saved_chapters (
+  filename VARCHAR NOT NULL,
+  start_ms INTEGER NOT NULL,
+  title VARCHAR NOT NULL,
+  PRIMARY KEY (filename, start_ms)
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS fingerprints (
+  filename VARCHAR NOT NULL PRIMARY KEY,
+  duration INTEGER NOT NULL,
+  fingerprint VARCHAR NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS plays (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  filename VARCHAR NOT NULL,
+  timestamp DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS plays_filename ON plays (filename);
+
+-- the last play of each file is all there was before
+INSERT INTO plays (filename, timestamp)
+SELECT filename, timestamp FROM recently_played WHERE timestamp IS NOT NULL;
@@ -0,0 +1,144 @@
+use directories::ProjectDirs;
+use gtk::glib::{self, ChecksumType};
+use lofty::config::WriteOptions;
+use lofty::file::TaggedFileExt;
+use lofty::picture::{Picture, PictureType};
+use lofty::probe::Probe;
+use lofty::tag::{Tag, TagExt};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// Art next to the files, in order of preference
+pub const FOLDER_ART: [&str; 5] = [
+  "cover.jpg",
+  "cover.png",
+  "cover.webp",
+  "folder.jpg",
+  "folder.png",
+];
+
+// The cover file names set_album_art writes, one per image type
+const COVER_FILES: [&str; 3] = ["cover.jpg", "cover.png", "cover.webp"];
+
+// Where art fetched for a folder goes. The app's cache rather than the
+// folder itself, which may be read-only or shared
+pub fn fetched_cover_path(folder: &Path) -> PathBuf {
+  let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
+  let key = glib::compute_checksum_for_string(ChecksumType::Md5, folder.to_string_lossy().as_ref())
+    .unwrap_or_default();
+  proj_dirs
+    .cache_dir()
+    .join("covers")
+    .join(format!("{}.jpg", key))
+}
+
+// The art for a file: folder art next to it, or else art fetched for its
+// folder. The folder's cover.jpg when there is neither, which callers check
+// for
+pub fn cover_path(filename: &str) -> PathBuf {
+  let folder = Path::new(filename).parent().unwrap_or(Path::new(""));
+  FOLDER_ART
+    .iter()
+    .map(|name| folder.join(name))
+    .chain(std::iter::once(fetched_cover_path(folder)))
+    .find(|p| p.exists())
+    .unwrap_or_else(|| folder.join("cover.jpg"))
+}
+
+pub fn write_fetched_cover(folder: &Path, data: &[u8]) -> std::io::Result<()> {
+  let path = fetched_cover_path(folder);
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  std::fs::write(path, data)
+}
+
+// The file extension for an image, going by its first bytes. JPEG unless it
+// is recognisably PNG or WebP
+fn image_extension(data: &[u8]) -> &'static str {
+  if data.starts_with(b"\x89PNG") {
+    "png"
+  } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+    "webp"
+  } else {
+    "jpg"
+  }
+}
+
+// Writes cover.jpg, cover.png or cover.webp to match the image, and removes
+// the others so an older cover doesn't keep showing instead
+pub fn write_cover_file(folder: &Path, data: &[u8]) -> std::io::Result<PathBuf> {
+  let path = folder.join(format!("cover.{}", image_extension(data)));
+  let mut f = std::fs::OpenOptions::new()
+    .create(true)
+    .truncate(true)
+    .write(true)
+    .open(&path)?;
+  f.write_all(data)?;
+  for name in COVER_FILES {
+    let other = folder.join(name);
+    if other != path && other.exists() {
+      std::fs::remove_file(other)?;
+    }
+  }
+  Ok(path)
+}
+
+pub fn embed_album_art(filename: &str, data: &[u8]) -> lofty::error::Result<()> {
+  let mut picture = Picture::from_reader(&mut &data[..])?;
+  picture.set_pic_type(PictureType::CoverFront);
+
+  let mut tagged_file = Probe::open(filename)?.read()?;
+  if tagged_file.primary_tag().is_none() {
+    let tag_type = tagged_file.primary_tag_type();
+    tagged_file.insert_tag(Tag::new(tag_type));
+  }
+  let tag = tagged_file.primary_tag_mut().unwrap();
+  tag.remove_picture_type(PictureType::CoverFront);
+  tag.push_picture(picture);
+  tag.save_to_path(filename, WriteOptions::default())
+}
+
+// Embeds the image into every file and drops a cover into each folder the
+// files live in, which is what the playlist view loads when a track plays.
+// Slow for a whole album, so it belongs on a worker thread
+pub fn set_album_art(filenames: &[String], data: &[u8]) {
+  let mut folders: Vec<&Path> = filenames
+    .iter()
+    .filter_map(|f| Path::new(f).parent())
+    .collect();
+  folders.sort();
+  folders.dedup();
+
+  for folder in folders {
+    if let Err(e) = write_cover_file(folder, data) {
+      warn!("Failed to write a cover to {}: {}", folder.display(), e);
+    }
+  }
+
+  for filename in filenames {
+    if let Err(e) = embed_album_art(filename, data) {
+      warn!("Failed to embed album art in {}: {}", filename, e);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn covers_keep_their_image_type() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("cover.jpg"), b"old").unwrap();
+    let png = b"\x89PNG\r\n\x1a\nrest";
+    let path = write_cover_file(dir.path(), png).unwrap();
+    assert_eq!(path, dir.path().join("cover.png"));
+    assert_eq!(std::fs::read(&path).unwrap(), png);
+    // the old jpg would otherwise win over the new png
+    assert!(!dir.path().join("cover.jpg").exists());
+    assert_eq!(image_extension(b"RIFF\0\0\0\0WEBPVP8 "), "webp");
+    assert_eq!(image_extension(b"\xff\xd8\xff\xe0"), "jpg");
+  }
+}
@@ -0,0 +1,84 @@
+use crate::gtk_helpers::{check_online, show_toast};
+use adw::prelude::*;
+use fml9000::album_art::set_album_art;
+use gtk::gio;
+use gtk::glib;
+use gtk::{Button, Entry, FileDialog, Orientation};
+use std::rc::Rc;
+
+// Writing covers and retagging a whole album takes a while, so it happens
+// off the main thread
+async fn apply(filenames: Vec<String>, data: Vec<u8>) {
+  gio::spawn_blocking(move || set_album_art(&filenames, &data))
+    .await
+    .expect("Failed to set album art");
+}
+
+pub fn from_file<W: IsA<gtk::Window>>(wnd: &Rc<W>, filenames: Vec<String>) {
+  let dialog = FileDialog::builder()
+    .title("Choose album art")
+    .accept_label("Open")
+    .build();
+
+  let wnd_rc = wnd.clone();
+  dialog.open(Some(&**wnd), gio::Cancellable::NONE, move |file| {
+    let Ok(file) = file else {
+      return;
+    };
+    glib::MainContext::default().spawn_local(async move {
+      match file.load_contents_future().await {
+        Ok((data, _)) => apply(filenames, data.to_vec()).await,
+        Err(e) => show_toast(
+          wnd_rc.upcast_ref::<gtk::Window>(),
+          &format!("Failed to read album art: {}", e),
+        ),
+      }
+    });
+  });
+}
+
+pub async fn from_url<W: IsA<gtk::Window>>(wnd: Rc<W>, filenames: Vec<String>) {
+  if !check_online(wnd.upcast_ref::<gtk::Window>()) {
+    return;
+  }
+  let f = gtk::Box::new(Orientation::Horizontal, 0);
+
+  let set_button = Button::builder().label("Set").build();
+  let textbox = Entry::builder()
+    .placeholder_text("https://...")
+    .hexpand(true)
+    .build();
+
+  f.append(&textbox);
+  f.append(&set_button);
+  let url_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(600)
+    .title("Set album art from URL")
+    .child(&f)
+    .build();
+
+  set_button.connect_clicked(glib::clone!(
+    #[weak]
+    textbox,
+    #[weak]
+    url_dialog,
+    move |_| {
+      let file = gio::File::for_uri(&textbox.text());
+      let filenames = filenames.clone();
+      let wnd = wnd.clone();
+      glib::MainContext::default().spawn_local(async move {
+        match file.load_contents_future().await {
+          Ok((data, _)) => apply(filenames, data.to_vec()).await,
+          Err(e) => show_toast(
+            wnd.upcast_ref::<gtk::Window>(),
+            &format!("Failed to download album art: {}", e),
+          ),
+        }
+      });
+      url_dialog.close();
+    }
+  ));
+  url_dialog.present();
+}
@@ -0,0 +1,70 @@
+use crate::album_art::cover_path;
+use crate::interned::Interned;
+use crate::models::Track;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// album artist (or artist) and album title
+pub type AlbumKey = (Option<Interned>, Option<Interned>);
+
+// An album as the views see it, put together from its tracks' tags. Tracks
+// are grouped by album artist (or artist) and album title, so same-named
+// albums by different artists stay apart
+pub struct Album {
+  pub artist: Option<Interned>,
+  pub title: Option<Interned>,
+  // earliest year tagged on any of the tracks
+  pub year: Option<i32>,
+  pub duration_ms: i64,
+  // in filename order, which is usually track order
+  pub tracks: Vec<Rc<Track>>,
+}
+
+impl Album {
+  pub fn track_count(&self) -> usize {
+    self.tracks.len()
+  }
+
+  // Looked up when asked for rather than when grouping, so building the grid
+  // doesn't touch the disk for every album
+  pub fn art(&self) -> PathBuf {
+    cover_path(&self.tracks[0].filename)
+  }
+
+  // Copies, for handing the album to a worker thread
+  pub fn filenames(&self) -> Vec<String> {
+    self.tracks.iter().map(|t| t.filename.clone()).collect()
+  }
+}
+
+pub fn album_key(track: &Track) -> AlbumKey {
+  (
+    track.album_artist.clone().or(track.artist.clone()),
+    track.album.clone(),
+  )
+}
+
+// Sorted by artist then title
+pub fn group_albums(tracks: &[Rc<Track>]) -> Vec<Album> {
+  let mut groups: BTreeMap<AlbumKey, Vec<&Rc<Track>>> = BTreeMap::new();
+  for track in tracks {
+    groups.entry(album_key(track)).or_default().push(track);
+  }
+  groups
+    .into_iter()
+    .map(|((artist, title), mut tracks)| {
+      tracks.sort_by(|a, b| a.filename.cmp(&b.filename));
+      Album {
+        artist,
+        title,
+        year: tracks.iter().filter_map(|t| t.year).min(),
+        duration_ms: tracks
+          .iter()
+          .map(|t| t.duration_ms.unwrap_or(0) as i64)
+          .sum(),
+        tracks: tracks.into_iter().cloned().collect(),
+      }
+    })
+    .collect()
+}
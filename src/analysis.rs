@@ -0,0 +1,61 @@
+use crate::bpm::{detect_bpm, store_bpm};
+use crate::key::{detect_key, store_key, Key};
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+
+// A minute from a little way in, past most intros, is plenty to find the
+// beat and the key in
+const SKIP: Duration = Duration::from_secs(15);
+const LENGTH: Duration = Duration::from_secs(60);
+
+// The part of a file tempo and key are worked out from, as interleaved
+// samples
+pub struct Excerpt {
+  pub samples: Vec<f32>,
+  pub channels: usize,
+  pub sample_rate: u32,
+}
+
+impl Excerpt {
+  // None when the file can't be decoded
+  pub fn decode(filename: &str) -> Option<Excerpt> {
+    let file = File::open(filename).ok()?;
+    let source = Decoder::new(BufReader::new(file)).ok()?;
+    let channels = source.channels() as usize;
+    let sample_rate = source.sample_rate();
+    let samples = source
+      .skip_duration(SKIP)
+      .take_duration(LENGTH)
+      .convert_samples::<f32>()
+      .collect();
+    Some(Excerpt {
+      samples,
+      channels,
+      sample_rate,
+    })
+  }
+}
+
+// Detects and stores the BPM and key of each file, decoding each once. A
+// minute of decoding a file, so call it off the main thread and in batches
+pub fn detect_bpm_and_key(filenames: &[String]) -> Vec<(Option<f32>, Option<Key>)> {
+  filenames
+    .iter()
+    .map(|filename| {
+      let Some(excerpt) = Excerpt::decode(filename) else {
+        return (None, None);
+      };
+      let bpm = detect_bpm(&excerpt);
+      if let Some(bpm) = bpm {
+        store_bpm(filename, bpm);
+      }
+      let key = detect_key(&excerpt);
+      if let Some(key) = key {
+        store_key(filename, key);
+      }
+      (bpm, key)
+    })
+    .collect()
+}
@@ -0,0 +1,114 @@
+use crate::album_art::{cover_path, embed_album_art, write_fetched_cover};
+use crate::albums::Album;
+use crate::network::download_as_app;
+use gtk::glib::Uri;
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use regex::Regex;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// MusicBrainz allows each client a request a second
+const MUSICBRAINZ_INTERVAL: Duration = Duration::from_secs(1);
+
+// when the last MusicBrainz request went out
+static LAST_LOOKUP: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub struct AlbumQuery {
+  pub artist: String,
+  pub album: String,
+  pub filenames: Vec<String>,
+}
+
+// Sleeps until MusicBrainz's rate limit allows another request. Callers on
+// other threads queue up behind the lock
+fn wait_for_musicbrainz() {
+  let mut last = LAST_LOOKUP.lock().unwrap_or_else(|e| e.into_inner());
+  if let Some(elapsed) = last.map(|at| at.elapsed()) {
+    if elapsed < MUSICBRAINZ_INTERVAL {
+      std::thread::sleep(MUSICBRAINZ_INTERVAL - elapsed);
+    }
+  }
+  *last = Some(Instant::now());
+}
+
+pub fn has_embedded_art(filename: &str) -> bool {
+  match Probe::open(filename).and_then(|p| p.read()) {
+    Ok(tagged_file) => tagged_file.tags().iter().any(|t| !t.pictures().is_empty()),
+    Err(_) => false,
+  }
+}
+
+// Albums that have an artist and title to search for and no folder or
+// fetched art. Embedded art is checked later on a worker thread since it
+// requires opening the files
+pub fn albums_missing_art(albums: &[Album]) -> Vec<AlbumQuery> {
+  albums
+    .iter()
+    .filter(|a| !a.tracks.iter().any(|t| cover_path(&t.filename).exists()))
+    .filter_map(|a| match (&a.artist, &a.title) {
+      (Some(artist), Some(album)) => Some(AlbumQuery {
+        artist: artist.to_string(),
+        album: album.to_string(),
+        filenames: a.filenames(),
+      }),
+      _ => None,
+    })
+    .collect()
+}
+
+fn lookup_release_id(artist: &str, album: &str) -> Option<String> {
+  let query = format!(
+    "release:\"{}\" AND artist:\"{}\"",
+    album.replace('"', ""),
+    artist.replace('"', "")
+  );
+  let url = format!(
+    "https://musicbrainz.org/ws/2/release/?limit=1&query={}",
+    Uri::escape_string(&query, None, false)
+  );
+  wait_for_musicbrainz();
+  let body = download_as_app(&url)?;
+  let re = Regex::new(r#"<release id="([0-9a-f-]{36})""#).unwrap();
+  re.captures(&String::from_utf8_lossy(&body))
+    .map(|c| c[1].to_string())
+}
+
+pub fn fetch_album_art(artist: &str, album: &str) -> Option<Vec<u8>> {
+  let release_id = lookup_release_id(artist, album)?;
+  download_as_app(&format!(
+    "https://coverartarchive.org/release/{}/front-500",
+    release_id
+  ))
+}
+
+// Blocking, run on a worker thread. Returns whether art was saved
+pub fn fetch_missing_art(query: &AlbumQuery, embed: bool) -> bool {
+  if query.filenames.iter().any(|f| has_embedded_art(f)) {
+    return false;
+  }
+  match fetch_album_art(&query.artist, &query.album) {
+    Some(data) => {
+      if let Some(folder) = Path::new(&query.filenames[0]).parent() {
+        if let Err(e) = write_fetched_cover(folder, &data) {
+          warn!(
+            "Failed to save the art fetched for {}: {}",
+            folder.display(),
+            e
+          );
+        }
+      }
+      if embed {
+        for filename in &query.filenames {
+          if let Err(e) = embed_album_art(filename, &data) {
+            warn!("Failed to embed album art in {}: {}", filename, e);
+          }
+        }
+      }
+      true
+    }
+    None => false,
+  }
+}
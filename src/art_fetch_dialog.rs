@@ -0,0 +1,51 @@
+use crate::gtk_helpers::show_toast;
+use adw::prelude::*;
+use fml9000::art_fetch::{fetch_missing_art, AlbumQuery};
+use gtk::gio;
+use gtk::{Button, Label, Orientation, ProgressBar};
+use std::cell::Cell;
+use std::rc::Rc;
+use tracing::info;
+
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, albums: Vec<AlbumQuery>, embed: bool) {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+
+  let label = Label::builder().label("Fetching missing album art").build();
+  let progress_bar = ProgressBar::builder().show_text(true).build();
+  let cancel_button = Button::builder().label("Cancel").build();
+
+  f.append(&label);
+  f.append(&progress_bar);
+  f.append(&cancel_button);
+  let progress_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(400)
+    .title("Fetch missing art")
+    .child(&f)
+    .build();
+
+  let cancelled = Rc::new(Cell::new(false));
+  let cancelled1 = cancelled.clone();
+  cancel_button.connect_clicked(move |_| cancelled1.set(true));
+  progress_dialog.present();
+
+  let total = albums.len();
+  let mut found = 0;
+  for (i, album) in albums.into_iter().enumerate() {
+    if cancelled.get() {
+      break;
+    }
+    label.set_text(&format!("{} - {}", album.artist, album.album));
+    progress_bar.set_fraction(i as f64 / total as f64);
+    progress_bar.set_text(Some(&format!("{} / {}", i + 1, total)));
+    if let Ok(true) = gio::spawn_blocking(move || fetch_missing_art(&album, embed)).await {
+      found += 1;
+    }
+  }
+
+  let message = format!("Fetched album art for {} of {} albums", found, total);
+  info!("{}", message);
+  progress_dialog.close();
+  show_toast(wnd.upcast_ref::<gtk::Window>(), &message);
+}
@@ -0,0 +1,140 @@
+use crate::gtk_helpers::{format_total_duration, str_or_unknown};
+use adw::prelude::*;
+use fml9000::albums::Album;
+use fml9000::artists::{artist_albums, recently_played_of};
+use fml9000::library_index::LibraryIndex;
+use fml9000::load_playlist_store;
+use fml9000::models::Track;
+use gtk::gio::{self, ListStore};
+use gtk::{Align, Button, FlowBox, Image, Label, Orientation, ScrolledWindow, SelectionMode};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const COVER_SIZE: i32 = 128;
+
+fn album_tile(album: &Album) -> Button {
+  let art = album.art();
+  let image = if art.exists() {
+    Image::from_file(&art)
+  } else {
+    Image::from_icon_name("media-optical-symbolic")
+  };
+  image.set_pixel_size(COVER_SIZE);
+  let name = match album.year {
+    Some(year) => format!("{} ({})", str_or_unknown(&album.title), year),
+    None => str_or_unknown(&album.title),
+  };
+  let tile = gtk::Box::new(Orientation::Vertical, 4);
+  tile.append(&image);
+  tile.append(
+    &Label::builder()
+      .label(&name)
+      .wrap(true)
+      .max_width_chars(16)
+      .build(),
+  );
+  tile.append(
+    &Label::builder()
+      .label(format!(
+        "{} tracks, {}",
+        album.track_count(),
+        format_total_duration(album.duration_ms)
+      ))
+      .css_classes(["dim-label"])
+      .build(),
+  );
+  Button::builder().child(&tile).build()
+}
+
+fn heading(text: &str) -> Label {
+  Label::builder()
+    .label(text)
+    .halign(Align::Start)
+    .css_classes(["title-4"])
+    .build()
+}
+
+// Albums by the artist as a cover grid, and what was played last. Clicking
+// either shows those tracks in the playlist view
+pub async fn dialog<W: IsA<gtk::Window>>(
+  wnd: Rc<W>,
+  artist: String,
+  playlist_store: ListStore,
+  library: Rc<RefCell<LibraryIndex>>,
+) {
+  let artist_albums = artist_albums(&library.borrow(), &artist);
+  let filenames = artist_albums.iter().flat_map(|a| a.filenames()).collect();
+  let recently_played = match gio::spawn_blocking(move || recently_played_of(filenames)).await {
+    Ok(recently_played) => recently_played,
+    Err(_) => return,
+  };
+
+  let f = gtk::Box::builder()
+    .orientation(Orientation::Vertical)
+    .spacing(8)
+    .margin_start(8)
+    .margin_end(8)
+    .margin_top(8)
+    .margin_bottom(8)
+    .build();
+  let artist_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(800)
+    .default_height(600)
+    .title(&artist)
+    .child(&ScrolledWindow::builder().child(&f).build())
+    .build();
+
+  let artist_dialog_rc = artist_dialog.clone();
+  let show = Rc::new(move |rows: &[Rc<Track>]| {
+    playlist_store.remove_all();
+    load_playlist_store(rows.iter(), &playlist_store);
+    artist_dialog_rc.close();
+  });
+
+  f.append(&heading("Albums"));
+  let albums = FlowBox::builder()
+    .selection_mode(SelectionMode::None)
+    .homogeneous(true)
+    .build();
+  for album in artist_albums {
+    let tile = album_tile(&album);
+    let show_rc = show.clone();
+    tile.connect_clicked(move |_| show_rc(&album.tracks));
+    albums.append(&tile);
+  }
+  f.append(&albums);
+
+  f.append(&heading("Recently played"));
+  if recently_played.is_empty() {
+    f.append(
+      &Label::builder()
+        .label("Nothing played yet")
+        .halign(Align::Start)
+        .build(),
+    );
+  } else {
+    let recently_played = library.borrow().by_filenames(&recently_played);
+    for track in &recently_played {
+      f.append(
+        &Label::builder()
+          .label(format!(
+            "{} — {}",
+            str_or_unknown(&track.title),
+            str_or_unknown(&track.album)
+          ))
+          .halign(Align::Start)
+          .build(),
+      );
+    }
+    let show_played = Button::builder()
+      .label("Show in playlist")
+      .halign(Align::Start)
+      .build();
+    show_played.connect_clicked(move |_| show(&recently_played));
+    f.append(&show_played);
+  }
+
+  artist_dialog.present();
+}
@@ -0,0 +1,90 @@
+use crate::albums::{group_albums, Album};
+use crate::connect_db;
+use crate::library_index::LibraryIndex;
+use crate::schema::recently_played;
+use diesel::prelude::*;
+use regex::Regex;
+use std::collections::HashSet;
+use tracing::{error, warn};
+
+const RECENTLY_PLAYED_LIMIT: usize = 10;
+
+// Split artist tags on these by default. "&" and "," are left out since
+// they are part of so many single artist names
+pub const DEFAULT_SEPARATORS: [&str; 5] = ["feat.", "feat", "ft.", "featuring", ";"];
+
+// Case, surrounding whitespace and a leading "The" don't tell artists apart,
+// so "The Beatles" and "beatles" end up on the same page
+pub fn normalize_artist(name: &str) -> String {
+  let name = name.trim().to_lowercase();
+  match name.strip_prefix("the ") {
+    Some(rest) => rest.trim_start().to_string(),
+    None => name,
+  }
+}
+
+// Separators with a letter in them only count as whole words, so "feat"
+// doesn't split "Little Feat", others like ";" or "/" count anywhere
+pub fn credit_splitter(separators: &[String]) -> Option<Regex> {
+  let alternatives: Vec<String> = separators
+    .iter()
+    .map(|s| s.trim())
+    .filter(|s| !s.is_empty())
+    .map(|s| match s.chars().any(char::is_alphabetic) {
+      true => format!(r"\s+{}\s+", regex::escape(s)),
+      false => format!(r"\s*{}\s*", regex::escape(s)),
+    })
+    .collect();
+  if alternatives.is_empty() {
+    return None;
+  }
+  Regex::new(&format!("(?i){}", alternatives.join("|")))
+    .map_err(|e| warn!("Bad artist separators: {}", e))
+    .ok()
+}
+
+// Each artist credited in a tag like "Artist feat. Other", normalized, with
+// the whole tag first
+pub fn artist_credits(name: &str, splitter: Option<&Regex>) -> Vec<String> {
+  let mut credits = vec![normalize_artist(name)];
+  if let Some(splitter) = splitter {
+    for credit in splitter.split(name).map(normalize_artist) {
+      if !credit.is_empty() && !credits.contains(&credit) {
+        credits.push(credit);
+      }
+    }
+  }
+  credits
+}
+
+// Albums with anything by the artist, matching either the artist or album
+// artist tag, oldest first
+pub fn artist_albums(library: &LibraryIndex, artist: &str) -> Vec<Album> {
+  let mut albums = group_albums(library.by_artist(artist));
+  albums.sort_by(|a, b| (a.year, &a.title).cmp(&(b.year, &b.title)));
+  albums
+}
+
+// Which of the filenames were played last, most recent first. Blocking, so
+// call it off the main thread
+pub fn recently_played_of(filenames: Vec<String>) -> Vec<String> {
+  let filenames: HashSet<String> = filenames.into_iter().collect();
+  let conn = &mut connect_db();
+  let played = match recently_played::table
+    .filter(recently_played::timestamp.is_not_null())
+    .select(recently_played::filename)
+    .order(recently_played::timestamp.desc())
+    .load::<String>(conn)
+  {
+    Ok(played) => played,
+    Err(e) => {
+      error!("Failed to load recently played: {}", e);
+      Vec::new()
+    }
+  };
+  played
+    .into_iter()
+    .filter(|f| filenames.contains(f))
+    .take(RECENTLY_PLAYED_LIMIT)
+    .collect()
+}
@@ -0,0 +1,99 @@
+use crate::connect_db;
+use crate::plays::played_often_until;
+use crate::schema::tracks;
+use chrono::{Datelike, Duration, Local, NaiveDateTime, TimeZone};
+use diesel::prelude::*;
+use std::collections::BTreeSet;
+use tracing::error;
+
+// tracks played at least this often, but last played longer ago than this,
+// count as forgotten
+const FORGOTTEN_PLAYS: i64 = 5;
+const FORGOTTEN_DAYS: i64 = 365;
+
+// Playlists worked out from the library each time they are opened
+#[derive(Clone, Copy, PartialEq)]
+pub enum AutoPlaylist {
+  // the first year of the decade, e.g. 1970
+  Decade(i32),
+  AddedThisMonth,
+  ForgottenGems,
+}
+
+impl AutoPlaylist {
+  pub fn name(self) -> String {
+    match self {
+      AutoPlaylist::Decade(start) => format!("{}s", start),
+      AutoPlaylist::AddedThisMonth => "Added this month".to_string(),
+      AutoPlaylist::ForgottenGems => "Forgotten gems".to_string(),
+    }
+  }
+}
+
+fn decades(conn: &mut SqliteConnection) -> BTreeSet<i32> {
+  tracks::table
+    .filter(tracks::year.is_not_null())
+    .select(tracks::year)
+    .distinct()
+    .load::<Option<i32>>(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to load years: {}", e);
+      Vec::new()
+    })
+    .into_iter()
+    .flatten()
+    .map(|year| year - year.rem_euclid(10))
+    .collect()
+}
+
+// A playlist for each decade the library has tracks from, oldest first,
+// then the time based ones
+pub fn auto_playlists() -> Vec<AutoPlaylist> {
+  let conn = &mut connect_db();
+  decades(conn)
+    .into_iter()
+    .map(AutoPlaylist::Decade)
+    .chain([AutoPlaylist::AddedThisMonth, AutoPlaylist::ForgottenGems])
+    .collect()
+}
+
+// Midnight on the first of this month here, in UTC like the added times
+// sqlite fills in
+fn start_of_month() -> NaiveDateTime {
+  let today = Local::now().date_naive();
+  let midnight = today
+    .with_day(1)
+    .unwrap_or(today)
+    .and_hms_opt(0, 0, 0)
+    .unwrap();
+  Local
+    .from_local_datetime(&midnight)
+    .earliest()
+    .map_or(midnight, |t| t.naive_utc())
+}
+
+pub fn auto_playlist_filenames(playlist: AutoPlaylist) -> Vec<String> {
+  let conn = &mut connect_db();
+  let filenames = match playlist {
+    AutoPlaylist::Decade(start) => tracks::table
+      .filter(tracks::year.between(start, start + 9))
+      .order((tracks::year, tracks::album, tracks::track))
+      .select(tracks::filename)
+      .load(conn),
+    AutoPlaylist::AddedThisMonth => tracks::table
+      .filter(tracks::added.ge(start_of_month()))
+      .order(tracks::added.desc())
+      .select(tracks::filename)
+      .load(conn),
+    // Played often, but not for a year, most played first
+    AutoPlaylist::ForgottenGems => played_often_until(
+      conn,
+      FORGOTTEN_PLAYS,
+      (Local::now() - Duration::days(FORGOTTEN_DAYS)).naive_local(),
+    ),
+  };
+  filenames.unwrap_or_else(|e| {
+    error!("Failed to load {}: {}", playlist.name(), e);
+    Vec::new()
+  })
+}
@@ -0,0 +1,88 @@
+// Library maintenance without the UI, e.g. from cron or over ssh
+//
+//   fml9000-scan scan [--checksums] [--fingerprints] <folder>...
+//   fml9000-scan organize [--dry-run] [--pattern <pattern>] <root>
+
+use fml9000::metadata::default_providers;
+use fml9000::organize::{apply_moves, plan_moves, DEFAULT_PATTERN};
+use fml9000::scanner::ScanOptions;
+use fml9000::{init_db, load_tracks, query_tracks, run_scan};
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage:
+  fml9000-scan scan [--checksums] [--fingerprints] <folder>...
+  fml9000-scan organize [--dry-run] [--pattern <pattern>] <root>";
+
+fn scan(args: &[String]) -> ExitCode {
+  let options = ScanOptions {
+    checksums: args.iter().any(|a| a == "--checksums"),
+    fingerprints: args.iter().any(|a| a == "--fingerprints"),
+  };
+  let folders: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+  if folders.is_empty() {
+    eprintln!("{}", USAGE);
+    return ExitCode::FAILURE;
+  }
+  let providers = default_providers(&[]);
+  for folder in folders {
+    run_scan(folder, &query_tracks(), &providers, options);
+  }
+  ExitCode::SUCCESS
+}
+
+fn organize(args: &[String]) -> ExitCode {
+  let mut dry_run = false;
+  let mut pattern = DEFAULT_PATTERN.to_string();
+  let mut root = None;
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--dry-run" => dry_run = true,
+      "--pattern" => match args.next() {
+        Some(p) => pattern = p.clone(),
+        None => {
+          eprintln!("{}", USAGE);
+          return ExitCode::FAILURE;
+        }
+      },
+      _ => root = Some(arg),
+    }
+  }
+  let Some(root) = root else {
+    eprintln!("{}", USAGE);
+    return ExitCode::FAILURE;
+  };
+  let moves = plan_moves(&load_tracks(), Path::new(root), &pattern);
+  if dry_run {
+    for m in &moves {
+      println!("{}\n  -> {}", m.from, m.to.display());
+    }
+    return ExitCode::SUCCESS;
+  }
+  let moved = apply_moves(&moves).len();
+  println!("Moved {} of {} files", moved, moves.len());
+  if moved == moves.len() {
+    ExitCode::SUCCESS
+  } else {
+    ExitCode::FAILURE
+  }
+}
+
+fn main() -> ExitCode {
+  fml9000::logging::init();
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let Some((command, rest)) = args.split_first() else {
+    eprintln!("{}", USAGE);
+    return ExitCode::FAILURE;
+  };
+  init_db();
+  match command.as_str() {
+    "scan" => scan(rest),
+    "organize" => organize(rest),
+    _ => {
+      eprintln!("{}", USAGE);
+      ExitCode::FAILURE
+    }
+  }
+}
@@ -0,0 +1,147 @@
+use crate::gtk_helpers::show_toast;
+use adw::prelude::*;
+use fml9000::properties::{save_chapters, Chapter};
+use fml9000::silence::{find_gaps, Gap};
+use gtk::gio;
+use gtk::glib::{self, MainContext};
+use gtk::{Button, CheckButton, Label, ListBox, Orientation, ScrolledWindow, SpinButton};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn format_time(d: Duration) -> String {
+  let secs = d.as_secs();
+  format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// A chapter at the start, then one where each checked gap ends
+fn checked_chapters(gaps: &[(Gap, CheckButton)]) -> Vec<Chapter> {
+  let starts = gaps
+    .iter()
+    .filter(|(_, check)| check.is_active())
+    .map(|(gap, _)| gap.end);
+  std::iter::once(Duration::ZERO)
+    .chain(starts)
+    .enumerate()
+    .map(|(i, start)| Chapter {
+      title: format!("Track {}", i + 1),
+      start,
+    })
+    .collect()
+}
+
+// Proposes where the tracks of a continuous mix start, from the silences
+// between them, for files that came without a CUE sheet or chapters. The
+// checked ones are saved as chapters, which the now playing page lists
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, filename: String) {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let settings_row = gtk::Box::new(Orientation::Horizontal, 6);
+  let threshold_spin = SpinButton::with_range(-80.0, -20.0, 1.0);
+  threshold_spin.set_value(-50.0);
+  let min_gap_spin = SpinButton::with_range(0.2, 10.0, 0.1);
+  min_gap_spin.set_digits(1);
+  min_gap_spin.set_value(1.5);
+  let detect_button = Button::builder().label("Detect").build();
+  let status = Label::new(None);
+  let results = ListBox::new();
+  let save_button = Button::builder()
+    .label("Save as chapters")
+    .sensitive(false)
+    .build();
+
+  settings_row.append(&Label::new(Some("Quieter than (dB)")));
+  settings_row.append(&threshold_spin);
+  settings_row.append(&Label::new(Some("for at least (s)")));
+  settings_row.append(&min_gap_spin);
+  settings_row.append(&detect_button);
+  f.append(&settings_row);
+  f.append(&status);
+  f.append(
+    &ScrolledWindow::builder()
+      .child(&results)
+      .vexpand(true)
+      .build(),
+  );
+  f.append(&save_button);
+  let boundaries_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(600)
+    .default_height(500)
+    .title(format!("Find track boundaries: {}", filename))
+    .child(&f)
+    .build();
+
+  let gaps: Rc<RefCell<Vec<(Gap, CheckButton)>>> = Rc::new(RefCell::new(Vec::new()));
+  let filename1 = filename.clone();
+  let gaps1 = gaps.clone();
+  detect_button.connect_clicked(glib::clone!(
+    #[weak]
+    threshold_spin,
+    #[weak]
+    min_gap_spin,
+    #[weak]
+    status,
+    #[weak]
+    results,
+    #[weak]
+    save_button,
+    move |button| {
+      let filename = filename1.clone();
+      let gaps = gaps1.clone();
+      let button = button.clone();
+      let threshold = threshold_spin.value() as f32;
+      let min_gap = Duration::from_secs_f64(min_gap_spin.value());
+      button.set_sensitive(false);
+      save_button.set_sensitive(false);
+      status.set_text("Listening for gaps...");
+      MainContext::default().spawn_local(async move {
+        let found = gio::spawn_blocking(move || find_gaps(&filename, threshold, min_gap)).await;
+        button.set_sensitive(true);
+        let Ok(found) = found else {
+          return;
+        };
+        while let Some(child) = results.first_child() {
+          results.remove(&child);
+        }
+        let mut gaps = gaps.borrow_mut();
+        gaps.clear();
+        for (i, gap) in found.into_iter().enumerate() {
+          let check = CheckButton::builder()
+            .label(format!(
+              "Track {} at {}, after {:.1} s of silence",
+              i + 2,
+              format_time(gap.end),
+              gap.length().as_secs_f32()
+            ))
+            .active(true)
+            .build();
+          results.append(&check);
+          gaps.push((gap, check));
+        }
+        status.set_text(&match gaps.len() {
+          0 => "No gaps found, try a higher threshold or shorter gap".to_string(),
+          n => format!("{} gaps found, uncheck any that are not between tracks", n),
+        });
+        save_button.set_sensitive(!gaps.is_empty());
+      });
+    }
+  ));
+
+  save_button.connect_clicked(glib::clone!(
+    #[weak]
+    boundaries_dialog,
+    move |button| {
+      let chapters = checked_chapters(&gaps.borrow());
+      match save_chapters(&filename, &chapters) {
+        Ok(()) => {
+          show_toast(button, &format!("Saved {} chapters", chapters.len()));
+          boundaries_dialog.close();
+        }
+        Err(e) => show_toast(button, &format!("Failed to save chapters: {}", e)),
+      }
+    }
+  ));
+  boundaries_dialog.present();
+  detect_button.emit_clicked();
+}
@@ -0,0 +1,123 @@
+use crate::analysis::Excerpt;
+use crate::connect_db;
+use crate::schema::track_bpm;
+use crate::writer::write_db;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use tracing::error;
+
+// samples per onset frame, per channel
+const HOP: usize = 256;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+// tempos are weighted towards this, an octave either way counting for less,
+// which sorts out most half and double tempo mix ups
+const LIKELY_BPM: f32 = 120.0;
+
+// How much louder each frame got than the one before, on a log scale.
+// Beats show up as regular peaks in this
+fn onset_envelope(samples: impl Iterator<Item = f32>, channels: usize) -> Vec<f32> {
+  let frame_len = HOP * channels;
+  let mut energies = Vec::new();
+  let mut sum = 0.0;
+  let mut n = 0;
+  for sample in samples {
+    sum += sample * sample;
+    n += 1;
+    if n == frame_len {
+      energies.push((1.0 + 100.0 * sum / frame_len as f32).ln());
+      sum = 0.0;
+      n = 0;
+    }
+  }
+  let mut envelope: Vec<f32> = energies
+    .windows(2)
+    .map(|w| (w[1] - w[0]).max(0.0))
+    .collect();
+  let mean = envelope.iter().sum::<f32>() / envelope.len().max(1) as f32;
+  envelope.iter_mut().for_each(|x| *x -= mean);
+  envelope
+}
+
+fn autocorrelation(envelope: &[f32], lag: usize) -> f32 {
+  envelope
+    .iter()
+    .zip(&envelope[lag..])
+    .map(|(a, b)| a * b)
+    .sum()
+}
+
+// The tempo whose beat period best lines the onsets up with themselves.
+// None when the excerpt is too short to tell
+pub fn detect_bpm(excerpt: &Excerpt) -> Option<f32> {
+  let frame_rate = excerpt.sample_rate as f32 / HOP as f32;
+  let envelope = onset_envelope(excerpt.samples.iter().copied(), excerpt.channels);
+
+  let min_lag = (60.0 * frame_rate / MAX_BPM).floor() as usize;
+  let max_lag = (60.0 * frame_rate / MIN_BPM).ceil() as usize;
+  if min_lag < 1 || envelope.len() < max_lag * 4 {
+    return None;
+  }
+  let correlations: Vec<f32> = (min_lag - 1..=max_lag + 1)
+    .map(|lag| autocorrelation(&envelope, lag))
+    .collect();
+  let weight = |lag: usize| {
+    let octaves = (60.0 * frame_rate / lag as f32 / LIKELY_BPM).log2();
+    (-0.5 * octaves * octaves).exp()
+  };
+  let (i, _) = (1..correlations.len() - 1)
+    .map(|i| (i, correlations[i] * weight(min_lag - 1 + i)))
+    .max_by(|a, b| a.1.total_cmp(&b.1))?;
+  if correlations[i] <= 0.0 {
+    return None;
+  }
+  // fit a parabola through the peak and its neighbours for a lag between
+  // frames
+  let (a, b, c) = (correlations[i - 1], correlations[i], correlations[i + 1]);
+  let denominator = a - 2.0 * b + c;
+  let offset = if denominator.abs() > f32::EPSILON {
+    (0.5 * (a - c) / denominator).clamp(-0.5, 0.5)
+  } else {
+    0.0
+  };
+  let lag = (min_lag - 1 + i) as f32 + offset;
+  Some((600.0 * frame_rate / lag).round() / 10.0)
+}
+
+pub fn load_bpms() -> HashMap<String, f32> {
+  let conn = &mut connect_db();
+  match track_bpm::table
+    .select((track_bpm::filename, track_bpm::bpm))
+    .load::<(String, f32)>(conn)
+  {
+    Ok(rows) => rows.into_iter().collect(),
+    Err(e) => {
+      error!("Failed to load BPMs: {}", e);
+      HashMap::new()
+    }
+  }
+}
+
+pub fn store_bpm(filename: &str, bpm: f32) {
+  if let Err(e) = write_db(|conn| {
+    diesel::replace_into(track_bpm::table)
+      .values((track_bpm::filename.eq(filename), track_bpm::bpm.eq(bpm)))
+      .execute(conn)
+  }) {
+    error!("Failed to store BPM of {}: {}", filename, e);
+  }
+}
+
+// Files with a known BPM in the range, slowest first
+pub fn filenames_in_bpm_range(min: f32, max: f32) -> Vec<String> {
+  let conn = &mut connect_db();
+  track_bpm::table
+    .filter(track_bpm::bpm.between(min, max))
+    .order(track_bpm::bpm.asc())
+    .select(track_bpm::filename)
+    .load::<String>(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to load BPM range: {}", e);
+      Vec::new()
+    })
+}
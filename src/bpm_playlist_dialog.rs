@@ -0,0 +1,105 @@
+use crate::gtk_helpers::show_toast;
+use crate::playlist_manager::load_playlist_mgr_store;
+use adw::prelude::*;
+use fml9000::bpm::filenames_in_bpm_range;
+use fml9000::key::{filenames_compatible_with, Key};
+use fml9000::playlists::save_as_playlist;
+use gtk::gio::ListStore;
+use gtk::glib;
+use gtk::{Button, DropDown, Entry, Label, Orientation, SpinButton};
+use std::rc::Rc;
+
+// Creates a playlist of the tracks between two tempos, slowest first, e.g.
+// for a workout or a DJ set, optionally only those in keys that mix well
+// with a chosen one. It is a snapshot, tracks analyzed later are not added
+// to it
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, playlist_mgr_store: ListStore) {
+  let f = gtk::Box::new(Orientation::Horizontal, 6);
+
+  let min_spin = SpinButton::with_range(40.0, 250.0, 1.0);
+  min_spin.set_value(120.0);
+  let max_spin = SpinButton::with_range(40.0, 250.0, 1.0);
+  max_spin.set_value(130.0);
+  let mut keys: Vec<Key> = Key::all().collect();
+  keys.sort_by_key(|k| k.wheel_order());
+  let key_names: Vec<String> = std::iter::once("Any key".to_string())
+    .chain(keys.iter().map(|k| format!("{} {}", k.camelot(), k.name())))
+    .collect();
+  let key_names: Vec<&str> = key_names.iter().map(String::as_str).collect();
+  let key_dropdown = DropDown::from_strings(&key_names);
+  key_dropdown.set_tooltip_text(Some("Only keys that mix well with this one"));
+  let create_button = Button::builder().label("Create").build();
+  let textbox = Entry::builder()
+    .placeholder_text("Playlist name")
+    .hexpand(true)
+    .activates_default(true)
+    .build();
+
+  f.append(&Label::new(Some("BPM")));
+  f.append(&min_spin);
+  f.append(&Label::new(Some("to")));
+  f.append(&max_spin);
+  f.append(&key_dropdown);
+  f.append(&textbox);
+  f.append(&create_button);
+  let bpm_playlist_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(600)
+    .title("New playlist from BPM and key")
+    .child(&f)
+    .default_widget(&create_button)
+    .build();
+
+  create_button.connect_clicked(glib::clone!(
+    #[weak]
+    textbox,
+    #[weak]
+    min_spin,
+    #[weak]
+    max_spin,
+    #[weak]
+    key_dropdown,
+    #[weak]
+    bpm_playlist_dialog,
+    move |_| {
+      let (min, max) = (min_spin.value() as f32, max_spin.value() as f32);
+      // the first entry is any key
+      let key = (key_dropdown.selected() as usize)
+        .checked_sub(1)
+        .and_then(|i| keys.get(i));
+      let name = match (textbox.text().as_str(), key) {
+        ("", None) => format!("{:.0}-{:.0} BPM", min, max),
+        ("", Some(key)) => format!("{:.0}-{:.0} BPM {}", min, max, key.camelot()),
+        (name, _) => name.to_string(),
+      };
+      let mut filenames = filenames_in_bpm_range(min.min(max), min.max(max));
+      if let Some(&key) = key {
+        let compatible = filenames_compatible_with(key);
+        filenames.retain(|f| compatible.contains(f));
+      }
+      if filenames.is_empty() {
+        show_toast(
+          &textbox,
+          "No tracks match, detect BPM and key from the track list first",
+        );
+        return;
+      }
+      match save_as_playlist(&name, &filenames) {
+        Ok(_) => {
+          load_playlist_mgr_store(&playlist_mgr_store);
+          show_toast(
+            &textbox,
+            &format!("Created playlist {} with {} tracks", name, filenames.len()),
+          );
+        }
+        Err(e) => show_toast(
+          &textbox,
+          &format!("Failed to create playlist {}: {}", name, e),
+        ),
+      }
+      bpm_playlist_dialog.close();
+    }
+  ));
+  bpm_playlist_dialog.present();
+}
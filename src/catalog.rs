@@ -0,0 +1,160 @@
+use crate::albums::{group_albums, Album};
+use crate::interned::Interned;
+use crate::models::Track;
+use gtk::gdk_pixbuf::Pixbuf;
+use serde_derive::Serialize;
+use std::fmt::Write;
+use std::path::Path;
+use std::rc::Rc;
+use tracing::warn;
+
+const THUMBNAIL_SIZE: i32 = 200;
+
+// Used when no template is set. A template is any HTML file with
+// {{catalog}} where the album list goes
+pub const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Music library</title>
+<style>
+body { font-family: sans-serif; max-width: 60em; margin: auto; }
+.album { display: flex; gap: 1em; margin: 1em 0; }
+.album img { width: 200px; height: 200px; object-fit: cover; }
+.album ol { margin: 0; }
+</style>
+</head>
+<body>
+{{catalog}}
+</body>
+</html>
+"#;
+
+#[derive(Serialize)]
+struct CatalogTrack {
+  track: Option<Interned>,
+  title: Option<String>,
+  artist: Option<Interned>,
+  duration_ms: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct CatalogAlbum {
+  artist: Option<Interned>,
+  title: Option<Interned>,
+  year: Option<i32>,
+  duration_ms: i64,
+  // relative to the catalog folder
+  art: Option<String>,
+  tracks: Vec<CatalogTrack>,
+}
+
+fn escape(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn or_unknown(s: Option<&str>) -> String {
+  escape(s.unwrap_or("Unknown"))
+}
+
+// m:ss
+fn format_duration(ms: i64) -> String {
+  let secs = ms / 1000;
+  format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+// Scaled down copies, so the catalog doesn't carry full size scans around
+fn write_thumbnail(album: &Album, dir: &Path, n: usize) -> Option<String> {
+  let art = album.art();
+  if !art.exists() {
+    return None;
+  }
+  let name = format!("art/{}.jpg", n);
+  let result = Pixbuf::from_file_at_scale(&art, THUMBNAIL_SIZE, THUMBNAIL_SIZE, true)
+    .and_then(|pixbuf| pixbuf.savev(dir.join(&name), "jpeg", &[("quality", "85")]));
+  match result {
+    Ok(()) => Some(name),
+    Err(e) => {
+      warn!("Failed to make thumbnail of {}: {}", art.display(), e);
+      None
+    }
+  }
+}
+
+fn catalog_album(album: &Album, art: Option<String>) -> CatalogAlbum {
+  CatalogAlbum {
+    artist: album.artist.clone(),
+    title: album.title.clone(),
+    year: album.year,
+    duration_ms: album.duration_ms,
+    art,
+    tracks: album
+      .tracks
+      .iter()
+      .map(|t| CatalogTrack {
+        track: t.track.clone(),
+        title: t.title.clone(),
+        artist: t.artist.clone(),
+        duration_ms: t.duration_ms,
+      })
+      .collect(),
+  }
+}
+
+// Albums under a heading per artist, in the order group_albums sorts them
+fn render_html(albums: &[CatalogAlbum]) -> String {
+  let mut html = String::new();
+  let mut artist = None;
+  for album in albums {
+    if artist != Some(&album.artist) {
+      artist = Some(&album.artist);
+      let _ = writeln!(html, "<h2>{}</h2>", or_unknown(album.artist.as_deref()));
+    }
+    let _ = writeln!(html, "<div class=\"album\">");
+    if let Some(art) = &album.art {
+      let _ = writeln!(html, "<img src=\"{}\" alt=\"\">", escape(art));
+    }
+    let year = album.year.map_or(String::new(), |y| format!(" ({})", y));
+    let _ = writeln!(
+      html,
+      "<div><h3>{}{}</h3><p>{} tracks, {}</p><ol>",
+      or_unknown(album.title.as_deref()),
+      year,
+      album.tracks.len(),
+      format_duration(album.duration_ms)
+    );
+    for track in &album.tracks {
+      let _ = writeln!(
+        html,
+        "<li>{} <small>{}</small></li>",
+        or_unknown(track.title.as_deref()),
+        track
+          .duration_ms
+          .map_or(String::new(), |ms| format_duration(ms as i64))
+      );
+    }
+    let _ = writeln!(html, "</ol></div></div>");
+  }
+  html
+}
+
+// Writes index.html, catalog.json and album art thumbnails into dir.
+// Returns the number of albums. Blocking, so call it off the main thread
+pub fn export_catalog(tracks: Vec<Track>, dir: &Path, template: &str) -> std::io::Result<usize> {
+  let tracks: Vec<Rc<Track>> = tracks.into_iter().map(Rc::new).collect();
+  let albums = group_albums(&tracks);
+  std::fs::create_dir_all(dir.join("art"))?;
+  let catalog: Vec<CatalogAlbum> = albums
+    .iter()
+    .enumerate()
+    .map(|(n, album)| catalog_album(album, write_thumbnail(album, dir, n)))
+    .collect();
+  let json = serde_json::to_string_pretty(&catalog).map_err(std::io::Error::other)?;
+  std::fs::write(dir.join("catalog.json"), json)?;
+  let html = template.replace("{{catalog}}", &render_html(&catalog));
+  std::fs::write(dir.join("index.html"), html)?;
+  Ok(catalog.len())
+}
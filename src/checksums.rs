@@ -0,0 +1,141 @@
+use crate::connect_db;
+use crate::schema::file_checksums;
+use crate::writer::write_db;
+use diesel::prelude::*;
+use gtk::glib::{Checksum, ChecksumType};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tracing::error;
+
+pub enum Status {
+  Ok,
+  // no checksum was stored yet, now there is
+  Added,
+  // modified since the last check, e.g. by a tag edit, so checksummed again
+  Updated,
+  // the contents changed while the modification time didn't, which editing
+  // the file never does
+  Corrupt,
+  Missing,
+  Unreadable(String),
+}
+
+impl Status {
+  pub fn is_problem(&self) -> bool {
+    matches!(
+      self,
+      Status::Corrupt | Status::Missing | Status::Unreadable(_)
+    )
+  }
+
+  pub fn describe(&self) -> &str {
+    match self {
+      Status::Ok => "OK",
+      Status::Added => "checksum added",
+      Status::Updated => "modified, checksum updated",
+      Status::Corrupt => "contents changed without being modified, possibly corrupt",
+      Status::Missing => "missing",
+      Status::Unreadable(e) => e,
+    }
+  }
+}
+
+fn sha256(path: &Path) -> std::io::Result<String> {
+  let mut file = File::open(path)?;
+  let mut checksum = Checksum::new(ChecksumType::Sha256).unwrap();
+  let mut buf = vec![0; 1 << 16];
+  loop {
+    let n = file.read(&mut buf)?;
+    if n == 0 {
+      break;
+    }
+    checksum.update(&buf[..n]);
+  }
+  Ok(checksum.string().unwrap_or_default().to_string())
+}
+
+fn modified_ms(path: &Path) -> std::io::Result<i64> {
+  let modified = std::fs::metadata(path)?.modified()?;
+  Ok(
+    modified
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |d| d.as_millis() as i64),
+  )
+}
+
+pub(crate) fn store(
+  conn: &mut SqliteConnection,
+  filename: &str,
+  sha256: &str,
+  modified_ms: i64,
+) -> QueryResult<usize> {
+  diesel::replace_into(file_checksums::table)
+    .values((
+      file_checksums::filename.eq(filename),
+      file_checksums::sha256.eq(sha256),
+      file_checksums::modified_ms.eq(modified_ms),
+    ))
+    .execute(conn)
+}
+
+fn save(filename: &str, sha256: &str, modified_ms: i64) {
+  if let Err(e) = write_db(|conn| store(conn, filename, sha256, modified_ms)) {
+    error!("Failed to store checksum of {}: {}", filename, e);
+  }
+}
+
+// For the scanner, which checksums new files when the preference is on.
+// Reads the whole file, so don't hold the write lock around it
+pub(crate) fn file_checksum(path: &Path) -> Option<(String, i64)> {
+  match (sha256(path), modified_ms(path)) {
+    (Ok(sha256), Ok(modified_ms)) => Some((sha256, modified_ms)),
+    (Err(e), _) | (_, Err(e)) => {
+      error!("Failed to checksum {}: {}", path.display(), e);
+      None
+    }
+  }
+}
+
+fn verify(conn: &mut SqliteConnection, filename: &str) -> Status {
+  let path = Path::new(filename);
+  let modified_ms = match modified_ms(path) {
+    Ok(ms) => ms,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Status::Missing,
+    Err(e) => return Status::Unreadable(e.to_string()),
+  };
+  let sha256 = match sha256(path) {
+    Ok(sha256) => sha256,
+    Err(e) => return Status::Unreadable(e.to_string()),
+  };
+  let stored: Option<(String, i64)> = file_checksums::table
+    .filter(file_checksums::filename.eq(filename))
+    .select((file_checksums::sha256, file_checksums::modified_ms))
+    .first(conn)
+    .optional()
+    .unwrap_or_else(|e| {
+      error!("Failed to load checksum of {}: {}", filename, e);
+      None
+    });
+  match stored {
+    None => {
+      save(filename, &sha256, modified_ms);
+      Status::Added
+    }
+    Some((_, stored_ms)) if stored_ms != modified_ms => {
+      save(filename, &sha256, modified_ms);
+      Status::Updated
+    }
+    // the stored checksum is kept, so the file keeps showing up until it is
+    // restored from a backup
+    Some((stored, _)) if stored != sha256 => Status::Corrupt,
+    Some(_) => Status::Ok,
+  }
+}
+
+// Reads every file in full, so call it off the main thread and in batches
+pub fn verify_files(filenames: &[String]) -> Vec<Status> {
+  let conn = &mut connect_db();
+  filenames.iter().map(|f| verify(conn, f)).collect()
+}
@@ -0,0 +1,71 @@
+use crate::albums::AlbumKey;
+use crate::connect_db;
+use crate::interned::Interned;
+use crate::schema::continuous_albums;
+use crate::writer::write_db;
+use diesel::prelude::*;
+use std::collections::HashSet;
+use tracing::error;
+
+// Tags that iTunes and taggers following it set to 1 on gapless albums
+pub const GAPLESS_TAGS: [&str; 2] = ["ITUNESGAPLESS", "ITUNPGAP"];
+
+// Missing artists and titles are stored as empty strings, since NULLs in
+// the primary key would never compare equal
+fn columns(key: &AlbumKey) -> (&str, &str) {
+  (
+    key.0.as_deref().unwrap_or(""),
+    key.1.as_deref().unwrap_or(""),
+  )
+}
+
+fn to_key((artist, album): (String, String)) -> AlbumKey {
+  (
+    Some(artist).filter(|a| !a.is_empty()).map(Interned::from),
+    Some(album).filter(|a| !a.is_empty()).map(Interned::from),
+  )
+}
+
+// Albums that always play straight through, live recordings mostly, where
+// shuffle cutting away mid-album would break songs that run into each other
+pub fn load_continuous_albums() -> HashSet<AlbumKey> {
+  let conn = &mut connect_db();
+  match continuous_albums::table
+    .select((continuous_albums::artist, continuous_albums::album))
+    .load::<(String, String)>(conn)
+  {
+    Ok(rows) => rows.into_iter().map(to_key).collect(),
+    Err(e) => {
+      error!("Failed to load continuous albums: {}", e);
+      HashSet::new()
+    }
+  }
+}
+
+// The scanner flags albums of new files that have gapless tags
+pub fn mark_continuous(conn: &mut SqliteConnection, key: &AlbumKey) -> QueryResult<usize> {
+  let (artist, album) = columns(key);
+  diesel::insert_or_ignore_into(continuous_albums::table)
+    .values((
+      continuous_albums::artist.eq(artist),
+      continuous_albums::album.eq(album),
+    ))
+    .execute(conn)
+}
+
+pub fn set_continuous(key: &AlbumKey, continuous: bool) -> QueryResult<()> {
+  write_db(|conn| {
+    if continuous {
+      mark_continuous(conn, key)?;
+    } else {
+      let (artist, album) = columns(key);
+      diesel::delete(
+        continuous_albums::table
+          .filter(continuous_albums::artist.eq(artist))
+          .filter(continuous_albums::album.eq(album)),
+      )
+      .execute(conn)?;
+    }
+    Ok(())
+  })
+}
@@ -0,0 +1,96 @@
+use fml9000::album_art::cover_path;
+use gtk::gdk::Texture;
+use gtk::gio;
+use gtk::glib::MainContext;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+type CoverChanged = Box<dyn Fn(Option<&Texture>)>;
+
+// None when the folder has no cover
+type Cover = (PathBuf, Option<Texture>);
+
+async fn load(path: PathBuf) -> Option<Texture> {
+  gio::spawn_blocking(move || Texture::from_filename(path).ok())
+    .await
+    .ok()
+    .flatten()
+}
+
+// The current track's album art, decoded off the main thread. The next
+// track's is loaded ahead of time, so switching to it is instant
+#[derive(Default)]
+pub struct CoverCache {
+  current: RefCell<Option<Cover>>,
+  next: RefCell<Option<Cover>>,
+  // the cover show was last asked for, so a slow load can't overwrite a
+  // later one
+  wanted: RefCell<Option<PathBuf>>,
+  // the cover preload is working on
+  preloading: RefCell<Option<PathBuf>>,
+  changed: RefCell<Vec<CoverChanged>>,
+}
+
+impl CoverCache {
+  pub fn current(&self) -> Option<Texture> {
+    self.current.borrow().as_ref().and_then(|(_, t)| t.clone())
+  }
+
+  pub fn connect_changed(&self, f: impl Fn(Option<&Texture>) + 'static) {
+    self.changed.borrow_mut().push(Box::new(f));
+  }
+
+  fn set(&self, cover: Cover) {
+    let texture = cover.1.clone();
+    self.current.replace(Some(cover));
+    for f in self.changed.borrow().iter() {
+      f(texture.as_ref());
+    }
+  }
+
+  // Switches to the art of the file's folder: at once if it is already
+  // showing or was preloaded, otherwise once it has loaded
+  pub fn show(self: &Rc<Self>, filename: &str) {
+    let path = cover_path(filename);
+    self.wanted.replace(Some(path.clone()));
+    if self
+      .current
+      .borrow()
+      .as_ref()
+      .is_some_and(|(p, _)| *p == path)
+    {
+      return;
+    }
+    let next = self.next.take();
+    if let Some(next) = next.filter(|(p, _)| *p == path) {
+      self.set(next);
+      return;
+    }
+    let cache = self.clone();
+    MainContext::default().spawn_local(async move {
+      let texture = load(path.clone()).await;
+      if cache.wanted.borrow().as_ref() == Some(&path) {
+        cache.set((path, texture));
+      }
+    });
+  }
+
+  // Loads the art of a file that is about to play
+  pub fn preload(self: &Rc<Self>, filename: &str) {
+    let path = cover_path(filename);
+    let known =
+      |cover: &RefCell<Option<Cover>>| cover.borrow().as_ref().is_some_and(|(p, _)| *p == path);
+    if known(&self.current) || known(&self.next) || self.preloading.borrow().as_ref() == Some(&path)
+    {
+      return;
+    }
+    self.preloading.replace(Some(path.clone()));
+    let cache = self.clone();
+    MainContext::default().spawn_local(async move {
+      let texture = load(path.clone()).await;
+      cache.preloading.replace(None);
+      cache.next.replace(Some((path, texture)));
+    });
+  }
+}
@@ -0,0 +1,64 @@
+use crate::gtk_helpers::show_toast;
+use crate::playlist_manager::load_playlist_mgr_store;
+use adw::prelude::*;
+use fml9000::playlists::playlists_containing;
+use fml9000::trash::trash_files;
+use gtk::gio::{self, ListStore};
+use gtk::AlertDialog;
+use std::rc::Rc;
+
+const LISTED_FILES: usize = 10;
+
+fn describe(filenames: &[String], playlists: &[String]) -> String {
+  let mut detail: Vec<String> = filenames.iter().take(LISTED_FILES).cloned().collect();
+  if filenames.len() > LISTED_FILES {
+    detail.push(format!("...and {} more", filenames.len() - LISTED_FILES));
+  }
+  if !playlists.is_empty() {
+    detail.push(String::new());
+    detail.push(format!(
+      "They will also be removed from these playlists: {}",
+      playlists.join(", ")
+    ));
+  }
+  detail.join("\n")
+}
+
+pub async fn dialog<W: IsA<gtk::Window>>(
+  wnd: Rc<W>,
+  filenames: Vec<String>,
+  playlist_mgr_store: ListStore,
+) {
+  if filenames.is_empty() {
+    return;
+  }
+  let query = filenames.clone();
+  let playlists = gio::spawn_blocking(move || playlists_containing(&query))
+    .await
+    .unwrap_or_default();
+
+  let confirm = AlertDialog::builder()
+    .modal(true)
+    .message(format!("Move {} files to the trash?", filenames.len()))
+    .detail(describe(&filenames, &playlists))
+    .buttons(["Cancel", "Move to trash"])
+    .cancel_button(0)
+    .default_button(0)
+    .build();
+  if confirm.choose_future(Some(&*wnd)).await != Ok(1) {
+    return;
+  }
+
+  let trashed: Vec<String> = gio::spawn_blocking(move || trash_files(&filenames))
+    .await
+    .unwrap_or_default();
+  show_toast(
+    wnd.upcast_ref::<gtk::Window>(),
+    &format!("Moved {} files to the trash", trashed.len()),
+  );
+  // the main window takes them out of the library and everything showing it
+  let _ = wnd
+    .upcast_ref::<gtk::Window>()
+    .activate_action("win.forget-files", Some(&trashed.to_variant()));
+  load_playlist_mgr_store(&playlist_mgr_store);
+}
@@ -0,0 +1,60 @@
+use adw::prelude::*;
+use fml9000::logging::{log_dir, recent_problems};
+use fml9000::profile::format_timings;
+use gtk::{Button, Label, Orientation, ScrolledWindow, TextView, WrapMode};
+use std::rc::Rc;
+
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>) {
+  let problems = recent_problems();
+  let text = if problems.is_empty() {
+    "No warnings or errors since startup".to_string()
+  } else {
+    problems.join("\n")
+  };
+  let text = format!(
+    "Startup timings\n{}\n\nProblems\n{}",
+    format_timings(),
+    text
+  );
+
+  let text_view = TextView::builder()
+    .editable(false)
+    .monospace(true)
+    .wrap_mode(WrapMode::WordChar)
+    .build();
+  text_view.buffer().set_text(&text);
+
+  let log_label = Label::builder()
+    .label(format!("Full logs: {}", log_dir().display()))
+    .selectable(true)
+    .hexpand(true)
+    .xalign(0.0)
+    .build();
+  // bug reports get the recent problems along with where to find the rest
+  let report = format!("{}\n\n{}", log_label.label(), text);
+  let copy_button = Button::builder().label("Copy").build();
+  copy_button.connect_clicked(move |button| button.clipboard().set_text(&report));
+
+  let footer = gtk::Box::new(Orientation::Horizontal, 0);
+  footer.append(&log_label);
+  footer.append(&copy_button);
+
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  f.append(
+    &ScrolledWindow::builder()
+      .child(&text_view)
+      .vexpand(true)
+      .build(),
+  );
+  f.append(&footer);
+
+  let diagnostics_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(800)
+    .default_height(500)
+    .title("Diagnostics")
+    .child(&f)
+    .build();
+  diagnostics_dialog.present();
+}
@@ -0,0 +1,88 @@
+use crate::network::is_offline;
+use chrono::Utc;
+use discord_rich_presence::activity::{Activity, ActivityType, Assets, Timestamps};
+use discord_rich_presence::{DiscordIpc, DiscordIpcClient};
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+use tracing::warn;
+
+// Art has to be uploaded to the Discord application ahead of time, so every
+// track shows the same icon under this asset name
+const ICON_ASSET: &str = "fml9000";
+
+pub struct NowPlaying {
+  pub title: String,
+  pub artist: String,
+  pub album: Option<String>,
+  pub elapsed: Duration,
+  pub duration: Option<Duration>,
+}
+
+fn set_activity(client: &mut DiscordIpcClient, now: &NowPlaying) -> bool {
+  let started = Utc::now().timestamp_millis() - now.elapsed.as_millis() as i64;
+  let mut timestamps = Timestamps::new().start(started);
+  if let Some(duration) = now.duration {
+    timestamps = timestamps.end(started + duration.as_millis() as i64);
+  }
+  let mut assets = Assets::new().large_image(ICON_ASSET);
+  if let Some(album) = &now.album {
+    assets = assets.large_text(album);
+  }
+  let activity = Activity::new()
+    .activity_type(ActivityType::Listening)
+    .details(&now.title)
+    .state(&now.artist)
+    .timestamps(timestamps)
+    .assets(assets);
+  client.set_activity(activity).is_ok()
+}
+
+// Shows what is playing in Discord through its local IPC socket. Talking to
+// Discord blocks, so it happens on a thread of its own that takes updates
+// over a channel and connects on first use. Nothing is sent while offline
+// mode is on
+pub struct DiscordPresence {
+  tx: Sender<Option<NowPlaying>>,
+}
+
+impl DiscordPresence {
+  pub fn start(client_id: String) -> Self {
+    let (tx, rx) = channel::<Option<NowPlaying>>();
+    std::thread::spawn(move || {
+      let mut client = DiscordIpcClient::new(&client_id);
+      let mut connected = false;
+      // ends once the sender is dropped
+      while let Ok(now) = rx.recv() {
+        let now = now.filter(|_| !is_offline());
+        if now.is_none() && !connected {
+          continue;
+        }
+        if !connected {
+          if let Err(e) = client.connect() {
+            warn!("Failed to connect to Discord: {}", e);
+            continue;
+          }
+          connected = true;
+        }
+        let sent = match &now {
+          Some(now) => set_activity(&mut client, now),
+          None => client.clear_activity().is_ok(),
+        };
+        // Discord may have been restarted, try again on the next update
+        if !sent {
+          let _ = client.close();
+          connected = false;
+        }
+      }
+      if connected {
+        let _ = client.close();
+      }
+    });
+    DiscordPresence { tx }
+  }
+
+  // None clears the presence
+  pub fn update(&self, now: Option<NowPlaying>) {
+    let _ = self.tx.send(now);
+  }
+}
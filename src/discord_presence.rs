@@ -0,0 +1,107 @@
+use crate::gtk_helpers::str_or_unknown;
+use crate::player::Player;
+use crate::settings::FmlSettings;
+use fml9000::discord::{DiscordPresence, NowPlaying};
+use gtk::glib;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// how often pausing, seeking and preference changes are picked up
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+// a start time further off than this means the track was seeked
+const SEEK_SLACK_MS: i64 = 2000;
+
+struct Sent {
+  filename: String,
+  start_ms: i64,
+}
+
+#[derive(Default)]
+struct State {
+  client_id: Option<String>,
+  presence: Option<DiscordPresence>,
+  sent: Option<Sent>,
+}
+
+fn now_ms() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_millis() as i64)
+}
+
+fn sync(player: &Player, settings: &RefCell<FmlSettings>, state: &mut State) {
+  let client_id = {
+    let s = settings.borrow();
+    s.discord_presence
+      .then(|| s.discord_client_id.clone())
+      .flatten()
+  };
+  // turning it off or changing the id drops the connection, which clears
+  // the presence on Discord's side
+  if client_id != state.client_id {
+    *state = State {
+      presence: client_id.clone().map(DiscordPresence::start),
+      client_id,
+      sent: None,
+    };
+  }
+  let Some(presence) = &state.presence else {
+    return;
+  };
+
+  let track = player.current.borrow().clone();
+  let track = track.filter(|_| !player.sink.borrow().is_paused());
+  let Some(track) = track else {
+    if state.sent.take().is_some() {
+      presence.update(None);
+    }
+    return;
+  };
+  let elapsed = player.position();
+  let start_ms = now_ms() - elapsed.as_millis() as i64;
+  if let Some(sent) = &state.sent {
+    if sent.filename == track.filename && (sent.start_ms - start_ms).abs() < SEEK_SLACK_MS {
+      return;
+    }
+  }
+  presence.update(Some(NowPlaying {
+    title: str_or_unknown(&track.title),
+    artist: str_or_unknown(&track.artist),
+    album: track.album.as_deref().map(str::to_string),
+    elapsed,
+    duration: track
+      .duration_ms
+      .map(|ms| Duration::from_millis(ms.max(0) as u64)),
+  }));
+  state.sent = Some(Sent {
+    filename: track.filename.clone(),
+    start_ms,
+  });
+}
+
+// Publishes the playing track to Discord while the preference is on,
+// following track changes right away and pausing, seeking and preference
+// changes on a timer
+pub fn add_discord_presence(player: &Rc<Player>, settings: &Rc<RefCell<FmlSettings>>) {
+  let state = Rc::new(RefCell::new(State::default()));
+
+  let player_rc = Rc::downgrade(player);
+  let settings_rc = settings.clone();
+  let state_rc = state.clone();
+  player.connect_track_changed(move |_| {
+    if let Some(player) = player_rc.upgrade() {
+      sync(&player, &settings_rc, &mut state_rc.borrow_mut());
+    }
+  });
+
+  let player_rc = Rc::downgrade(player);
+  let settings = settings.clone();
+  glib::timeout_add_local(SYNC_INTERVAL, move || {
+    let Some(player) = player_rc.upgrade() else {
+      return glib::ControlFlow::Break;
+    };
+    sync(&player, &settings, &mut state.borrow_mut());
+    glib::ControlFlow::Continue
+  });
+}
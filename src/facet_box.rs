@@ -1,32 +1,336 @@
-use crate::grid_cell::Entry;
+use crate::grid_cell::{Entry, GridCell};
 use crate::gtk_helpers::{
-  get_album_artist_or_artist, get_cell, get_selection, setup_col, str_or_unknown,
+  add_type_ahead, check_online, get_cell, get_selection, setup_col, show_toast, str_or_unknown,
 };
+use crate::player::Player;
+use crate::settings::FmlSettings;
+use adw::prelude::*;
+use fml9000::albums::AlbumKey;
+use fml9000::art_fetch::albums_missing_art;
+use fml9000::continuous::set_continuous;
+use fml9000::fingerprint::unknown_tracks;
+use fml9000::library_index::LibraryIndex;
 use fml9000::models::Track;
-use fml9000::{load_playlist_store, Facet};
+use fml9000::search::Query;
+use fml9000::subsonic::Server;
+use fml9000::{load_playlist_store_chunked, Facet};
 use gtk::gio::ListStore;
-use gtk::glib::BoxedAnyObject;
-use adw::prelude::*;
+use gtk::glib::{self, BoxedAnyObject, MainContext, WeakRef};
 use gtk::{
-  ColumnView, ColumnViewColumn, CustomFilter, CustomSorter, FilterListModel, MultiSelection,
-  Orientation, ScrolledWindow, SearchEntry, SignalListItemFactory, SortListModel,
+  gdk, gio, ApplicationWindow, ColumnView, ColumnViewColumn, CustomFilter, CustomSorter,
+  FilterListModel, GestureClick, ListItem, MultiSelection, Orientation, PopoverMenu,
+  ScrolledWindow, SearchEntry, SignalListItemFactory, SortListModel,
 };
-use regex::Regex;
-use std::cell::Ref;
+use std::cell::{Cell, Ref, RefCell};
 use std::rc::Rc;
 
+fn selected_tracks(sel: &MultiSelection, library: &LibraryIndex) -> Vec<Rc<Track>> {
+  let selection = sel.selection();
+  let mut result = Vec::new();
+  for i in 0..selection.size() {
+    let item = get_selection(sel, selection.nth(i as u32));
+    let r: Ref<Facet> = item.borrow();
+    if !r.all {
+      result.extend(library.by_facet(&r).iter().cloned());
+    }
+  }
+  result
+}
+
+fn selected_filenames(sel: &MultiSelection, library: &LibraryIndex) -> Vec<String> {
+  selected_tracks(sel, library)
+    .iter()
+    .map(|x| x.filename.clone())
+    .collect()
+}
+
+// The album title ranks above the artist, and (All) always shows first
+fn facet_score(query: &Query, obj: &glib::Object) -> Option<i64> {
+  let facet: Ref<Facet> = obj.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+  if facet.all {
+    return Some(i64::MAX);
+  }
+  query.score_fields(&[
+    facet.album.as_deref(),
+    facet.album_artist_or_artist.as_deref(),
+  ])
+}
+
+fn highlight_matches(cell: &GridCell, query: Option<&Query>) {
+  let ranges = query.map_or(Vec::new(), |q| q.match_ranges(&cell.text()));
+  cell.set_highlights(&ranges);
+}
+
+fn facet_text(facet: &Facet) -> String {
+  if facet.all {
+    "(All)".to_string()
+  } else {
+    format!(
+      "{} // {}",
+      str_or_unknown(&facet.album_artist_or_artist),
+      str_or_unknown(&facet.album),
+    )
+  }
+}
+
+// The albums behind the selected facets, leaving out (All)
+fn selected_albums(sel: &MultiSelection) -> Vec<AlbumKey> {
+  let selection = sel.selection();
+  let mut result = Vec::new();
+  for i in 0..selection.size() {
+    let item = get_selection(sel, selection.nth(i as u32));
+    let r: Ref<Facet> = item.borrow();
+    if !r.all {
+      result.push((r.album_artist_or_artist.clone(), r.album.clone()));
+    }
+  }
+  result
+}
+
+fn create_context_menu(
+  facet_columnview: &ColumnView,
+  facet_sel: &Rc<MultiSelection>,
+  playlist_store: &ListStore,
+  library: &Rc<RefCell<LibraryIndex>>,
+  player: &Rc<Player>,
+  wnd: &Rc<ApplicationWindow>,
+  settings: &Rc<RefCell<FmlSettings>>,
+) {
+  let menu = gio::Menu::new();
+  menu.append(Some("Artist page..."), Some("facet.artist-page"));
+  menu.append(Some("Set album art from file..."), Some("facet.art-file"));
+  menu.append(Some("Set album art from URL..."), Some("facet.art-url"));
+  menu.append(Some("Fetch missing album art..."), Some("facet.fetch-art"));
+  menu.append(Some("Organize files..."), Some("facet.organize"));
+  menu.append(Some("Convert files..."), Some("facet.convert"));
+  menu.append(Some("Identify unknown tracks..."), Some("facet.identify"));
+  menu.append(Some("Inbox..."), Some("facet.inbox"));
+  menu.append(Some("Remote library..."), Some("facet.remote"));
+  menu.append(
+    Some("Play straight through when shuffling"),
+    Some("facet.continuous"),
+  );
+  let popover_menu = PopoverMenu::from_model(Some(&menu));
+  popover_menu.set_has_arrow(false);
+  popover_menu.set_parent(facet_columnview);
+
+  let gesture = GestureClick::new();
+  gesture.set_button(gdk::BUTTON_SECONDARY);
+  gesture.connect_released(move |gesture, _, x, y| {
+    gesture.set_state(gtk::EventSequenceState::Claimed);
+    popover_menu.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+    popover_menu.popup();
+  });
+  facet_columnview.add_controller(gesture);
+
+  let actions = gio::SimpleActionGroup::new();
+
+  let artist_page = gio::SimpleAction::new("artist-page", None);
+  let facet_sel_rc = facet_sel.clone();
+  let playlist_store_rc = playlist_store.clone();
+  let library_rc = library.clone();
+  let wnd_rc = wnd.clone();
+  artist_page.connect_activate(move |_, _| {
+    let selection = facet_sel_rc.selection();
+    let artist = if selection.is_empty() {
+      None
+    } else {
+      let item = get_selection(&facet_sel_rc, selection.minimum());
+      let r: Ref<Facet> = item.borrow();
+      r.album_artist_or_artist.as_deref().map(str::to_string)
+    };
+    match artist {
+      Some(artist) => {
+        MainContext::default().spawn_local(crate::artist_page::dialog(
+          Rc::clone(&wnd_rc),
+          artist,
+          playlist_store_rc.clone(),
+          Rc::clone(&library_rc),
+        ));
+      }
+      None => show_toast(&*wnd_rc, "Select an artist first"),
+    }
+  });
+  actions.add_action(&artist_page);
+
+  let art_file = gio::SimpleAction::new("art-file", None);
+  let facet_sel_rc = facet_sel.clone();
+  let library_rc = library.clone();
+  let wnd_rc = wnd.clone();
+  art_file.connect_activate(move |_, _| {
+    let filenames = selected_filenames(&facet_sel_rc, &library_rc.borrow());
+    crate::album_art_dialog::from_file(&wnd_rc, filenames);
+  });
+  actions.add_action(&art_file);
+
+  let art_url = gio::SimpleAction::new("art-url", None);
+  let facet_sel_rc = facet_sel.clone();
+  let library_rc = library.clone();
+  let wnd_rc = wnd.clone();
+  art_url.connect_activate(move |_, _| {
+    let filenames = selected_filenames(&facet_sel_rc, &library_rc.borrow());
+    MainContext::default().spawn_local(crate::album_art_dialog::from_url(
+      Rc::clone(&wnd_rc),
+      filenames,
+    ));
+  });
+  actions.add_action(&art_url);
+
+  let fetch_art = gio::SimpleAction::new("fetch-art", None);
+  let library_rc = library.clone();
+  let wnd_rc = wnd.clone();
+  let settings_rc = settings.clone();
+  fetch_art.connect_activate(move |_, _| {
+    if !check_online(&*wnd_rc) {
+      return;
+    }
+    let albums = albums_missing_art(library_rc.borrow().albums());
+    let embed = settings_rc.borrow().embed_fetched_art;
+    MainContext::default().spawn_local(crate::art_fetch_dialog::dialog(
+      Rc::clone(&wnd_rc),
+      albums,
+      embed,
+    ));
+  });
+  actions.add_action(&fetch_art);
+
+  let organize = gio::SimpleAction::new("organize", None);
+  let facet_sel_rc = facet_sel.clone();
+  let library_rc = library.clone();
+  let wnd_rc = wnd.clone();
+  let settings_rc = settings.clone();
+  organize.connect_activate(move |_, _| {
+    MainContext::default().spawn_local(crate::organize_dialog::dialog(
+      Rc::clone(&wnd_rc),
+      Rc::clone(&settings_rc),
+      selected_tracks(&facet_sel_rc, &library_rc.borrow()),
+    ));
+  });
+  actions.add_action(&organize);
+
+  let convert = gio::SimpleAction::new("convert", None);
+  let facet_sel_rc = facet_sel.clone();
+  let library_rc = library.clone();
+  let wnd_rc = wnd.clone();
+  convert.connect_activate(move |_, _| {
+    MainContext::default().spawn_local(crate::transcode_dialog::dialog(
+      Rc::clone(&wnd_rc),
+      selected_filenames(&facet_sel_rc, &library_rc.borrow()),
+    ));
+  });
+  actions.add_action(&convert);
+
+  let identify = gio::SimpleAction::new("identify", None);
+  let library_rc = library.clone();
+  let wnd_rc = wnd.clone();
+  let settings_rc = settings.clone();
+  identify.connect_activate(move |_, _| match &settings_rc.borrow().acoustid_key {
+    _ if !check_online(&*wnd_rc) => (),
+    Some(key) => {
+      MainContext::default().spawn_local(crate::identify_dialog::dialog(
+        Rc::clone(&wnd_rc),
+        unknown_tracks(library_rc.borrow().tracks()),
+        key.clone(),
+      ));
+    }
+    None => show_toast(&*wnd_rc, "Set an AcoustID API key in preferences first"),
+  });
+  actions.add_action(&identify);
+
+  let inbox = gio::SimpleAction::new("inbox", None);
+  let wnd_rc = wnd.clone();
+  let settings_rc = settings.clone();
+  inbox.connect_activate(move |_, _| {
+    let folder = settings_rc.borrow().inbox_folder.clone();
+    match folder {
+      Some(folder) => {
+        MainContext::default().spawn_local(crate::inbox_dialog::dialog(
+          Rc::clone(&wnd_rc),
+          Rc::clone(&settings_rc),
+          folder,
+        ));
+      }
+      None => show_toast(&*wnd_rc, "Set an inbox folder in preferences first"),
+    }
+  });
+  actions.add_action(&inbox);
+
+  let remote = gio::SimpleAction::new("remote", None);
+  let player_rc = player.clone();
+  let wnd_rc = wnd.clone();
+  let settings_rc = settings.clone();
+  remote.connect_activate(move |_, _| {
+    let s = settings_rc.borrow();
+    match &s.subsonic_url {
+      _ if !check_online(&*wnd_rc) => (),
+      Some(url) => {
+        MainContext::default().spawn_local(crate::remote_dialog::dialog(
+          Rc::clone(&wnd_rc),
+          Rc::clone(&player_rc),
+          Server {
+            url: url.clone(),
+            user: s.subsonic_user.clone(),
+            password: s.subsonic_password.clone(),
+          },
+        ));
+      }
+      None => show_toast(&*wnd_rc, "Set a Subsonic server in preferences first"),
+    }
+  });
+  actions.add_action(&remote);
+
+  // flags the selected albums, or clears them if they all already are
+  let continuous = gio::SimpleAction::new("continuous", None);
+  let facet_sel_rc = facet_sel.clone();
+  let player_rc = player.clone();
+  let wnd_rc = wnd.clone();
+  continuous.connect_activate(move |_, _| {
+    let albums = selected_albums(&facet_sel_rc);
+    if albums.is_empty() {
+      show_toast(&*wnd_rc, "Select an album first");
+      return;
+    }
+    let mut flagged = player_rc.continuous.borrow_mut();
+    let value = !albums.iter().all(|a| flagged.contains(a));
+    for album in albums {
+      if let Err(e) = set_continuous(&album, value) {
+        show_toast(&*wnd_rc, &format!("Failed to save album: {}", e));
+        return;
+      }
+      if value {
+        flagged.insert(album);
+      } else {
+        flagged.remove(&album);
+      }
+    }
+    show_toast(
+      &*wnd_rc,
+      if value {
+        "Shuffle will play these albums straight through"
+      } else {
+        "Shuffle will mix these albums in again"
+      },
+    );
+  });
+  actions.add_action(&continuous);
+
+  facet_columnview.insert_action_group("facet", Some(&actions));
+}
+
 pub fn create_facet_box(
   playlist_store: ListStore,
   facet_store: ListStore,
   filter: CustomFilter,
-  tracks: &Rc<Vec<Rc<Track>>>,
+  library: &Rc<RefCell<LibraryIndex>>,
+  player: &Rc<Player>,
+  wnd: &Rc<ApplicationWindow>,
+  settings: &Rc<RefCell<FmlSettings>>,
 ) -> gtk::Box {
   let case_insensitive_sorter = CustomSorter::new(|obj1, obj2| {
     let k1: Ref<Facet> = obj1.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
     let k2: Ref<Facet> = obj2.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
-    let emp = "".to_string();
-    let t1 = k1.album_artist_or_artist.as_ref().unwrap_or(&emp);
-    let t2 = k2.album_artist_or_artist.as_ref().unwrap_or(&emp);
+    let t1 = k1.album_artist_or_artist.as_deref().unwrap_or("");
+    let t2 = k2.album_artist_or_artist.as_deref().unwrap_or("");
     t1.to_lowercase().cmp(&t2.to_lowercase()).into()
   });
   let facet_filter = FilterListModel::new(Some(facet_store), Some(filter));
@@ -35,7 +339,7 @@ pub fn create_facet_box(
     Some(case_insensitive_sorter.clone()),
   );
 
-  let facet_sel = MultiSelection::new(Some(facet_sort));
+  let facet_sel = MultiSelection::new(Some(facet_sort.clone()));
   let facet_columnview = ColumnView::builder().model(&facet_sel).build();
 
   let facet_sel_rc = Rc::new(facet_sel);
@@ -54,75 +358,104 @@ pub fn create_facet_box(
     .sorter(&case_insensitive_sorter)
     .build();
   facet_columnview.append_column(&facet_col);
+  create_context_menu(
+    &facet_columnview,
+    &facet_sel_rc,
+    &playlist_store,
+    library,
+    player,
+    wnd,
+    settings,
+  );
+  add_type_ahead(&facet_columnview, |obj| facet_text(&obj.borrow()));
   let playlist_store_rc1 = playlist_store.clone();
 
-  let tracks_rc = tracks.clone();
+  let library_rc = library.clone();
+  let load_generation = Rc::new(Cell::new(0u32));
   facet_sel_rc.connect_selection_changed(move |_, _, _| {
     let selection = facet_sel_rc1.selection();
-    match gtk::BitsetIter::init_first(&selection) {
-      Some(result) => {
-        let (iter, first_pos) = result;
-        playlist_store_rc1.remove_all();
-        let item = get_selection(&facet_sel_rc1, first_pos);
-        let r: Ref<Facet> = item.borrow();
-        let con = tracks_rc.iter().filter(|x| {
-          get_album_artist_or_artist(x) == r.album_artist_or_artist && x.album == r.album
-        });
-
-        load_playlist_store(con, &playlist_store_rc1);
-
-        for pos in iter {
-          let item = get_selection(&facet_sel_rc1, pos);
-          let r: Ref<Facet> = item.borrow();
-          let con = tracks_rc.iter().filter(|x| {
-            get_album_artist_or_artist(x) == r.album_artist_or_artist && x.album == r.album
-          });
-
-          load_playlist_store(con, &playlist_store_rc1);
-        }
-      }
-      None => { /* empty selection */ }
+    if selection.is_empty() {
+      return;
+    }
+    let mut rows = Vec::new();
+    for i in 0..selection.size() {
+      let item = get_selection(&facet_sel_rc1, selection.nth(i as u32));
+      let r: Ref<Facet> = item.borrow();
+      rows.extend(library_rc.borrow().by_facet(&r).iter().cloned());
     }
+
+    // a newer selection supersedes a load that is still in progress
+    let generation = load_generation.get() + 1;
+    load_generation.set(generation);
+    playlist_store_rc1.remove_all();
+    let load_generation = load_generation.clone();
+    MainContext::default().spawn_local(load_playlist_store_chunked(
+      rows,
+      playlist_store_rc1.clone(),
+      move || load_generation.get() == generation,
+    ));
   });
 
-  facet.connect_setup(|_factory, item| setup_col(item));
+  // the active search, and every cell so rows that stay bound can be
+  // highlighted again when it changes
+  let search: Rc<RefCell<Option<Rc<Query>>>> = Rc::new(RefCell::new(None));
+  let cells: Rc<RefCell<Vec<WeakRef<GridCell>>>> = Rc::new(RefCell::new(Vec::new()));
+  let cells_rc = cells.clone();
+  facet.connect_setup(move |_factory, item| {
+    setup_col(item);
+    let cell = item
+      .downcast_ref::<ListItem>()
+      .and_then(|item| item.child())
+      .and_downcast::<GridCell>();
+    if let Some(cell) = cell {
+      cells_rc.borrow_mut().push(cell.downgrade());
+    }
+  });
+  let search_rc = search.clone();
   facet.connect_bind(move |_factory, item| {
     let (cell, obj) = get_cell(item);
     let r: Ref<Facet> = obj.borrow();
     cell.set_entry(&Entry {
-      name: if r.all {
-        "(All)".to_string()
-      } else {
-        format!(
-          "{} // {}",
-          str_or_unknown(&r.album_artist_or_artist),
-          str_or_unknown(&r.album),
-        )
-      },
+      name: facet_text(&r),
     });
+    highlight_matches(&cell, search_rc.borrow().as_deref());
   });
 
   let facet_box = gtk::Box::new(Orientation::Vertical, 0);
   let search_bar = SearchEntry::builder().build();
 
+  let settings_rc = settings.clone();
   search_bar.connect_search_changed(move |s| {
-    let text = s.text();
-    let re = Regex::new(&format!("(?i){}", regex::escape(text.as_str()))).unwrap();
-    let filter = CustomFilter::new(move |obj| {
-      let r = obj.downcast_ref::<BoxedAnyObject>().unwrap();
-      let k: Ref<Facet> = r.borrow();
-      let k0 = k.all;
-      let k1 = match &k.album {
-        Some(s) => re.is_match(&s),
-        None => false,
-      };
-      let k2 = match &k.album_artist_or_artist {
-        Some(s) => re.is_match(&s),
-        None => false,
-      };
-      k0 || k1 || k2
+    let query = match Query::parse(&s.text(), settings_rc.borrow().search_mode) {
+      Ok(query) => Rc::new(query),
+      // leave the last good filter in place while a regex is being typed
+      Err(_) => {
+        s.add_css_class("error");
+        return;
+      }
+    };
+    s.remove_css_class("error");
+    search.replace(Some(query.clone()));
+    cells.borrow_mut().retain(|cell| match cell.upgrade() {
+      Some(cell) => {
+        highlight_matches(&cell, Some(&query));
+        true
+      }
+      None => false,
     });
-    facet_filter.set_filter(Some(&filter))
+    let query_rc = query.clone();
+    let filter = CustomFilter::new(move |obj| facet_score(&query_rc, obj).is_some());
+    facet_filter.set_filter(Some(&filter));
+    if query.is_ranked() {
+      let sorter = CustomSorter::new(move |obj1, obj2| {
+        facet_score(&query, obj2)
+          .cmp(&facet_score(&query, obj1))
+          .into()
+      });
+      facet_sort.set_sorter(Some(&sorter));
+    } else {
+      facet_sort.set_sorter(Some(&case_insensitive_sorter));
+    }
   });
   facet_box.append(&search_bar);
   facet_box.append(&facet_wnd);
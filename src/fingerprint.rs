@@ -0,0 +1,166 @@
+use crate::connect_db;
+use crate::metadata::Metadata;
+use crate::models::{TagUpdate, Track};
+use crate::network::download;
+use crate::schema::fingerprints;
+use diesel::prelude::*;
+use gtk::glib::Uri;
+use regex::Regex;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+use tracing::{error, warn};
+
+pub struct Fingerprint {
+  pub duration: u32,
+  pub fingerprint: String,
+}
+
+pub struct Suggestion {
+  pub filename: String,
+  pub update: TagUpdate,
+}
+
+pub fn is_unknown(track: &Track) -> bool {
+  track.title.is_none() || track.artist.is_none()
+}
+
+// The same test for a file the scanner just read
+pub(crate) fn is_untagged(metadata: &Metadata) -> bool {
+  metadata.title.is_none() || metadata.artist.is_none()
+}
+
+pub fn unknown_tracks(tracks: &[Rc<Track>]) -> Vec<String> {
+  tracks
+    .iter()
+    .filter(|t| is_unknown(t))
+    .map(|t| t.filename.clone())
+    .collect()
+}
+
+// Blocking. Requires fpcalc from chromaprint on the PATH
+pub fn fingerprint(filename: &str) -> std::io::Result<Fingerprint> {
+  let output = Command::new("fpcalc").arg(filename).output()?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let mut duration = None;
+  let mut fingerprint = None;
+  for line in stdout.lines() {
+    if let Some(d) = line.strip_prefix("DURATION=") {
+      duration = d.parse().ok();
+    } else if let Some(f) = line.strip_prefix("FINGERPRINT=") {
+      fingerprint = Some(f.to_string());
+    }
+  }
+  match (duration, fingerprint) {
+    (Some(duration), Some(fingerprint)) => Ok(Fingerprint {
+      duration,
+      fingerprint,
+    }),
+    _ => Err(std::io::Error::other(format!(
+      "fpcalc failed: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    ))),
+  }
+}
+
+fn unescape(s: &str) -> String {
+  s.replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&apos;", "'")
+    .replace("&amp;", "&")
+}
+
+fn first_capture(re: &str, text: &str) -> Option<String> {
+  Regex::new(re)
+    .unwrap()
+    .captures(text)
+    .map(|c| c[1].to_string())
+}
+
+fn first_match(re: &str, text: &str) -> Option<String> {
+  first_capture(re, text).map(|s| unescape(&s))
+}
+
+// Takes the first recording of the best scoring result. The release group
+// block is cut out before looking for the recording title since release
+// groups have titles of their own
+fn parse_lookup(xml: &str) -> Option<TagUpdate> {
+  let recording = first_capture(r"(?s)<recording>(.*?)</recording>", xml)?;
+  let releasegroups = Regex::new(r"(?s)<releasegroups>.*?</releasegroups>").unwrap();
+  let without_releasegroups = releasegroups.replace_all(&recording, "");
+  Some(TagUpdate {
+    artist: first_match(r"(?s)<artist>.*?<name>(.*?)</name>", &without_releasegroups),
+    title: Some(first_match(
+      r"<title>(.*?)</title>",
+      &without_releasegroups,
+    )?),
+    album: first_match(r"(?s)<releasegroup>.*?<title>(.*?)</title>", &recording),
+  })
+}
+
+// For the scanner, which fingerprints untagged new files when the preference
+// is on. Runs fpcalc over the whole file, so don't hold the write lock around
+// it
+pub(crate) fn scan_fingerprint(path: &Path) -> Option<Fingerprint> {
+  match fingerprint(&path.to_string_lossy()) {
+    Ok(fp) => Some(fp),
+    Err(e) => {
+      warn!("Failed to fingerprint {}: {}", path.display(), e);
+      None
+    }
+  }
+}
+
+pub(crate) fn store(
+  conn: &mut SqliteConnection,
+  filename: &str,
+  fp: &Fingerprint,
+) -> QueryResult<usize> {
+  diesel::replace_into(fingerprints::table)
+    .values((
+      fingerprints::filename.eq(filename),
+      fingerprints::duration.eq(fp.duration as i32),
+      fingerprints::fingerprint.eq(&fp.fingerprint),
+    ))
+    .execute(conn)
+}
+
+fn stored(filename: &str) -> Option<Fingerprint> {
+  fingerprints::table
+    .filter(fingerprints::filename.eq(filename))
+    .select((fingerprints::duration, fingerprints::fingerprint))
+    .first::<(i32, String)>(&mut connect_db())
+    .optional()
+    .unwrap_or_else(|e| {
+      error!("Failed to load fingerprint of {}: {}", filename, e);
+      None
+    })
+    .map(|(duration, fingerprint)| Fingerprint {
+      duration: duration as u32,
+      fingerprint,
+    })
+}
+
+// Blocking, run on a worker thread. Uses the fingerprint from the scan when
+// there is one
+pub fn identify(filename: &str, client_key: &str) -> Option<Suggestion> {
+  let fp = match stored(filename).map_or_else(|| fingerprint(filename), Ok) {
+    Ok(fp) => fp,
+    Err(e) => {
+      warn!("Failed to fingerprint {}: {}", filename, e);
+      return None;
+    }
+  };
+  let url = format!(
+    "https://api.acoustid.org/v2/lookup?format=xml&meta=recordings+releasegroups&client={}&duration={}&fingerprint={}",
+    Uri::escape_string(client_key, None, false),
+    fp.duration,
+    fp.fingerprint
+  );
+  let body = download(&url)?;
+  parse_lookup(&String::from_utf8_lossy(&body)).map(|update| Suggestion {
+    filename: filename.to_string(),
+    update,
+  })
+}
@@ -0,0 +1,354 @@
+use crate::gtk_helpers::str_or_unknown;
+use crate::player::Player;
+use crate::playlist_manager::add_tracks_to_playlist;
+use crate::settings::{write_settings, FmlSettings};
+use adw::prelude::*;
+use fml9000::models::Track;
+use fml9000::playlists::read_playlists;
+use fml9000::properties::{chapters_of, read_lyrics};
+use gtk::gio::{self, ListStore};
+use gtk::glib::{self, MainContext};
+use gtk::{
+  Align, ApplicationWindow, Button, ContentFit, Label, MenuButton, Orientation, Picture, Popover,
+  ScrolledWindow, Stack, StackTransitionType, ToggleButton, Widget,
+};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+const LIBRARY: &str = "library";
+const FOCUS: &str = "focus";
+const NEXT_UP: usize = 5;
+
+struct FocusPage {
+  art: Picture,
+  title: Label,
+  subtitle: Label,
+  next_up: gtk::Box,
+  lyrics: Label,
+  lyrics_btn: ToggleButton,
+  chapters: gtk::Box,
+  chapters_btn: MenuButton,
+}
+
+impl FocusPage {
+  fn show_track(&self, track: &Track, player: &Rc<Player>) {
+    self.title.set_label(&str_or_unknown(&track.title));
+    self.subtitle.set_label(&format!(
+      "{} — {}",
+      str_or_unknown(&track.artist),
+      str_or_unknown(&track.album)
+    ));
+    if self.lyrics_btn.is_active() {
+      self.load_lyrics(track.filename.clone());
+    }
+    self.load_chapters(track.filename.clone(), player);
+  }
+
+  // The button only shows for files that have chapters. Picking one jumps
+  // to where it starts
+  fn load_chapters(&self, filename: String, player: &Rc<Player>) {
+    let chapters = self.chapters.clone();
+    let chapters_btn = self.chapters_btn.clone();
+    let player = Rc::downgrade(player);
+    chapters_btn.set_visible(false);
+    MainContext::default().spawn_local(async move {
+      let Ok(list) = gio::spawn_blocking(move || chapters_of(&filename)).await else {
+        return;
+      };
+      while let Some(child) = chapters.first_child() {
+        chapters.remove(&child);
+      }
+      chapters_btn.set_visible(!list.is_empty());
+      for chapter in list {
+        let secs = chapter.start.as_secs();
+        let btn = Button::builder()
+          .label(format!("{}:{:02}  {}", secs / 60, secs % 60, chapter.title))
+          .css_classes(["flat"])
+          .build();
+        let player = player.clone();
+        btn.connect_clicked(glib::clone!(
+          #[weak]
+          chapters_btn,
+          move |_| {
+            chapters_btn.popdown();
+            if let Some(player) = player.upgrade() {
+              player.seek(chapter.start);
+            }
+          }
+        ));
+        chapters.append(&btn);
+      }
+    });
+  }
+
+  fn load_lyrics(&self, filename: String) {
+    let lyrics = self.lyrics.clone();
+    MainContext::default().spawn_local(async move {
+      let text = gio::spawn_blocking(move || read_lyrics(&filename))
+        .await
+        .ok()
+        .flatten();
+      lyrics.set_label(text.as_deref().unwrap_or("No lyrics in this file's tags"));
+    });
+  }
+
+  fn fill_next_up(&self, player: &Player) {
+    while let Some(child) = self.next_up.first_child() {
+      self.next_up.remove(&child);
+    }
+    let upcoming = player.upcoming(NEXT_UP);
+    if upcoming.is_empty() {
+      self.next_up.append(&Label::new(Some("Nothing up next")));
+    }
+    for (i, track) in upcoming.iter().enumerate() {
+      self.next_up.append(
+        &Label::builder()
+          .label(format!(
+            "{}. {} - {}",
+            i + 1,
+            str_or_unknown(&track.artist),
+            str_or_unknown(&track.title)
+          ))
+          .xalign(0.0)
+          .build(),
+      );
+    }
+  }
+}
+
+// Lists the user playlists to add the playing track to, rebuilt each time
+// it opens so new playlists show up
+fn create_add_to_playlist_button(
+  player: &Rc<Player>,
+  playlist_mgr_store: &ListStore,
+  settings: &Rc<RefCell<FmlSettings>>,
+) -> MenuButton {
+  let list = gtk::Box::new(Orientation::Vertical, 0);
+  let popover = Popover::builder().child(&list).build();
+  let add_btn = MenuButton::builder()
+    .label("Add to playlist")
+    .popover(&popover)
+    .build();
+
+  let player = player.clone();
+  let playlist_mgr_store = playlist_mgr_store.clone();
+  let settings = settings.clone();
+  popover.connect_show(move |popover| {
+    while let Some(child) = list.first_child() {
+      list.remove(&child);
+    }
+    let playlists = read_playlists();
+    if playlists.is_empty() {
+      list.append(&Label::new(Some("No playlists yet")));
+    }
+    for playlist in playlists {
+      let btn = Button::builder().label(&playlist.name).build();
+      btn.add_css_class("flat");
+      let player = player.clone();
+      let playlist_mgr_store = playlist_mgr_store.clone();
+      let settings = settings.clone();
+      btn.connect_clicked(glib::clone!(
+        #[weak]
+        popover,
+        move |btn| {
+          popover.popdown();
+          let Some(track) = player.current.borrow().clone() else {
+            return;
+          };
+          add_tracks_to_playlist(
+            btn,
+            &settings,
+            &playlist_mgr_store,
+            playlist.id,
+            vec![track.filename.clone()],
+          );
+        }
+      ));
+      list.append(&btn);
+    }
+  });
+  add_btn
+}
+
+fn create_focus_page(
+  player: &Rc<Player>,
+  playlist_mgr_store: &ListStore,
+  settings: &Rc<RefCell<FmlSettings>>,
+) -> gtk::Box {
+  let page = Rc::new(FocusPage {
+    art: Picture::builder()
+      .content_fit(ContentFit::Contain)
+      .hexpand(true)
+      .vexpand(true)
+      .build(),
+    title: Label::builder()
+      .label("Nothing playing")
+      .css_classes(["title-1"])
+      .wrap(true)
+      .xalign(0.0)
+      .build(),
+    subtitle: Label::builder()
+      .css_classes(["title-3"])
+      .wrap(true)
+      .xalign(0.0)
+      .build(),
+    next_up: gtk::Box::new(Orientation::Vertical, 2),
+    lyrics: Label::builder()
+      .wrap(true)
+      .xalign(0.0)
+      .selectable(true)
+      .build(),
+    lyrics_btn: ToggleButton::with_label("Lyrics"),
+    chapters: gtk::Box::new(Orientation::Vertical, 0),
+    chapters_btn: MenuButton::builder()
+      .label("Chapters")
+      .visible(false)
+      .build(),
+  });
+  page
+    .chapters_btn
+    .set_popover(Some(&Popover::builder().child(&page.chapters).build()));
+
+  let lyrics_wnd = ScrolledWindow::builder()
+    .child(&page.lyrics)
+    .vexpand(true)
+    .visible(false)
+    .build();
+  let page_rc = page.clone();
+  let player_rc = player.clone();
+  let lyrics_wnd_rc = lyrics_wnd.clone();
+  page.lyrics_btn.connect_toggled(move |btn| {
+    lyrics_wnd_rc.set_visible(btn.is_active());
+    if let (true, Some(track)) = (btn.is_active(), player_rc.current.borrow().as_ref()) {
+      page_rc.load_lyrics(track.filename.clone());
+    }
+  });
+
+  let actions = gtk::Box::new(Orientation::Horizontal, 4);
+  actions.append(&create_add_to_playlist_button(
+    player,
+    playlist_mgr_store,
+    settings,
+  ));
+  actions.append(&page.lyrics_btn);
+  actions.append(&page.chapters_btn);
+
+  let info = gtk::Box::builder()
+    .orientation(Orientation::Vertical)
+    .spacing(8)
+    .width_request(360)
+    .valign(Align::Fill)
+    .build();
+  info.append(&page.title);
+  info.append(&page.subtitle);
+  info.append(&actions);
+  info.append(
+    &Label::builder()
+      .label("Next up")
+      .css_classes(["heading"])
+      .xalign(0.0)
+      .build(),
+  );
+  info.append(&page.next_up);
+  info.append(&lyrics_wnd);
+
+  if let Some(track) = player.current.borrow().as_ref() {
+    page.show_track(track, player);
+  }
+  page.art.set_paintable(player.covers.current().as_ref());
+  player.covers.connect_changed(glib::clone!(
+    #[weak(rename_to = art)]
+    page.art,
+    move |texture| art.set_paintable(texture)
+  ));
+  page.fill_next_up(player);
+  let page_rc = page.clone();
+  let player_rc = Rc::downgrade(player);
+  player.connect_track_changed(move |track| {
+    if let Some(player) = player_rc.upgrade() {
+      page_rc.show_track(track, &player);
+      page_rc.fill_next_up(&player);
+    }
+  });
+
+  let container = gtk::Box::builder()
+    .orientation(Orientation::Horizontal)
+    .spacing(24)
+    .margin_start(24)
+    .margin_end(24)
+    .margin_top(24)
+    .margin_bottom(24)
+    .build();
+  container.append(&page.art);
+  container.append(&info);
+
+  // the queue may have changed while the library was showing
+  let player_rc = Rc::downgrade(player);
+  container.connect_map(move |_| {
+    if let Some(player) = player_rc.upgrade() {
+      page.fill_next_up(&player);
+    }
+  });
+  container
+}
+
+fn set_focus_mode(stack: &Stack, settings: &RefCell<FmlSettings>, on: bool) {
+  let mut s = settings.borrow_mut();
+  s.focus_mode = on;
+  stack.set_visible_child_name(if on { FOCUS } else { LIBRARY });
+  write_settings(&s).expect("Failed to write");
+}
+
+// win.focus-mode (F12) swaps the library panes for a now playing page with large album art,
+// what is up next and the track's lyrics, leaving the header bar's transport
+// controls in place. Whether it is on is saved so the next start comes back
+// to the same layout
+pub fn add_focus_mode(
+  wnd: &ApplicationWindow,
+  library: &impl IsA<Widget>,
+  player: &Rc<Player>,
+  playlist_mgr_store: &ListStore,
+  settings: &Rc<RefCell<FmlSettings>>,
+) -> Stack {
+  let stack = Stack::builder()
+    .transition_type(StackTransitionType::Crossfade)
+    .build();
+  stack.add_named(library, Some(LIBRARY));
+  stack.add_named(
+    &create_focus_page(player, playlist_mgr_store, settings),
+    Some(FOCUS),
+  );
+  if settings.borrow().focus_mode {
+    stack.set_visible_child_name(FOCUS);
+  }
+
+  // optionally switch over when playback starts from stopped, but not on
+  // every track change so leaving the page sticks
+  let stopped = Rc::new(Cell::new(true));
+  let stopped_rc = stopped.clone();
+  player.connect_stopped(move || stopped_rc.set(true));
+  let settings_rc = settings.clone();
+  player.connect_track_changed(glib::clone!(
+    #[weak]
+    stack,
+    move |_| {
+      let switch = stopped.replace(false) && settings_rc.borrow().focus_on_play;
+      if switch && !settings_rc.borrow().focus_mode {
+        set_focus_mode(&stack, &settings_rc, true);
+      }
+    }
+  ));
+
+  let focus_mode = gio::SimpleAction::new("focus-mode", None);
+  let settings = settings.clone();
+  focus_mode.connect_activate(glib::clone!(
+    #[weak]
+    stack,
+    move |_, _| {
+      let on = !settings.borrow().focus_mode;
+      set_focus_mode(&stack, &settings, on);
+    }
+  ));
+  wnd.add_action(&focus_mode);
+  stack
+}
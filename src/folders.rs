@@ -0,0 +1,84 @@
+use crate::connect_db;
+use crate::schema::{folder_scans, tracks};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use std::path::MAIN_SEPARATOR;
+use tracing::error;
+
+pub struct FolderStats {
+  pub track_count: i64,
+  pub last_scan: Option<NaiveDateTime>,
+}
+
+impl FolderStats {
+  // e.g. "1250 tracks, scanned Jan 14 09:30"
+  pub fn describe(&self) -> String {
+    let scanned = self.last_scan.map_or("never scanned".to_string(), |d| {
+      format!("scanned {}", d.format("%b %-d %H:%M"))
+    });
+    format!("{} tracks, {}", self.track_count, scanned)
+  }
+}
+
+// A LIKE pattern for everything under the folder, with LIKE's wildcards in
+// the folder name itself escaped
+fn under_folder(folder: &str) -> String {
+  let folder = folder
+    .trim_end_matches(MAIN_SEPARATOR)
+    .replace('\\', "\\\\")
+    .replace('%', "\\%")
+    .replace('_', "\\_");
+  let separator = if MAIN_SEPARATOR == '\\' { "\\\\" } else { "/" };
+  format!("{}{}%", folder, separator)
+}
+
+// Whether the file lives somewhere under the folder
+pub fn contains(folder: &str, filename: &str) -> bool {
+  filename
+    .strip_prefix(folder.trim_end_matches(MAIN_SEPARATOR))
+    .is_some_and(|rest| rest.starts_with(MAIN_SEPARATOR))
+}
+
+// scanned is when the scan started, so files changed while it ran are read
+// again next time
+pub fn record_scan(
+  conn: &mut SqliteConnection,
+  folder: &str,
+  scanned: NaiveDateTime,
+) -> QueryResult<usize> {
+  diesel::replace_into(folder_scans::table)
+    .values((
+      folder_scans::folder.eq(folder),
+      folder_scans::scanned.eq(scanned),
+    ))
+    .execute(conn)
+}
+
+pub fn last_scan(conn: &mut SqliteConnection, folder: &str) -> QueryResult<Option<NaiveDateTime>> {
+  folder_scans::table
+    .filter(folder_scans::folder.eq(folder))
+    .select(folder_scans::scanned)
+    .first(conn)
+    .optional()
+}
+
+// Blocking, so call it off the main thread
+pub fn folder_stats(folder: &str) -> FolderStats {
+  let conn = &mut connect_db();
+  let track_count = tracks::table
+    .filter(tracks::filename.like(under_folder(folder)).escape('\\'))
+    .count()
+    .get_result(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to count tracks in {}: {}", folder, e);
+      0
+    });
+  let last_scan = last_scan(conn, folder).unwrap_or_else(|e| {
+    error!("Failed to load last scan of {}: {}", folder, e);
+    None
+  });
+  FolderStats {
+    track_count,
+    last_scan,
+  }
+}
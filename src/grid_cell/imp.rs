@@ -4,6 +4,7 @@ use adw::subclass::prelude::*;
 
 use gtk::BinLayout;
 use gtk::CompositeTemplate;
+use std::cell::RefCell;
 
 #[derive(Debug, Default, CompositeTemplate)]
 #[template(file = "grid_cell.ui")]
@@ -13,6 +14,7 @@ pub struct GridCell {
   // to gtk::Label. The benefits for using gtk::Inscription are explained here
   // https://gtk-rs.org/gtk4-rs/git/docs/gtk4/struct.Inscription.html
   pub name: TemplateChild<gtk::Inscription>,
+  pub text: RefCell<String>,
 }
 
 #[glib::object_subclass]
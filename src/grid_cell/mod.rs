@@ -1,6 +1,7 @@
 mod imp;
 use gtk::glib;
 use adw::subclass::prelude::*;
+use std::ops::Range;
 
 glib::wrapper! {
     pub struct GridCell(ObjectSubclass<imp::GridCell>)
@@ -24,5 +25,30 @@ impl GridCell {
 
   pub fn set_entry(&self, entry: &Entry) {
     self.imp().name.set_text(Some(&entry.name));
+    self.imp().text.replace(entry.name.clone());
+  }
+
+  pub fn text(&self) -> String {
+    self.imp().text.borrow().clone()
+  }
+
+  // Bolds the given byte ranges of the text, e.g. what a search matched
+  pub fn set_highlights(&self, ranges: &[Range<usize>]) {
+    let text = self.imp().text.borrow();
+    if ranges.is_empty() {
+      self.imp().name.set_text(Some(&text));
+      return;
+    }
+    let mut markup = String::new();
+    let mut pos = 0;
+    for range in ranges {
+      markup.push_str(&glib::markup_escape_text(&text[pos..range.start]));
+      markup.push_str("<b>");
+      markup.push_str(&glib::markup_escape_text(&text[range.clone()]));
+      markup.push_str("</b>");
+      pos = range.end;
+    }
+    markup.push_str(&glib::markup_escape_text(&text[pos..]));
+    self.imp().name.set_markup(Some(&markup));
   }
 }
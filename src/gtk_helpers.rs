@@ -1,18 +1,96 @@
 use crate::grid_cell::GridCell;
 use adw::prelude::*;
+use fml9000::interned::Interned;
 use fml9000::models::Track;
-use gtk::gdk;
-use gtk::glib::{BoxedAnyObject, Bytes, Object};
-use gtk::{Button, Image, ListItem, MultiSelection, SelectionModel};
+use fml9000::network::is_offline;
+use gtk::gdk::{self, ModifierType};
+use gtk::gio;
+use gtk::glib::{self, BoxedAnyObject, Bytes, Object, Propagation};
+use gtk::{
+  Button, ColumnView, DirectionType, EventControllerKey, Image, ListItem, ListScrollFlags,
+  MultiSelection, SelectionModel, Widget,
+};
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tracing::warn;
 
-pub fn str_or_unknown(str: &Option<String>) -> String {
-  str.as_ref().unwrap_or(&"(Unknown)".to_string()).to_string()
+// Tags come as Strings or, for the ones tracks share, Interned
+pub fn str_or_unknown<S: AsRef<str>>(str: &Option<S>) -> String {
+  str.as_ref().map_or("(Unknown)", |s| s.as_ref()).to_string()
 }
 
-pub fn get_album_artist_or_artist(track: &Track) -> Option<String> {
+// Shows a transient, non-modal message in the main window. Works from any
+// widget, including ones inside dialogs, by following transient_for up to
+// the main window
+pub fn show_toast(widget: &impl IsA<Widget>, title: &str) {
+  match main_window(widget)
+    .and_then(|w| w.child())
+    .and_downcast::<adw::ToastOverlay>()
+  {
+    Some(overlay) => overlay.add_toast(adw::Toast::new(&glib::markup_escape_text(title))),
+    None => warn!("{}", title),
+  }
+}
+
+fn main_window(widget: &impl IsA<Widget>) -> Option<gtk::Window> {
+  let mut wnd = widget.as_ref().root().and_downcast::<gtk::Window>();
+  while let Some(parent) = wnd.as_ref().and_then(|w| w.transient_for()) {
+    wnd = Some(parent);
+  }
+  wnd
+}
+
+// Like show_toast, with a button that runs the action. The action is added
+// to the main window, replacing any earlier one of the same name
+pub fn show_action_toast(
+  widget: &impl IsA<Widget>,
+  title: &str,
+  button_label: &str,
+  action: &gio::SimpleAction,
+) {
+  let Some(wnd) = main_window(widget).and_downcast::<gtk::ApplicationWindow>() else {
+    warn!("{}", title);
+    return;
+  };
+  wnd.add_action(action);
+  let toast = adw::Toast::builder()
+    .title(glib::markup_escape_text(title))
+    .button_label(button_label)
+    .action_name(format!("win.{}", action.name()))
+    .build();
+  if let Some(overlay) = wnd.child().and_downcast::<adw::ToastOverlay>() {
+    overlay.add_toast(toast);
+  }
+}
+
+// For actions that go online: says so and returns false in offline mode
+pub fn check_online(widget: &impl IsA<Widget>) -> bool {
+  if is_offline() {
+    show_toast(
+      widget,
+      "Offline mode is on, turn it off in the header bar to go online",
+    );
+    return false;
+  }
+  true
+}
+
+pub fn get_album_artist_or_artist(track: &Track) -> Option<Interned> {
   return track.album_artist.clone().or(track.artist.clone());
 }
 
+// e.g. "3 h 12 m", or "45 m" under an hour
+pub fn format_total_duration(ms: i64) -> String {
+  let minutes = ms / 60_000;
+  if minutes >= 60 {
+    format!("{} h {} m", minutes / 60, minutes % 60)
+  } else {
+    format!("{} m", minutes)
+  }
+}
+
 pub fn setup_col(item: &Object) {
   item
     .downcast_ref::<ListItem>()
@@ -49,3 +127,95 @@ pub fn load_img(a: &'static [u8]) -> Image {
 pub fn create_button(img: &Image) -> Button {
   Button::builder().child(img).build()
 }
+
+pub fn selected_objects(sel: &MultiSelection) -> Vec<BoxedAnyObject> {
+  let selection = sel.selection();
+  (0..selection.size())
+    .filter_map(|i| sel.item(selection.nth(i as u32)))
+    .filter_map(|obj| obj.downcast::<BoxedAnyObject>().ok())
+    .collect()
+}
+
+// Takes every row for the given files out of a store of tracks
+pub fn remove_tracks(store: &gio::ListStore, filenames: &HashSet<String>) {
+  let mut i = 0;
+  while let Some(obj) = store.item(i) {
+    let r: Ref<Rc<Track>> = obj.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+    if filenames.contains(&r.filename) {
+      store.remove(i);
+    } else {
+      i += 1;
+    }
+  }
+}
+
+// Swaps in tracks renamed by LibraryIndex::rename, keeping each row where
+// it was
+pub fn replace_renamed(store: &gio::ListStore, renamed: &HashMap<String, Rc<Track>>) {
+  for i in 0..store.n_items() {
+    let new = {
+      let obj = store.item(i).unwrap();
+      let r: Ref<Rc<Track>> = obj.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+      renamed.get(&r.filename).cloned()
+    };
+    if let Some(new) = new {
+      store.splice(i, 1, &[BoxedAnyObject::new(new)]);
+    }
+  }
+}
+
+// Jumps to the first row whose text starts with what has been typed. The typed
+// prefix starts over after a pause, like type-ahead in file managers
+pub fn add_type_ahead(view: &ColumnView, text: impl Fn(&BoxedAnyObject) -> String + 'static) {
+  let prefix = RefCell::new(String::new());
+  let last_key = Cell::new(Instant::now());
+  let controller = EventControllerKey::new();
+  controller.connect_key_pressed(move |controller, key, _, modifiers| {
+    let c = match key.to_unicode() {
+      Some(c) if !c.is_control() => c,
+      _ => return Propagation::Proceed,
+    };
+    if modifiers.intersects(ModifierType::CONTROL_MASK | ModifierType::ALT_MASK) {
+      return Propagation::Proceed;
+    }
+    let mut prefix = prefix.borrow_mut();
+    if last_key.get().elapsed() > Duration::from_secs(1) {
+      prefix.clear();
+    }
+    last_key.set(Instant::now());
+    if c == ' ' && prefix.is_empty() {
+      return Propagation::Proceed;
+    }
+    prefix.extend(c.to_lowercase());
+
+    let view = controller.widget().and_downcast::<ColumnView>().unwrap();
+    let model = view.model().unwrap();
+    let found =
+      (0..model.n_items()).find(|&i| match model.item(i).and_downcast::<BoxedAnyObject>() {
+        Some(obj) => text(&obj).to_lowercase().starts_with(prefix.as_str()),
+        None => false,
+      });
+    if let Some(pos) = found {
+      model.select_item(pos, true);
+      view.scroll_to(pos, None, ListScrollFlags::FOCUS, None);
+    }
+    Propagation::Stop
+  });
+  view.add_controller(controller);
+}
+
+// win.next-pane (F6) moves focus to the next pane, wrapping around
+pub fn add_pane_cycling(wnd: &gtk::ApplicationWindow, panes: Vec<Widget>) {
+  let next_pane = gio::SimpleAction::new("next-pane", None);
+  next_pane.connect_activate(glib::clone!(
+    #[weak]
+    wnd,
+    move |_, _| {
+      let current = GtkWindowExt::focus(&wnd)
+        .and_then(|focus| panes.iter().position(|p| focus.is_ancestor(p)))
+        .unwrap_or(panes.len() - 1);
+      panes[(current + 1) % panes.len()].child_focus(DirectionType::TabForward);
+    }
+  ));
+  wnd.add_action(&next_pane);
+}
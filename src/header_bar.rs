@@ -1,11 +1,16 @@
 use crate::gtk_helpers::{create_button, load_img};
+use crate::player::{Player, RepeatMode};
 use crate::settings::FmlSettings;
 use adw::prelude::*;
-use gtk::glib::MainContext;
-use gtk::{Adjustment, Orientation, Scale, ScaleButton};
-use rodio::Sink;
-use std::cell::RefCell;
+use chrono::{Local, Timelike};
+use fml9000::levels::to_dbfs;
+use fml9000::night_mode::in_night_hours;
+use gtk::glib::{self, MainContext, Propagation};
+use gtk::{Adjustment, Button, Label, LevelBar, Orientation, Scale, ScaleButton, ToggleButton};
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 static PREV_SVG: &[u8] = include_bytes!("img/prev.svg");
 static STOP_SVG: &[u8] = include_bytes!("img/stop.svg");
@@ -14,14 +19,44 @@ static PAUSE_SVG: &[u8] = include_bytes!("img/pause.svg");
 static PLAY_SVG: &[u8] = include_bytes!("img/play.svg");
 static SETTINGS_SVG: &[u8] = include_bytes!("img/settings.svg");
 
+// the level meter's range, and how often it is redrawn
+const METER_FLOOR_DB: f32 = -60.0;
+const METER_INTERVAL: Duration = Duration::from_millis(50);
+// how often the night mode schedule is checked
+const NIGHT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+fn mode_button(icon_name: &str, tooltip: &str) -> Button {
+  Button::builder()
+    .icon_name(icon_name)
+    .tooltip_text(tooltip)
+    .build()
+}
+
+// The sink volume, capped while night mode is on
+fn effective_volume(s: &FmlSettings) -> f32 {
+  let volume = if s.night_mode {
+    s.volume.min(s.night_volume_cap)
+  } else {
+    s.volume
+  };
+  volume as f32
+}
+
+// m:ss, for the labels either side of the seek slider
+fn format_position(secs: f64) -> String {
+  let secs = secs as u64;
+  format!("{}:{:02}", secs / 60, secs % 60)
+}
+
 pub fn create_header_bar(
   settings: Rc<RefCell<FmlSettings>>,
-  sink: Rc<RefCell<Sink>>,
+  player: &Rc<Player>,
   wnd: &Rc<gtk::ApplicationWindow>,
 ) -> gtk::Box {
-  let sink1 = sink.clone();
-  let sink2 = sink.clone();
-  let sink3 = sink.clone();
+  let sink = player.sink.clone();
+  let player1 = player.clone();
+  let player2 = player.clone();
+  let player3 = player.clone();
   let wnd1 = wnd.clone();
 
   let prev_btn = create_button(&load_img(PREV_SVG));
@@ -30,12 +65,53 @@ pub fn create_header_bar(
   let pause_btn = create_button(&load_img(PAUSE_SVG));
   let play_btn = create_button(&load_img(PLAY_SVG));
   let settings_btn = create_button(&load_img(SETTINGS_SVG));
+  let shuffle_btn = mode_button(
+    player.shuffle.get().icon_name(),
+    player.shuffle.get().label(),
+  );
+  let repeat_btn = mode_button(player.repeat.get().icon_name(), player.repeat.get().label());
+  repeat_btn.add_css_class("dim-label");
+  let shortcuts_btn = Button::builder()
+    .icon_name("preferences-desktop-keyboard-shortcuts-symbolic")
+    .tooltip_text("Keyboard shortcuts")
+    .action_name("win.show-shortcuts")
+    .build();
+  let offline_btn = ToggleButton::builder()
+    .icon_name("network-offline-symbolic")
+    .tooltip_text("Offline mode")
+    .active(settings.borrow().offline)
+    .build();
+
+  let night_btn = ToggleButton::builder()
+    .icon_name("weather-clear-night-symbolic")
+    .tooltip_text("Night mode: compress loud passages and cap the volume")
+    .active(settings.borrow().night_mode)
+    .build();
 
   let button_box = gtk::Box::new(Orientation::Horizontal, 0);
   let seek_slider = Scale::builder()
     .hexpand(true)
     .orientation(Orientation::Horizontal)
-    .adjustment(&Adjustment::new(0.0, 0.0, 1.0, 0.01, 0.0, 0.0))
+    .adjustment(&Adjustment::new(0.0, 0.0, 1.0, 1.0, 10.0, 0.0))
+    .build();
+  let elapsed_label = Label::new(Some(&format_position(0.0)));
+  let remaining_label = Label::new(Some(&format_position(0.0)));
+
+  let level_bar = LevelBar::builder()
+    .width_request(48)
+    .valign(gtk::Align::Center)
+    .tooltip_text("Peak level")
+    .build();
+  // the default offsets color the bar by how full it is, which reads
+  // like a warning on loud but clean masters
+  level_bar.remove_offset_value(Some(gtk::LEVEL_BAR_OFFSET_LOW));
+  level_bar.remove_offset_value(Some(gtk::LEVEL_BAR_OFFSET_HIGH));
+  level_bar.remove_offset_value(Some(gtk::LEVEL_BAR_OFFSET_FULL));
+  let clip_label = Label::builder()
+    .label("CLIP")
+    .tooltip_text("This track clipped")
+    .opacity(0.0)
+    .css_classes(["error", "caption-heading"])
     .build();
 
   let volume_button = ScaleButton::builder()
@@ -44,37 +120,209 @@ pub fn create_header_bar(
       s.volume
     })
     .build();
+  {
+    let s = settings.borrow();
+    player.night_mode.store(s.night_mode, Ordering::Relaxed);
+    sink.borrow().set_volume(effective_volume(&s));
+  }
+  let sink3 = sink.clone();
   let settings1 = settings.clone();
   volume_button.connect_value_changed(move |_, volume| {
     let sink = sink.borrow();
     let mut s = settings1.borrow_mut();
     s.volume = volume;
     crate::settings::write_settings(&s).expect("Failed to write");
-    sink.set_volume(volume as f32);
+    sink.set_volume(effective_volume(&s));
   });
 
   button_box.append(&settings_btn);
+  button_box.append(&shortcuts_btn);
+  button_box.append(&elapsed_label);
   button_box.append(&seek_slider);
+  button_box.append(&remaining_label);
   button_box.append(&play_btn);
   button_box.append(&pause_btn);
   button_box.append(&prev_btn);
   button_box.append(&next_btn);
   button_box.append(&stop_btn);
+  button_box.append(&shuffle_btn);
+  button_box.append(&repeat_btn);
+  button_box.append(&offline_btn);
+  button_box.append(&night_btn);
+  button_box.append(&level_bar);
+  button_box.append(&clip_label);
   button_box.append(&volume_button);
 
+  let player10 = player.clone();
   pause_btn.connect_clicked(move |_| {
-    let sink = sink1.borrow();
-    sink.pause();
+    player10.pause();
   });
 
+  let player11 = player.clone();
   play_btn.connect_clicked(move |_| {
-    let sink = sink2.borrow();
-    sink.play();
+    player11.resume();
   });
 
   stop_btn.connect_clicked(move |_| {
-    let sink = sink3.borrow();
-    sink.stop()
+    player1.stop();
+  });
+
+  prev_btn.connect_clicked(move |_| {
+    player2.play_prev();
+  });
+
+  next_btn.connect_clicked(move |_| {
+    player3.play_next();
+  });
+
+  let player4 = player.clone();
+  shuffle_btn.connect_clicked(move |btn| {
+    let mode = player4.shuffle.get().cycle();
+    player4.shuffle.set(mode);
+    btn.set_icon_name(mode.icon_name());
+    btn.set_tooltip_text(Some(mode.label()));
+  });
+
+  let player5 = player.clone();
+  repeat_btn.connect_clicked(move |btn| {
+    let mode = player5.repeat.get().cycle();
+    player5.repeat.set(mode);
+    btn.set_icon_name(mode.icon_name());
+    btn.set_tooltip_text(Some(mode.label()));
+    if mode == RepeatMode::Off {
+      btn.add_css_class("dim-label");
+    } else {
+      btn.remove_css_class("dim-label");
+    }
+  });
+
+  // the slider's range is the playing track's length in seconds
+  let player_weak = Rc::downgrade(player);
+  player.connect_track_changed(glib::clone!(
+    #[weak]
+    seek_slider,
+    move |_| {
+      let Some(player) = player_weak.upgrade() else {
+        return;
+      };
+      let duration = player.duration().as_secs_f64();
+      seek_slider.adjustment().set_upper(duration.max(1.0));
+    }
+  ));
+  let player6 = player.clone();
+  seek_slider.connect_change_value(move |_, _, value| {
+    player6.seek(Duration::from_secs_f64(value.max(0.0)));
+    Propagation::Proceed
+  });
+  let player7 = Rc::downgrade(player);
+  glib::timeout_add_local(
+    Duration::from_millis(500),
+    glib::clone!(
+      #[weak]
+      seek_slider,
+      #[weak]
+      elapsed_label,
+      #[weak]
+      remaining_label,
+      #[upgrade_or]
+      glib::ControlFlow::Break,
+      move || {
+        let Some(player) = player7.upgrade() else {
+          return glib::ControlFlow::Break;
+        };
+        let (elapsed, duration) = match player.current.borrow().as_ref() {
+          Some(_) => (
+            player.position().as_secs_f64(),
+            player.duration().as_secs_f64(),
+          ),
+          None => (0.0, 0.0),
+        };
+        seek_slider.set_value(elapsed);
+        elapsed_label.set_label(&format_position(elapsed));
+        remaining_label.set_label(&format!(
+          "-{}",
+          format_position((duration - elapsed).max(0.0))
+        ));
+        glib::ControlFlow::Continue
+      }
+    ),
+  );
+
+  // the clip label stays lit until the next track so a single clipped
+  // sample isn't missed
+  let player8 = Rc::downgrade(player);
+  glib::timeout_add_local(
+    METER_INTERVAL,
+    glib::clone!(
+      #[weak]
+      level_bar,
+      #[weak]
+      clip_label,
+      #[upgrade_or]
+      glib::ControlFlow::Break,
+      move || {
+        let Some(player) = player8.upgrade() else {
+          return glib::ControlFlow::Break;
+        };
+        let peak = if player.sink.borrow().is_paused() {
+          0.0
+        } else {
+          player.levels.take_peak()
+        };
+        let db = to_dbfs(peak);
+        level_bar.set_value((1.0 - db / METER_FLOOR_DB).clamp(0.0, 1.0) as f64);
+        level_bar.set_tooltip_text(Some(&format!("Peak {:.1} dBFS", db.max(METER_FLOOR_DB))));
+        clip_label.set_opacity(if player.levels.clipped() { 1.0 } else { 0.0 });
+        glib::ControlFlow::Continue
+      }
+    ),
+  );
+
+  let player9 = player.clone();
+  let settings3 = settings.clone();
+  night_btn.connect_toggled(move |btn| {
+    let mut s = settings3.borrow_mut();
+    s.night_mode = btn.is_active();
+    crate::settings::write_settings(&s).expect("Failed to write");
+    player9.night_mode.store(s.night_mode, Ordering::Relaxed);
+    sink3.borrow().set_volume(effective_volume(&s));
+  });
+  // the schedule only flips the toggle when the night starts or ends, so
+  // it can still be switched by hand in between
+  let settings4 = settings.clone();
+  let sink4 = player.sink.clone();
+  let was_night = Cell::new(None);
+  let check_night = glib::clone!(
+    #[weak]
+    night_btn,
+    move || {
+      let (scheduled, is_night) = {
+        let s = settings4.borrow();
+        // picks up a changed volume cap from preferences
+        sink4.borrow().set_volume(effective_volume(&s));
+        let hour = Local::now().hour();
+        (
+          s.night_schedule,
+          in_night_hours(hour, s.night_start, s.night_end),
+        )
+      };
+      if scheduled && was_night.replace(Some(is_night)) != Some(is_night) {
+        night_btn.set_active(is_night);
+      }
+    }
+  );
+  check_night();
+  glib::timeout_add_local(NIGHT_CHECK_INTERVAL, move || {
+    check_night();
+    glib::ControlFlow::Continue
+  });
+
+  let settings2 = settings.clone();
+  offline_btn.connect_toggled(move |btn| {
+    let mut s = settings2.borrow_mut();
+    s.offline = btn.is_active();
+    fml9000::network::set_offline(s.offline);
+    crate::settings::write_settings(&s).expect("Failed to write");
   });
 
   settings_btn.connect_clicked(move |_| {
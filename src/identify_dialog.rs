@@ -0,0 +1,91 @@
+use adw::prelude::*;
+use fml9000::fingerprint::{identify, Suggestion};
+use fml9000::tag_writer::write_tags;
+use gtk::gio;
+use gtk::glib;
+use gtk::{Button, CheckButton, Label, Orientation, ProgressBar, ScrolledWindow};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn describe(s: &Suggestion) -> String {
+  let unknown = "?".to_string();
+  format!(
+    "{}\n  -> {} - {} ({})",
+    s.filename,
+    s.update.artist.as_ref().unwrap_or(&unknown),
+    s.update.title.as_ref().unwrap_or(&unknown),
+    s.update.album.as_ref().unwrap_or(&unknown),
+  )
+}
+
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, filenames: Vec<String>, client_key: String) {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let suggestion_list = gtk::Box::new(Orientation::Vertical, 0);
+
+  let label = Label::builder().label("Identifying unknown tracks").build();
+  let progress_bar = ProgressBar::builder().show_text(true).build();
+  let suggestion_wnd = ScrolledWindow::builder()
+    .child(&suggestion_list)
+    .vexpand(true)
+    .build();
+  let apply_button = Button::builder()
+    .label("Apply selected")
+    .sensitive(false)
+    .build();
+
+  f.append(&label);
+  f.append(&progress_bar);
+  f.append(&suggestion_wnd);
+  f.append(&apply_button);
+  let identify_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(800)
+    .default_height(600)
+    .title("Identify unknown tracks")
+    .child(&f)
+    .build();
+  identify_dialog.present();
+
+  let suggestions: Rc<RefCell<Vec<(CheckButton, Suggestion)>>> = Rc::new(RefCell::new(Vec::new()));
+  apply_button.connect_clicked(glib::clone!(
+    #[weak]
+    identify_dialog,
+    #[strong]
+    suggestions,
+    move |_| {
+      for (check, suggestion) in suggestions.borrow().iter() {
+        if check.is_active() {
+          write_tags(&suggestion.filename, &suggestion.update);
+        }
+      }
+      identify_dialog.close();
+    }
+  ));
+
+  let total = filenames.len();
+  for (i, filename) in filenames.into_iter().enumerate() {
+    if !identify_dialog.is_visible() {
+      return;
+    }
+    progress_bar.set_fraction(i as f64 / total as f64);
+    progress_bar.set_text(Some(&format!("{} / {}", i + 1, total)));
+    let key = client_key.clone();
+    if let Ok(Some(suggestion)) = gio::spawn_blocking(move || identify(&filename, &key)).await {
+      let check = CheckButton::builder()
+        .label(describe(&suggestion))
+        .active(true)
+        .build();
+      suggestion_list.append(&check);
+      suggestions.borrow_mut().push((check, suggestion));
+    }
+  }
+
+  progress_bar.set_fraction(1.0);
+  label.set_text(&format!(
+    "Found matches for {} of {} tracks",
+    suggestions.borrow().len(),
+    total
+  ));
+  apply_button.set_sensitive(true);
+}
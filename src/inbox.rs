@@ -0,0 +1,87 @@
+use crate::interned::Interned;
+use crate::metadata::{read_metadata, MetadataProvider};
+use crate::models::{NewTrack, Track};
+use crate::organize::{move_file, plan_moves, rename_in_db};
+use crate::platform;
+use crate::schema::tracks;
+use crate::writer::write_db;
+use chrono::Local;
+use diesel::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
+use tracing::{error, warn};
+use walkdir::WalkDir;
+
+// The files waiting in the inbox as tracks that aren't in the library yet,
+// in filename order. Blocking, so call it off the main thread
+pub fn read_inbox(folder: &str, providers: &[Box<dyn MetadataProvider>]) -> Vec<Track> {
+  let mut tracks: Vec<Track> = WalkDir::new(folder)
+    .into_iter()
+    .filter_map(|e| e.ok())
+    .filter(|e| e.file_type().is_file())
+    .filter_map(|e| {
+      let m = read_metadata(providers, e.path())?;
+      Some(Track {
+        filename: platform::library_path(e.path()),
+        artist: m.artist.map(Interned::from),
+        title: m.title,
+        album: m.album.map(Interned::from),
+        genre: m.genre.map(Interned::from),
+        album_artist: m.album_artist.map(Interned::from),
+        track: m.track.map(Interned::from),
+        added: Some(Local::now().naive_local()),
+        year: m.year,
+        duration_ms: m.duration_ms,
+        scan_session_id: None,
+      })
+    })
+    .collect();
+  tracks.sort_by(|a, b| a.filename.cmp(&b.filename));
+  tracks
+}
+
+// A file that was scanned already, e.g. an inbox inside a library folder,
+// keeps its row under the new name
+fn add_to_library(conn: &mut SqliteConnection, track: &Track, from: &str) -> QueryResult<()> {
+  rename_in_db(conn, from, &track.filename)?;
+  diesel::insert_or_ignore_into(tracks::table)
+    .values(NewTrack {
+      filename: &track.filename,
+      artist: track.artist.as_deref(),
+      title: track.title.as_deref(),
+      album: track.album.as_deref(),
+      genre: track.genre.as_deref(),
+      track: track.track.as_deref(),
+      album_artist: track.album_artist.as_deref(),
+      year: track.year,
+      duration_ms: track.duration_ms,
+      scan_session_id: None,
+    })
+    .execute(conn)?;
+  Ok(())
+}
+
+// Moves the tracks to where pattern puts them under root and adds them to
+// the library. Returns the number that made it
+pub fn commit_inbox(tracks: Vec<Track>, root: &Path, pattern: &str) -> usize {
+  let tracks: Vec<Rc<Track>> = tracks.into_iter().map(Rc::new).collect();
+  let moves = plan_moves(&tracks, root, pattern);
+  let mut committed = 0;
+  for track in tracks {
+    let mut track = Rc::unwrap_or_clone(track);
+    let from = track.filename.clone();
+    // plan_moves leaves out files that are already where they belong
+    if let Some(m) = moves.iter().find(|m| m.from == from) {
+      if let Err(e) = move_file(Path::new(&from), &m.to) {
+        warn!("Failed to move {} to {}: {}", from, m.to.display(), e);
+        continue;
+      }
+      track.filename = platform::library_path(&m.to);
+    }
+    match write_db(|conn| add_to_library(conn, &track, &from)) {
+      Ok(()) => committed += 1,
+      Err(e) => error!("Failed to add {} to the library: {}", track.filename, e),
+    }
+  }
+  committed
+}
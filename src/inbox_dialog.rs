@@ -0,0 +1,206 @@
+use crate::settings::{write_settings, FmlSettings};
+use adw::prelude::*;
+use fml9000::inbox::{commit_inbox, read_inbox};
+use fml9000::interned::Interned;
+use fml9000::metadata::default_providers;
+use fml9000::models::{TagUpdate, Track};
+use fml9000::organize::plan_moves;
+use fml9000::tag_writer::write_tags;
+use gtk::gio;
+use gtk::glib::{self, MainContext};
+use gtk::{Button, Entry, Grid, Label, Orientation, ScrolledWindow, TextView};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+struct InboxRow {
+  track: Track,
+  artist: Entry,
+  album: Entry,
+  title: Entry,
+}
+
+// Some(text) when the entry no longer matches the tag
+fn changed(entry: &Entry, original: Option<&str>) -> Option<String> {
+  let text = entry.text().to_string();
+  (text != original.unwrap_or("")).then_some(text)
+}
+
+impl InboxRow {
+  // The track with the edits applied, and the tags to write for them
+  fn edited(&self) -> (Track, Option<TagUpdate>) {
+    let update = TagUpdate {
+      artist: changed(&self.artist, self.track.artist.as_deref()),
+      album: changed(&self.album, self.track.album.as_deref()),
+      title: changed(&self.title, self.track.title.as_deref()),
+    };
+    let mut track = self.track.clone();
+    if update.artist.is_none() && update.album.is_none() && update.title.is_none() {
+      return (track, None);
+    }
+    track.artist = update
+      .artist
+      .as_deref()
+      .map(Interned::from)
+      .or(track.artist);
+    track.album = update.album.as_deref().map(Interned::from).or(track.album);
+    track.title = update.title.clone().or(track.title);
+    (track, Some(update))
+  }
+}
+
+fn entry(text: Option<&str>) -> Entry {
+  Entry::builder()
+    .text(text.unwrap_or(""))
+    .hexpand(true)
+    .build()
+}
+
+fn create_grid(tracks: Vec<Track>, inbox: &str) -> (Grid, Vec<InboxRow>) {
+  let grid = Grid::builder().row_spacing(4).column_spacing(8).build();
+  for (column, heading) in ["File", "Artist", "Album", "Title"].iter().enumerate() {
+    grid.attach(&Label::new(Some(heading)), column as i32, 0, 1, 1);
+  }
+  let mut rows = Vec::new();
+  for (i, track) in tracks.into_iter().enumerate() {
+    let row = InboxRow {
+      artist: entry(track.artist.as_deref()),
+      album: entry(track.album.as_deref()),
+      title: entry(track.title.as_deref()),
+      track,
+    };
+    let name = Path::new(&row.track.filename)
+      .strip_prefix(inbox)
+      .map_or(PathBuf::from(&row.track.filename), |p| p.to_path_buf());
+    let i = i as i32 + 1;
+    grid.attach(&Label::new(Some(&name.display().to_string())), 0, i, 1, 1);
+    grid.attach(&row.artist, 1, i, 1, 1);
+    grid.attach(&row.album, 2, i, 1, 1);
+    grid.attach(&row.title, 3, i, 1, 1);
+    rows.push(row);
+  }
+  (grid, rows)
+}
+
+// Lists what is waiting in the inbox folder with its tags editable, and
+// files it into the first library folder using the organize pattern
+pub async fn dialog<W: IsA<gtk::Window>>(
+  wnd: Rc<W>,
+  settings: Rc<RefCell<FmlSettings>>,
+  inbox: String,
+) {
+  let extra_formats = settings.borrow().extra_formats.clone();
+  let folder = inbox.clone();
+  let tracks = match gio::spawn_blocking(move || {
+    read_inbox(&folder, &default_providers(&extra_formats))
+  })
+  .await
+  {
+    Ok(tracks) => tracks,
+    Err(_) => return,
+  };
+
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let button_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let pattern_entry = Entry::builder()
+    .text(&settings.borrow().organize_pattern)
+    .hexpand(true)
+    .build();
+  let preview_button = Button::builder().label("Preview").build();
+  let commit_button = Button::builder()
+    .label("Add to library")
+    .sensitive(!tracks.is_empty())
+    .build();
+  let status = TextView::builder().editable(false).monospace(true).build();
+  let title = format!("Inbox: {} files", tracks.len());
+  let (grid, rows) = create_grid(tracks, &inbox);
+  let rows = Rc::new(rows);
+
+  button_row.append(&pattern_entry);
+  button_row.append(&preview_button);
+  button_row.append(&commit_button);
+  f.append(&button_row);
+  f.append(&ScrolledWindow::builder().child(&grid).vexpand(true).build());
+  f.append(
+    &ScrolledWindow::builder()
+      .child(&status)
+      .min_content_height(150)
+      .build(),
+  );
+  let inbox_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(1000)
+    .default_height(700)
+    .title(title)
+    .child(&f)
+    .build();
+
+  let rows_rc = rows.clone();
+  preview_button.connect_clicked(glib::clone!(
+    #[weak]
+    pattern_entry,
+    #[weak]
+    status,
+    #[weak]
+    settings,
+    move |_| {
+      let text = match settings.borrow().folders.first() {
+        Some(folder) => {
+          let tracks: Vec<Rc<Track>> = rows_rc.iter().map(|r| Rc::new(r.edited().0)).collect();
+          plan_moves(&tracks, Path::new(folder), &pattern_entry.text())
+            .iter()
+            .map(|m| format!("{}\n  -> {}\n", m.from, m.to.display()))
+            .collect()
+        }
+        None => "Set a music folder in preferences first".to_string(),
+      };
+      status.buffer().set_text(&text);
+    }
+  ));
+
+  commit_button.connect_clicked(glib::clone!(
+    #[weak]
+    pattern_entry,
+    #[weak]
+    status,
+    #[weak]
+    settings,
+    move |button| {
+      let Some(root) = settings.borrow().folders.first().cloned() else {
+        status
+          .buffer()
+          .set_text("Set a music folder in preferences first");
+        return;
+      };
+      let pattern = pattern_entry.text().to_string();
+      {
+        let mut s = settings.borrow_mut();
+        s.organize_pattern = pattern.clone();
+        write_settings(&s).expect("Failed to write");
+      }
+      let (tracks, updates): (Vec<Track>, Vec<Option<TagUpdate>>) =
+        rows.iter().map(|r| r.edited()).unzip();
+      button.set_sensitive(false);
+      status.buffer().set_text("Adding to library...");
+      MainContext::default().spawn_local(async move {
+        let total = tracks.len();
+        let committed = gio::spawn_blocking(move || {
+          for (track, update) in tracks.iter().zip(&updates) {
+            if let Some(update) = update {
+              write_tags(&track.filename, update);
+            }
+          }
+          commit_inbox(tracks, Path::new(&root), &pattern)
+        })
+        .await
+        .unwrap_or(0);
+        status.buffer().set_text(&format!(
+          "Added {} of {} files, they show up after a restart",
+          committed, total
+        ));
+      });
+    }
+  ));
+  inbox_dialog.present();
+}
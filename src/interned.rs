@@ -0,0 +1,108 @@
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use serde::{Serialize, Serializer};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{LazyLock, Mutex};
+
+// A tag value kept once however many tracks share it. Libraries have far
+// fewer distinct artists, albums and genres than tracks, so rows loaded with
+// these point at one copy instead of each holding their own String. A plain
+// pointer, half the size of an Arc<str> and with no count to keep
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, FromSqlRow)]
+pub struct Interned(&'static String);
+
+// Every value handed out so far, keyed by its text. Values are leaked rather
+// than counted since the pool never lets go of them anyway, and it only
+// grows with new tag values, not with reloads of the same library
+static POOL: LazyLock<Mutex<HashMap<&'static str, &'static String>>> =
+  LazyLock::new(Default::default);
+
+impl Interned {
+  pub fn new(s: &str) -> Self {
+    let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(shared) = pool.get(s) {
+      return Interned(shared);
+    }
+    let shared: &'static String = Box::leak(Box::new(s.to_string()));
+    pool.insert(shared.as_str(), shared);
+    Interned(shared)
+  }
+
+  pub fn as_str(&self) -> &str {
+    self.0
+  }
+}
+
+impl Deref for Interned {
+  type Target = str;
+
+  fn deref(&self) -> &str {
+    self.0
+  }
+}
+
+impl AsRef<str> for Interned {
+  fn as_ref(&self) -> &str {
+    self.0
+  }
+}
+
+impl Borrow<str> for Interned {
+  fn borrow(&self) -> &str {
+    self.0
+  }
+}
+
+impl From<&str> for Interned {
+  fn from(s: &str) -> Self {
+    Interned::new(s)
+  }
+}
+
+impl From<String> for Interned {
+  fn from(s: String) -> Self {
+    Interned::new(&s)
+  }
+}
+
+impl fmt::Display for Interned {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.0)
+  }
+}
+
+impl fmt::Debug for Interned {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(self.0, f)
+  }
+}
+
+impl FromSql<Text, Sqlite> for Interned {
+  fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+    let s = <String as FromSql<Text, Sqlite>>::from_sql(value)?;
+    Ok(Interned::new(&s))
+  }
+}
+
+impl Serialize for Interned {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn equal_values_share_one_copy() {
+    let a = Interned::new("The Band");
+    let b = Interned::from("The Band".to_string());
+    assert!(std::ptr::eq(a.0, b.0));
+    assert!(!std::ptr::eq(a.0, Interned::new("The Other Band").0));
+  }
+}
@@ -0,0 +1,232 @@
+use crate::analysis::Excerpt;
+use crate::connect_db;
+use crate::schema::track_key;
+use crate::writer::write_db;
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+use tracing::error;
+
+// samples are averaged down to around this rate, which keeps everything up
+// to the top note below
+const RATE: u32 = 11025;
+// at RATE, fine enough to tell the lowest notes apart
+const FRAME: usize = 4096;
+// C2 to B5, where the harmony of most music is
+const LOWEST_NOTE: i32 = 36;
+const HIGHEST_NOTE: i32 = 83;
+
+// Krumhansl-Kessler key profiles, how well each of the twelve notes fits a
+// major or minor key on its tonic
+const MAJOR: [f32; 12] = [
+  6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR: [f32; 12] = [
+  6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+const NOTE_NAMES: [&str; 12] = [
+  "C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+  // pitch class of the tonic, 0 is C
+  pub tonic: u8,
+  pub minor: bool,
+}
+
+impl Key {
+  // how it is stored, 0-11 for the major keys and 12-23 for the minor ones
+  pub fn index(self) -> i32 {
+    self.tonic as i32 + if self.minor { 12 } else { 0 }
+  }
+
+  pub fn from_index(index: i32) -> Option<Key> {
+    (0..24).contains(&index).then_some(Key {
+      tonic: (index % 12) as u8,
+      minor: index >= 12,
+    })
+  }
+
+  pub fn all() -> impl Iterator<Item = Key> {
+    (0..24).filter_map(Key::from_index)
+  }
+
+  // Position on the Camelot wheel, 1-12. Each step round it is a fifth, and
+  // relative major and minor keys share a number
+  pub fn camelot_number(self) -> u8 {
+    let major_tonic = if self.minor {
+      (self.tonic + 3) % 12
+    } else {
+      self.tonic
+    };
+    (7 * major_tonic + 7) % 12 + 1
+  }
+
+  // e.g. 8A for A minor and 8B for C major
+  pub fn camelot(self) -> String {
+    format!(
+      "{}{}",
+      self.camelot_number(),
+      if self.minor { 'A' } else { 'B' }
+    )
+  }
+
+  pub fn name(self) -> String {
+    format!(
+      "{}{}",
+      NOTE_NAMES[self.tonic as usize],
+      if self.minor { "m" } else { "" }
+    )
+  }
+
+  // Orders keys round the wheel, so harmonically close keys sort together
+  pub fn wheel_order(self) -> (u8, bool) {
+    (self.camelot_number(), !self.minor)
+  }
+
+  // Keys that mix smoothly into each other: the same key, a step either way
+  // round the wheel, or its relative major or minor
+  pub fn is_compatible(self, other: Key) -> bool {
+    let (a, b) = (self.camelot_number(), other.camelot_number());
+    let steps = (a as i32 - b as i32).rem_euclid(12);
+    if self.minor == other.minor {
+      steps == 0 || steps == 1 || steps == 11
+    } else {
+      steps == 0
+    }
+  }
+}
+
+fn hann(n: usize) -> Vec<f32> {
+  (0..n)
+    .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos())
+    .collect()
+}
+
+// How strong each of the twelve notes is over the whole excerpt, from a
+// Goertzel filter per note over each frame
+fn chroma(samples: &[f32], rate: f32) -> [f32; 12] {
+  let window = hann(FRAME);
+  let coefficients: Vec<(usize, f32)> = (LOWEST_NOTE..=HIGHEST_NOTE)
+    .map(|note| {
+      let freq = 440.0 * 2f32.powf((note - 69) as f32 / 12.0);
+      (note as usize % 12, 2.0 * (2.0 * PI * freq / rate).cos())
+    })
+    .collect();
+  let mut chroma = [0.0; 12];
+  let mut windowed = vec![0.0; FRAME];
+  for frame in samples.chunks_exact(FRAME) {
+    for ((w, s), h) in windowed.iter_mut().zip(frame).zip(&window) {
+      *w = s * h;
+    }
+    let mut frame_chroma = [0.0; 12];
+    for &(pitch_class, coefficient) in &coefficients {
+      let (mut s1, mut s2) = (0.0, 0.0);
+      for &x in &windowed {
+        let s0 = x + coefficient * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+      }
+      let power = s1 * s1 + s2 * s2 - coefficient * s1 * s2;
+      frame_chroma[pitch_class] += power.max(0.0).sqrt();
+    }
+    // each frame counts the same however loud it is, silent ones not at all
+    let total: f32 = frame_chroma.iter().sum();
+    if total > 1e-6 {
+      for (c, f) in chroma.iter_mut().zip(frame_chroma) {
+        *c += f / total;
+      }
+    }
+  }
+  chroma
+}
+
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+  let mean_a = a.iter().sum::<f32>() / a.len() as f32;
+  let mean_b = b.iter().sum::<f32>() / b.len() as f32;
+  let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+  for (x, y) in a.iter().zip(b) {
+    ab += (x - mean_a) * (y - mean_b);
+    aa += (x - mean_a) * (x - mean_a);
+    bb += (y - mean_b) * (y - mean_b);
+  }
+  ab / (aa * bb).sqrt().max(f32::EPSILON)
+}
+
+// The key whose profile best matches the notes of the excerpt. None when it
+// has no notes to go by
+pub fn detect_key(excerpt: &Excerpt) -> Option<Key> {
+  let step = (excerpt.sample_rate / RATE).max(1) as usize;
+  let rate = excerpt.sample_rate as f32 / step as f32;
+  // down to mono, then averaging away the samples above the rate
+  let samples: Vec<f32> = excerpt
+    .samples
+    .chunks_exact(excerpt.channels * step)
+    .map(|c| c.iter().sum::<f32>() / c.len() as f32)
+    .collect();
+  let chroma = chroma(&samples, rate);
+  if chroma.iter().all(|&c| c == 0.0) {
+    return None;
+  }
+  let score = |key: Key| {
+    let profile = if key.minor { &MINOR } else { &MAJOR };
+    let rotated: Vec<f32> = (0..12)
+      .map(|i| profile[(i + 12 - key.tonic as usize) % 12])
+      .collect();
+    correlation(&chroma, &rotated)
+  };
+  Key::all()
+    .map(|key| (key, score(key)))
+    .max_by(|a, b| a.1.total_cmp(&b.1))
+    .map(|(key, _)| key)
+}
+
+pub fn load_keys() -> HashMap<String, Key> {
+  let conn = &mut connect_db();
+  match track_key::table
+    .select((track_key::filename, track_key::musical_key))
+    .load::<(String, i32)>(conn)
+  {
+    Ok(rows) => rows
+      .into_iter()
+      .filter_map(|(filename, index)| Some((filename, Key::from_index(index)?)))
+      .collect(),
+    Err(e) => {
+      error!("Failed to load keys: {}", e);
+      HashMap::new()
+    }
+  }
+}
+
+pub fn store_key(filename: &str, key: Key) {
+  if let Err(e) = write_db(|conn| {
+    diesel::replace_into(track_key::table)
+      .values((
+        track_key::filename.eq(filename),
+        track_key::musical_key.eq(key.index()),
+      ))
+      .execute(conn)
+  }) {
+    error!("Failed to store key of {}: {}", filename, e);
+  }
+}
+
+// Files in a key that mixes well with the given one
+pub fn filenames_compatible_with(key: Key) -> HashSet<String> {
+  let indexes: Vec<i32> = Key::all()
+    .filter(|k| k.is_compatible(key))
+    .map(Key::index)
+    .collect();
+  let conn = &mut connect_db();
+  track_key::table
+    .filter(track_key::musical_key.eq_any(indexes))
+    .select(track_key::filename)
+    .load::<String>(conn)
+    .map(|rows| rows.into_iter().collect())
+    .unwrap_or_else(|e| {
+      error!("Failed to load keys: {}", e);
+      HashSet::new()
+    })
+}
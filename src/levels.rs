@@ -0,0 +1,100 @@
+use rodio::source::SeekError;
+use rodio::{Sample, Source};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Samples this close to full scale count as clipped
+const CLIP_LEVEL: f32 = 0.999;
+
+// Written from the audio thread as samples go out, read by the UI
+#[derive(Default)]
+pub struct Levels {
+  // f32 bits, since there is no atomic float
+  peak: AtomicU32,
+  clipped: AtomicBool,
+}
+
+impl Levels {
+  // The highest absolute sample since the last call, 0.0 to 1.0
+  pub fn take_peak(&self) -> f32 {
+    f32::from_bits(self.peak.swap(0, Ordering::Relaxed))
+  }
+
+  // Whether anything clipped since the last reset
+  pub fn clipped(&self) -> bool {
+    self.clipped.load(Ordering::Relaxed)
+  }
+
+  pub fn reset(&self) {
+    self.peak.store(0, Ordering::Relaxed);
+    self.clipped.store(false, Ordering::Relaxed);
+  }
+
+  fn record(&self, level: f32) {
+    // non-negative floats order the same as their bits
+    self.peak.fetch_max(level.to_bits(), Ordering::Relaxed);
+    if level >= CLIP_LEVEL {
+      self.clipped.store(true, Ordering::Relaxed);
+    }
+  }
+}
+
+// Decibels relative to full scale, for display
+pub fn to_dbfs(level: f32) -> f32 {
+  20.0 * level.max(1e-5).log10()
+}
+
+// Passes samples through unchanged while recording their levels
+pub struct Metered<S> {
+  input: S,
+  levels: Arc<Levels>,
+}
+
+pub fn meter<S>(input: S, levels: Arc<Levels>) -> Metered<S> {
+  Metered { input, levels }
+}
+
+impl<S> Iterator for Metered<S>
+where
+  S: Source,
+  S::Item: Sample,
+{
+  type Item = S::Item;
+
+  fn next(&mut self) -> Option<S::Item> {
+    let sample = self.input.next()?;
+    self.levels.record(sample.to_f32().abs());
+    Some(sample)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.input.size_hint()
+  }
+}
+
+impl<S> Source for Metered<S>
+where
+  S: Source,
+  S::Item: Sample,
+{
+  fn current_frame_len(&self) -> Option<usize> {
+    self.input.current_frame_len()
+  }
+
+  fn channels(&self) -> u16 {
+    self.input.channels()
+  }
+
+  fn sample_rate(&self) -> u32 {
+    self.input.sample_rate()
+  }
+
+  fn total_duration(&self) -> Option<Duration> {
+    self.input.total_duration()
+  }
+
+  fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+    self.input.try_seek(pos)
+  }
+}
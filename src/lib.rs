@@ -1,111 +1,156 @@
+pub mod album_art;
+pub mod albums;
+pub mod analysis;
+pub mod art_fetch;
+pub mod artists;
+pub mod auto_playlists;
+pub mod bpm;
+pub mod catalog;
+pub mod checksums;
 mod chunked_iterator;
+pub mod continuous;
+pub mod discord;
+pub mod fingerprint;
+pub mod folders;
+pub mod inbox;
+pub mod interned;
+pub mod key;
+pub mod levels;
+pub mod library_index;
+pub mod logging;
+pub mod metadata;
 pub mod models;
+pub mod network;
+pub mod night_mode;
+pub mod offsets;
+pub mod organize;
+pub mod platform;
+pub mod playlist_stats;
+pub mod playlists;
+pub mod plays;
+pub mod profile;
+pub mod properties;
+pub mod queue;
+pub mod related;
+pub mod scan_sessions;
+pub mod scanner;
 pub mod schema;
+pub mod search;
+pub mod sessions;
+pub mod silence;
+pub mod subsonic;
+pub mod subsonic_server;
+pub mod tag_writer;
+pub mod transcode;
+pub mod trash;
+pub mod writer;
+pub mod zones;
 
+use self::interned::Interned;
+use self::library_index::LibraryIndex;
 use self::models::*;
-use self::schema::tracks;
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use directories::ProjectDirs;
-use gtk::gio;
-use gtk::glib::BoxedAnyObject;
-use lofty::file::TaggedFileExt;
-use lofty::prelude::Accessor;
-use lofty::probe::Probe;
-use lofty::tag::ItemKey;
-use std::collections::HashSet;
+use gtk::gio::{self, prelude::*};
+use gtk::glib::{self, BoxedAnyObject};
+use std::cell::Ref;
 use std::rc::Rc;
-use walkdir::WalkDir;
+use std::time::{Duration, Instant};
+use tracing::{error, info_span, warn};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
-fn run_migration(conn: &mut SqliteConnection) {
-  conn.run_pending_migrations(MIGRATIONS).unwrap();
+// Once at startup, before anything else opens the database
+pub fn init_db() {
+  let _span = info_span!("init_db").entered();
+  connect_db().run_pending_migrations(MIGRATIONS).unwrap();
 }
 
-#[derive(Hash, Eq, Ord, PartialEq, PartialOrd, Debug)]
+#[derive(Clone, Hash, Eq, Ord, PartialEq, PartialOrd, Debug)]
 pub struct Facet {
-  pub album_artist_or_artist: Option<String>,
-  pub album_artist: Option<String>,
-  pub album: Option<String>,
+  pub album_artist_or_artist: Option<Interned>,
+  pub album_artist: Option<Interned>,
+  pub album: Option<Interned>,
   pub all: bool,
 }
 
 pub fn connect_db() -> SqliteConnection {
+  let _span = info_span!("connect_db").entered();
   let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
-  let path = proj_dirs.config_dir().join("library.db");
-  let database_url = format!("sqlite://{}", path.to_str().unwrap());
-  SqliteConnection::establish(&database_url)
-    .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+  let dir = proj_dirs.config_dir();
+  if let Err(e) = std::fs::create_dir_all(dir) {
+    error!("Failed to create {}: {}", dir.display(), e);
+  }
+  // a plain path rather than a sqlite:// URL, which doesn't take Windows
+  // drive letters and backslashes
+  open_db(&dir.join("library.db").to_string_lossy())
 }
 
-fn hashset(data: &Vec<Rc<Track>>) -> HashSet<&std::string::String> {
-  HashSet::from_iter(data.iter().map(|elt| &elt.filename))
+pub(crate) fn open_db(database_url: &str) -> SqliteConnection {
+  let mut conn = SqliteConnection::establish(database_url)
+    .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
+  configure_connection(&mut conn);
+  conn
 }
 
-pub fn run_scan(folder: &str, rows: &Vec<Rc<Track>>) {
-  let hash = hashset(rows);
-  let mut conn = connect_db();
-  let transaction_size = 20;
-
-  for chunk in chunked_iterator::ChunkedIterator::new(
-    WalkDir::new(folder).into_iter().filter_map(|e| e.ok()),
-    transaction_size,
+// WAL lets the views keep reading while a scan writes. Writes all go
+// through writer::write_db, the busy timeout only covers another fml9000
+// process holding the lock. sqlite leaves foreign keys off unless asked on
+// every connection, without them deleting a playlist would leave its
+// playlist_tracks rows behind
+fn configure_connection(conn: &mut SqliteConnection) {
+  if let Err(e) = conn.batch_execute(
+    "PRAGMA busy_timeout = 10000; PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; \
+     PRAGMA foreign_keys = ON;",
   ) {
-    for file in chunk {
-      if file.file_type().is_file() {
-        let path = file.path();
-        let path_str = path.display().to_string();
-        if !hash.contains(&path_str) {
-          let tagged_file = Probe::open(&path_str)
-            .expect("ERROR: Bad path provided!")
-            .read();
-          match tagged_file {
-            Ok(tagged_file) => {
-              let tag = match tagged_file.primary_tag() {
-                Some(primary_tag) => Some(primary_tag),
-                None => tagged_file.first_tag(),
-              };
-              match tag {
-                Some(t) => {
-                  diesel::insert_into(tracks::table)
-                    .values(NewTrack {
-                      filename: &path_str,
-                      artist: t.artist().as_deref(),
-                      album: t.album().as_deref(),
-                      album_artist: t.get_string(&ItemKey::AlbumArtist),
-                      title: t.title().as_deref(),
-                      track: t.get_string(&ItemKey::TrackNumber),
-                      genre: t.genre().as_deref(),
-                    })
-                    .execute(&mut conn);
-                }
-                None => (),
-              }
-            }
-            Err(_) => (),
-          };
-        }
-      }
-    }
+    warn!("Failed to configure database connection: {}", e);
   }
 }
 
-pub fn add_track_to_recently_played(_path: &str) -> () {
-  // let conn = connect_db();
-  // conn.execute("INSERT INTO recently_played (filename) VALUES (?)", (path,))?;
+// A fresh library for tests, configured like the real one
+#[cfg(test)]
+pub(crate) fn memory_db() -> SqliteConnection {
+  let mut conn = open_db(":memory:");
+  conn.run_pending_migrations(MIGRATIONS).unwrap();
+  conn
+}
 
-  // Ok(())
+// Returns the scan session the new files were recorded under, if any were
+// found
+pub fn run_scan(
+  folder: &str,
+  rows: &[Track],
+  providers: &[Box<dyn metadata::MetadataProvider>],
+  options: scanner::ScanOptions,
+) -> Option<i32> {
+  let last_scan = folders::last_scan(&mut connect_db(), folder).unwrap_or_else(|e| {
+    error!("Failed to load last scan of {}: {}", folder, e);
+    None
+  });
+  scanner::scan_folder(
+    writer::writer(),
+    folder,
+    rows,
+    last_scan,
+    providers,
+    options,
+  )
+  .session_id
 }
 
-pub fn load_tracks() -> Vec<Rc<Track>> {
+pub fn query_tracks() -> Vec<Track> {
   use self::schema::tracks::dsl::*;
 
+  let _span = info_span!("load_tracks").entered();
   let conn = &mut connect_db();
-  let results = tracks.load::<Track>(conn).expect("Error loading tracks");
+  tracks.load::<Track>(conn).expect("Error loading tracks")
+}
 
-  results.into_iter().map(|r| Rc::new(r)).collect()
+pub fn load_tracks() -> Vec<Rc<Track>> {
+  query_tracks().into_iter().map(Rc::new).collect()
 }
 
 pub fn load_playlist_store<'a, I>(vals: I, store: &gio::ListStore)
@@ -117,25 +162,54 @@ where
   }
 }
 
-pub fn load_facet_store(rows: &[Rc<Track>], facet_store: &gio::ListStore) {
-  let mut facets = HashSet::new();
-  for row in rows {
-    facets.insert(Facet {
-      album: row.album.clone(),
-      album_artist: row.album_artist.clone(),
-      album_artist_or_artist: row.album_artist.clone().or(row.artist.clone()),
-      all: false,
-    });
+pub const STORE_CHUNK_SIZE: usize = 2000;
+
+// Appends rows a chunk at a time, yielding to the main loop in between so
+// large libraries don't stall the UI. Stops early once is_current returns
+// false, e.g. when the store has been cleared for a newer selection
+pub async fn load_playlist_store_chunked(
+  rows: Vec<Rc<Track>>,
+  store: gio::ListStore,
+  is_current: impl Fn() -> bool,
+) {
+  let started = Instant::now();
+  for chunk in rows.chunks(STORE_CHUNK_SIZE) {
+    if !is_current() {
+      return;
+    }
+    let objs: Vec<BoxedAnyObject> = chunk
+      .iter()
+      .map(|row| BoxedAnyObject::new(row.clone()))
+      .collect();
+    store.extend_from_slice(&objs);
+    glib::timeout_future(Duration::ZERO).await;
+  }
+  profile::record_timing("populate_store", started.elapsed());
+}
+
+// Drops the rows for albums that no longer have any tracks, after files were
+// removed from the index
+pub fn remove_empty_facets(index: &LibraryIndex, facet_store: &gio::ListStore) {
+  let mut i = 0;
+  while let Some(obj) = facet_store.item(i) {
+    let facet: Ref<Facet> = obj.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+    if facet.all || index.facets().binary_search(&facet).is_ok() {
+      i += 1;
+    } else {
+      drop(facet);
+      facet_store.remove(i);
+    }
   }
+}
+
+pub fn load_facet_store(index: &LibraryIndex, facet_store: &gio::ListStore) {
   facet_store.append(&BoxedAnyObject::new(Facet {
     album: None,
     album_artist: None,
     album_artist_or_artist: None,
     all: true,
   }));
-  let mut v = Vec::from_iter(facets);
-  v.sort();
-  for uniq in v {
-    facet_store.append(&BoxedAnyObject::new(uniq))
+  for facet in index.facets() {
+    facet_store.append(&BoxedAnyObject::new(facet.clone()))
   }
 }
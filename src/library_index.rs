@@ -0,0 +1,268 @@
+use crate::albums::{album_key, group_albums, Album, AlbumKey};
+use crate::artists::{artist_credits, credit_splitter, normalize_artist};
+use crate::interned::Interned;
+use crate::models::Track;
+use crate::Facet;
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// A track that hashes and compares by its filename, so the filename lookup
+// can be a set of the tracks themselves instead of keeping a second copy
+// of every filename as map keys
+struct ByFilename(Rc<Track>);
+
+impl Hash for ByFilename {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.0.filename.hash(state)
+  }
+}
+
+impl PartialEq for ByFilename {
+  fn eq(&self, other: &Self) -> bool {
+    self.0.filename == other.0.filename
+  }
+}
+
+impl Eq for ByFilename {}
+
+impl Borrow<str> for ByFilename {
+  fn borrow(&self) -> &str {
+    &self.0.filename
+  }
+}
+
+// The loaded library with lookups by filename, artist and album, and its
+// albums, built once up front, so the views don't rescan every track on each selection
+pub struct LibraryIndex {
+  tracks: Vec<Rc<Track>>,
+  by_filename: HashSet<ByFilename>,
+  // keyed by normalize_artist of both the artist and album artist tags,
+  // and of each artist credited in them
+  by_artist: HashMap<String, Vec<Rc<Track>>>,
+  by_album: HashMap<AlbumKey, Vec<Rc<Track>>>,
+  albums: Vec<Album>,
+  facets: Vec<Facet>,
+}
+
+impl LibraryIndex {
+  // separators split tags like "Artist feat. Other" into artist credits
+  pub fn new(tracks: Vec<Rc<Track>>, separators: &[String]) -> Self {
+    let splitter = credit_splitter(separators);
+    let mut by_filename = HashSet::with_capacity(tracks.len());
+    let mut by_artist: HashMap<String, Vec<Rc<Track>>> = HashMap::new();
+    let mut by_album: HashMap<AlbumKey, Vec<Rc<Track>>> = HashMap::new();
+    let mut facets = BTreeSet::new();
+    for track in &tracks {
+      by_filename.insert(ByFilename(track.clone()));
+
+      let mut artists: Vec<String> = [&track.artist, &track.album_artist]
+        .into_iter()
+        .flatten()
+        .flat_map(|a| artist_credits(a, splitter.as_ref()))
+        .collect();
+      artists.sort();
+      artists.dedup();
+      for artist in artists {
+        by_artist.entry(artist).or_default().push(track.clone());
+      }
+
+      let (album_artist_or_artist, album) = album_key(track);
+      facets.insert(Facet {
+        album: album.clone(),
+        album_artist: track.album_artist.clone(),
+        album_artist_or_artist: album_artist_or_artist.clone(),
+        all: false,
+      });
+      by_album
+        .entry((album_artist_or_artist, album))
+        .or_default()
+        .push(track.clone());
+    }
+    LibraryIndex {
+      albums: group_albums(&tracks),
+      tracks,
+      by_filename,
+      by_artist,
+      by_album,
+      facets: facets.into_iter().collect(),
+    }
+  }
+
+  pub fn tracks(&self) -> &[Rc<Track>] {
+    &self.tracks
+  }
+
+  pub fn get(&self, filename: &str) -> Option<&Rc<Track>> {
+    self.by_filename.get(filename).map(|t| &t.0)
+  }
+
+  // Keeps the order of filenames, skipping files that are no longer in the
+  // library
+  pub fn by_filenames(&self, filenames: &[String]) -> Vec<Rc<Track>> {
+    filenames
+      .iter()
+      .filter_map(|f| self.get(f))
+      .cloned()
+      .collect()
+  }
+
+  // Everything tagged with the artist as either the artist or album artist
+  pub fn by_artist(&self, artist: &str) -> &[Rc<Track>] {
+    self
+      .by_artist
+      .get(&normalize_artist(artist))
+      .map_or(&[], |t| t.as_slice())
+  }
+
+  pub fn by_album(
+    &self,
+    album_artist_or_artist: &Option<Interned>,
+    album: &Option<Interned>,
+  ) -> &[Rc<Track>] {
+    self
+      .by_album
+      .get(&(album_artist_or_artist.clone(), album.clone()))
+      .map_or(&[], |t| t.as_slice())
+  }
+
+  // The tracks a facet row stands for
+  pub fn by_facet(&self, facet: &Facet) -> &[Rc<Track>] {
+    if facet.all {
+      &self.tracks
+    } else {
+      self.by_album(&facet.album_artist_or_artist, &facet.album)
+    }
+  }
+
+  // Sorted by artist then title
+  pub fn albums(&self) -> &[Album] {
+    &self.albums
+  }
+
+  // Distinct album artist / album pairs, sorted
+  pub fn facets(&self) -> &[Facet] {
+    &self.facets
+  }
+
+  // Drops files that were deleted, along with any artist, album or facet
+  // they were the last track of
+  pub fn remove(&mut self, filenames: &HashSet<String>) {
+    let keep = |t: &Rc<Track>| !filenames.contains(&t.filename);
+    self.tracks.retain(keep);
+    self.by_filename.retain(|t| keep(&t.0));
+    for tracks in self.by_artist.values_mut() {
+      tracks.retain(keep);
+    }
+    self.by_artist.retain(|_, tracks| !tracks.is_empty());
+    for tracks in self.by_album.values_mut() {
+      tracks.retain(keep);
+    }
+    self.by_album.retain(|_, tracks| !tracks.is_empty());
+    self.albums = group_albums(&self.tracks);
+    let by_album = &self.by_album;
+    self
+      .facets
+      .retain(|f| by_album.contains_key(&(f.album_artist_or_artist.clone(), f.album.clone())));
+  }
+
+  // Points files that were moved at their new names. Returns the renamed
+  // tracks by old filename, so the views can swap in the same ones
+  pub fn rename(&mut self, renames: &HashMap<String, String>) -> HashMap<String, Rc<Track>> {
+    let renamed: HashMap<String, Rc<Track>> = self
+      .tracks
+      .iter()
+      .filter_map(|t| {
+        let to = renames.get(&t.filename)?;
+        let track = Track {
+          filename: to.clone(),
+          ..Track::clone(t)
+        };
+        Some((t.filename.clone(), Rc::new(track)))
+      })
+      .collect();
+    let swap = |t: &mut Rc<Track>| {
+      if let Some(new) = renamed.get(&t.filename) {
+        *t = new.clone();
+      }
+    };
+    self.tracks.iter_mut().for_each(swap);
+    for tracks in self
+      .by_artist
+      .values_mut()
+      .chain(self.by_album.values_mut())
+    {
+      tracks.iter_mut().for_each(swap);
+    }
+    self.by_filename = self.tracks.iter().map(|t| ByFilename(t.clone())).collect();
+    self.albums = group_albums(&self.tracks);
+    renamed
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn track(filename: &str, artist: &str, album: &str) -> Rc<Track> {
+    Rc::new(Track {
+      filename: filename.to_string(),
+      artist: Some(artist.into()),
+      title: None,
+      album: Some(album.into()),
+      genre: None,
+      album_artist: None,
+      track: None,
+      added: None,
+      year: None,
+      duration_ms: None,
+      scan_session_id: None,
+    })
+  }
+
+  #[test]
+  fn removed_files_leave_every_lookup() {
+    let mut library = LibraryIndex::new(
+      vec![
+        track("a1.mp3", "A", "First"),
+        track("a2.mp3", "A", "First"),
+        track("b1.mp3", "B", "Second"),
+      ],
+      &[],
+    );
+    library.remove(&HashSet::from(["a1.mp3".to_string(), "b1.mp3".to_string()]));
+    assert_eq!(library.tracks().len(), 1);
+    assert!(library.get("a1.mp3").is_none());
+    assert_eq!(library.by_artist("A").len(), 1);
+    assert!(library.by_artist("B").is_empty());
+    assert_eq!(library.albums().len(), 1);
+    let albums: Vec<_> = library.facets().iter().map(|f| f.album.clone()).collect();
+    assert_eq!(albums, [Some("First".into())]);
+  }
+
+  #[test]
+  fn renamed_files_are_found_under_their_new_name() {
+    let mut library = LibraryIndex::new(
+      vec![track("a1.mp3", "A", "First"), track("a2.mp3", "A", "First")],
+      &[],
+    );
+    let renamed = library.rename(&HashMap::from([(
+      "a1.mp3".to_string(),
+      "A/First/a1.mp3".to_string(),
+    )]));
+    assert_eq!(renamed["a1.mp3"].filename, "A/First/a1.mp3");
+    assert!(library.get("a1.mp3").is_none());
+    assert!(library.get("A/First/a1.mp3").is_some());
+    let by_artist: Vec<&str> = library
+      .by_artist("A")
+      .iter()
+      .map(|t| t.filename.as_str())
+      .collect();
+    assert_eq!(by_artist, ["A/First/a1.mp3", "a2.mp3"]);
+    assert!(Rc::ptr_eq(
+      &library.albums()[0].tracks[0],
+      &renamed["a1.mp3"]
+    ));
+  }
+}
@@ -0,0 +1,95 @@
+use crate::profile::SpanTimings;
+use chrono::Local;
+use directories::ProjectDirs;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{warn, Event, Level, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+const RECENT_LIMIT: usize = 200;
+const KEPT_LOG_FILES: usize = 7;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+pub fn log_dir() -> PathBuf {
+  let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
+  proj_dirs.data_dir().join("logs")
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+  fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+    if field.name() == "message" {
+      let _ = write!(self.0, "{:?}", value);
+    } else {
+      let _ = write!(self.0, " {}={:?}", field.name(), value);
+    }
+  }
+}
+
+// Keeps the last warnings and errors in memory for the diagnostics dialog
+struct RecentProblems;
+
+impl<S: Subscriber> Layer<S> for RecentProblems {
+  fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    let level = *event.metadata().level();
+    if level > Level::WARN {
+      return;
+    }
+    let mut visitor = MessageVisitor(String::new());
+    event.record(&mut visitor);
+    let line = format!(
+      "{} {} {}: {}",
+      Local::now().format("%Y-%m-%d %H:%M:%S"),
+      level,
+      event.metadata().target(),
+      visitor.0
+    );
+    let mut recent = RECENT.lock().unwrap();
+    if recent.len() == RECENT_LIMIT {
+      recent.pop_front();
+    }
+    recent.push_back(line);
+  }
+}
+
+// Logs to stderr and to a daily file in the data dir, keeping a week of
+// files around. Call once at startup
+pub fn init() {
+  let file_layer = RollingFileAppender::builder()
+    .rotation(Rotation::DAILY)
+    .filename_prefix("fml9000")
+    .filename_suffix("log")
+    .max_log_files(KEPT_LOG_FILES)
+    .build(log_dir())
+    .map(|appender| fmt::layer().with_ansi(false).with_writer(appender));
+  let file_error = file_layer.as_ref().err().map(|e| e.to_string());
+  tracing_subscriber::registry()
+    .with(LevelFilter::INFO)
+    .with(fmt::layer().with_writer(std::io::stderr))
+    .with(file_layer.ok())
+    .with(RecentProblems)
+    .with(SpanTimings)
+    .init();
+  // reported once there is a subscriber, so it reaches stderr and the
+  // diagnostics dialog
+  if let Some(e) = file_error {
+    warn!(
+      "Failed to open log directory {}: {}",
+      log_dir().display(),
+      e
+    );
+  }
+}
+
+pub fn recent_problems() -> Vec<String> {
+  RECENT.lock().unwrap().iter().cloned().collect()
+}
@@ -1,41 +1,126 @@
+mod album_art_dialog;
+mod art_fetch_dialog;
+mod artist_page;
+mod boundaries_dialog;
+mod bpm_playlist_dialog;
+mod cover_cache;
+mod delete_dialog;
+mod diagnostics_dialog;
+mod discord_presence;
 mod facet_box;
+mod focus_mode;
 mod grid_cell;
 mod gtk_helpers;
 mod header_bar;
+mod identify_dialog;
+mod inbox_dialog;
 mod load_css;
+mod mini_player;
+mod new_playlist_dialog;
+mod organize_dialog;
+mod outputs_menu;
+mod player;
 mod playlist_manager;
+mod playlist_stats_dialog;
 mod playlist_view;
 mod preferences_dialog;
+mod properties_dialog;
+mod queue_menu;
+mod related_panel;
+mod remote_dialog;
+mod rename_playlist_dialog;
+mod sessions_menu;
 mod settings;
+mod shortcuts;
+mod sleep_inhibit;
+#[cfg(unix)]
+mod suspend;
+mod transcode_dialog;
+mod verify_dialog;
 
 use adw::prelude::*;
 use adw::Application;
+use discord_presence::add_discord_presence;
 use facet_box::create_facet_box;
-use fml9000::{load_facet_store, load_playlist_store, load_tracks, run_scan};
-use gtk::gio::ListStore;
-use gtk::glib::BoxedAnyObject;
-use gtk::{ApplicationWindow, CustomFilter, Image, Orientation, Paned};
+use fml9000::folders;
+use fml9000::library_index::LibraryIndex;
+use fml9000::metadata::default_providers;
+use fml9000::models::Track;
+use fml9000::profile::{format_timings, record_timing, since_start};
+use fml9000::scan_sessions::session_filenames;
+use fml9000::scanner::ScanOptions;
+use fml9000::sessions::{read_last_session, save_last_session, Session};
+use fml9000::subsonic_server::{
+  forget_tracks, rename_tracks, serve_tracks, start_server, ServerConfig,
+};
+use fml9000::{
+  load_facet_store, load_playlist_store, load_playlist_store_chunked, query_tracks,
+  remove_empty_facets, run_scan,
+};
+use focus_mode::add_focus_mode;
+use gtk::gio::{self, ListStore};
+use gtk::glib::{self, BoxedAnyObject, MainContext, Propagation, SignalHandlerId};
+use gtk::{
+  Align, ApplicationWindow, Button, CustomFilter, Image, Label, Orientation, Paned, ScrolledWindow,
+  Spinner,
+};
+use gtk_helpers::{add_pane_cycling, remove_tracks, replace_renamed};
 use header_bar::create_header_bar;
+use mini_player::create_mini_player_button;
+use outputs_menu::{create_outputs_button, open_saved_zones};
+use player::Player;
 use playlist_manager::create_playlist_manager;
-use playlist_view::create_playlist_view;
+use playlist_view::{create_playlist_pane, create_playlist_view};
+use queue_menu::create_queue_button;
+use related_panel::create_related_panel;
 use rodio::{OutputStream, OutputStreamHandle, Sink};
-use std::cell::RefCell;
+use sessions_menu::{
+  add_session_autosave, create_sessions_button, current_session, restore_session,
+};
+use settings::FmlSettings;
+use shortcuts::add_window_shortcuts;
+use sleep_inhibit::add_sleep_inhibit;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use std::time::Duration;
+use tracing::{info, warn};
 
 const APP_ID: &str = "com.github.fml9000";
 
 fn main() {
+  fml9000::profile::start();
+  fml9000::logging::init();
+  fml9000::init_db();
   let app = Application::builder().application_id(APP_ID).build();
+  app.add_main_option(
+    "profile-startup",
+    glib::Char::from(0),
+    glib::OptionFlags::NONE,
+    glib::OptionArg::None,
+    "Print how long each startup step took",
+    None,
+  );
+  let profile_startup = Rc::new(Cell::new(false));
+  let profile_startup_rc = profile_startup.clone();
+  app.connect_handle_local_options(move |_, options| {
+    profile_startup_rc.set(options.contains("profile-startup"));
+    -1
+  });
   let (_stream, stream_handle) = OutputStream::try_default().unwrap();
 
   let stream_handle_rc = Rc::new(stream_handle);
   app.connect_activate(move |application| {
-    app_main(&application, &stream_handle_rc);
+    app_main(&application, &stream_handle_rc, profile_startup.get());
   });
   app.run();
 }
 
-fn app_main(application: &Application, stream_handle: &Rc<OutputStreamHandle>) {
+fn app_main(
+  application: &Application,
+  stream_handle: &Rc<OutputStreamHandle>,
+  profile_startup: bool,
+) {
   let wnd = ApplicationWindow::builder()
     .default_width(1200)
     .default_height(600)
@@ -44,65 +129,428 @@ fn app_main(application: &Application, stream_handle: &Rc<OutputStreamHandle>) {
     .build();
 
   let wnd_rc = Rc::new(wnd);
-  let wnd_rc1 = wnd_rc.clone();
   let sink_refcell_rc = Rc::new(RefCell::new(Sink::try_new(&stream_handle).unwrap()));
-  let sink_refcell_rc1 = sink_refcell_rc.clone();
 
   let settings_rc = Rc::new(RefCell::new(crate::settings::read_settings()));
+  fml9000::network::set_offline(settings_rc.borrow().offline);
+  if settings_rc.borrow().serve_library {
+    start_library_server(&wnd_rc, &settings_rc.borrow());
+  }
 
   load_css::load_css();
+  load_library(&wnd_rc, &sink_refcell_rc, &settings_rc, profile_startup);
+}
+
+// Runs until the window closes. Only once per start, since rescans refill
+// the library it serves rather than rebuilding it
+fn start_library_server(wnd: &ApplicationWindow, settings: &FmlSettings) {
+  if settings.serve_password.is_empty() {
+    warn!("Not serving the library: set a password for it in preferences");
+    return;
+  }
+  let config = ServerConfig {
+    port: settings.serve_port,
+    user: settings.serve_user.clone(),
+    password: settings.serve_password.clone(),
+  };
+  match start_server(config) {
+    Ok(service) => {
+      wnd.connect_destroy(move |_| service.stop());
+    }
+    Err(e) => warn!("Failed to start the Subsonic server: {}", e),
+  }
+}
+
+// Scans the library folders and loads what is in them, for running on a
+// worker thread. Comes back with the tracks and the scan sessions the new
+// ones came in with
+fn scan_library(settings: &FmlSettings) -> impl FnOnce() -> (Vec<Track>, Vec<i32>) + Send {
+  let folders = settings.folders.clone();
+  let disabled = settings.disabled_folders.clone();
+  let extra_formats = settings.extra_formats.clone();
+  let options = ScanOptions {
+    checksums: settings.checksums,
+    fingerprints: settings.fingerprints,
+  };
+  move || {
+    use std::time::Instant;
+    let now = Instant::now();
+
+    let providers = default_providers(&extra_formats);
+    let new_sessions: Vec<i32> = folders
+      .iter()
+      .filter(|folder| !disabled.contains(folder))
+      .filter_map(|folder| run_scan(folder, &query_tracks(), &providers, options))
+      .collect();
+
+    let elapsed = now.elapsed();
+    info!("Loaded library in {:.2?}", elapsed);
+    // tracks under disabled folders stay in the database for when the
+    // folder comes back
+    let rows = query_tracks()
+      .into_iter()
+      .filter(|t| !disabled.iter().any(|f| folders::contains(f, &t.filename)))
+      .collect::<Vec<_>>();
+    // the library server, if running, offers the same tracks
+    serve_tracks(&rows);
+    (rows, new_sessions)
+  }
+}
+
+// Scans the library folders behind a spinner, then swaps in the main UI
+fn load_library(
+  wnd_rc: &Rc<ApplicationWindow>,
+  sink_refcell_rc: &Rc<RefCell<Sink>>,
+  settings_rc: &Rc<RefCell<FmlSettings>>,
+  profile_startup: bool,
+) {
+  let spinner = Spinner::builder()
+    .spinning(true)
+    .width_request(48)
+    .height_request(48)
+    .build();
+  let placeholder = gtk::Box::builder()
+    .orientation(Orientation::Vertical)
+    .halign(Align::Center)
+    .valign(Align::Center)
+    .build();
+  placeholder.append(&spinner);
+  placeholder.append(&Label::new(Some("Loading library...")));
+  wnd_rc.set_child(Some(&placeholder));
+  wnd_rc.present();
+
+  let scan = scan_library(&settings_rc.borrow());
+  let wnd_rc = wnd_rc.clone();
+  let sink_refcell_rc = sink_refcell_rc.clone();
+  let settings_rc = settings_rc.clone();
+  MainContext::default().spawn_local(async move {
+    // Track rows are plain data until they get back to the main thread, where
+    // they are wrapped in Rc for sharing between the views
+    let (rows, new_sessions) = gio::spawn_blocking(scan)
+      .await
+      .expect("Failed to load library");
+
+    build_main_ui(
+      &wnd_rc,
+      &sink_refcell_rc,
+      &settings_rc,
+      rows.into_iter().map(Rc::new).collect(),
+      new_sessions,
+    );
+
+    wnd_rc.add_tick_callback(move |_, _| {
+      record_timing("first_frame", since_start());
+      if profile_startup {
+        info!("Startup timings:\n{}", format_timings());
+      }
+      glib::ControlFlow::Break
+    });
+  });
+}
+
+// Reports the startup scan, offering to play whatever it found
+fn notify_scan(
+  wnd: &ApplicationWindow,
+  sessions: &[i32],
+  player: &Rc<Player>,
+  playlist_store: &ListStore,
+  library: &LibraryIndex,
+) {
+  let filenames: Vec<String> = sessions
+    .iter()
+    .flat_map(|session| session_filenames(*session))
+    .collect();
+  let new_tracks = library.by_filenames(&filenames);
+  let toast = adw::Toast::builder()
+    .title(format!("Scan complete: {} new tracks", new_tracks.len()))
+    .button_label("Play new additions")
+    .action_name("win.play-new-additions")
+    .build();
+
+  let play = gio::SimpleAction::new("play-new-additions", None);
+  play.connect_activate(glib::clone!(
+    #[weak]
+    playlist_store,
+    #[strong]
+    player,
+    move |_, _| {
+      playlist_store.remove_all();
+      load_playlist_store(new_tracks.iter(), &playlist_store);
+      player.take_context();
+      if let Some(track) = new_tracks.first() {
+        player.play_track(track);
+      }
+    }
+  ));
+  wnd.add_action(&play);
+
+  if let Some(overlay) = wnd.child().and_downcast::<adw::ToastOverlay>() {
+    overlay.add_toast(toast);
+  }
+}
+
+// The view only reaches its full height once it is laid out, so the offset
+// is applied as soon as the scroll range is big enough to hold it
+fn restore_scroll(scrolled_wnd: &ScrolledWindow, scroll: f64) {
+  let handler: Rc<RefCell<Option<SignalHandlerId>>> = Rc::new(RefCell::new(None));
+  let handler_rc = handler.clone();
+  let id = scrolled_wnd.vadjustment().connect_changed(move |adj| {
+    if adj.upper() - adj.page_size() < scroll {
+      return;
+    }
+    adj.set_value(scroll);
+    if let Some(id) = handler_rc.take() {
+      adj.disconnect(id);
+    }
+  });
+  handler.replace(Some(id));
+}
+
+// Shown in place of the browser while there is nothing in the library.
+// "Scan now" scans behind the page and hands what it found to scanned
+fn create_empty_library(
+  wnd_rc: &Rc<ApplicationWindow>,
+  settings_rc: &Rc<RefCell<FmlSettings>>,
+  scanned: impl Fn(Vec<Rc<Track>>, Vec<i32>) + 'static,
+) -> adw::StatusPage {
+  let add_button = Button::builder()
+    .label("Add folders...")
+    .css_classes(["pill", "suggested-action"])
+    .build();
+  let scan_button = Button::builder()
+    .label("Scan now")
+    .css_classes(["pill"])
+    .build();
+  let buttons = gtk::Box::builder()
+    .orientation(Orientation::Horizontal)
+    .spacing(12)
+    .halign(Align::Center)
+    .build();
+  buttons.append(&add_button);
+  buttons.append(&scan_button);
 
+  let wnd = wnd_rc.clone();
+  let settings = settings_rc.clone();
+  add_button.connect_clicked(move |_| {
+    MainContext::default().spawn_local(crate::preferences_dialog::dialog(
+      Rc::clone(&wnd),
+      Rc::clone(&settings),
+    ));
+  });
+  let settings = settings_rc.clone();
+  let scanned = Rc::new(scanned);
+  scan_button.connect_clicked(move |button| {
+    button.set_sensitive(false);
+    button.set_label("Scanning...");
+    let scan = scan_library(&settings.borrow());
+    MainContext::default().spawn_local(glib::clone!(
+      #[weak]
+      button,
+      #[strong]
+      scanned,
+      async move {
+        let (rows, new_sessions) = gio::spawn_blocking(scan)
+          .await
+          .expect("Failed to load library");
+        button.set_sensitive(true);
+        button.set_label("Scan now");
+        scanned(rows.into_iter().map(Rc::new).collect(), new_sessions);
+      }
+    ));
+  });
+
+  adw::StatusPage::builder()
+    .icon_name("folder-music-symbolic")
+    .title("Your library is empty")
+    .description("Add the folders your music lives in, then scan them")
+    .child(&buttons)
+    .build()
+}
+
+// After a crash the track that was playing can be picked back up, even
+// with resuming on startup turned off
+fn offer_resume(
+  wnd: &ApplicationWindow,
+  session: &Session,
+  player: &Rc<Player>,
+  library: &LibraryIndex,
+) {
+  let Some(track) = session.current.as_ref().and_then(|f| library.get(f)) else {
+    return;
+  };
+  let toast = adw::Toast::builder()
+    .title("Resume where you left off?")
+    .button_label("Resume")
+    .action_name("win.resume-session")
+    .timeout(0)
+    .build();
+
+  let resume = gio::SimpleAction::new("resume-session", None);
+  let track = track.clone();
+  let position = Duration::from_secs_f64(session.position);
+  resume.connect_activate(glib::clone!(
+    #[strong]
+    player,
+    move |_, _| {
+      player.play_track(&track);
+      player.seek(position);
+    }
+  ));
+  wnd.add_action(&resume);
+
+  if let Some(overlay) = wnd.child().and_downcast::<adw::ToastOverlay>() {
+    overlay.add_toast(toast);
+  }
+}
+
+// Files deleted from disk leave the library index and every view of it
+fn add_forget_action(
+  wnd: &ApplicationWindow,
+  library: &Rc<RefCell<LibraryIndex>>,
+  facet_store: &ListStore,
+  playlist_store: &ListStore,
+  player: &Rc<Player>,
+) {
+  let forget = gio::SimpleAction::new("forget-files", Some(&Vec::<String>::static_variant_type()));
+  forget.connect_activate(glib::clone!(
+    #[strong]
+    library,
+    #[weak]
+    facet_store,
+    #[weak]
+    playlist_store,
+    #[strong]
+    player,
+    move |_, param| {
+      let Some(filenames) = param.and_then(|p| p.get::<Vec<String>>()) else {
+        return;
+      };
+      let filenames: HashSet<String> = filenames.into_iter().collect();
+      library.borrow_mut().remove(&filenames);
+      remove_empty_facets(&library.borrow(), &facet_store);
+      remove_tracks(&playlist_store, &filenames);
+      player.forget(&filenames);
+      gio::spawn_blocking(move || forget_tracks(&filenames));
+    }
+  ));
+  wnd.add_action(&forget);
+}
+
+// Files moved by organize take their new names in the library index and
+// every view of it
+fn add_rename_action(
+  wnd: &ApplicationWindow,
+  library: &Rc<RefCell<LibraryIndex>>,
+  playlist_store: &ListStore,
+  player: &Rc<Player>,
+) {
+  let rename = gio::SimpleAction::new(
+    "rename-files",
+    Some(&Vec::<(String, String)>::static_variant_type()),
+  );
+  rename.connect_activate(glib::clone!(
+    #[strong]
+    library,
+    #[weak]
+    playlist_store,
+    #[strong]
+    player,
+    move |_, param| {
+      let Some(moved) = param.and_then(|p| p.get::<Vec<(String, String)>>()) else {
+        return;
+      };
+      let renames: HashMap<String, String> = moved.into_iter().collect();
+      let renamed = library.borrow_mut().rename(&renames);
+      replace_renamed(&playlist_store, &renamed);
+      player.rename(&renamed);
+      gio::spawn_blocking(move || rename_tracks(&renames));
+    }
+  ));
+  wnd.add_action(&rename);
+}
+
+fn build_main_ui(
+  wnd_rc: &Rc<ApplicationWindow>,
+  sink_refcell_rc: &Rc<RefCell<Sink>>,
+  settings_rc: &Rc<RefCell<FmlSettings>>,
+  rows: Vec<Rc<Track>>,
+  new_sessions: Vec<i32>,
+) {
   let filter = CustomFilter::new(|_| true);
   let playlist_store = ListStore::new::<BoxedAnyObject>();
   let playlist_mgr_store = ListStore::new::<BoxedAnyObject>();
   let album_art = Image::builder().vexpand(true).build();
   let album_art_rc = Rc::new(album_art);
-  let album_art_rc1 = album_art_rc.clone();
-  let rows_rc = Rc::new(load_tracks());
-  let rows_rc1 = rows_rc.clone();
-  let rows_rc2 = rows_rc.clone();
-
-  use std::time::Instant;
-  let now = Instant::now();
-
-  {
-    let s = settings_rc.borrow();
-    match &s.folder {
-      Some(folder) => {
-        run_scan(&folder, &rows_rc2);
-      }
-      None => {}
-    }
-  }
-
-  let elapsed = now.elapsed();
-  println!("Elapsed: {:.2?}", elapsed);
+  let library = Rc::new(RefCell::new(LibraryIndex::new(
+    rows,
+    &settings_rc.borrow().artist_separators,
+  )));
 
   let facet_store = ListStore::new::<BoxedAnyObject>();
-  load_playlist_store(rows_rc.iter(), &playlist_store);
-  load_facet_store(&rows_rc1, &facet_store);
+  load_facet_store(&library.borrow(), &facet_store);
+  // the view comes back as it was left, or shows the whole library
+  let last_session = read_last_session().filter(|s| !s.filenames.is_empty());
+  if last_session.is_none() {
+    MainContext::default().spawn_local(load_playlist_store_chunked(
+      library.borrow().tracks().to_vec(),
+      playlist_store.clone(),
+      || true,
+    ));
+  }
 
+  let player = Player::new(sink_refcell_rc, &album_art_rc, wnd_rc);
+  player.start_auto_advance();
+  open_saved_zones(&player, settings_rc);
+  add_discord_presence(&player, settings_rc);
+  add_sleep_inhibit(&player, wnd_rc, settings_rc);
+  #[cfg(unix)]
+  suspend::add_suspend_pause(&player, settings_rc);
+  // the user playlist in the playlist view, if that's what it shows
+  let shown_playlist = Rc::new(Cell::new(None));
   let playlist_wnd = create_playlist_view(
     playlist_store.clone(),
-    &sink_refcell_rc,
-    &album_art_rc1,
-    &wnd_rc1,
+    playlist_mgr_store.clone(),
+    &shown_playlist,
+    &player,
+    wnd_rc,
+    settings_rc,
+  );
+  let playlist_mgr_wnd = create_playlist_manager(
+    &playlist_mgr_store,
+    &playlist_store,
+    &shown_playlist,
+    &library,
+    wnd_rc,
+  );
+  let facet_box = create_facet_box(
+    playlist_store.clone(),
+    facet_store.clone(),
+    filter,
+    &library,
+    &player,
+    wnd_rc,
+    settings_rc,
   );
-  let playlist_mgr_wnd = create_playlist_manager(&playlist_mgr_store);
-  let facet_box = create_facet_box(playlist_store, facet_store, filter, &rows_rc);
 
   let ltopbottom = Paned::builder()
     .vexpand(true)
     .orientation(Orientation::Vertical)
     .start_child(&facet_box)
-    .end_child(&playlist_wnd)
+    .end_child(&create_playlist_pane(&playlist_wnd, &playlist_store))
+    .build();
+
+  let now_playing = Paned::builder()
+    .vexpand(true)
+    .orientation(Orientation::Vertical)
+    .start_child(&*album_art_rc)
+    .end_child(&create_related_panel(&player, &library))
     .build();
 
   let rtopbottom = Paned::builder()
     .vexpand(true)
     .orientation(Orientation::Vertical)
     .start_child(&playlist_mgr_wnd)
-    .end_child(&*album_art_rc)
+    .end_child(&now_playing)
     .build();
 
   let lrpane = Paned::builder()
@@ -111,13 +559,129 @@ fn app_main(application: &Application, stream_handle: &Rc<OutputStreamHandle>) {
     .start_child(&ltopbottom)
     .end_child(&rtopbottom)
     .build();
+  if library.borrow().tracks().is_empty() {
+    // a scan from the empty page fills the views already built, and puts
+    // the browser back once there is something to browse
+    let scanned = glib::clone!(
+      #[strong]
+      library,
+      #[weak]
+      facet_store,
+      #[weak]
+      playlist_store,
+      #[weak]
+      lrpane,
+      #[strong]
+      ltopbottom,
+      #[strong]
+      player,
+      #[strong]
+      wnd_rc,
+      #[strong]
+      settings_rc,
+      move |rows: Vec<Rc<Track>>, new_sessions: Vec<i32>| {
+        if rows.is_empty() {
+          return;
+        }
+        *library.borrow_mut() = LibraryIndex::new(rows, &settings_rc.borrow().artist_separators);
+        facet_store.remove_all();
+        load_facet_store(&library.borrow(), &facet_store);
+        if playlist_store.n_items() == 0 {
+          MainContext::default().spawn_local(load_playlist_store_chunked(
+            library.borrow().tracks().to_vec(),
+            playlist_store.clone(),
+            || true,
+          ));
+        }
+        lrpane.set_start_child(Some(&ltopbottom));
+        if !new_sessions.is_empty() {
+          notify_scan(
+            &wnd_rc,
+            &new_sessions,
+            &player,
+            &playlist_store,
+            &library.borrow(),
+          );
+        }
+      }
+    );
+    lrpane.set_start_child(Some(&create_empty_library(wnd_rc, settings_rc, scanned)));
+  }
 
   let main_ui = gtk::Box::new(Orientation::Vertical, 0);
 
-  let button_box = create_header_bar(settings_rc, sink_refcell_rc1, &wnd_rc);
+  let button_box = create_header_bar(settings_rc.clone(), &player, wnd_rc);
+  button_box.append(&create_sessions_button(&player, &playlist_store, &library));
+  button_box.append(&create_queue_button(&player, &playlist_mgr_store, wnd_rc));
+  button_box.append(&create_mini_player_button(&player, wnd_rc));
+  button_box.append(&create_outputs_button(&player, settings_rc));
 
   main_ui.append(&button_box);
-  main_ui.append(&lrpane);
-  wnd_rc.set_child(Some(&main_ui));
-  wnd_rc.present();
+  main_ui.append(&add_focus_mode(
+    wnd_rc,
+    &lrpane,
+    &player,
+    &playlist_mgr_store,
+    settings_rc,
+  ));
+  let toast_overlay = adw::ToastOverlay::new();
+  toast_overlay.set_child(Some(&main_ui));
+  wnd_rc.set_child(Some(&toast_overlay));
+  if !new_sessions.is_empty() {
+    notify_scan(
+      wnd_rc,
+      &new_sessions,
+      &player,
+      &playlist_store,
+      &library.borrow(),
+    );
+  }
+  if let Some(session) = last_session {
+    let resume = settings_rc.borrow().resume_playback;
+    restore_session(
+      &session,
+      &player,
+      &playlist_store,
+      &library.borrow(),
+      resume,
+    );
+    restore_scroll(&playlist_wnd, session.scroll);
+    if !resume && !session.clean_exit {
+      offer_resume(wnd_rc, &session, &player, &library.borrow());
+    }
+  }
+  add_forget_action(wnd_rc, &library, &facet_store, &playlist_store, &player);
+  add_rename_action(wnd_rc, &library, &playlist_store, &player);
+  add_session_autosave(&player, &playlist_store, &playlist_wnd);
+  wnd_rc.connect_close_request(glib::clone!(
+    #[weak]
+    playlist_store,
+    #[weak]
+    playlist_wnd,
+    #[strong]
+    player,
+    #[upgrade_or]
+    Propagation::Proceed,
+    move |_| {
+      let session = Session {
+        scroll: playlist_wnd.vadjustment().value(),
+        clean_exit: true,
+        ..current_session("Last session", &player, &playlist_store)
+      };
+      if let Err(e) = save_last_session(&session) {
+        warn!("Failed to save session: {}", e);
+      }
+      Propagation::Proceed
+    }
+  ));
+
+  add_window_shortcuts(wnd_rc);
+  add_pane_cycling(
+    wnd_rc,
+    vec![
+      facet_box.upcast(),
+      playlist_wnd.upcast(),
+      playlist_mgr_wnd.upcast(),
+    ],
+  );
 }
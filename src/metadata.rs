@@ -0,0 +1,172 @@
+use crate::continuous::GAPLESS_TAGS;
+use lofty::error::ErrorKind;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::prelude::Accessor;
+use lofty::probe::Probe;
+use lofty::tag::ItemKey;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tracing::warn;
+
+// Tracker modules and game music that lofty doesn't read. ffprobe can get
+// at least a title and duration out of most of them
+pub const FFPROBE_EXTENSIONS: [&str; 12] = [
+  "mod", "xm", "s3m", "it", "mptm", "sid", "nsf", "spc", "vgm", "vgz", "gbs", "ay",
+];
+
+// What the scanner stores for a file
+#[derive(Default)]
+pub struct Metadata {
+  pub artist: Option<String>,
+  pub title: Option<String>,
+  pub album: Option<String>,
+  pub album_artist: Option<String>,
+  pub track: Option<String>,
+  pub genre: Option<String>,
+  pub year: Option<i32>,
+  pub duration_ms: Option<i32>,
+  // tagged for gapless playback, see continuous
+  pub gapless: bool,
+}
+
+pub trait MetadataProvider {
+  // None when the provider doesn't handle the file, so the next one is
+  // tried
+  fn read(&self, path: &Path) -> Option<Metadata>;
+}
+
+pub struct Lofty;
+
+impl MetadataProvider for Lofty {
+  fn read(&self, path: &Path) -> Option<Metadata> {
+    let tagged_file = match Probe::open(path).and_then(|p| p.read()) {
+      Ok(tagged_file) => tagged_file,
+      // cover images, playlists and the like are skipped quietly
+      Err(e) if matches!(e.kind(), ErrorKind::UnknownFormat) => return None,
+      Err(e) => {
+        warn!("Failed to read {}: {}", path.display(), e);
+        return None;
+      }
+    };
+    let duration_ms = Some(tagged_file.properties().duration().as_millis() as i32);
+    // untagged files are still tracks, the ones identify is for
+    let Some(t) = tagged_file
+      .primary_tag()
+      .or_else(|| tagged_file.first_tag())
+    else {
+      return Some(Metadata {
+        duration_ms,
+        ..Default::default()
+      });
+    };
+    let gapless = t.items().any(|item| match item.key() {
+      ItemKey::Unknown(key) => {
+        GAPLESS_TAGS.iter().any(|g| g.eq_ignore_ascii_case(key)) && item.value().text() == Some("1")
+      }
+      _ => false,
+    });
+    Some(Metadata {
+      artist: t.artist().map(|s| s.to_string()),
+      title: t.title().map(|s| s.to_string()),
+      album: t.album().map(|s| s.to_string()),
+      album_artist: t.get_string(&ItemKey::AlbumArtist).map(|s| s.to_string()),
+      track: t.get_string(&ItemKey::TrackNumber).map(|s| s.to_string()),
+      genre: t.genre().map(|s| s.to_string()),
+      year: t.year().map(|y| y as i32),
+      duration_ms,
+      gapless,
+    })
+  }
+}
+
+// Falls back to ffprobe for an allowlist of extensions. Does nothing when
+// ffprobe isn't installed
+pub struct Ffprobe {
+  extensions: Vec<String>,
+}
+
+impl Ffprobe {
+  // extra_extensions extends FFPROBE_EXTENSIONS, e.g. from preferences
+  pub fn new(extra_extensions: &[String]) -> Self {
+    let extensions = FFPROBE_EXTENSIONS
+      .iter()
+      .map(|e| e.to_string())
+      .chain(
+        extra_extensions
+          .iter()
+          .map(|e| e.trim_start_matches('.').to_lowercase()),
+      )
+      .collect();
+    Ffprobe { extensions }
+  }
+
+  fn handles(&self, path: &Path) -> bool {
+    path
+      .extension()
+      .map(|e| e.to_string_lossy().to_lowercase())
+      .is_some_and(|e| self.extensions.contains(&e))
+  }
+}
+
+impl MetadataProvider for Ffprobe {
+  fn read(&self, path: &Path) -> Option<Metadata> {
+    if !self.handles(path) {
+      return None;
+    }
+    let output = Command::new("ffprobe")
+      .args([
+        "-v",
+        "quiet",
+        "-show_entries",
+        "format=duration:format_tags",
+        "-of",
+        "default=noprint_wrappers=1",
+      ])
+      .arg(path)
+      .stdin(Stdio::null())
+      .output()
+      .ok()
+      .filter(|o| o.status.success())?;
+    Some(parse_ffprobe(&String::from_utf8_lossy(&output.stdout)))
+  }
+}
+
+// Lines like "duration=183.2" and "TAG:title=Foo", with tag names in
+// whatever case the file has them
+fn parse_ffprobe(output: &str) -> Metadata {
+  let mut m = Metadata::default();
+  for line in output.lines() {
+    let Some((key, value)) = line.split_once('=') else {
+      continue;
+    };
+    let value = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+    match key.to_lowercase().as_str() {
+      "duration" => {
+        m.duration_ms = value
+          .and_then(|v| v.parse::<f64>().ok())
+          .map(|s| (s * 1000.0) as i32)
+      }
+      "tag:title" => m.title = value,
+      "tag:artist" | "tag:author" => m.artist = m.artist.take().or(value),
+      "tag:album" | "tag:game" => m.album = m.album.take().or(value),
+      "tag:album_artist" => m.album_artist = value,
+      "tag:track" => m.track = value,
+      "tag:genre" => m.genre = value,
+      "tag:itunesgapless" | "tag:itunpgap" => m.gapless = value.as_deref() == Some("1"),
+      "tag:date" | "tag:year" => {
+        m.year = value.and_then(|v| v.get(..4).and_then(|y| y.parse().ok()))
+      }
+      _ => (),
+    }
+  }
+  m
+}
+
+pub fn default_providers(extra_extensions: &[String]) -> Vec<Box<dyn MetadataProvider>> {
+  vec![Box::new(Lofty), Box::new(Ffprobe::new(extra_extensions))]
+}
+
+// The first provider that can read the file wins
+pub fn read_metadata(providers: &[Box<dyn MetadataProvider>], path: &Path) -> Option<Metadata> {
+  providers.iter().find_map(|p| p.read(path))
+}
@@ -0,0 +1,163 @@
+use crate::gtk_helpers::str_or_unknown;
+use crate::player::Player;
+use adw::prelude::*;
+use fml9000::models::Track;
+use gtk::glib::{self, Propagation};
+use gtk::{Adjustment, ApplicationWindow, Button, Image, Label, Orientation, Scale, ToggleButton};
+use std::rc::Rc;
+use std::time::Duration;
+
+const ART_SIZE: i32 = 96;
+
+fn transport_button(icon: &str, tooltip: &str) -> Button {
+  Button::builder()
+    .icon_name(icon)
+    .tooltip_text(tooltip)
+    .build()
+}
+
+fn show_track(title: &Label, seek: &Scale, track: &Track, length: Duration) {
+  title.set_label(&format!(
+    "{}\n{}",
+    str_or_unknown(&track.title),
+    str_or_unknown(&track.artist)
+  ));
+  let duration = length.as_secs_f64();
+  seek.adjustment().set_upper(duration);
+  seek.set_sensitive(duration > 0.0);
+}
+
+// A small window with the now playing track and transport controls that
+// drives the same Player as the main window. It is kept above the main
+// window while open, since GTK4 leaves always-on-top to the window manager
+fn create_mini_player(player: &Rc<Player>, wnd: &Rc<ApplicationWindow>) -> gtk::Window {
+  let art = Image::builder().pixel_size(ART_SIZE).build();
+  let title = Label::builder()
+    .label("Nothing playing")
+    .xalign(0.0)
+    .hexpand(true)
+    .ellipsize(gtk::pango::EllipsizeMode::End)
+    .build();
+  let seek = Scale::builder()
+    .orientation(Orientation::Horizontal)
+    .adjustment(&Adjustment::new(0.0, 0.0, 1.0, 1.0, 10.0, 0.0))
+    .sensitive(false)
+    .build();
+
+  let prev_btn = transport_button("media-skip-backward-symbolic", "Previous");
+  let play_btn = transport_button("media-playback-start-symbolic", "Play");
+  let pause_btn = transport_button("media-playback-pause-symbolic", "Pause");
+  let next_btn = transport_button("media-skip-forward-symbolic", "Next");
+  let controls = gtk::Box::new(Orientation::Horizontal, 0);
+  controls.append(&prev_btn);
+  controls.append(&play_btn);
+  controls.append(&pause_btn);
+  controls.append(&next_btn);
+
+  let info = gtk::Box::new(Orientation::Vertical, 4);
+  info.append(&title);
+  info.append(&controls);
+  info.append(&seek);
+  let f = gtk::Box::builder()
+    .orientation(Orientation::Horizontal)
+    .spacing(8)
+    .margin_start(8)
+    .margin_end(8)
+    .margin_top(8)
+    .margin_bottom(8)
+    .build();
+  f.append(&art);
+  f.append(&info);
+
+  let mini_player = gtk::Window::builder()
+    .transient_for(&**wnd)
+    .title("fml9000")
+    .default_width(360)
+    .resizable(false)
+    .hide_on_close(true)
+    .destroy_with_parent(true)
+    .child(&f)
+    .build();
+
+  if let Some(track) = player.current.borrow().as_ref() {
+    show_track(&title, &seek, track, player.duration());
+  }
+  let player_weak = Rc::downgrade(player);
+  player.connect_track_changed(glib::clone!(
+    #[weak]
+    title,
+    #[weak]
+    seek,
+    move |track| {
+      if let Some(player) = player_weak.upgrade() {
+        show_track(&title, &seek, track, player.duration());
+      }
+    }
+  ));
+  art.set_paintable(player.covers.current().as_ref());
+  player.covers.connect_changed(glib::clone!(
+    #[weak]
+    art,
+    move |texture| art.set_paintable(texture)
+  ));
+
+  let player_rc = player.clone();
+  prev_btn.connect_clicked(move |_| {
+    player_rc.play_prev();
+  });
+  let player_rc = player.clone();
+  play_btn.connect_clicked(move |_| player_rc.resume());
+  let player_rc = player.clone();
+  pause_btn.connect_clicked(move |_| player_rc.pause());
+  let player_rc = player.clone();
+  next_btn.connect_clicked(move |_| {
+    player_rc.play_next();
+  });
+
+  let player_rc = player.clone();
+  seek.connect_change_value(move |_, _, value| {
+    player_rc.seek(Duration::from_secs_f64(value.max(0.0)));
+    Propagation::Proceed
+  });
+
+  // follow playback while the window is open
+  let player_rc = player.clone();
+  glib::timeout_add_local(
+    Duration::from_millis(500),
+    glib::clone!(
+      #[weak]
+      mini_player,
+      #[weak]
+      seek,
+      #[upgrade_or]
+      glib::ControlFlow::Break,
+      move || {
+        if mini_player.is_visible() {
+          seek.set_value(player_rc.position().as_secs_f64());
+        }
+        glib::ControlFlow::Continue
+      }
+    ),
+  );
+  mini_player
+}
+
+pub fn create_mini_player_button(player: &Rc<Player>, wnd: &Rc<ApplicationWindow>) -> ToggleButton {
+  let mini_player = create_mini_player(player, wnd);
+  let mini_btn = ToggleButton::builder()
+    .icon_name("view-restore-symbolic")
+    .tooltip_text("Mini player")
+    .build();
+  mini_btn.connect_toggled(glib::clone!(
+    #[weak]
+    mini_player,
+    move |btn| mini_player.set_visible(btn.is_active())
+  ));
+  // closing the window itself pops the button back out
+  mini_player.connect_visible_notify(glib::clone!(
+    #[weak]
+    mini_btn,
+    move |w| mini_btn.set_active(w.is_visible())
+  ));
+  mini_btn
+}
@@ -1,17 +1,36 @@
-use crate::schema::{recently_played, tracks};
+use crate::interned::Interned;
+use crate::schema::{playlist_tracks, playlists, recently_played, scan_sessions, tracks};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
-#[derive(Queryable)]
+// Tags that repeat from track to track are interned, track numbers
+// included. Titles and filenames mostly don't repeat
+#[derive(Clone, Queryable)]
 pub struct Track {
   pub filename: String,
-  pub artist: Option<String>,
+  pub artist: Option<Interned>,
   pub title: Option<String>,
-  pub album: Option<String>,
-  pub genre: Option<String>,
-  pub album_artist: Option<String>,
-  pub track: Option<String>,
+  pub album: Option<Interned>,
+  pub genre: Option<Interned>,
+  pub album_artist: Option<Interned>,
+  pub track: Option<Interned>,
   pub added: Option<NaiveDateTime>,
+  pub year: Option<i32>,
+  pub duration_ms: Option<i32>,
+  pub scan_session_id: Option<i32>,
+}
+
+impl Track {
+  // The leading number of the track tag, so "3/12" and "03" are both 3
+  pub fn track_number(&self) -> Option<u32> {
+    let digits: String = self
+      .track
+      .as_ref()?
+      .chars()
+      .take_while(|c| c.is_ascii_digit())
+      .collect();
+    digits.parse().ok()
+  }
 }
 
 #[derive(Queryable)]
@@ -30,6 +49,9 @@ pub struct NewTrack<'a> {
   pub genre: Option<&'a str>,
   pub track: Option<&'a str>,
   pub album_artist: Option<&'a str>,
+  pub year: Option<i32>,
+  pub duration_ms: Option<i32>,
+  pub scan_session_id: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -37,3 +59,37 @@ pub struct NewTrack<'a> {
 pub struct NewRecentlyPlayed<'a> {
   pub filename: &'a str,
 }
+
+#[derive(AsChangeset)]
+#[diesel(table_name = tracks)]
+pub struct TagUpdate {
+  pub artist: Option<String>,
+  pub title: Option<String>,
+  pub album: Option<String>,
+}
+
+#[derive(Queryable)]
+pub struct UserPlaylist {
+  pub id: i32,
+  pub name: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = playlists)]
+pub struct NewPlaylist<'a> {
+  pub name: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = playlist_tracks)]
+pub struct NewPlaylistTrack<'a> {
+  pub playlist_id: i32,
+  pub filename: &'a str,
+  pub position: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = scan_sessions)]
+pub struct NewScanSession<'a> {
+  pub folder: &'a str,
+}
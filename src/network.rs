@@ -0,0 +1,143 @@
+use gtk::gio;
+use gtk::glib::{Uri, UriFlags};
+use gtk::prelude::*;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+
+// Everything that goes online checks this first, so nothing touches the
+// network while offline mode is on
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_offline(offline: bool) {
+  OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+  OFFLINE.load(Ordering::Relaxed)
+}
+
+// Blocking, run on a worker thread. None when offline or the request fails
+pub fn download(url: &str) -> Option<Vec<u8>> {
+  if is_offline() {
+    return None;
+  }
+  match gio::File::for_uri(url).load_contents(gio::Cancellable::NONE) {
+    Ok((data, _)) => Some(data.to_vec()),
+    Err(_) => None,
+  }
+}
+
+// How the app introduces itself to web services that ask clients to,
+// MusicBrainz among them
+pub const USER_AGENT: &str = concat!(
+  "fml9000/",
+  env!("CARGO_PKG_VERSION"),
+  " ( https://github.com/cmdcolin/fml9000 )"
+);
+
+// redirects followed before giving up, Cover Art Archive takes two
+const MAX_REDIRECTS: usize = 5;
+const TIMEOUT_SECS: u32 = 30;
+
+enum Response {
+  Body(Vec<u8>),
+  Redirect(String),
+}
+
+// A whole HTTP/1.0 response, which comes without chunked encoding and ends
+// when the server closes the connection
+fn parse_response(data: &[u8]) -> Result<Response, String> {
+  let end = data
+    .windows(4)
+    .position(|w| w == b"\r\n\r\n")
+    .ok_or("Incomplete response")?;
+  let head = String::from_utf8_lossy(&data[..end]);
+  let mut lines = head.lines();
+  let status = lines
+    .next()
+    .and_then(|l| l.split_whitespace().nth(1))
+    .and_then(|s| s.parse::<u16>().ok())
+    .ok_or("Malformed status line")?;
+  match status {
+    200 => Ok(Response::Body(data[end + 4..].to_vec())),
+    301 | 302 | 303 | 307 | 308 => lines
+      .filter_map(|l| l.split_once(':'))
+      .find(|(name, _)| name.eq_ignore_ascii_case("location"))
+      .map(|(_, value)| Response::Redirect(value.trim().to_string()))
+      .ok_or_else(|| "Redirect without a location".to_string()),
+    _ => Err(format!("HTTP {}", status)),
+  }
+}
+
+fn get(uri: &Uri) -> Result<Response, String> {
+  let https = uri.scheme() == "https";
+  let client = gio::SocketClient::new();
+  client.set_tls(https);
+  client.set_timeout(TIMEOUT_SECS);
+  let connection = client
+    .connect_to_uri(
+      &uri.to_str(),
+      if https { 443 } else { 80 },
+      gio::Cancellable::NONE,
+    )
+    .map_err(|e| e.to_string())?;
+  let target = match uri.query() {
+    Some(query) => format!("{}?{}", uri.path(), query),
+    None => uri.path().to_string(),
+  };
+  let request = format!(
+    "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: {}\r\nAccept: */*\r\n\r\n",
+    target,
+    uri.host().unwrap_or_default(),
+    USER_AGENT
+  );
+  let mut out = connection.output_stream().into_write();
+  out
+    .write_all(request.as_bytes())
+    .map_err(|e| e.to_string())?;
+  let mut data = Vec::new();
+  connection
+    .input_stream()
+    .into_read()
+    .read_to_end(&mut data)
+    .map_err(|e| e.to_string())?;
+  parse_response(&data)
+}
+
+// download for web services that want to know who is asking: sends
+// USER_AGENT and follows redirects. Blocking, run on a worker thread
+pub fn download_as_app(url: &str) -> Option<Vec<u8>> {
+  if is_offline() {
+    return None;
+  }
+  let mut uri = Uri::parse(url, UriFlags::NONE).ok()?;
+  for _ in 0..=MAX_REDIRECTS {
+    match get(&uri) {
+      Ok(Response::Body(body)) => return Some(body),
+      Ok(Response::Redirect(location)) => {
+        uri = uri.parse_relative(&location, UriFlags::NONE).ok()?
+      }
+      Err(e) => {
+        warn!("Failed to download {}: {}", uri.to_str(), e);
+        return None;
+      }
+    }
+  }
+  warn!("Too many redirects from {}", url);
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_the_body_or_where_to_go_instead() {
+    let ok = parse_response(b"HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\n\r\n\xff\xd8");
+    assert!(matches!(ok, Ok(Response::Body(body)) if body == b"\xff\xd8"));
+    let moved = parse_response(b"HTTP/1.1 307 Temporary Redirect\r\nlocation: /next\r\n\r\n");
+    assert!(matches!(moved, Ok(Response::Redirect(to)) if to == "/next"));
+    assert!(parse_response(b"HTTP/1.1 503 Service Unavailable\r\n\r\n").is_err());
+  }
+}
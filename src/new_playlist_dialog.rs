@@ -0,0 +1,60 @@
+use crate::gtk_helpers::show_toast;
+use crate::playlist_manager::load_playlist_mgr_store;
+use adw::prelude::*;
+use fml9000::playlists::save_as_playlist;
+use gtk::gio::ListStore;
+use gtk::glib;
+use gtk::{Button, Entry, Orientation};
+use std::rc::Rc;
+
+// Creates a playlist from the entered name and adds the given files to it
+pub async fn dialog<W: IsA<gtk::Window>>(
+  wnd: Rc<W>,
+  filenames: Vec<String>,
+  playlist_mgr_store: ListStore,
+) {
+  let f = gtk::Box::new(Orientation::Horizontal, 0);
+
+  let create_button = Button::builder().label("Create").build();
+  let textbox = Entry::builder()
+    .placeholder_text("Playlist name")
+    .hexpand(true)
+    .activates_default(true)
+    .build();
+
+  f.append(&textbox);
+  f.append(&create_button);
+  let new_playlist_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(400)
+    .title("New playlist")
+    .child(&f)
+    .default_widget(&create_button)
+    .build();
+
+  create_button.connect_clicked(glib::clone!(
+    #[weak]
+    textbox,
+    #[weak]
+    new_playlist_dialog,
+    move |_| {
+      let name = textbox.text();
+      if name.is_empty() {
+        return;
+      }
+      match save_as_playlist(&name, &filenames) {
+        Ok(_) => {
+          load_playlist_mgr_store(&playlist_mgr_store);
+          show_toast(&textbox, &format!("Created playlist {}", name));
+        }
+        Err(e) => show_toast(
+          &textbox,
+          &format!("Failed to create playlist {}: {}", name, e),
+        ),
+      }
+      new_playlist_dialog.close();
+    }
+  ));
+  new_playlist_dialog.present();
+}
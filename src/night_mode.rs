@@ -0,0 +1,98 @@
+use rodio::source::SeekError;
+use rodio::{Sample, Source};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Levels above THRESHOLD are reduced by RATIO, then everything is lifted by
+// MAKEUP_GAIN, so quiet passages come up while peaks come down
+const THRESHOLD: f32 = 0.25;
+const RATIO: f32 = 4.0;
+const MAKEUP_GAIN: f32 = 2.0;
+// how fast the envelope falls back after a peak, per sample. It rises
+// instantly, so the makeup gain can't push a sudden peak into clipping
+const RELEASE: f32 = 0.0001;
+
+// Whether hour (0-23) falls in a night from start to end, which may wrap
+// past midnight, e.g. 22 to 7
+pub fn in_night_hours(hour: u32, start: u32, end: u32) -> bool {
+  if start <= end {
+    (start..end).contains(&hour)
+  } else {
+    hour >= start || hour < end
+  }
+}
+
+// A compressor that can be switched on and off mid-track. The flag is
+// shared with the UI, and while it is off samples pass through untouched
+pub struct Compressed<S> {
+  input: S,
+  enabled: Arc<AtomicBool>,
+  envelope: f32,
+}
+
+pub fn compress<S>(input: S, enabled: Arc<AtomicBool>) -> Compressed<S> {
+  Compressed {
+    input,
+    enabled,
+    envelope: 0.0,
+  }
+}
+
+impl<S> Iterator for Compressed<S>
+where
+  S: Source,
+  S::Item: Sample,
+{
+  type Item = S::Item;
+
+  fn next(&mut self) -> Option<S::Item> {
+    let sample = self.input.next()?;
+    if !self.enabled.load(Ordering::Relaxed) {
+      self.envelope = 0.0;
+      return Some(sample);
+    }
+    let level = sample.to_f32().abs();
+    self.envelope = if level > self.envelope {
+      level
+    } else {
+      self.envelope + (level - self.envelope) * RELEASE
+    };
+    let gain = if self.envelope > THRESHOLD {
+      (THRESHOLD / self.envelope).powf(1.0 - 1.0 / RATIO)
+    } else {
+      1.0
+    };
+    Some(sample.amplify(gain * MAKEUP_GAIN))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.input.size_hint()
+  }
+}
+
+impl<S> Source for Compressed<S>
+where
+  S: Source,
+  S::Item: Sample,
+{
+  fn current_frame_len(&self) -> Option<usize> {
+    self.input.current_frame_len()
+  }
+
+  fn channels(&self) -> u16 {
+    self.input.channels()
+  }
+
+  fn sample_rate(&self) -> u32 {
+    self.input.sample_rate()
+  }
+
+  fn total_duration(&self) -> Option<Duration> {
+    self.input.total_duration()
+  }
+
+  fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+    self.input.try_seek(pos)
+  }
+}
@@ -0,0 +1,50 @@
+use crate::connect_db;
+use crate::schema::track_offsets;
+use crate::writer::write_db;
+use diesel::prelude::*;
+use std::time::Duration;
+use tracing::error;
+
+// Where playback of a track starts and stops, for skipping long intros and
+// outros. The default plays the whole file
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Offsets {
+  pub start: Duration,
+  pub end: Option<Duration>,
+}
+
+pub fn load_offsets(filename: &str) -> Offsets {
+  let conn = &mut connect_db();
+  let row: Option<(i32, Option<i32>)> = track_offsets::table
+    .filter(track_offsets::filename.eq(filename))
+    .select((track_offsets::start_ms, track_offsets::end_ms))
+    .first(conn)
+    .optional()
+    .unwrap_or_else(|e| {
+      error!("Failed to load offsets of {}: {}", filename, e);
+      None
+    });
+  row.map_or(Offsets::default(), |(start_ms, end_ms)| Offsets {
+    start: Duration::from_millis(start_ms.max(0) as u64),
+    end: end_ms.map(|ms| Duration::from_millis(ms.max(0) as u64)),
+  })
+}
+
+// The default offsets remove the row rather than storing it
+pub fn save_offsets(filename: &str, offsets: &Offsets) -> QueryResult<()> {
+  write_db(|conn| {
+    if *offsets == Offsets::default() {
+      diesel::delete(track_offsets::table.filter(track_offsets::filename.eq(filename)))
+        .execute(conn)?;
+    } else {
+      diesel::replace_into(track_offsets::table)
+        .values((
+          track_offsets::filename.eq(filename),
+          track_offsets::start_ms.eq(offsets.start.as_millis() as i32),
+          track_offsets::end_ms.eq(offsets.end.map(|d| d.as_millis() as i32)),
+        ))
+        .execute(conn)?;
+    }
+    Ok(())
+  })
+}
@@ -0,0 +1,241 @@
+use crate::models::Track;
+use crate::platform::{self, is_reserved_name};
+use crate::writer::write_db;
+use diesel::prelude::*;
+use regex::{Captures, Regex};
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tracing::{error, warn};
+
+pub const DEFAULT_PATTERN: &str = "{album_artist}/{year} - {album}/{track:02} {title}.{ext}";
+
+pub struct Move {
+  pub from: String,
+  pub to: PathBuf,
+}
+
+// Tag values end up as single path components, so path separators and
+// characters that are reserved on common filesystems are replaced
+fn sanitize(s: &str) -> String {
+  let s: String = s
+    .chars()
+    .map(|c| match c {
+      '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .collect();
+  let s = s.trim().trim_end_matches('.');
+  if s.is_empty() {
+    "Unknown".to_string()
+  } else {
+    s.to_string()
+  }
+}
+
+fn field(track: &Track, name: &str) -> Option<String> {
+  match name {
+    "artist" => track.artist.as_deref().map(str::to_string),
+    "album_artist" => track
+      .album_artist
+      .as_deref()
+      .or(track.artist.as_deref())
+      .map(str::to_string),
+    "album" => track.album.as_deref().map(str::to_string),
+    "title" => track.title.clone(),
+    "genre" => track.genre.as_deref().map(str::to_string),
+    "year" => track.year.map(|y| y.to_string()),
+    "track" => track.track_number().map(|n| n.to_string()),
+    "ext" => Path::new(&track.filename)
+      .extension()
+      .map(|e| e.to_string_lossy().to_string()),
+    _ => None,
+  }
+}
+
+pub fn render_pattern(pattern: &str, track: &Track) -> PathBuf {
+  let re = Regex::new(r"\{(\w+)(?::0?(\d+))?\}").unwrap();
+  let mut path = PathBuf::new();
+  for component in pattern.split('/').filter(|c| !c.is_empty()) {
+    let rendered = re.replace_all(component, |caps: &Captures| {
+      let name = &caps[1];
+      match (name, caps.get(2)) {
+        ("track", Some(width)) => match track.track_number() {
+          Some(n) => format!("{:0width$}", n, width = width.as_str().parse().unwrap_or(0)),
+          None => "00".to_string(),
+        },
+        _ => sanitize(&field(track, name).unwrap_or_default()),
+      }
+    });
+    // Windows can't create files or folders named like devices, e.g. CON.mp3
+    if is_reserved_name(&rendered) {
+      path.push(format!("_{}", rendered));
+    } else {
+      path.push(rendered.as_ref());
+    }
+  }
+  path
+}
+
+fn with_suffix(path: &Path, n: u32) -> PathBuf {
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+  let name = match path.extension() {
+    Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+    None => format!("{} ({})", stem, n),
+  };
+  path.with_file_name(name)
+}
+
+// Computes where each track would go without touching the disk, so the result
+// doubles as the dry-run preview. Destinations that already exist or that two
+// tracks would share get a numbered suffix. Sharing ignores case, since
+// "Foo/a.flac" and "foo/A.flac" are the same file on macOS and Windows
+pub fn plan_moves(tracks: &[Rc<Track>], root: &Path, pattern: &str) -> Vec<Move> {
+  let taken_key = |p: &Path| p.to_string_lossy().to_lowercase();
+  let mut taken = HashSet::new();
+  let mut moves = Vec::new();
+  for track in tracks {
+    let dest = root.join(render_pattern(pattern, track));
+    if dest == Path::new(&track.filename) {
+      continue;
+    }
+    let mut to = dest.clone();
+    let mut n = 2;
+    while taken.contains(&taken_key(&to)) || to.exists() {
+      to = with_suffix(&dest, n);
+      n += 1;
+    }
+    taken.insert(taken_key(&to));
+    moves.push(Move {
+      from: track.filename.clone(),
+      to,
+    });
+  }
+  moves
+}
+
+pub(crate) fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+  if let Some(parent) = to.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  match std::fs::rename(from, to) {
+    // rename can't cross filesystems, copy instead. Either way the file ends
+    // up in one place, never both
+    Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+      let copied = std::fs::copy(from, to).and_then(|_| std::fs::remove_file(from));
+      if copied.is_err() {
+        std::fs::remove_file(to).ok();
+      }
+      copied
+    }
+    result => result,
+  }
+}
+
+// Run it inside write_db, so it's all or nothing and a file is never left
+// under its old name in some tables
+pub fn rename_in_db(conn: &mut SqliteConnection, from: &str, to: &str) -> QueryResult<()> {
+  use crate::schema::{
+    file_checksums, fingerprints, playlist_tracks, plays, recently_played, saved_chapters,
+    track_bpm, track_key, track_offsets, tracks,
+  };
+  diesel::update(tracks::table.filter(tracks::filename.eq(from)))
+    .set(tracks::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(recently_played::table.filter(recently_played::filename.eq(from)))
+    .set(recently_played::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(plays::table.filter(plays::filename.eq(from)))
+    .set(plays::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(playlist_tracks::table.filter(playlist_tracks::filename.eq(from)))
+    .set(playlist_tracks::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(track_offsets::table.filter(track_offsets::filename.eq(from)))
+    .set(track_offsets::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(file_checksums::table.filter(file_checksums::filename.eq(from)))
+    .set(file_checksums::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(fingerprints::table.filter(fingerprints::filename.eq(from)))
+    .set(fingerprints::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(track_bpm::table.filter(track_bpm::filename.eq(from)))
+    .set(track_bpm::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(track_key::table.filter(track_key::filename.eq(from)))
+    .set(track_key::filename.eq(to))
+    .execute(conn)?;
+  diesel::update(saved_chapters::table.filter(saved_chapters::filename.eq(from)))
+    .set(saved_chapters::filename.eq(to))
+    .execute(conn)?;
+  Ok(())
+}
+
+// Returns the old and new filename of each file that was moved. A file whose
+// database update fails is moved back, so the library never points at a
+// file that isn't there
+pub fn apply_moves(moves: &[Move]) -> Vec<(String, String)> {
+  let mut moved = Vec::new();
+  for m in moves {
+    let to = platform::library_path(&m.to);
+    if let Err(e) = move_file(Path::new(&m.from), &m.to) {
+      warn!("Failed to move {} to {}: {}", m.from, to, e);
+      continue;
+    }
+    match write_db(|conn| rename_in_db(conn, &m.from, &to)) {
+      Ok(()) => moved.push((m.from.clone(), to)),
+      Err(e) => {
+        error!("Failed to update {} in database: {}", m.from, e);
+        if let Err(e) = move_file(&m.to, Path::new(&m.from)) {
+          error!("Failed to move {} back to {}: {}", to, m.from, e);
+        }
+      }
+    }
+  }
+  moved
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn track(filename: &str, title: &str) -> Rc<Track> {
+    Rc::new(Track {
+      filename: filename.to_string(),
+      artist: Some("Artist".into()),
+      title: Some(title.to_string()),
+      album: None,
+      genre: None,
+      album_artist: None,
+      track: None,
+      added: None,
+      year: None,
+      duration_ms: None,
+      scan_session_id: None,
+    })
+  }
+
+  #[test]
+  fn destinations_differing_only_in_case_get_a_suffix() {
+    let tracks = [track("/in/1.flac", "Song"), track("/in/2.flac", "SONG")];
+    let moves = plan_moves(&tracks, Path::new("/out"), "{title}.{ext}");
+    let to: Vec<&Path> = moves.iter().map(|m| m.to.as_path()).collect();
+    assert_eq!(
+      to,
+      [Path::new("/out/Song.flac"), Path::new("/out/SONG (2).flac")]
+    );
+  }
+
+  #[test]
+  fn failed_move_leaves_nothing_behind() {
+    let dir = TempDir::new().unwrap();
+    let to = dir.path().join("moved/a.flac");
+    let result = move_file(&dir.path().join("missing.flac"), &to);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!to.exists());
+  }
+}
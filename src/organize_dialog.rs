@@ -0,0 +1,119 @@
+use crate::settings::{write_settings, FmlSettings};
+use adw::prelude::*;
+use fml9000::models::Track;
+use fml9000::organize::{apply_moves, plan_moves};
+use gtk::gio;
+use gtk::glib::{self, MainContext};
+use gtk::{Button, Entry, Orientation, ScrolledWindow, TextView};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+pub async fn dialog<W: IsA<gtk::Window>>(
+  wnd: Rc<W>,
+  settings: Rc<RefCell<FmlSettings>>,
+  tracks: Vec<Rc<Track>>,
+) {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let button_row = gtk::Box::new(Orientation::Horizontal, 0);
+
+  let pattern_entry = Entry::builder()
+    .text(&settings.borrow().organize_pattern)
+    .hexpand(true)
+    .build();
+  let preview_button = Button::builder().label("Preview").build();
+  let apply_button = Button::builder().label("Apply").build();
+  let preview = TextView::builder().editable(false).monospace(true).build();
+  let preview_wnd = ScrolledWindow::builder()
+    .child(&preview)
+    .vexpand(true)
+    .build();
+
+  button_row.append(&pattern_entry);
+  button_row.append(&preview_button);
+  button_row.append(&apply_button);
+  f.append(&button_row);
+  f.append(&preview_wnd);
+  let organize_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(1000)
+    .default_height(600)
+    .title(format!("Organize {} files", tracks.len()))
+    .child(&f)
+    .build();
+
+  let tracks = Rc::new(tracks);
+  let tracks1 = tracks.clone();
+  preview_button.connect_clicked(glib::clone!(
+    #[weak]
+    pattern_entry,
+    #[weak]
+    preview,
+    #[weak]
+    settings,
+    move |_| {
+      let text = match settings.borrow().folders.first() {
+        Some(folder) => plan_moves(&tracks1, Path::new(folder), &pattern_entry.text())
+          .iter()
+          .map(|m| format!("{}\n  -> {}\n", m.from, m.to.display()))
+          .collect(),
+        None => "Set a music folder in preferences first".to_string(),
+      };
+      preview.buffer().set_text(&text);
+    }
+  ));
+
+  apply_button.connect_clicked(glib::clone!(
+    #[strong]
+    wnd,
+    #[weak]
+    pattern_entry,
+    #[weak]
+    preview,
+    #[weak]
+    settings,
+    move |button| {
+      let mut s = settings.borrow_mut();
+      s.organize_pattern = pattern_entry.text().to_string();
+      write_settings(&s).expect("Failed to write");
+      let Some(folder) = s.folders.first() else {
+        preview
+          .buffer()
+          .set_text("Set a music folder in preferences first");
+        return;
+      };
+      let moves = plan_moves(&tracks, Path::new(folder), &pattern_entry.text());
+      let total = moves.len();
+      preview
+        .buffer()
+        .set_text(&format!("Moving {} files...", total));
+      // moving across filesystems copies whole files, so it runs off the
+      // main thread
+      button.set_sensitive(false);
+      MainContext::default().spawn_local(glib::clone!(
+        #[strong]
+        wnd,
+        #[weak]
+        preview,
+        #[weak]
+        button,
+        async move {
+          let moved = gio::spawn_blocking(move || apply_moves(&moves))
+            .await
+            .unwrap_or_default();
+          preview
+            .buffer()
+            .set_text(&format!("Moved {} of {} files", moved.len(), total));
+          // the main window points the library and everything showing it at
+          // the new names
+          let _ = wnd
+            .upcast_ref::<gtk::Window>()
+            .activate_action("win.rename-files", Some(&moved.to_variant()));
+          button.set_sensitive(true);
+        }
+      ));
+    }
+  ));
+  organize_dialog.present();
+}
@@ -0,0 +1,181 @@
+use crate::gtk_helpers::show_toast;
+use crate::player::Player;
+use crate::settings::{write_settings, FmlSettings, ZoneSettings};
+use adw::prelude::*;
+use fml9000::zones::{open_zone, output_devices};
+use gtk::glib;
+use gtk::{CheckButton, Label, MenuButton, Orientation, Popover, SpinButton};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use tracing::warn;
+
+fn open(zone: &ZoneSettings) -> Result<fml9000::zones::Zone, String> {
+  open_zone(
+    &zone.device,
+    zone.volume as f32,
+    Duration::from_millis(zone.delay_ms as u64),
+  )
+}
+
+// Reopens the zones that were on last time. Ones whose device is gone stay
+// in the settings, so they come back when it does
+pub fn open_saved_zones(player: &Rc<Player>, settings: &Rc<RefCell<FmlSettings>>) {
+  for zone in settings.borrow().zones.iter() {
+    match open(zone) {
+      Ok(zone) => player.add_zone(zone),
+      Err(e) => warn!("Failed to open {}: {}", zone.device, e),
+    }
+  }
+}
+
+fn create_output_row(
+  device: &str,
+  player: &Rc<Player>,
+  settings: &Rc<RefCell<FmlSettings>>,
+) -> gtk::Box {
+  let saved = settings
+    .borrow()
+    .zones
+    .iter()
+    .find(|z| z.device == device)
+    .cloned();
+  let zone = saved.clone().unwrap_or(ZoneSettings {
+    device: device.to_string(),
+    volume: 1.0,
+    delay_ms: 0,
+  });
+  let row = gtk::Box::new(Orientation::Horizontal, 6);
+  let check = CheckButton::builder()
+    .label(device)
+    .active(saved.is_some())
+    .hexpand(true)
+    .build();
+  let volume_spin = SpinButton::with_range(0.0, 100.0, 5.0);
+  volume_spin.set_value((zone.volume * 100.0).round());
+  volume_spin.set_tooltip_text(Some("Volume (%)"));
+  let delay_spin = SpinButton::with_range(0.0, 2000.0, 10.0);
+  delay_spin.set_value(zone.delay_ms as f64);
+  delay_spin.set_tooltip_text(Some("Delay (ms), from the next track on"));
+  row.append(&check);
+  row.append(&volume_spin);
+  row.append(&delay_spin);
+
+  // the zone as the row currently has it
+  let device = device.to_string();
+  let current = glib::clone!(
+    #[weak]
+    volume_spin,
+    #[weak]
+    delay_spin,
+    #[upgrade_or_panic]
+    move || ZoneSettings {
+      device: device.clone(),
+      volume: volume_spin.value() / 100.0,
+      delay_ms: delay_spin.value() as u32,
+    }
+  );
+
+  let current1 = current.clone();
+  check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    #[strong]
+    player,
+    move |b| {
+      let zone = current1();
+      let mut s = settings.borrow_mut();
+      s.zones.retain(|z| z.device != zone.device);
+      player.remove_zone(&zone.device);
+      if b.is_active() {
+        match open(&zone) {
+          Ok(opened) => {
+            player.add_zone(opened);
+            s.zones.push(zone);
+          }
+          Err(e) => {
+            show_toast(b, &format!("Failed to open {}: {}", zone.device, e));
+            drop(s);
+            b.set_active(false);
+            return;
+          }
+        }
+      }
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+
+  let changed = move |settings: &RefCell<FmlSettings>, player: &Player| {
+    let zone = current();
+    player.tune_zone(
+      &zone.device,
+      zone.volume as f32,
+      Duration::from_millis(zone.delay_ms as u64),
+    );
+    let mut s = settings.borrow_mut();
+    if let Some(saved) = s.zones.iter_mut().find(|z| z.device == zone.device) {
+      *saved = zone;
+      write_settings(&s).expect("Failed to write");
+    }
+  };
+  let changed = Rc::new(changed);
+  let changed1 = changed.clone();
+  volume_spin.connect_value_changed(glib::clone!(
+    #[weak]
+    settings,
+    #[strong]
+    player,
+    move |_| changed1(&settings, &player)
+  ));
+  delay_spin.connect_value_changed(glib::clone!(
+    #[weak]
+    settings,
+    #[strong]
+    player,
+    move |_| changed(&settings, &player)
+  ));
+  row
+}
+
+fn fill_outputs_list(list: &gtk::Box, player: &Rc<Player>, settings: &Rc<RefCell<FmlSettings>>) {
+  while let Some(child) = list.first_child() {
+    list.remove(&child);
+  }
+  let devices = output_devices();
+  if devices.is_empty() {
+    list.append(&Label::new(Some("No outputs found")));
+  }
+  for device in devices {
+    list.append(&create_output_row(&device, player, settings));
+  }
+}
+
+// Extra outputs that play along with the main one, each with its own volume
+// and a delay to line it up with the others. The device list is read again
+// each time the menu opens, so newly plugged in outputs show up
+pub fn create_outputs_button(
+  player: &Rc<Player>,
+  settings: &Rc<RefCell<FmlSettings>>,
+) -> MenuButton {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let outputs_list = gtk::Box::new(Orientation::Vertical, 0);
+  f.append(&Label::new(Some("Also play on (volume %, delay ms)")));
+  f.append(&outputs_list);
+
+  let popover = Popover::builder().child(&f).build();
+  let outputs_btn = MenuButton::builder()
+    .label("Outputs")
+    .popover(&popover)
+    .build();
+
+  popover.connect_show(glib::clone!(
+    #[weak]
+    outputs_list,
+    #[strong]
+    player,
+    #[strong]
+    settings,
+    move |_| fill_outputs_list(&outputs_list, &player, &settings)
+  ));
+  outputs_btn
+}
@@ -0,0 +1,25 @@
+use std::path::Path;
+
+// Names Windows keeps for devices, with or without an extension
+const RESERVED_NAMES: [&str; 22] = [
+  "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+  "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// The form filenames are stored in the library under. On Windows a music
+// folder set with forward slashes would otherwise give paths with mixed
+// separators that never match what was stored before, so every file would
+// look new on each scan
+pub fn library_path(path: &Path) -> String {
+  let path = path.display().to_string();
+  if cfg!(windows) {
+    path.replace('/', "\\")
+  } else {
+    path
+  }
+}
+
+pub fn is_reserved_name(name: &str) -> bool {
+  let stem = name.split('.').next().unwrap_or(name).trim_end();
+  RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem))
+}
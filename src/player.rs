@@ -0,0 +1,744 @@
+use crate::cover_cache::CoverCache;
+use crate::gtk_helpers::{
+  get_album_artist_or_artist, remove_tracks, replace_renamed, show_toast, str_or_unknown,
+};
+use adw::prelude::*;
+use fml9000::albums::{album_key, AlbumKey};
+use fml9000::continuous::load_continuous_albums;
+use fml9000::levels::{meter, Levels};
+use fml9000::models::Track;
+use fml9000::night_mode::compress;
+use fml9000::offsets::{load_offsets, Offsets};
+use fml9000::plays::record_play;
+use fml9000::queue;
+use fml9000::zones::Zone;
+use gtk::gio::{ListModel, ListStore};
+use gtk::glib::{self, BoxedAnyObject, Object};
+use gtk::{ApplicationWindow, CustomFilter, FilterListModel, Image};
+use rodio::{Decoder, Sink, Source};
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+const HISTORY_LIMIT: usize = 500;
+// how close to the end of a track the next one's album art is loaded
+const PRELOAD_AHEAD: Duration = Duration::from_secs(15);
+// how far into a track it counts as played, or half way for shorter ones,
+// so skipped tracks don't
+const PLAYED_AFTER: Duration = Duration::from_secs(30);
+
+type TrackChanged = Box<dyn Fn(&Rc<Track>)>;
+type Stopped = Box<dyn Fn()>;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShuffleMode {
+  Off,
+  Tracks,
+  // picks a random album and plays it through in view order
+  Albums,
+}
+
+impl ShuffleMode {
+  pub fn cycle(self) -> ShuffleMode {
+    match self {
+      ShuffleMode::Off => ShuffleMode::Tracks,
+      ShuffleMode::Tracks => ShuffleMode::Albums,
+      ShuffleMode::Albums => ShuffleMode::Off,
+    }
+  }
+
+  pub fn label(self) -> &'static str {
+    match self {
+      ShuffleMode::Off => "Shuffle: off",
+      ShuffleMode::Tracks => "Shuffle: tracks",
+      ShuffleMode::Albums => "Shuffle: albums",
+    }
+  }
+
+  pub fn icon_name(self) -> &'static str {
+    match self {
+      ShuffleMode::Off => "media-playlist-consecutive-symbolic",
+      ShuffleMode::Tracks => "media-playlist-shuffle-symbolic",
+      ShuffleMode::Albums => "media-optical-symbolic",
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RepeatMode {
+  Off,
+  // starts over from the top of the list after the last track
+  All,
+  One,
+}
+
+impl RepeatMode {
+  pub fn cycle(self) -> RepeatMode {
+    match self {
+      RepeatMode::Off => RepeatMode::All,
+      RepeatMode::All => RepeatMode::One,
+      RepeatMode::One => RepeatMode::Off,
+    }
+  }
+
+  pub fn label(self) -> &'static str {
+    match self {
+      RepeatMode::Off => "Repeat: off",
+      RepeatMode::All => "Repeat: all",
+      RepeatMode::One => "Repeat: one",
+    }
+  }
+
+  pub fn icon_name(self) -> &'static str {
+    match self {
+      RepeatMode::One => "media-playlist-repeat-song-symbolic",
+      _ => "media-playlist-repeat-symbolic",
+    }
+  }
+}
+
+fn decode(filename: &str) -> Result<Decoder<BufReader<File>>, String> {
+  let file = File::open(filename).map_err(|e| format!("Failed to open {}: {}", filename, e))?;
+  Decoder::new(BufReader::new(file)).map_err(|e| format!("Failed to decode {}: {}", filename, e))
+}
+
+fn length(track: &Track) -> Duration {
+  Duration::from_millis(track.duration_ms.unwrap_or(0).max(0) as u64)
+}
+
+fn same_album(a: &Track, b: &Track) -> bool {
+  get_album_artist_or_artist(a) == get_album_artist_or_artist(b) && a.album == b.album
+}
+
+pub struct Player {
+  pub sink: Rc<RefCell<Sink>>,
+  pub current: RefCell<Option<Rc<Track>>>,
+  pub queue: RefCell<VecDeque<Rc<Track>>>,
+  // tracks in the order they were actually played, for previous in shuffle
+  history: RefCell<Vec<Rc<Track>>>,
+  pub shuffle: Cell<ShuffleMode>,
+  pub repeat: Cell<RepeatMode>,
+  pub stop_after: Cell<bool>,
+  // albums that shuffle plays straight through, see continuous
+  pub continuous: RefCell<HashSet<AlbumKey>>,
+  // peak and clipping of what is being played, reset for each track
+  pub levels: Arc<Levels>,
+  // compresses whatever is playing while set, see night_mode
+  pub night_mode: Arc<AtomicBool>,
+  // outputs playing along with the main sink, see zones
+  pub zones: RefCell<Vec<Zone>>,
+  // where the current track's end offset, if it has one, cuts it short
+  end: Cell<Option<Duration>>,
+  // the current track's length as the decoder reports it, for tracks
+  // scanned before lengths were stored
+  decoded_length: Cell<Option<Duration>>,
+  // whether the current track has been recorded as played yet
+  played: Cell<bool>,
+  // where current_pos last found the current track
+  last_pos: Cell<Option<u32>>,
+  // the playlist view's live sorted model, a snapshot of it taken when
+  // playback started from the view, and the one next/prev actually walk,
+  // which is narrower while only the selection is being played
+  view_model: RefCell<Option<ListModel>>,
+  context: RefCell<Option<ListModel>>,
+  model: RefCell<Option<ListModel>>,
+  // the current track's album art, with the next one's loaded ahead
+  pub covers: Rc<CoverCache>,
+  wnd: Rc<ApplicationWindow>,
+  track_changed: RefCell<Vec<TrackChanged>>,
+  stopped: RefCell<Vec<Stopped>>,
+}
+
+impl Player {
+  pub fn new(
+    sink: &Rc<RefCell<Sink>>,
+    album_art: &Rc<Image>,
+    wnd: &Rc<ApplicationWindow>,
+  ) -> Rc<Self> {
+    let covers = Rc::new(CoverCache::default());
+    let album_art = album_art.clone();
+    covers.connect_changed(move |texture| album_art.set_paintable(texture));
+    Rc::new(Player {
+      sink: sink.clone(),
+      current: RefCell::new(None),
+      queue: RefCell::new(VecDeque::new()),
+      history: RefCell::new(Vec::new()),
+      shuffle: Cell::new(ShuffleMode::Off),
+      repeat: Cell::new(RepeatMode::Off),
+      stop_after: Cell::new(false),
+      continuous: RefCell::new(load_continuous_albums()),
+      levels: Arc::new(Levels::default()),
+      night_mode: Arc::new(AtomicBool::new(false)),
+      zones: RefCell::new(Vec::new()),
+      end: Cell::new(None),
+      decoded_length: Cell::new(None),
+      played: Cell::new(false),
+      last_pos: Cell::new(None),
+      view_model: RefCell::new(None),
+      context: RefCell::new(None),
+      model: RefCell::new(None),
+      covers,
+      wnd: wnd.clone(),
+      track_changed: RefCell::new(Vec::new()),
+      stopped: RefCell::new(Vec::new()),
+    })
+  }
+
+  pub fn play_track(&self, track: &Rc<Track>) {
+    if let Some(prev) = self.current.borrow().clone() {
+      let mut history = self.history.borrow_mut();
+      if history.len() >= HISTORY_LIMIT {
+        history.remove(0);
+      }
+      history.push(prev);
+    }
+    self.start_track(track);
+  }
+
+  // current is set even if the file can't be played, so auto-advance skips
+  // past it instead of retrying it
+  fn start_track(&self, track: &Rc<Track>) {
+    // whichever way playback moves on, stop after was for the track before
+    self.stop_after.set(false);
+    self.current.replace(Some(track.clone()));
+    self.decoded_length.set(None);
+    self.played.set(false);
+    let source = match decode(&track.filename) {
+      Ok(source) => source,
+      Err(message) => {
+        warn!("{}", message);
+        show_toast(&*self.wnd, &message);
+        // the previous track would otherwise play on under this one's name
+        self.sink.borrow().stop();
+        for zone in self.zones.borrow().iter() {
+          zone.sink.stop();
+        }
+        return;
+      }
+    };
+
+    let sink = self.sink.borrow_mut();
+    if !sink.empty() {
+      sink.stop();
+    }
+
+    // kill and recreate sink, xref
+    // https://github.com/betta-cyber/netease-music-tui/pull/27/
+    // https://github.com/RustAudio/rodio/issues/315
+    sink.stop();
+    self.levels.reset();
+    self.decoded_length.set(source.total_duration());
+    sink.append(meter(
+      compress(source, self.night_mode.clone()),
+      self.levels.clone(),
+    ));
+    sink.play();
+    let offsets = load_offsets(&track.filename);
+    if !offsets.start.is_zero() {
+      if let Err(e) = sink.try_seek(offsets.start) {
+        warn!("Failed to skip to the start offset: {}", e);
+      }
+    }
+    self.end.set(offsets.end);
+    drop(sink);
+    for zone in self.zones.borrow().iter() {
+      self.start_zone(zone, &track.filename, offsets.start);
+    }
+
+    self.covers.show(&track.filename);
+
+    self.wnd.set_title(Some(&format!(
+      "fml9000 // {} - {} - {}",
+      str_or_unknown(&track.artist),
+      str_or_unknown(&track.album),
+      str_or_unknown(&track.title),
+    )));
+
+    for f in self.track_changed.borrow().iter() {
+      f(track);
+    }
+  }
+
+  // Zones decode the file separately, so one that fails only goes quiet
+  fn start_zone(&self, zone: &Zone, filename: &str, pos: Duration) {
+    zone.sink.stop();
+    let source = match decode(filename) {
+      Ok(source) => source,
+      Err(message) => {
+        warn!("{}: {}", zone.device, message);
+        return;
+      }
+    };
+    zone
+      .sink
+      .append(compress(source, self.night_mode.clone()).delay(zone.delay));
+    if !pos.is_zero() {
+      if let Err(e) = zone.sink.try_seek(pos) {
+        warn!("Failed to seek on {}: {}", zone.device, e);
+      }
+    }
+    if self.sink.borrow().is_paused() {
+      zone.sink.pause();
+    } else {
+      zone.sink.play();
+    }
+  }
+
+  // Joins in on the current track where the main output is
+  pub fn add_zone(&self, zone: Zone) {
+    if let Some(track) = self.current.borrow().as_ref() {
+      self.start_zone(&zone, &track.filename, self.position());
+    }
+    self.zones.borrow_mut().push(zone);
+  }
+
+  pub fn remove_zone(&self, device: &str) {
+    self.zones.borrow_mut().retain(|z| z.device != device);
+  }
+
+  // The volume changes right away, the delay from the next track on
+  pub fn tune_zone(&self, device: &str, volume: f32, delay: Duration) {
+    for zone in self.zones.borrow_mut().iter_mut() {
+      if zone.device == device {
+        zone.sink.set_volume(volume);
+        zone.delay = delay;
+      }
+    }
+  }
+
+  // Called whenever a new track starts playing
+  pub fn connect_track_changed(&self, f: impl Fn(&Rc<Track>) + 'static) {
+    self.track_changed.borrow_mut().push(Box::new(f));
+  }
+
+  pub fn connect_stopped(&self, f: impl Fn() + 'static) {
+    self.stopped.borrow_mut().push(Box::new(f));
+  }
+
+  // What plays next as far as it can be known: the queue, then the tracks
+  // after the current one unless shuffling
+  pub fn upcoming(&self, n: usize) -> Vec<Rc<Track>> {
+    let mut upcoming: Vec<Rc<Track>> = self.queue.borrow().iter().take(n).cloned().collect();
+    if self.shuffle.get() != ShuffleMode::Off {
+      return upcoming;
+    }
+    if let Some(model) = self.active_model() {
+      let start = self.current_pos(&model).map_or(0, |pos| pos + 1);
+      let rest = (start..model.n_items()).map_while(|i| Self::track_at(&model, i));
+      upcoming.extend(rest.take(n - upcoming.len()));
+    }
+    upcoming
+  }
+
+  // Time left until playback runs out: the rest of the current track, then
+  // everything upcoming. None while shuffle or repeat keep it going.
+  // Upcoming tracks the scan couldn't get a length for count as zero. Walks
+  // the rest of the view, so it is only worked out while the queue is shown
+  pub fn remaining(&self) -> Option<Duration> {
+    let current = self.current_left();
+    if self.stop_after.get() {
+      return Some(current);
+    }
+    if self.shuffle.get() != ShuffleMode::Off || self.repeat.get() != RepeatMode::Off {
+      return None;
+    }
+    let queued: Duration = self.queue.borrow().iter().map(|t| length(t)).sum();
+    let rest: Duration = match self.active_model() {
+      Some(model) => {
+        let start = self.current_pos(&model).map_or(0, |pos| pos + 1);
+        (start..model.n_items())
+          .map_while(|i| Self::track_at(&model, i))
+          .map(|t| length(&t))
+          .sum()
+      }
+      None => Duration::ZERO,
+    };
+    Some(current + queued + rest)
+  }
+
+  // Records the current track as played once it has got far enough in
+  fn count_play(&self) {
+    if self.played.get() {
+      return;
+    }
+    let Some(track) = self.current.borrow().clone() else {
+      return;
+    };
+    let duration = self.duration();
+    let threshold = if duration.is_zero() {
+      PLAYED_AFTER
+    } else {
+      PLAYED_AFTER.min(duration / 2)
+    };
+    if self.position() >= threshold {
+      self.played.set(true);
+      record_play(&track.filename);
+    }
+  }
+
+  // Up to the end offset if the track has one
+  fn current_left(&self) -> Duration {
+    if self.current.borrow().is_none() {
+      return Duration::ZERO;
+    }
+    let end = self.end.get().unwrap_or_else(|| self.duration());
+    end.saturating_sub(self.position())
+  }
+
+  // The current track's length, from the library or else the decoder. Zero
+  // when neither knows it
+  pub fn duration(&self) -> Duration {
+    match self.current.borrow().as_ref() {
+      Some(t) if t.duration_ms.is_some() => length(t),
+      Some(_) => self.decoded_length.get().unwrap_or_default(),
+      None => Duration::ZERO,
+    }
+  }
+
+  pub fn set_model(&self, model: &impl IsA<ListModel>) {
+    self.view_model.replace(Some(model.clone().upcast()));
+  }
+
+  // Snapshots the view as the list to keep playing from, so browsing to
+  // other facets or re-sorting afterwards doesn't change what plays next
+  pub fn take_context(&self) {
+    let Some(view_model) = self.view_model.borrow().clone() else {
+      return;
+    };
+    let store = ListStore::new::<BoxedAnyObject>();
+    let items: Vec<Object> = (0..view_model.n_items())
+      .filter_map(|i| view_model.item(i))
+      .collect();
+    store.extend_from_slice(&items);
+    let context: ListModel = store.upcast();
+    self.context.replace(Some(context.clone()));
+    self.model.replace(Some(context));
+  }
+
+  // Before anything has been played from the view, next/prev follow it live
+  fn active_model(&self) -> Option<ListModel> {
+    let model = self.model.borrow().clone();
+    model.or_else(|| self.view_model.borrow().clone())
+  }
+
+  // Restricts next/prev and shuffle to the given files, or lifts the
+  // restriction with None
+  pub fn set_selection_only(&self, filenames: Option<HashSet<String>>) {
+    let model = match filenames {
+      Some(filenames) => {
+        self.take_context();
+        let filter = CustomFilter::new(move |obj| {
+          let r: Ref<Rc<Track>> = obj.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+          filenames.contains(&r.filename)
+        });
+        let context = self.context.borrow().clone();
+        context.map(|m| FilterListModel::new(Some(m), Some(filter)).upcast())
+      }
+      None => self.context.borrow().clone(),
+    };
+    self.model.replace(model);
+  }
+
+  pub fn selection_only(&self) -> bool {
+    self.model.borrow().as_ref() != self.context.borrow().as_ref()
+  }
+
+  fn track_at(model: &ListModel, pos: u32) -> Option<Rc<Track>> {
+    let obj = model.item(pos)?;
+    let r: Ref<Rc<Track>> = obj.downcast_ref::<BoxedAnyObject>()?.borrow();
+    Some(r.clone())
+  }
+
+  // Looks the current track up by filename, trying where it was last found
+  // first. Re-sorting the view moves it around, so that is only a guess
+  fn current_pos(&self, model: &ListModel) -> Option<u32> {
+    let current = self.current.borrow();
+    let filename = &current.as_ref()?.filename;
+    let is_current = |i: u32| match Self::track_at(model, i) {
+      Some(t) => &t.filename == filename,
+      None => false,
+    };
+    let pos = self
+      .last_pos
+      .get()
+      .filter(|&i| is_current(i))
+      .or_else(|| (0..model.n_items()).find(|&i| is_current(i)));
+    self.last_pos.set(pos);
+    pos
+  }
+
+  fn play_relative(&self, forward: bool) -> bool {
+    let model = match self.active_model() {
+      Some(model) => model,
+      None => return false,
+    };
+    let next = match (self.current_pos(&model), forward) {
+      (Some(pos), true) => pos.checked_add(1),
+      (Some(pos), false) => pos.checked_sub(1),
+      (None, _) => Some(0),
+    };
+    // a played selection repeats from its start, A/B style
+    let wraps = self.selection_only() || self.repeat.get() == RepeatMode::All;
+    let next = match next {
+      Some(pos) if pos >= model.n_items() && forward && wraps => Some(0),
+      next => next,
+    };
+    match next.and_then(|pos| Self::track_at(&model, pos)) {
+      Some(track) => {
+        self.play_track(&track);
+        true
+      }
+      None => false,
+    }
+  }
+
+  // Queued tracks play before the rest of the view
+  pub fn enqueue(&self, tracks: impl IntoIterator<Item = Rc<Track>>) {
+    self.queue.borrow_mut().extend(queue::entries(tracks));
+  }
+
+  // Ahead of anything already queued, so these play right after the
+  // current track
+  pub fn enqueue_next(&self, tracks: Vec<Rc<Track>>) {
+    let mut queue = self.queue.borrow_mut();
+    for track in queue::entries(tracks).into_iter().rev() {
+      queue.push_front(track);
+    }
+  }
+
+  // The entry itself rather than its position, which auto-advance shifts. A
+  // track queued twice keeps its other entry
+  pub fn remove_from_queue(&self, entry: &Rc<Track>) {
+    queue::remove_entry(&mut self.queue.borrow_mut(), entry);
+  }
+
+  fn random_pos(model: &ListModel) -> Option<u32> {
+    match model.n_items() {
+      0 => None,
+      n => Some(glib::random_int_range(0, n as i32) as u32),
+    }
+  }
+
+  // A random track, moved back to the start of its album if that album is
+  // continuous so it isn't joined halfway through
+  fn random_track_pos(&self, model: &ListModel) -> Option<u32> {
+    let pos = Self::random_pos(model)?;
+    let pick = Self::track_at(model, pos)?;
+    if !self.continuous.borrow().contains(&album_key(&pick)) {
+      return Some(pos);
+    }
+    (0..model.n_items()).find(|&i| match Self::track_at(model, i) {
+      Some(t) => same_album(&t, &pick),
+      None => false,
+    })
+  }
+
+  // The track after the current one, if it is on the same album
+  fn next_on_album(&self, model: &ListModel) -> Option<u32> {
+    let current = self.current.borrow().clone()?;
+    let pos = self.current_pos(model)?;
+    let next = Self::track_at(model, pos + 1)?;
+    same_album(&current, &next).then_some(pos + 1)
+  }
+
+  // Continuous albums play through even when shuffling tracks
+  fn next_continuous_pos(&self, model: &ListModel) -> Option<u32> {
+    let current = self.current.borrow().clone()?;
+    if !self.continuous.borrow().contains(&album_key(&current)) {
+      return None;
+    }
+    self.next_on_album(model)
+  }
+
+  // Carries on through the current album, then jumps to the first track of
+  // a random album
+  fn next_album_pos(&self, model: &ListModel) -> Option<u32> {
+    if let Some(pos) = self.next_on_album(model) {
+      return Some(pos);
+    }
+    let pick = Self::track_at(model, Self::random_pos(model)?)?;
+    (0..model.n_items()).find(|&i| match Self::track_at(model, i) {
+      Some(t) => same_album(&t, &pick),
+      None => false,
+    })
+  }
+
+  fn play_shuffled(&self) -> bool {
+    let model = match self.active_model() {
+      Some(model) => model,
+      None => return false,
+    };
+    let next = match self.shuffle.get() {
+      ShuffleMode::Albums => self.next_album_pos(&model),
+      _ => self
+        .next_continuous_pos(&model)
+        .or_else(|| self.random_track_pos(&model)),
+    };
+    match next.and_then(|pos| Self::track_at(&model, pos)) {
+      Some(track) => {
+        self.play_track(&track);
+        true
+      }
+      None => false,
+    }
+  }
+
+  pub fn play_next(&self) -> bool {
+    let queued = self.queue.borrow_mut().pop_front();
+    match queued {
+      Some(track) => {
+        self.play_track(&track);
+        true
+      }
+      None if self.shuffle.get() == ShuffleMode::Off => self.play_relative(true),
+      None => self.play_shuffled(),
+    }
+  }
+
+  // In shuffle mode previous walks back through what was actually played
+  // rather than the track above in the view
+  pub fn play_prev(&self) -> bool {
+    if self.shuffle.get() == ShuffleMode::Off {
+      return self.play_relative(false);
+    }
+    let prev = self.history.borrow_mut().pop();
+    match prev {
+      Some(track) => {
+        self.start_track(&track);
+        true
+      }
+      None => false,
+    }
+  }
+
+  // Drops files that no longer exist from the queue, history and the list
+  // being played from
+  pub fn forget(&self, filenames: &HashSet<String>) {
+    let context = self.context.borrow().clone();
+    if let Some(store) = context.and_downcast_ref::<ListStore>() {
+      remove_tracks(store, filenames);
+    }
+    self
+      .queue
+      .borrow_mut()
+      .retain(|t| !filenames.contains(&t.filename));
+    self
+      .history
+      .borrow_mut()
+      .retain(|t| !filenames.contains(&t.filename));
+  }
+
+  // Follows files that organize moved
+  pub fn rename(&self, renamed: &HashMap<String, Rc<Track>>) {
+    let context = self.context.borrow().clone();
+    if let Some(store) = context.and_downcast_ref::<ListStore>() {
+      replace_renamed(store, renamed);
+    }
+    // each queue entry is its own Rc, see queue
+    for entry in self.queue.borrow_mut().iter_mut() {
+      if let Some(new) = renamed.get(&entry.filename) {
+        *entry = Rc::new(Track::clone(new));
+      }
+    }
+    let mut history = self.history.borrow_mut();
+    let mut current = self.current.borrow_mut();
+    for track in history.iter_mut().chain(current.iter_mut()) {
+      if let Some(new) = renamed.get(&track.filename) {
+        *track = new.clone();
+      }
+    }
+  }
+
+  pub fn pause(&self) {
+    self.sink.borrow().pause();
+    for zone in self.zones.borrow().iter() {
+      zone.sink.pause();
+    }
+  }
+
+  pub fn resume(&self) {
+    self.sink.borrow().play();
+    for zone in self.zones.borrow().iter() {
+      zone.sink.play();
+    }
+  }
+
+  pub fn stop(&self) {
+    self.current.replace(None);
+    self.sink.borrow().stop();
+    for zone in self.zones.borrow().iter() {
+      zone.sink.stop();
+    }
+    for f in self.stopped.borrow().iter() {
+      f();
+    }
+  }
+
+  // Polls the sink and moves on to the next visible track when the current
+  // one runs out. Stopping clears current, so a stopped sink is left alone.
+  // stop_after only applies to the track playing when it was set, and
+  // repeating one track only to running out, not to pressing next. Near the
+  // end of a track, the next one's album art is loaded ahead, and far
+  // enough in it is recorded as played
+  pub fn start_auto_advance(self: &Rc<Self>) {
+    let player = Rc::downgrade(self);
+    glib::timeout_add_local(Duration::from_millis(250), move || {
+      let Some(player) = player.upgrade() else {
+        return glib::ControlFlow::Break;
+      };
+      let finished = player.current.borrow().is_some()
+        && (player.sink.borrow().empty()
+          || player.end.get().is_some_and(|end| player.position() >= end));
+      if !finished {
+        player.count_play();
+        if player.current.borrow().is_some() && player.current_left() <= PRELOAD_AHEAD {
+          if let Some(next) = player.upcoming(1).first() {
+            player.covers.preload(&next.filename);
+          }
+        }
+        return glib::ControlFlow::Continue;
+      }
+      let current = player.current.borrow().clone();
+      match current {
+        _ if player.stop_after.replace(false) => player.stop(),
+        Some(track) if player.repeat.get() == RepeatMode::One => player.start_track(&track),
+        _ if !player.play_next() => player.stop(),
+        _ => (),
+      }
+      glib::ControlFlow::Continue
+    });
+  }
+
+  // Picks up offsets edited while their track is playing
+  pub fn offsets_changed(&self, filename: &str, offsets: &Offsets) {
+    if self
+      .current
+      .borrow()
+      .as_ref()
+      .is_some_and(|t| t.filename == filename)
+    {
+      self.end.set(offsets.end);
+    }
+  }
+
+  pub fn position(&self) -> Duration {
+    self.sink.borrow().get_pos()
+  }
+
+  pub fn seek(&self, pos: Duration) {
+    if let Err(e) = self.sink.borrow().try_seek(pos) {
+      warn!("Failed to seek: {}", e);
+    }
+    for zone in self.zones.borrow().iter() {
+      if let Err(e) = zone.sink.try_seek(pos) {
+        warn!("Failed to seek on {}: {}", zone.device, e);
+      }
+    }
+  }
+}
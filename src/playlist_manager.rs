@@ -1,33 +1,337 @@
-use crate::grid_cell::Entry;
-use crate::gtk_helpers::{get_cell, setup_col};
-use gtk::gio::ListStore;
-use gtk::glib::BoxedAnyObject;
-use gtk::{ColumnView, ColumnViewColumn, ScrolledWindow, SignalListItemFactory, SingleSelection};
-use std::cell::Ref;
+use crate::gtk_helpers::{format_total_duration, show_action_toast, show_toast};
+use crate::settings::FmlSettings;
+use crate::shortcuts::{add_key_bindings, PLAYLISTS};
+use adw::prelude::*;
+use fml9000::auto_playlists::{auto_playlist_filenames, auto_playlists, AutoPlaylist};
+use fml9000::library_index::LibraryIndex;
+use fml9000::load_playlist_store;
+use fml9000::playlists::{
+  add_to_playlist, create_untitled_playlist, playlist_duplicates, playlist_filenames,
+  playlist_summaries, read_playlists, remove_duplicates, rename_playlist, DuplicatePolicy,
+};
+use fml9000::scan_sessions::{recent_sessions, session_filenames};
+use gtk::gdk;
+use gtk::gio::{self, ListStore};
+use gtk::glib::{self, BoxedAnyObject, MainContext, SourceId};
+use gtk::{
+  ApplicationWindow, Button, ColumnView, ColumnViewColumn, EditableLabel, GestureClick, Label,
+  ListItem, Orientation, PopoverMenu, ScrolledWindow, SignalListItemFactory, SingleSelection,
+  Widget,
+};
+use std::cell::{Cell, Ref, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
 
 struct Playlist {
   name: String,
+  // None for the built-in playlists
+  id: Option<i32>,
+  count: i64,
+  duration_ms: i64,
+  // set on the import batches listed under "Recently added"
+  scan_session: Option<i32>,
+  // set on the playlists listed under "Auto playlists"
+  auto: Option<AutoPlaylist>,
 }
 
-pub fn create_playlist_manager(playlist_mgr_store: &ListStore) -> ScrolledWindow {
+const RECENTLY_ADDED: &str = "Recently added";
+const AUTO_PLAYLISTS: &str = "Auto playlists";
+const LISTED_SCAN_SESSIONS: i64 = 10;
+
+impl Playlist {
+  fn builtin(name: &str) -> Playlist {
+    Playlist {
+      name: name.to_string(),
+      id: None,
+      count: 0,
+      duration_ms: 0,
+      scan_session: None,
+      auto: None,
+    }
+  }
+
+  // listed under one of the built-in playlists
+  fn is_nested(&self) -> bool {
+    self.scan_session.is_some() || self.auto.is_some()
+  }
+}
+
+// "Recently added" and "Auto playlists" start collapsed, and activating
+// one shows or hides the entries under it
+fn toggle_nested(playlist_mgr_store: &ListStore, pos: u32, nested: impl FnOnce() -> Vec<Playlist>) {
+  let is_nested = |i| {
+    playlist_mgr_store
+      .item(i)
+      .and_downcast::<BoxedAnyObject>()
+      .is_some_and(|obj| obj.borrow::<Playlist>().is_nested())
+  };
+  if is_nested(pos + 1) {
+    while is_nested(pos + 1) {
+      playlist_mgr_store.remove(pos + 1);
+    }
+    return;
+  }
+  let objs: Vec<BoxedAnyObject> = nested().into_iter().map(BoxedAnyObject::new).collect();
+  playlist_mgr_store.splice(pos + 1, 0, &objs);
+}
+
+fn scan_session_playlists() -> Vec<Playlist> {
+  recent_sessions(LISTED_SCAN_SESSIONS)
+    .into_iter()
+    .map(|session| Playlist {
+      name: session.describe(),
+      scan_session: Some(session.id),
+      ..Playlist::builtin("")
+    })
+    .collect()
+}
+
+fn auto_playlist_entries() -> Vec<Playlist> {
+  auto_playlists()
+    .into_iter()
+    .map(|auto| Playlist {
+      name: auto.name(),
+      auto: Some(auto),
+      ..Playlist::builtin("")
+    })
+    .collect()
+}
+
+// Called again whenever a user playlist is created or changed
+pub fn load_playlist_mgr_store(playlist_mgr_store: &ListStore) {
+  playlist_mgr_store.remove_all();
+  playlist_mgr_store.append(&BoxedAnyObject::new(Playlist::builtin(RECENTLY_ADDED)));
+  playlist_mgr_store.append(&BoxedAnyObject::new(Playlist::builtin("Recently played")));
+  playlist_mgr_store.append(&BoxedAnyObject::new(Playlist::builtin(AUTO_PLAYLISTS)));
+  for playlist in playlist_summaries() {
+    playlist_mgr_store.append(&BoxedAnyObject::new(Playlist {
+      name: playlist.name,
+      id: Some(playlist.id),
+      count: playlist.count,
+      duration_ms: playlist.duration_ms,
+      scan_session: None,
+      auto: None,
+    }));
+  }
+}
+
+fn playlist_name(playlist_id: i32) -> String {
+  read_playlists()
+    .into_iter()
+    .find(|p| p.id == playlist_id)
+    .map_or(String::new(), |p| p.name)
+}
+
+// Adds files to a user playlist. Files it already holds are added, skipped
+// or, with "Ask", held back behind an "Add anyway" button as the duplicates
+// preference says
+pub fn add_tracks_to_playlist(
+  widget: &impl IsA<Widget>,
+  settings: &RefCell<FmlSettings>,
+  playlist_mgr_store: &ListStore,
+  playlist_id: i32,
+  filenames: Vec<String>,
+) {
+  let name = playlist_name(playlist_id);
+  let policy = settings.borrow().playlist_duplicates;
+  let duplicates = playlist_duplicates(playlist_id, &filenames);
+  let (add, held_back): (Vec<String>, Vec<String>) = match policy {
+    DuplicatePolicy::Allow => (filenames, Vec::new()),
+    _ => filenames.into_iter().partition(|f| !duplicates.contains(f)),
+  };
+  let added = match add_to_playlist(playlist_id, &add) {
+    Ok(n) => n,
+    Err(e) => {
+      show_toast(widget, &format!("Failed to add to playlist: {}", e));
+      return;
+    }
+  };
+  load_playlist_mgr_store(playlist_mgr_store);
+  let title = format!("Added {} tracks to {}", added, name);
+  match policy {
+    _ if duplicates.is_empty() => show_toast(widget, &title),
+    DuplicatePolicy::Allow => show_toast(
+      widget,
+      &format!("{}, {} were already in it", title, duplicates.len()),
+    ),
+    DuplicatePolicy::Skip => show_toast(
+      widget,
+      &format!("{}, skipped {} duplicates", title, held_back.len()),
+    ),
+    DuplicatePolicy::Ask => {
+      let add_anyway = gio::SimpleAction::new("add-duplicates", None);
+      let playlist_mgr_store = playlist_mgr_store.clone();
+      add_anyway.connect_activate(move |_, _| {
+        if add_to_playlist(playlist_id, &held_back).is_ok() {
+          load_playlist_mgr_store(&playlist_mgr_store);
+        }
+      });
+      show_action_toast(
+        widget,
+        &format!("{}, {} are already in it", title, duplicates.len()),
+        "Add anyway",
+        &add_anyway,
+      );
+    }
+  }
+}
+
+fn display_name(r: &Playlist) -> String {
+  match r.id {
+    Some(_) => format!(
+      "{} ({} · {})",
+      r.name,
+      r.count,
+      format_total_duration(r.duration_ms)
+    ),
+    None => r.name.clone(),
+  }
+}
+
+fn item_playlist_id(item: &ListItem) -> Option<i32> {
+  let obj = item.item().and_downcast::<BoxedAnyObject>()?;
+  let r: Ref<Playlist> = obj.borrow();
+  r.id
+}
+
+// User playlists are renamed in place: clicking the name of the already
+// selected row again, slower than a double click, starts editing. The label
+// ignores the pointer otherwise so clicks still select and activate rows
+fn setup_editable_row(item: &ListItem, playlist_mgr_store: &ListStore) {
+  let label = EditableLabel::builder().can_target(false).build();
+  let row = gtk::Box::new(Orientation::Horizontal, 0);
+  row.append(&label);
+  item.set_child(Some(&row));
+
+  let pending: Rc<Cell<Option<SourceId>>> = Rc::new(Cell::new(None));
+  let gesture = GestureClick::new();
+  gesture.connect_pressed(glib::clone!(
+    #[weak]
+    item,
+    #[weak]
+    label,
+    move |_, n_press, _, _| {
+      if let Some(source) = pending.take() {
+        source.remove();
+      }
+      if n_press != 1 || !item.is_selected() || item_playlist_id(&item).is_none() {
+        return;
+      }
+      let delay = gtk::Settings::default().map_or(400, |s| s.gtk_double_click_time());
+      let pending_rc = pending.clone();
+      pending.set(Some(glib::timeout_add_local_once(
+        Duration::from_millis(delay as u64),
+        move || {
+          pending_rc.set(None);
+          label.start_editing();
+        },
+      )));
+    }
+  ));
+  row.add_controller(gesture);
+
+  label.connect_editing_notify(glib::clone!(
+    #[weak]
+    item,
+    #[weak]
+    playlist_mgr_store,
+    move |label| {
+      let Some(obj) = item.item().and_downcast::<BoxedAnyObject>() else {
+        return;
+      };
+      let r: Ref<Playlist> = obj.borrow();
+      let Some(id) = r.id else {
+        return;
+      };
+      label.set_can_target(label.is_editing());
+      if label.is_editing() {
+        label.set_text(&r.name);
+        return;
+      }
+      let name = label.text();
+      if name.is_empty() || name == r.name {
+        label.set_text(&display_name(&r));
+        return;
+      }
+      if let Err(e) = rename_playlist(id, &name) {
+        show_toast(
+          label,
+          &format!("Failed to rename playlist to {}: {}", name, e),
+        );
+      }
+      // reloading rebinds this row, so wait until the signal is done
+      glib::idle_add_local_once(move || load_playlist_mgr_store(&playlist_mgr_store));
+    }
+  ));
+}
+
+// Acts on the selected playlist, like the keyboard shortcuts
+fn add_context_menu(columnview: &ColumnView) {
+  let menu = gio::Menu::new();
+  menu.append(Some("Rename..."), Some("playlists.rename"));
+  menu.append(Some("Statistics..."), Some("playlists.stats"));
+  menu.append(
+    Some("Remove duplicates"),
+    Some("playlists.remove-duplicates"),
+  );
+  menu.append(
+    Some("New playlist from BPM and key..."),
+    Some("playlists.bpm-range"),
+  );
+  let popover_menu = PopoverMenu::from_model(Some(&menu));
+  popover_menu.set_has_arrow(false);
+  popover_menu.set_parent(columnview);
+
+  let gesture = GestureClick::new();
+  gesture.set_button(gdk::BUTTON_SECONDARY);
+  gesture.connect_released(move |gesture, _, x, y| {
+    gesture.set_state(gtk::EventSequenceState::Claimed);
+    popover_menu.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+    popover_menu.popup();
+  });
+  columnview.add_controller(gesture);
+}
+
+pub fn create_playlist_manager(
+  playlist_mgr_store: &ListStore,
+  playlist_store: &ListStore,
+  shown_playlist: &Rc<Cell<Option<i32>>>,
+  library: &Rc<RefCell<LibraryIndex>>,
+  wnd: &Rc<ApplicationWindow>,
+) -> gtk::Box {
   let playlist_mgr_sel = SingleSelection::builder().model(playlist_mgr_store).build();
   let playlist_mgr_columnview = ColumnView::builder().model(&playlist_mgr_sel).build();
   let playlist_mgr = SignalListItemFactory::new();
 
-  playlist_mgr.connect_setup(move |_factory, item| setup_col(item));
+  // set by the + button so the new playlist's row opens for editing once
+  // it is bound
+  let edit_on_bind: Rc<Cell<Option<i32>>> = Rc::new(Cell::new(None));
+
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  playlist_mgr.connect_setup(move |_factory, item| {
+    setup_editable_row(
+      item.downcast_ref::<ListItem>().unwrap(),
+      &playlist_mgr_store_rc,
+    )
+  });
+  let edit_on_bind_rc = edit_on_bind.clone();
   playlist_mgr.connect_bind(move |_factory, item| {
-    let (cell, obj) = get_cell(item);
+    let item = item.downcast_ref::<ListItem>().unwrap();
+    let label = item
+      .child()
+      .and_then(|row| row.first_child())
+      .and_downcast::<EditableLabel>()
+      .unwrap();
+    let obj = item.item().and_downcast::<BoxedAnyObject>().unwrap();
     let r: Ref<Playlist> = obj.borrow();
-    cell.set_entry(&Entry {
-      name: format!("{}", r.name),
-    });
+    label.set_text(&display_name(&r));
+    label.set_margin_start(if r.is_nested() { 16 } else { 0 });
+    if r.id.is_some() && r.id == edit_on_bind_rc.get() {
+      edit_on_bind_rc.set(None);
+      glib::idle_add_local_once(move || label.start_editing());
+    }
   });
-  playlist_mgr_store.append(&BoxedAnyObject::new(Playlist {
-    name: "Recently added".to_string(),
-  }));
-  playlist_mgr_store.append(&BoxedAnyObject::new(Playlist {
-    name: "Recently played".to_string(),
-  }));
+  load_playlist_mgr_store(playlist_mgr_store);
 
   let playlist_mgr_col = ColumnViewColumn::builder()
     .title("Playlists")
@@ -37,9 +341,162 @@ pub fn create_playlist_manager(playlist_mgr_store: &ListStore) -> ScrolledWindow
 
   playlist_mgr_columnview.append_column(&playlist_mgr_col);
 
+  let playlist_store = playlist_store.clone();
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let library = library.clone();
+  let shown_playlist = shown_playlist.clone();
+  playlist_mgr_columnview.connect_activate(move |columnview, pos| {
+    let obj = columnview
+      .model()
+      .and_then(|m| m.item(pos))
+      .and_downcast::<BoxedAnyObject>()
+      .unwrap();
+    let r: Ref<Playlist> = obj.borrow();
+    let filenames = match (r.id, r.scan_session, r.auto) {
+      (Some(id), _, _) => playlist_filenames(id),
+      (_, Some(session), _) => session_filenames(session),
+      (_, _, Some(auto)) => auto_playlist_filenames(auto),
+      _ => {
+        match r.name.as_str() {
+          RECENTLY_ADDED => toggle_nested(&playlist_mgr_store_rc, pos, scan_session_playlists),
+          AUTO_PLAYLISTS => toggle_nested(&playlist_mgr_store_rc, pos, auto_playlist_entries),
+          _ => (),
+        }
+        return;
+      }
+    };
+    let rows = library.borrow().by_filenames(&filenames);
+    playlist_store.remove_all();
+    // after clearing, which resets it. Left unset for an empty playlist, as
+    // the next view's clear wouldn't reset it
+    shown_playlist.set(r.id.filter(|_| !rows.is_empty()));
+    load_playlist_store(rows.iter(), &playlist_store);
+  });
+
+  // Keyboard users get the modal dialogs instead: F2 renames the selected
+  // playlist and Insert creates one
+  let actions = gio::SimpleActionGroup::new();
+  let rename = gio::SimpleAction::new("rename", None);
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let playlist_mgr_sel_rc = playlist_mgr_sel.clone();
+  let wnd_rc = wnd.clone();
+  rename.connect_activate(move |_, _| {
+    let Some(obj) = playlist_mgr_sel_rc
+      .selected_item()
+      .and_downcast::<BoxedAnyObject>()
+    else {
+      return;
+    };
+    let r: Ref<Playlist> = obj.borrow();
+    if let Some(id) = r.id {
+      MainContext::default().spawn_local(crate::rename_playlist_dialog::dialog(
+        Rc::clone(&wnd_rc),
+        id,
+        r.name.clone(),
+        playlist_mgr_store_rc.clone(),
+      ));
+    }
+  });
+  actions.add_action(&rename);
+  let stats = gio::SimpleAction::new("stats", None);
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let playlist_mgr_sel_rc = playlist_mgr_sel.clone();
+  let wnd_rc = wnd.clone();
+  stats.connect_activate(move |_, _| {
+    let Some(obj) = playlist_mgr_sel_rc
+      .selected_item()
+      .and_downcast::<BoxedAnyObject>()
+    else {
+      return;
+    };
+    let r: Ref<Playlist> = obj.borrow();
+    if let Some(id) = r.id {
+      MainContext::default().spawn_local(crate::playlist_stats_dialog::dialog(
+        Rc::clone(&wnd_rc),
+        id,
+        r.name.clone(),
+        playlist_mgr_store_rc.clone(),
+      ));
+    }
+  });
+  actions.add_action(&stats);
+  let new = gio::SimpleAction::new("new", None);
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let wnd_rc = wnd.clone();
+  new.connect_activate(move |_, _| {
+    MainContext::default().spawn_local(crate::new_playlist_dialog::dialog(
+      Rc::clone(&wnd_rc),
+      Vec::new(),
+      playlist_mgr_store_rc.clone(),
+    ));
+  });
+  actions.add_action(&new);
+  let dedupe = gio::SimpleAction::new("remove-duplicates", None);
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let wnd_rc = wnd.clone();
+  dedupe.connect_activate(move |_, _| {
+    let Some(id) = playlist_mgr_sel
+      .selected_item()
+      .and_downcast::<BoxedAnyObject>()
+      .and_then(|obj| obj.borrow::<Playlist>().id)
+    else {
+      return;
+    };
+    match remove_duplicates(id) {
+      Ok(n) => {
+        load_playlist_mgr_store(&playlist_mgr_store_rc);
+        show_toast(&*wnd_rc, &format!("Removed {} duplicates", n));
+      }
+      Err(e) => show_toast(&*wnd_rc, &format!("Failed to remove duplicates: {}", e)),
+    }
+  });
+  actions.add_action(&dedupe);
+  let bpm_range = gio::SimpleAction::new("bpm-range", None);
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let wnd_rc = wnd.clone();
+  bpm_range.connect_activate(move |_, _| {
+    MainContext::default().spawn_local(crate::bpm_playlist_dialog::dialog(
+      Rc::clone(&wnd_rc),
+      playlist_mgr_store_rc.clone(),
+    ));
+  });
+  actions.add_action(&bpm_range);
+  playlist_mgr_columnview.insert_action_group("playlists", Some(&actions));
+  add_context_menu(&playlist_mgr_columnview);
+  add_key_bindings(&playlist_mgr_columnview, PLAYLISTS);
+
+  let add_btn = Button::builder()
+    .icon_name("list-add-symbolic")
+    .tooltip_text("New playlist")
+    .build();
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  add_btn.connect_clicked(move |btn| match create_untitled_playlist() {
+    Ok(id) => {
+      edit_on_bind.set(Some(id));
+      load_playlist_mgr_store(&playlist_mgr_store_rc);
+    }
+    Err(e) => show_toast(btn, &format!("Failed to create playlist: {}", e)),
+  });
+
+  // the header sits outside the list, so it can't name the action
+  let stats_btn = Button::builder()
+    .icon_name("document-properties-symbolic")
+    .tooltip_text("Statistics and fill to duration")
+    .build();
+  stats_btn.connect_clicked(move |_| stats.activate(None));
+
+  let header = gtk::Box::new(Orientation::Horizontal, 0);
+  header.append(&Label::builder().label("Playlists").hexpand(true).build());
+  header.append(&stats_btn);
+  header.append(&add_btn);
+
   let playlist_mgr_wnd = ScrolledWindow::builder()
     .child(&playlist_mgr_columnview)
+    .vexpand(true)
     .build();
 
-  playlist_mgr_wnd
+  let playlist_mgr_box = gtk::Box::new(Orientation::Vertical, 0);
+  playlist_mgr_box.append(&header);
+  playlist_mgr_box.append(&playlist_mgr_wnd);
+  playlist_mgr_box
 }
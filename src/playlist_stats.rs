@@ -0,0 +1,140 @@
+use crate::models::Track;
+use crate::playlists::{add_to_playlist, playlist_filenames};
+use crate::query_tracks;
+use diesel::QueryResult;
+use gtk::glib;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+// a fill may land this far either side of its target
+const SLACK_MS: i64 = 30_000;
+
+pub struct PlaylistStats {
+  pub count: usize,
+  pub duration_ms: i64,
+  // tracks scanned before durations were recorded
+  pub unknown_durations: usize,
+  // most common first, tracks without a genre are left out
+  pub genres: Vec<(String, usize)>,
+  pub average_year: Option<f64>,
+}
+
+// What fill_to_duration pads a playlist with
+#[derive(Clone, Copy)]
+pub enum FillCriteria {
+  Genres,
+  Artists,
+  Anything,
+}
+
+impl FillCriteria {
+  pub const ALL: [FillCriteria; 3] = [
+    FillCriteria::Genres,
+    FillCriteria::Artists,
+    FillCriteria::Anything,
+  ];
+
+  pub fn label(self) -> &'static str {
+    match self {
+      FillCriteria::Genres => "Same genres",
+      FillCriteria::Artists => "Same artists",
+      FillCriteria::Anything => "Anything",
+    }
+  }
+}
+
+fn artist_of(track: &Track) -> Option<String> {
+  track
+    .album_artist
+    .as_ref()
+    .or(track.artist.as_ref())
+    .map(|a| a.to_lowercase())
+}
+
+// The playlist's tracks that are still in the library, plus the library
+fn load_playlist_tracks(playlist_id: i32) -> (Vec<Track>, Vec<Track>) {
+  let library = query_tracks();
+  let by_filename: HashMap<&str, &Track> =
+    library.iter().map(|t| (t.filename.as_str(), t)).collect();
+  let tracks = playlist_filenames(playlist_id)
+    .iter()
+    .filter_map(|f| by_filename.get(f.as_str()).map(|t| Track::clone(t)))
+    .collect();
+  (tracks, library)
+}
+
+fn duration_ms(tracks: &[Track]) -> i64 {
+  tracks
+    .iter()
+    .filter_map(|t| t.duration_ms)
+    .map(i64::from)
+    .sum()
+}
+
+pub fn playlist_stats(playlist_id: i32) -> PlaylistStats {
+  let (tracks, _) = load_playlist_tracks(playlist_id);
+  let mut genres: HashMap<String, usize> = HashMap::new();
+  for genre in tracks.iter().filter_map(|t| t.genre.as_deref()) {
+    *genres.entry(genre.to_string()).or_default() += 1;
+  }
+  let mut genres: Vec<(String, usize)> = genres.into_iter().collect();
+  genres.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+  let years: Vec<i32> = tracks.iter().filter_map(|t| t.year).collect();
+  PlaylistStats {
+    count: tracks.len(),
+    duration_ms: duration_ms(&tracks),
+    unknown_durations: tracks.iter().filter(|t| t.duration_ms.is_none()).count(),
+    genres,
+    average_year: (!years.is_empty())
+      .then(|| years.iter().map(|&y| y as f64).sum::<f64>() / years.len() as f64),
+  }
+}
+
+// Pads the playlist out to about the target length, e.g. an hour for a
+// commute, with random tracks that match the criteria and are not in it
+// yet. Returns how many tracks were added
+pub fn fill_to_duration(
+  playlist_id: i32,
+  target: Duration,
+  criteria: FillCriteria,
+) -> QueryResult<usize> {
+  let (tracks, library) = load_playlist_tracks(playlist_id);
+  let mut remaining = target.as_millis() as i64 - duration_ms(&tracks);
+  if remaining <= SLACK_MS {
+    return Ok(0);
+  }
+  let present: HashSet<&str> = tracks.iter().map(|t| t.filename.as_str()).collect();
+  let genres: HashSet<String> = tracks
+    .iter()
+    .filter_map(|t| t.genre.as_ref().map(|g| g.to_lowercase()))
+    .collect();
+  let artists: HashSet<String> = tracks.iter().filter_map(artist_of).collect();
+  let mut candidates: Vec<&Track> = library
+    .iter()
+    .filter(|t| t.duration_ms.is_some() && !present.contains(t.filename.as_str()))
+    .filter(|t| match criteria {
+      FillCriteria::Genres => t
+        .genre
+        .as_ref()
+        .is_some_and(|g| genres.contains(&g.to_lowercase())),
+      FillCriteria::Artists => artist_of(t).is_some_and(|a| artists.contains(&a)),
+      FillCriteria::Anything => true,
+    })
+    .collect();
+  for i in (1..candidates.len()).rev() {
+    candidates.swap(i, glib::random_int_range(0, i as i32 + 1) as usize);
+  }
+  let mut picked = Vec::new();
+  for track in candidates {
+    let length = track.duration_ms.unwrap_or(0) as i64;
+    if length <= remaining + SLACK_MS {
+      picked.push(track.filename.clone());
+      remaining -= length;
+      if remaining <= SLACK_MS {
+        break;
+      }
+    }
+  }
+  add_to_playlist(playlist_id, &picked)?;
+  Ok(picked.len())
+}
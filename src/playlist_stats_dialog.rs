@@ -0,0 +1,121 @@
+use crate::gtk_helpers::{format_total_duration, show_toast};
+use crate::playlist_manager::load_playlist_mgr_store;
+use adw::prelude::*;
+use fml9000::playlist_stats::{fill_to_duration, playlist_stats, FillCriteria, PlaylistStats};
+use gtk::gio::{self, ListStore};
+use gtk::glib::{self, MainContext};
+use gtk::{Button, DropDown, Label, Orientation, SpinButton};
+use std::rc::Rc;
+use std::time::Duration;
+
+// genres beyond this many are summed up as "other"
+const GENRE_LIMIT: usize = 8;
+
+fn describe(stats: &PlaylistStats) -> String {
+  let mut lines = vec![format!(
+    "{} tracks, {}",
+    stats.count,
+    format_total_duration(stats.duration_ms)
+  )];
+  if stats.unknown_durations > 0 {
+    lines.push(format!(
+      "{} tracks have no recorded length, rescan to count them",
+      stats.unknown_durations
+    ));
+  }
+  if let Some(year) = stats.average_year {
+    lines.push(format!("Average year {:.0}", year));
+  }
+  if !stats.genres.is_empty() {
+    lines.push(String::new());
+    for (genre, n) in stats.genres.iter().take(GENRE_LIMIT) {
+      lines.push(format!(
+        "{}  {} ({:.0}%)",
+        genre,
+        n,
+        100.0 * *n as f64 / stats.count as f64
+      ));
+    }
+    let other: usize = stats.genres.iter().skip(GENRE_LIMIT).map(|(_, n)| n).sum();
+    if other > 0 {
+      lines.push(format!("Other genres  {}", other));
+    }
+  }
+  lines.join("\n")
+}
+
+async fn refresh(stats_label: Label, playlist_id: i32) {
+  if let Ok(stats) = gio::spawn_blocking(move || playlist_stats(playlist_id)).await {
+    stats_label.set_text(&describe(&stats));
+  }
+}
+
+// Totals for a playlist, and padding it out to a length, e.g. a 60 minute
+// commute, with tracks like the ones already in it
+pub async fn dialog<W: IsA<gtk::Window>>(
+  wnd: Rc<W>,
+  playlist_id: i32,
+  name: String,
+  playlist_mgr_store: ListStore,
+) {
+  let f = gtk::Box::new(Orientation::Vertical, 6);
+  let stats_label = Label::builder()
+    .label("Loading...")
+    .xalign(0.0)
+    .selectable(true)
+    .build();
+  let fill_row = gtk::Box::new(Orientation::Horizontal, 6);
+  let minutes_spin = SpinButton::with_range(5.0, 600.0, 5.0);
+  minutes_spin.set_value(60.0);
+  let labels: Vec<&str> = FillCriteria::ALL.iter().map(|c| c.label()).collect();
+  let criteria_dropdown = DropDown::from_strings(&labels);
+  let fill_button = Button::builder().label("Fill").build();
+
+  fill_row.append(&Label::new(Some("Fill to (minutes)")));
+  fill_row.append(&minutes_spin);
+  fill_row.append(&Label::new(Some("with")));
+  fill_row.append(&criteria_dropdown);
+  fill_row.append(&fill_button);
+  f.append(&stats_label);
+  f.append(&fill_row);
+  let stats_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(450)
+    .title(format!("Playlist statistics: {}", name))
+    .child(&f)
+    .build();
+
+  fill_button.connect_clicked(glib::clone!(
+    #[weak]
+    stats_label,
+    #[weak]
+    minutes_spin,
+    #[weak]
+    criteria_dropdown,
+    move |button| {
+      let target = Duration::from_secs(minutes_spin.value() as u64 * 60);
+      let criteria = FillCriteria::ALL[criteria_dropdown.selected() as usize];
+      let button = button.clone();
+      let playlist_mgr_store = playlist_mgr_store.clone();
+      button.set_sensitive(false);
+      MainContext::default().spawn_local(async move {
+        let added =
+          gio::spawn_blocking(move || fill_to_duration(playlist_id, target, criteria)).await;
+        button.set_sensitive(true);
+        match added {
+          Ok(Ok(0)) => show_toast(&button, "Nothing added, already long enough or no matches"),
+          Ok(Ok(n)) => {
+            show_toast(&button, &format!("Added {} tracks", n));
+            load_playlist_mgr_store(&playlist_mgr_store);
+            refresh(stats_label, playlist_id).await;
+          }
+          Ok(Err(e)) => show_toast(&button, &format!("Failed to fill playlist: {}", e)),
+          Err(_) => (),
+        }
+      });
+    }
+  ));
+  stats_dialog.present();
+  refresh(stats_label, playlist_id).await;
+}
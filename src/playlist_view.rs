@@ -1,51 +1,553 @@
 use crate::grid_cell::Entry;
-use crate::gtk_helpers::{get_cell, get_playlist_activate_selection, setup_col, str_or_unknown};
+use crate::gtk_helpers::{
+  add_type_ahead, get_cell, get_playlist_activate_selection, selected_objects, setup_col,
+  show_toast, str_or_unknown,
+};
+use crate::player::Player;
+use crate::playlist_manager::add_tracks_to_playlist;
+use crate::settings::FmlSettings;
+use crate::shortcuts::{add_key_bindings, PLAYLIST};
 use adw::prelude::*;
-use fml9000::add_track_to_recently_played;
+use fml9000::analysis::detect_bpm_and_key;
+use fml9000::bpm::load_bpms;
+use fml9000::key::{load_keys, Key};
 use fml9000::models::Track;
-use gtk::gio::ListStore;
+use fml9000::playlists::{read_playlists, remove_from_playlist};
+use fml9000::related::more_like_this;
+use gtk::gdk;
+use gtk::gio::{self, ListStore};
+use gtk::glib::{BoxedAnyObject, MainContext};
 use gtk::{
-  ApplicationWindow, ColumnView, ColumnViewColumn, Image, MultiSelection, ScrolledWindow,
-  SignalListItemFactory,
+  Align, ApplicationWindow, ColumnView, ColumnViewColumn, CustomSorter, GestureClick, Label,
+  MultiSelection, Overlay, PopoverMenu, ScrolledWindow, SignalListItemFactory, SortListModel,
+  Stack,
 };
-use rodio::{Decoder, Sink};
-use std::cell::{Ref, RefCell};
-use std::fs::File;
-use std::io::BufReader;
-use std::path::PathBuf;
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
 
-fn create_column(cb: impl Fn(Ref<Rc<Track>>) -> String + 'static) -> SignalListItemFactory {
+fn create_column(cb: impl Fn(&Track) -> String + 'static) -> SignalListItemFactory {
   let col = SignalListItemFactory::new();
   col.connect_setup(move |_factory, item| setup_col(item));
   col.connect_bind(move |_factory, item| {
     let (cell, obj) = get_cell(item);
     let r: Ref<Rc<Track>> = obj.borrow();
-    cell.set_entry(&Entry { name: cb(r) });
+    cell.set_entry(&Entry { name: cb(&r) });
   });
   return col;
 }
 
+fn create_sorter<T: Ord>(key: impl Fn(&Track) -> T + 'static) -> CustomSorter {
+  CustomSorter::new(move |obj1, obj2| {
+    let k1: Ref<Rc<Track>> = obj1.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+    let k2: Ref<Rc<Track>> = obj2.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+    key(&k1).cmp(&key(&k2)).into()
+  })
+}
+
+fn selected_tracks(sel: &MultiSelection) -> Vec<Rc<Track>> {
+  selected_objects(sel)
+    .iter()
+    .map(|obj| {
+      let r: Ref<Rc<Track>> = obj.borrow();
+      r.clone()
+    })
+    .collect()
+}
+
+fn selected_filenames(sel: &MultiSelection) -> Vec<String> {
+  selected_tracks(sel)
+    .iter()
+    .map(|t| t.filename.clone())
+    .collect()
+}
+
+fn path_text(tracks: &[Rc<Track>]) -> String {
+  tracks
+    .iter()
+    .map(|t| t.filename.as_str())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+fn artist_title_text(tracks: &[Rc<Track>]) -> String {
+  tracks
+    .iter()
+    .map(|t| {
+      format!(
+        "{} - {}",
+        str_or_unknown(&t.artist),
+        str_or_unknown(&t.title)
+      )
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+// Local files have no web URL, so the snippet links to the file itself
+fn share_text(tracks: &[Rc<Track>]) -> String {
+  tracks
+    .iter()
+    .map(|t| {
+      format!(
+        "{} — {} — {}",
+        str_or_unknown(&t.title),
+        str_or_unknown(&t.artist),
+        gio::File::for_path(&t.filename).uri()
+      )
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+// The playlist submenu is rebuilt each time the menu opens so it picks up
+// playlists created since
+fn fill_playlists_menu(playlists_menu: &gio::Menu) {
+  playlists_menu.remove_all();
+  for playlist in read_playlists() {
+    let item = gio::MenuItem::new(Some(&playlist.name), None);
+    item.set_action_and_target_value(Some("playlist.add-to"), Some(&playlist.id.to_variant()));
+    playlists_menu.append_item(&item);
+  }
+  playlists_menu.append(Some("New playlist..."), Some("playlist.new-playlist"));
+}
+
+fn add_clipboard_action(
+  actions: &gio::SimpleActionGroup,
+  name: &str,
+  playlist_sel: &MultiSelection,
+  wnd: &Rc<ApplicationWindow>,
+  text: fn(&[Rc<Track>]) -> String,
+) {
+  let action = gio::SimpleAction::new(name, None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let wnd_rc = wnd.clone();
+  action.connect_activate(move |_, _| {
+    wnd_rc
+      .clipboard()
+      .set_text(&text(&selected_tracks(&playlist_sel_rc)));
+  });
+  actions.add_action(&action);
+}
+
+fn create_context_menu(
+  columnview: &ColumnView,
+  playlist_sel: &MultiSelection,
+  playlist_mgr_store: &ListStore,
+  player: &Rc<Player>,
+  wnd: &Rc<ApplicationWindow>,
+  settings: &Rc<RefCell<FmlSettings>>,
+) -> gio::SimpleActionGroup {
+  // the first section is titled with how many rows it will act on, so it
+  // is swapped back in on each popup
+  let menu = gio::Menu::new();
+  let items_section = gio::Menu::new();
+  let playlists_menu = gio::Menu::new();
+  items_section.append(Some("Queue"), Some("playlist.enqueue"));
+  items_section.append_submenu(Some("Add to playlist"), &playlists_menu);
+  items_section.append(Some("Remove from playlist"), Some("playlist.remove"));
+  items_section.append(Some("Copy file path"), Some("playlist.copy-path"));
+  items_section.append(
+    Some("Copy artist/title"),
+    Some("playlist.copy-artist-title"),
+  );
+  items_section.append(Some("Share"), Some("playlist.share"));
+  items_section.append(Some("More like this"), Some("playlist.more-like-this"));
+  items_section.append(Some("Detect BPM and key"), Some("playlist.analyze"));
+  items_section.append(Some("Properties..."), Some("playlist.properties"));
+  items_section.append(
+    Some("Find track boundaries..."),
+    Some("playlist.boundaries"),
+  );
+  items_section.append(Some("Delete file from disk..."), Some("playlist.delete"));
+  menu.append_section(None, &items_section);
+  let playback_section = gio::Menu::new();
+  playback_section.append(Some("Stop after this track"), Some("playlist.stop-after"));
+  playback_section.append(Some("Play only selection"), Some("playlist.selection-only"));
+  menu.append_section(None, &playback_section);
+  let popover_menu = PopoverMenu::from_model(Some(&menu));
+  popover_menu.set_has_arrow(false);
+  popover_menu.set_parent(columnview);
+
+  // Toggled from the menu or with Ctrl+T and Ctrl+L. These toggle the
+  // player's own state, since it clears stop_after by itself, and the check
+  // marks are refreshed from it on each popup
+  let stop_after = gio::SimpleAction::new_stateful("stop-after", None, &false.to_variant());
+  let player_rc = player.clone();
+  stop_after.connect_activate(move |action, _| {
+    let value = !player_rc.stop_after.get();
+    player_rc.stop_after.set(value);
+    action.set_state(&value.to_variant());
+  });
+
+  let selection_only = gio::SimpleAction::new_stateful("selection-only", None, &false.to_variant());
+  let player_rc = player.clone();
+  let playlist_sel_rc = playlist_sel.clone();
+  selection_only.connect_activate(move |action, _| {
+    let value = !player_rc.selection_only();
+    player_rc.set_selection_only(
+      value.then(|| selected_filenames(&playlist_sel_rc).into_iter().collect()),
+    );
+    action.set_state(&value.to_variant());
+  });
+
+  // properties only make sense for a single file
+  let properties = gio::SimpleAction::new("properties", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let wnd_rc = wnd.clone();
+  let player_rc = player.clone();
+  properties.connect_activate(move |_, _| {
+    if let Some(filename) = selected_filenames(&playlist_sel_rc).into_iter().next() {
+      MainContext::default().spawn_local(crate::properties_dialog::dialog(
+        Rc::clone(&wnd_rc),
+        filename,
+        Rc::clone(&player_rc),
+      ));
+    }
+  });
+
+  // splits a single file mix, so also one file at a time
+  let boundaries = gio::SimpleAction::new("boundaries", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let wnd_rc = wnd.clone();
+  boundaries.connect_activate(move |_, _| {
+    if let Some(filename) = selected_filenames(&playlist_sel_rc).into_iter().next() {
+      MainContext::default().spawn_local(crate::boundaries_dialog::dialog(
+        Rc::clone(&wnd_rc),
+        filename,
+      ));
+    }
+  });
+
+  // queues similar tracks to play after the current one
+  let more_like_this_action = gio::SimpleAction::new("more-like-this", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let player_rc = player.clone();
+  let wnd_rc = wnd.clone();
+  more_like_this_action.connect_activate(move |_, _| {
+    let Some(seed) = selected_tracks(&playlist_sel_rc).into_iter().next() else {
+      return;
+    };
+    let player = player_rc.clone();
+    let wnd = wnd_rc.clone();
+    MainContext::default().spawn_local(async move {
+      let title = str_or_unknown(&seed.title);
+      let seed = Track::clone(&seed);
+      let Ok(tracks) = gio::spawn_blocking(move || more_like_this(&seed)).await else {
+        return;
+      };
+      let message = match tracks.len() {
+        0 => format!("Nothing much like {}", title),
+        n => format!("Queued {} tracks like {}", n, title),
+      };
+      player.enqueue_next(tracks.into_iter().map(Rc::new).collect());
+      show_toast(&*wnd, &message);
+    });
+  });
+
+  let gesture = GestureClick::new();
+  gesture.set_button(gdk::BUTTON_SECONDARY);
+  let player_rc = player.clone();
+  let playlist_sel_rc = playlist_sel.clone();
+  let stop_after_rc = stop_after.clone();
+  let selection_only_rc = selection_only.clone();
+  let properties_rc = properties.clone();
+  let boundaries_rc = boundaries.clone();
+  let more_like_this_rc = more_like_this_action.clone();
+  gesture.connect_released(move |gesture, _, x, y| {
+    gesture.set_state(gtk::EventSequenceState::Claimed);
+    let count = playlist_sel_rc.selection().size();
+    let title = match count {
+      1 => "1 item".to_string(),
+      n => format!("{} items", n),
+    };
+    menu.remove(0);
+    menu.insert_section(0, Some(&title), &items_section);
+    properties_rc.set_enabled(count == 1);
+    boundaries_rc.set_enabled(count == 1);
+    more_like_this_rc.set_enabled(count == 1);
+    stop_after_rc.set_state(&player_rc.stop_after.get().to_variant());
+    selection_only_rc.set_state(&player_rc.selection_only().to_variant());
+    fill_playlists_menu(&playlists_menu);
+    popover_menu.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+    popover_menu.popup();
+  });
+  columnview.add_controller(gesture);
+
+  let actions = gio::SimpleActionGroup::new();
+  actions.add_action(&stop_after);
+  actions.add_action(&selection_only);
+  actions.add_action(&properties);
+  actions.add_action(&boundaries);
+  actions.add_action(&more_like_this_action);
+
+  let enqueue = gio::SimpleAction::new("enqueue", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let player_rc = player.clone();
+  enqueue.connect_activate(move |_, _| player_rc.enqueue(selected_tracks(&playlist_sel_rc)));
+  actions.add_action(&enqueue);
+
+  let add_to = gio::SimpleAction::new("add-to", Some(&i32::static_variant_type()));
+  let playlist_sel_rc = playlist_sel.clone();
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let wnd_rc = wnd.clone();
+  let settings = settings.clone();
+  add_to.connect_activate(move |_, param| {
+    let Some(id) = param.and_then(|p| p.get::<i32>()) else {
+      return;
+    };
+    add_tracks_to_playlist(
+      &*wnd_rc,
+      &settings,
+      &playlist_mgr_store_rc,
+      id,
+      selected_filenames(&playlist_sel_rc),
+    );
+  });
+  actions.add_action(&add_to);
+
+  let new_playlist = gio::SimpleAction::new("new-playlist", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let wnd_rc = wnd.clone();
+  new_playlist.connect_activate(move |_, _| {
+    MainContext::default().spawn_local(crate::new_playlist_dialog::dialog(
+      Rc::clone(&wnd_rc),
+      selected_filenames(&playlist_sel_rc),
+      playlist_mgr_store_rc.clone(),
+    ));
+  });
+  actions.add_action(&new_playlist);
+
+  add_clipboard_action(&actions, "copy-path", playlist_sel, wnd, path_text);
+  add_clipboard_action(
+    &actions,
+    "copy-artist-title",
+    playlist_sel,
+    wnd,
+    artist_title_text,
+  );
+  add_clipboard_action(&actions, "share", playlist_sel, wnd, share_text);
+
+  let delete = gio::SimpleAction::new("delete", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let playlist_mgr_store_rc = playlist_mgr_store.clone();
+  let wnd_rc = wnd.clone();
+  delete.connect_activate(move |_, _| {
+    MainContext::default().spawn_local(crate::delete_dialog::dialog(
+      Rc::clone(&wnd_rc),
+      selected_filenames(&playlist_sel_rc),
+      playlist_mgr_store_rc.clone(),
+    ));
+  });
+  actions.add_action(&delete);
+
+  columnview.insert_action_group("playlist", Some(&actions));
+  actions
+}
+
+// Tempo and key of the tracks analyzed so far, shared by the columns and
+// the action that fills them in
+struct Analysis {
+  bpms: HashMap<String, f32>,
+  keys: HashMap<String, Key>,
+}
+
+impl Analysis {
+  fn load() -> Analysis {
+    Analysis {
+      bpms: load_bpms(),
+      keys: load_keys(),
+    }
+  }
+
+  fn is_done(&self, filename: &str) -> bool {
+    self.bpms.contains_key(filename) && self.keys.contains_key(filename)
+  }
+}
+
+// Takes the selected rows out of the playlist being shown, the files stay in
+// the library. Other views have no list to remove them from
+fn add_remove_action(
+  actions: &gio::SimpleActionGroup,
+  playlist_sel: &MultiSelection,
+  playlist_store: &ListStore,
+  shown_playlist: &Rc<Cell<Option<i32>>>,
+  wnd: &Rc<ApplicationWindow>,
+) {
+  let remove = gio::SimpleAction::new("remove", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let playlist_store_rc = playlist_store.clone();
+  let shown_playlist_rc = shown_playlist.clone();
+  let wnd_rc = wnd.clone();
+  remove.connect_activate(move |_, _| {
+    let Some(playlist_id) = shown_playlist_rc.get() else {
+      return;
+    };
+    // the store is in playlist order, so a row's repeat count among the
+    // rows before it picks out its entry in the playlist
+    let mut positions: Vec<u32> = selected_objects(&playlist_sel_rc)
+      .iter()
+      .filter_map(|obj| playlist_store_rc.find(obj))
+      .collect();
+    positions.sort_unstable();
+    let mut repeats: HashMap<String, usize> = HashMap::new();
+    let mut entries: Vec<(String, usize)> = Vec::new();
+    let last = positions.last().map_or(0, |p| p + 1);
+    for pos in 0..last {
+      let obj = playlist_store_rc
+        .item(pos)
+        .and_downcast::<BoxedAnyObject>()
+        .unwrap();
+      let r: Ref<Rc<Track>> = obj.borrow();
+      let n = repeats.entry(r.filename.clone()).or_default();
+      if positions.binary_search(&pos).is_ok() {
+        entries.push((r.filename.clone(), *n));
+      }
+      *n += 1;
+    }
+    if let Err(e) = remove_from_playlist(playlist_id, &entries) {
+      show_toast(&*wnd_rc, &format!("Failed to remove from playlist: {}", e));
+      return;
+    }
+    for pos in positions.into_iter().rev() {
+      playlist_store_rc.remove(pos);
+    }
+  });
+  let remove_rc = remove.clone();
+  let shown_playlist_rc = shown_playlist.clone();
+  // every view clears the store before filling it, and an empty store has
+  // nothing left to remove either
+  playlist_store.connect_items_changed(move |store, _, _, _| {
+    if store.n_items() == 0 {
+      shown_playlist_rc.set(None);
+    }
+    remove_rc.set_enabled(shown_playlist_rc.get().is_some());
+  });
+  remove.set_enabled(shown_playlist.get().is_some());
+  actions.add_action(&remove);
+}
+
+// Analyzes the selected tracks that are missing a BPM or key, a few at a
+// time off the main thread, updating their rows as results come in
+fn add_analyze_action(
+  actions: &gio::SimpleActionGroup,
+  playlist_sel: &MultiSelection,
+  playlist_store: &ListStore,
+  analysis: &Rc<RefCell<Analysis>>,
+  wnd: &Rc<ApplicationWindow>,
+) {
+  let analyze = gio::SimpleAction::new("analyze", None);
+  let playlist_sel_rc = playlist_sel.clone();
+  let playlist_store_rc = playlist_store.clone();
+  let analysis_rc = analysis.clone();
+  let wnd_rc = wnd.clone();
+  analyze.connect_activate(move |action, _| {
+    let objs: Vec<BoxedAnyObject> = selected_objects(&playlist_sel_rc)
+      .into_iter()
+      .filter(|obj| {
+        let r: Ref<Rc<Track>> = obj.borrow();
+        !analysis_rc.borrow().is_done(&r.filename)
+      })
+      .collect();
+    if objs.is_empty() {
+      show_toast(&*wnd_rc, "BPM and key already detected");
+      return;
+    }
+    // one run at a time
+    action.set_enabled(false);
+    let action = action.clone();
+    let playlist_store = playlist_store_rc.clone();
+    let analysis = analysis_rc.clone();
+    let wnd = wnd_rc.clone();
+    MainContext::default().spawn_local(async move {
+      show_toast(
+        &*wnd,
+        &format!("Detecting BPM and key of {} tracks...", objs.len()),
+      );
+      let mut found = 0;
+      for batch in objs.chunks(8) {
+        let filenames: Vec<String> = batch
+          .iter()
+          .map(|obj| obj.borrow::<Rc<Track>>().filename.clone())
+          .collect();
+        let filenames1 = filenames.clone();
+        let Ok(results) = gio::spawn_blocking(move || detect_bpm_and_key(&filenames1)).await else {
+          break;
+        };
+        let results = filenames.into_iter().zip(results);
+        for (obj, (filename, (bpm, key))) in batch.iter().zip(results) {
+          let mut a = analysis.borrow_mut();
+          if let Some(bpm) = bpm {
+            a.bpms.insert(filename.clone(), bpm);
+          }
+          if let Some(key) = key {
+            a.keys.insert(filename, key);
+          }
+          if bpm.is_some() || key.is_some() {
+            found += 1;
+            drop(a);
+            if let Some(pos) = playlist_store.find(obj) {
+              playlist_store.items_changed(pos, 1, 1);
+            }
+          }
+        }
+      }
+      action.set_enabled(true);
+      show_toast(
+        &*wnd,
+        &format!("Analyzed {} of {} tracks", found, objs.len()),
+      );
+    });
+  });
+  actions.add_action(&analyze);
+}
+
 pub fn create_playlist_view(
   playlist_store: ListStore,
-  sink: &Rc<RefCell<Sink>>,
-  album_art: &Rc<Image>,
-  wnd_rc: &Rc<ApplicationWindow>,
+  playlist_mgr_store: ListStore,
+  shown_playlist: &Rc<Cell<Option<i32>>>,
+  player: &Rc<Player>,
+  wnd: &Rc<ApplicationWindow>,
+  settings: &Rc<RefCell<FmlSettings>>,
 ) -> ScrolledWindow {
-  let playlist_sel = MultiSelection::new(Some(playlist_store));
-  let playlist_columnview = ColumnView::builder().model(&playlist_sel).build();
-  let album_art_rc = album_art.clone();
-  let artistalbum = create_column(|r| {
+  let playlist_columnview = ColumnView::builder().build();
+  let playlist_sort =
+    SortListModel::new(Some(playlist_store.clone()), playlist_columnview.sorter());
+  let playlist_sel = MultiSelection::new(Some(playlist_sort));
+  playlist_columnview.set_model(Some(&playlist_sel));
+
+  let artistalbum_text = |r: &Track| {
     format!(
       "{} // {}",
       str_or_unknown(&r.album),
       str_or_unknown(&r.artist),
     )
-  });
+  };
+  let title_text = |r: &Track| r.title.clone().unwrap_or_default();
+  let filename_text = |r: &Track| r.filename.clone();
 
-  let track = create_column(|r| format!("{}", r.track.as_ref().unwrap_or(&"".to_string())));
-  let title = create_column(|r| format!("{}", r.title.as_ref().unwrap_or(&"".to_string())));
-  let filename = create_column(|r| format!("{}", r.filename));
+  let artistalbum = create_column(artistalbum_text);
+  let track = create_column(|r| r.track.as_deref().unwrap_or_default().to_string());
+  let title = create_column(title_text);
+  let filename = create_column(filename_text);
+  let analysis = Rc::new(RefCell::new(Analysis::load()));
+  let analysis_rc = analysis.clone();
+  let bpm = create_column(move |r| {
+    analysis_rc
+      .borrow()
+      .bpms
+      .get(&r.filename)
+      .map_or(String::new(), |b| format!("{:.0}", b))
+  });
+  let analysis_rc = analysis.clone();
+  let key = create_column(move |r| {
+    analysis_rc
+      .borrow()
+      .keys
+      .get(&r.filename)
+      .map_or(String::new(), |k| format!("{} {}", k.camelot(), k.name()))
+  });
 
   let playlist_col1 = ColumnViewColumn::builder()
     .expand(false)
@@ -53,6 +555,7 @@ pub fn create_playlist_view(
     .fixed_width(400)
     .title("Album / Artist")
     .factory(&artistalbum)
+    .sorter(&create_sorter(move |r| artistalbum_text(r).to_lowercase()))
     .build();
 
   let playlist_col2 = ColumnViewColumn::builder()
@@ -61,6 +564,7 @@ pub fn create_playlist_view(
     .title("#")
     .fixed_width(20)
     .factory(&track)
+    .sorter(&create_sorter(Track::track_number))
     .build();
 
   let playlist_col3 = ColumnViewColumn::builder()
@@ -69,6 +573,7 @@ pub fn create_playlist_view(
     .title("Title")
     .fixed_width(300)
     .factory(&title)
+    .sorter(&create_sorter(move |r| title_text(r).to_lowercase()))
     .build();
 
   let playlist_col4 = ColumnViewColumn::builder()
@@ -77,55 +582,124 @@ pub fn create_playlist_view(
     .fixed_width(2000)
     .title("Filename")
     .factory(&filename)
+    .sorter(&create_sorter(filename_text))
+    .build();
+
+  // f32 is not Ord, so this sorts on tenths of a beat per minute. Tracks
+  // not analyzed yet sort first
+  let analysis_rc = analysis.clone();
+  let playlist_col5 = ColumnViewColumn::builder()
+    .expand(false)
+    .resizable(true)
+    .title("BPM")
+    .fixed_width(50)
+    .factory(&bpm)
+    .sorter(&create_sorter(move |r| {
+      analysis_rc
+        .borrow()
+        .bpms
+        .get(&r.filename)
+        .map(|b| (b * 10.0).round() as i32)
+    }))
+    .build();
+
+  // round the Camelot wheel, so keys that mix well sit next to each other
+  let analysis_rc = analysis.clone();
+  let playlist_col6 = ColumnViewColumn::builder()
+    .expand(false)
+    .resizable(true)
+    .title("Key")
+    .fixed_width(70)
+    .factory(&key)
+    .sorter(&create_sorter(move |r| {
+      analysis_rc
+        .borrow()
+        .keys
+        .get(&r.filename)
+        .map(|k| k.wheel_order())
+    }))
     .build();
 
   playlist_columnview.append_column(&playlist_col1);
   playlist_columnview.append_column(&playlist_col2);
   playlist_columnview.append_column(&playlist_col3);
+  playlist_columnview.append_column(&playlist_col5);
+  playlist_columnview.append_column(&playlist_col6);
   playlist_columnview.append_column(&playlist_col4);
 
-  let sink = sink.clone();
-  let wnd = wnd_rc.clone();
+  // playing a row snapshots the sorted model, so playback follows what was
+  // on screen at the time
+  player.set_model(&playlist_sel);
+  let actions = create_context_menu(
+    &playlist_columnview,
+    &playlist_sel,
+    &playlist_mgr_store,
+    player,
+    wnd,
+    settings,
+  );
+  add_analyze_action(&actions, &playlist_sel, &playlist_store, &analysis, wnd);
+  add_remove_action(
+    &actions,
+    &playlist_sel,
+    &playlist_store,
+    shown_playlist,
+    wnd,
+  );
+
+  add_type_ahead(&playlist_columnview, move |obj| {
+    let r: Ref<Rc<Track>> = obj.borrow();
+    artistalbum_text(&r)
+  });
+
+  // plain Enter is left to the activate signal below, only Ctrl+Enter is
+  // bound
+  add_key_bindings(&playlist_columnview, PLAYLIST);
+
+  let player = player.clone();
 
   playlist_columnview.connect_activate(move |columnview, pos| {
     let selection = columnview.model().unwrap();
     let item = get_playlist_activate_selection(&selection, pos);
     let r: Ref<Rc<Track>> = item.borrow();
-    let f1 = r.filename.clone();
-    let f2 = r.filename.clone();
-    let f3 = r.filename.clone();
-
-    let file = BufReader::new(File::open(f1).unwrap());
-    let source = Decoder::new(file).unwrap();
-
-    let sink = sink.borrow_mut();
-    if !sink.empty() {
-      sink.stop();
-    }
-
-    // kill and recreate sink, xref
-    // https://github.com/betta-cyber/netease-music-tui/pull/27/
-    // https://github.com/RustAudio/rodio/issues/315
-    sink.stop();
-    sink.append(source);
-    sink.play();
-
-    add_track_to_recently_played(&f3);
-
-    let mut p = PathBuf::from(f2);
-    p.pop();
-    p.push("cover.jpg");
-    album_art_rc.set_from_file(Some(p));
-
-    wnd.set_title(Some(&format!(
-      "fml9000 // {} - {} - {}",
-      str_or_unknown(&r.artist),
-      str_or_unknown(&r.album),
-      str_or_unknown(&r.title),
-    )));
+    player.take_context();
+    player.play_track(&r);
   });
 
   ScrolledWindow::builder()
     .child(&playlist_columnview)
     .build()
 }
+
+// Puts a hint in place of an empty list and a track count badge over the
+// corner of a full one
+pub fn create_playlist_pane(playlist_wnd: &ScrolledWindow, playlist_store: &ListStore) -> Overlay {
+  let empty = adw::StatusPage::builder()
+    .icon_name("view-list-symbolic")
+    .title("No tracks here")
+    .description("Pick an artist or album above, or right click tracks and choose Add to playlist")
+    .css_classes(["compact"])
+    .build();
+  let stack = Stack::new();
+  stack.add_named(playlist_wnd, Some("tracks"));
+  stack.add_named(&empty, Some("empty"));
+
+  let badge = Label::builder()
+    .halign(Align::End)
+    .valign(Align::End)
+    .css_classes(["osd", "count-badge"])
+    .can_target(false)
+    .build();
+  let overlay = Overlay::builder().child(&stack).build();
+  overlay.add_overlay(&badge);
+
+  let update = move |store: &ListStore| {
+    let n = store.n_items();
+    stack.set_visible_child_name(if n == 0 { "empty" } else { "tracks" });
+    badge.set_visible(n > 0);
+    badge.set_label(&format!("{} tracks", n));
+  };
+  update(playlist_store);
+  playlist_store.connect_items_changed(move |store, _, _, _| update(store));
+  overlay
+}
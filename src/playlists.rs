@@ -0,0 +1,328 @@
+use crate::connect_db;
+use crate::models::{NewPlaylist, NewPlaylistTrack, UserPlaylist};
+use crate::schema::{playlist_tracks, playlists, tracks};
+use crate::writer::write_db;
+use diesel::dsl::{count, max, sum};
+use diesel::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::error;
+
+// What adding files that are already in a playlist does
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum DuplicatePolicy {
+  #[default]
+  Allow,
+  Skip,
+  Ask,
+}
+
+impl DuplicatePolicy {
+  pub const ALL: [DuplicatePolicy; 3] = [
+    DuplicatePolicy::Allow,
+    DuplicatePolicy::Skip,
+    DuplicatePolicy::Ask,
+  ];
+
+  pub fn label(self) -> &'static str {
+    match self {
+      DuplicatePolicy::Allow => "Add them again",
+      DuplicatePolicy::Skip => "Skip them",
+      DuplicatePolicy::Ask => "Ask",
+    }
+  }
+}
+
+pub struct PlaylistSummary {
+  pub id: i32,
+  pub name: String,
+  pub count: i64,
+  // tracks scanned before durations were recorded count as zero
+  pub duration_ms: i64,
+}
+
+pub fn read_playlists() -> Vec<UserPlaylist> {
+  let conn = &mut connect_db();
+  playlists::table
+    .order(playlists::name)
+    .load::<UserPlaylist>(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to load playlists: {}", e);
+      Vec::new()
+    })
+}
+
+pub fn playlist_summaries() -> Vec<PlaylistSummary> {
+  let conn = &mut connect_db();
+  playlists::table
+    .left_join(playlist_tracks::table)
+    .left_join(tracks::table.on(tracks::filename.eq(playlist_tracks::filename)))
+    .group_by((playlists::id, playlists::name))
+    .select((
+      playlists::id,
+      playlists::name,
+      count(playlist_tracks::id.nullable()),
+      sum(tracks::duration_ms.nullable()),
+    ))
+    .order(playlists::name)
+    .load::<(i32, String, i64, Option<i64>)>(conn)
+    .map(|rows| {
+      rows
+        .into_iter()
+        .map(|(id, name, count, duration_ms)| PlaylistSummary {
+          id,
+          name,
+          count,
+          duration_ms: duration_ms.unwrap_or(0),
+        })
+        .collect()
+    })
+    .unwrap_or_else(|e| {
+      error!("Failed to load playlists: {}", e);
+      Vec::new()
+    })
+}
+
+fn insert_playlist(conn: &mut SqliteConnection, name: &str) -> QueryResult<i32> {
+  diesel::insert_into(playlists::table)
+    .values(NewPlaylist { name })
+    .execute(conn)?;
+  playlists::table
+    .filter(playlists::name.eq(name))
+    .select(playlists::id)
+    .first(conn)
+}
+
+pub fn create_playlist(name: &str) -> QueryResult<i32> {
+  write_db(|conn| insert_playlist(conn, name))
+}
+
+// Picks "New playlist", "New playlist 2" and so on, whichever is free
+pub fn create_untitled_playlist() -> QueryResult<i32> {
+  let taken: Vec<String> = playlists::table
+    .select(playlists::name)
+    .load(&mut connect_db())?;
+  let name = (1..)
+    .map(|i| match i {
+      1 => "New playlist".to_string(),
+      i => format!("New playlist {}", i),
+    })
+    .find(|name| !taken.contains(name))
+    .unwrap();
+  create_playlist(&name)
+}
+
+pub fn rename_playlist(playlist_id: i32, name: &str) -> QueryResult<usize> {
+  write_db(|conn| {
+    diesel::update(playlists::table.filter(playlists::id.eq(playlist_id)))
+      .set(playlists::name.eq(name))
+      .execute(conn)
+  })
+}
+
+fn append_tracks(
+  conn: &mut SqliteConnection,
+  playlist_id: i32,
+  filenames: &[String],
+) -> QueryResult<usize> {
+  let last: Option<i32> = playlist_tracks::table
+    .filter(playlist_tracks::playlist_id.eq(playlist_id))
+    .select(max(playlist_tracks::position))
+    .first(conn)?;
+  let start = last.map_or(0, |p| p + 1);
+  let rows: Vec<NewPlaylistTrack> = filenames
+    .iter()
+    .enumerate()
+    .map(|(i, filename)| NewPlaylistTrack {
+      playlist_id,
+      filename,
+      position: start + i as i32,
+    })
+    .collect();
+  diesel::insert_into(playlist_tracks::table)
+    .values(&rows)
+    .execute(conn)
+}
+
+// Appends after the last item already in the playlist
+pub fn add_to_playlist(playlist_id: i32, filenames: &[String]) -> QueryResult<usize> {
+  if filenames.is_empty() {
+    return Ok(0);
+  }
+  write_db(|conn| append_tracks(conn, playlist_id, filenames))
+}
+
+// The given files that the playlist already holds
+pub fn playlist_duplicates(playlist_id: i32, filenames: &[String]) -> Vec<String> {
+  let present: HashSet<String> = playlist_filenames(playlist_id).into_iter().collect();
+  filenames
+    .iter()
+    .filter(|f| present.contains(*f))
+    .cloned()
+    .collect()
+}
+
+// Entries are a filename and which of its repeats in the playlist it is, 0
+// for the first, since a file can be in a playlist more than once
+fn remove_entries(
+  conn: &mut SqliteConnection,
+  playlist_id: i32,
+  entries: &[(String, usize)],
+) -> QueryResult<usize> {
+  let rows: Vec<(i32, String)> = playlist_tracks::table
+    .filter(playlist_tracks::playlist_id.eq(playlist_id))
+    .order(playlist_tracks::position)
+    .select((playlist_tracks::id, playlist_tracks::filename))
+    .load(conn)?;
+  let wanted: HashSet<&(String, usize)> = entries.iter().collect();
+  let mut seen: HashMap<String, usize> = HashMap::new();
+  let ids: Vec<i32> = rows
+    .into_iter()
+    .filter_map(|(id, filename)| {
+      let n = seen.entry(filename.clone()).or_default();
+      let entry = (filename, *n);
+      *n += 1;
+      wanted.contains(&entry).then_some(id)
+    })
+    .collect();
+  diesel::delete(playlist_tracks::table.filter(playlist_tracks::id.eq_any(ids))).execute(conn)
+}
+
+pub fn remove_from_playlist(playlist_id: i32, entries: &[(String, usize)]) -> QueryResult<usize> {
+  write_db(|conn| remove_entries(conn, playlist_id, entries))
+}
+
+// Keeps the first entry of each file, returning how many were removed
+pub fn remove_duplicates(playlist_id: i32) -> QueryResult<usize> {
+  write_db(|conn| {
+    let rows: Vec<(i32, String)> = playlist_tracks::table
+      .filter(playlist_tracks::playlist_id.eq(playlist_id))
+      .order(playlist_tracks::position)
+      .select((playlist_tracks::id, playlist_tracks::filename))
+      .load(conn)?;
+    let mut seen = HashSet::new();
+    let repeats: Vec<i32> = rows
+      .into_iter()
+      .filter(|(_, filename)| !seen.insert(filename.clone()))
+      .map(|(id, _)| id)
+      .collect();
+    diesel::delete(playlist_tracks::table.filter(playlist_tracks::id.eq_any(repeats))).execute(conn)
+  })
+}
+
+fn save_playlist(
+  conn: &mut SqliteConnection,
+  name: &str,
+  filenames: &[String],
+) -> QueryResult<i32> {
+  let id = insert_playlist(conn, name)?;
+  append_tracks(conn, id, filenames)?;
+  Ok(id)
+}
+
+// Creates a playlist holding exactly the given files, e.g. a snapshot of the
+// play queue. One transaction, so a failure doesn't leave an empty playlist
+pub fn save_as_playlist(name: &str, filenames: &[String]) -> QueryResult<i32> {
+  write_db(|conn| save_playlist(conn, name, filenames))
+}
+
+pub fn playlist_filenames(playlist_id: i32) -> Vec<String> {
+  let conn = &mut connect_db();
+  playlist_tracks::table
+    .filter(playlist_tracks::playlist_id.eq(playlist_id))
+    .order(playlist_tracks::position)
+    .select(playlist_tracks::filename)
+    .load(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to load playlist {}: {}", playlist_id, e);
+      Vec::new()
+    })
+}
+
+// Names of the playlists that contain any of the given files
+pub fn playlists_containing(filenames: &[String]) -> Vec<String> {
+  let conn = &mut connect_db();
+  playlists::table
+    .inner_join(playlist_tracks::table)
+    .filter(playlist_tracks::filename.eq_any(filenames))
+    .select(playlists::name)
+    .distinct()
+    .order(playlists::name)
+    .load(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to load playlists: {}", e);
+      Vec::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory_db;
+  use crate::writer::Writer;
+
+  #[test]
+  fn deleting_a_playlist_deletes_its_tracks() {
+    let conn = &mut memory_db();
+    let id = insert_playlist(conn, "Mix").unwrap();
+    append_tracks(conn, id, &["a.mp3".to_string(), "b.mp3".to_string()]).unwrap();
+    diesel::delete(playlists::table.filter(playlists::id.eq(id)))
+      .execute(conn)
+      .unwrap();
+    let left: i64 = playlist_tracks::table.count().get_result(conn).unwrap();
+    assert_eq!(left, 0);
+  }
+
+  #[test]
+  fn saved_playlist_holds_the_files_in_order() {
+    let conn = &mut memory_db();
+    let files = ["b.mp3".to_string(), "a.mp3".to_string()];
+    let id = save_playlist(conn, "Queue", &files).unwrap();
+    let saved: Vec<String> = playlist_tracks::table
+      .filter(playlist_tracks::playlist_id.eq(id))
+      .order(playlist_tracks::position)
+      .select(playlist_tracks::filename)
+      .load(conn)
+      .unwrap();
+    assert_eq!(saved, files);
+  }
+
+  #[test]
+  fn removes_only_the_chosen_repeat() {
+    let conn = &mut memory_db();
+    let files = ["a.mp3", "b.mp3", "a.mp3", "c.mp3"].map(String::from);
+    let id = save_playlist(conn, "Mix", &files).unwrap();
+    let removed = remove_entries(
+      conn,
+      id,
+      &[("a.mp3".to_string(), 1), ("c.mp3".to_string(), 0)],
+    )
+    .unwrap();
+    assert_eq!(removed, 2);
+    let left: Vec<String> = playlist_tracks::table
+      .filter(playlist_tracks::playlist_id.eq(id))
+      .order(playlist_tracks::position)
+      .select(playlist_tracks::filename)
+      .load(conn)
+      .unwrap();
+    assert_eq!(left, ["a.mp3", "b.mp3"]);
+  }
+
+  #[test]
+  fn failed_save_leaves_no_playlist() {
+    let mut conn = memory_db();
+    // makes adding the tracks fail after the playlist row went in
+    diesel::sql_query("DROP TABLE playlist_tracks")
+      .execute(&mut conn)
+      .unwrap();
+    let writer = Writer::new(conn);
+    let files = ["a.mp3".to_string()];
+    assert!(writer
+      .write(|conn| save_playlist(conn, "Queue", &files))
+      .is_err());
+    let n: i64 = writer
+      .write(|conn| playlists::table.count().get_result(conn))
+      .unwrap();
+    assert_eq!(n, 0);
+  }
+}
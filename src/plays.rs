@@ -0,0 +1,124 @@
+use crate::schema::{plays, recently_played, tracks};
+use crate::writer::write_db;
+use chrono::{Local, NaiveDateTime};
+use diesel::dsl::{count_star, max};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use tracing::error;
+
+// Every play gets a row in plays, for counting, and the latest one is kept
+// in recently_played too, for what was played when
+pub(crate) fn record(
+  conn: &mut SqliteConnection,
+  filename: &str,
+  at: NaiveDateTime,
+) -> QueryResult<()> {
+  diesel::insert_into(plays::table)
+    .values((plays::filename.eq(filename), plays::timestamp.eq(at)))
+    .execute(conn)?;
+  diesel::replace_into(recently_played::table)
+    .values((
+      recently_played::filename.eq(filename),
+      recently_played::timestamp.eq(at),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+pub fn record_play(filename: &str) {
+  let now = Local::now().naive_local();
+  if let Err(e) = write_db(|conn| record(conn, filename, now)) {
+    error!("Failed to record play of {}: {}", filename, e);
+  }
+}
+
+pub fn play_counts(conn: &mut SqliteConnection) -> QueryResult<HashMap<String, i64>> {
+  let counts = plays::table
+    .group_by(plays::filename)
+    .select((plays::filename, count_star()))
+    .load::<(String, i64)>(conn)?;
+  Ok(counts.into_iter().collect())
+}
+
+// Files still in the library played at least min_plays times but not since
+// before, most played first
+pub fn played_often_until(
+  conn: &mut SqliteConnection,
+  min_plays: i64,
+  before: NaiveDateTime,
+) -> QueryResult<Vec<String>> {
+  plays::table
+    .filter(plays::filename.eq_any(tracks::table.select(tracks::filename)))
+    .group_by(plays::filename)
+    .having(
+      count_star()
+        .ge(min_plays)
+        .and(max(plays::timestamp).lt(before)),
+    )
+    .order(count_star().desc())
+    .select(plays::filename)
+    .load(conn)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory_db;
+  use chrono::NaiveDate;
+
+  fn day(d: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2025, 1, d)
+      .unwrap()
+      .and_hms_opt(12, 0, 0)
+      .unwrap()
+  }
+
+  fn library(conn: &mut SqliteConnection, filenames: &[&str]) {
+    for filename in filenames {
+      diesel::insert_into(tracks::table)
+        .values(tracks::filename.eq(filename))
+        .execute(conn)
+        .unwrap();
+    }
+  }
+
+  #[test]
+  fn counts_every_play_and_keeps_the_last() {
+    let conn = &mut memory_db();
+    record(conn, "a.mp3", day(1)).unwrap();
+    record(conn, "a.mp3", day(2)).unwrap();
+    record(conn, "b.mp3", day(3)).unwrap();
+    let counts = play_counts(conn).unwrap();
+    assert_eq!(counts["a.mp3"], 2);
+    assert_eq!(counts["b.mp3"], 1);
+    let last: Option<NaiveDateTime> = recently_played::table
+      .filter(recently_played::filename.eq("a.mp3"))
+      .select(recently_played::timestamp)
+      .first(conn)
+      .unwrap();
+    assert_eq!(last, Some(day(2)));
+  }
+
+  #[test]
+  fn often_played_files_not_played_lately() {
+    let conn = &mut memory_db();
+    library(
+      conn,
+      &["often.mp3", "oftener.mp3", "once.mp3", "lately.mp3"],
+    );
+    for d in 1..=3 {
+      record(conn, "often.mp3", day(d)).unwrap();
+      record(conn, "lately.mp3", day(d + 10)).unwrap();
+    }
+    for d in 1..=4 {
+      record(conn, "oftener.mp3", day(d)).unwrap();
+    }
+    record(conn, "once.mp3", day(1)).unwrap();
+    // played often, but the file has left the library
+    for d in 1..=3 {
+      record(conn, "gone.mp3", day(d)).unwrap();
+    }
+    let forgotten = played_often_until(conn, 3, day(10)).unwrap();
+    assert_eq!(forgotten, ["oftener.mp3", "often.mp3"]);
+  }
+}
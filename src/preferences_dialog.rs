@@ -1,23 +1,360 @@
+use crate::gtk_helpers::show_toast;
 use crate::settings::{write_settings, FmlSettings};
 use adw::prelude::*;
+use fml9000::catalog::{export_catalog, DEFAULT_TEMPLATE};
+use fml9000::folders::folder_stats;
+use fml9000::playlists::DuplicatePolicy;
+use fml9000::query_tracks;
+use fml9000::search::SearchMode;
 use gtk::gio;
-use gtk::glib;
-use gtk::{Button, Entry, FileDialog, Orientation};
+use gtk::glib::{self, MainContext};
+use gtk::{
+  Align, Button, CheckButton, DropDown, Entry, FileDialog, Label, Orientation, PasswordEntry,
+  SpinButton,
+};
 use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+fn create_folder_row(
+  list: &gtk::Box,
+  settings: &Rc<RefCell<FmlSettings>>,
+  folder: &str,
+) -> gtk::Box {
+  let row = gtk::Box::new(Orientation::Horizontal, 6);
+  let enabled_check = CheckButton::builder()
+    .active(
+      !settings
+        .borrow()
+        .disabled_folders
+        .iter()
+        .any(|f| f == folder),
+    )
+    .tooltip_text("Scan this folder and show its tracks, from the next start")
+    .build();
+  let path_label = Label::builder()
+    .label(folder)
+    .hexpand(true)
+    .halign(Align::Start)
+    .selectable(true)
+    .build();
+  let stats_label = Label::builder().css_classes(["dim-label"]).build();
+  let remove_button = Button::builder()
+    .icon_name("list-remove-symbolic")
+    .tooltip_text("Remove from library")
+    .build();
+  row.append(&enabled_check);
+  row.append(&path_label);
+  row.append(&stats_label);
+  row.append(&remove_button);
+
+  // counting can take a moment on a big library or a slow share
+  let f = folder.to_string();
+  MainContext::default().spawn_local(glib::clone!(
+    #[weak]
+    stats_label,
+    async move {
+      if let Ok(stats) = gio::spawn_blocking(move || folder_stats(&f)).await {
+        stats_label.set_text(&stats.describe());
+      }
+    }
+  ));
+
+  let folder = folder.to_string();
+  let folder_rc = folder.clone();
+  enabled_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.disabled_folders.retain(|f| *f != folder_rc);
+      if !b.is_active() {
+        s.disabled_folders.push(folder_rc.clone());
+      }
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  remove_button.connect_clicked(glib::clone!(
+    #[weak]
+    list,
+    #[weak]
+    row,
+    #[weak]
+    settings,
+    move |_| {
+      let mut s = settings.borrow_mut();
+      s.folders.retain(|f| *f != folder);
+      s.disabled_folders.retain(|f| *f != folder);
+      write_settings(&s).expect("Failed to write");
+      list.remove(&row);
+    }
+  ));
+  row
+}
+
+fn add_folders(list: &gtk::Box, settings: &Rc<RefCell<FmlSettings>>, folders: Vec<String>) {
+  for folder in folders {
+    if settings.borrow().folders.contains(&folder) {
+      continue;
+    }
+    list.append(&create_folder_row(list, settings, &folder));
+    let mut s = settings.borrow_mut();
+    s.folders.push(folder);
+    write_settings(&s).expect("Failed to write");
+  }
+}
+
+// Reads the template, if one is set, and writes the catalog off the main
+// thread
+async fn export_catalog_to(button: Button, template: Option<String>, dir: PathBuf) {
+  let name = dir.display().to_string();
+  let result = gio::spawn_blocking(move || {
+    let template = match template {
+      Some(path) => std::fs::read_to_string(path)?,
+      None => DEFAULT_TEMPLATE.to_string(),
+    };
+    export_catalog(query_tracks(), &dir, &template)
+  })
+  .await;
+  match result {
+    Ok(Ok(albums)) => show_toast(&button, &format!("Exported {} albums to {}", albums, name)),
+    Ok(Err(e)) => show_toast(&button, &format!("Failed to export catalog: {}", e)),
+    Err(_) => (),
+  }
+}
+
 pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, settings: Rc<RefCell<FmlSettings>>) {
-  let f = gtk::Box::new(Orientation::Horizontal, 0);
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+
+  // the folders scanned into the library on startup
+  let folder_list = gtk::Box::new(Orientation::Vertical, 0);
+  for folder in settings.borrow().folders.iter() {
+    folder_list.append(&create_folder_row(&folder_list, &settings, folder));
+  }
+  let open_button = Button::builder().label("Add folders...").build();
+  // network shares that the file chooser doesn't show can be typed in, as
+  // long as they are mounted somewhere
+  let folder_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let path_entry = Entry::builder()
+    .placeholder_text("Folder path, e.g. a mounted NFS or SMB share")
+    .hexpand(true)
+    .build();
+  let add_path_button = Button::builder().label("Add").build();
+
+  let inbox_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let inbox_entry = Entry::builder()
+    .text(settings.borrow().inbox_folder.as_deref().unwrap_or(""))
+    .placeholder_text("A folder outside the library folders")
+    .hexpand(true)
+    .build();
+  inbox_row.append(&Label::new(Some("Inbox folder")));
+  inbox_row.append(&inbox_entry);
+
+  let embed_check = CheckButton::builder()
+    .label("Embed fetched album art into files")
+    .active(settings.borrow().embed_fetched_art)
+    .build();
+
+  let focus_check = CheckButton::builder()
+    .label("Switch to the now playing page (F12) when playback starts")
+    .active(settings.borrow().focus_on_play)
+    .build();
+
+  let resume_check = CheckButton::builder()
+    .label("Resume playback on startup")
+    .active(settings.borrow().resume_playback)
+    .build();
+
+  let suspend_check = CheckButton::builder()
+    .label("Resume playback after the computer wakes from sleep")
+    .active(settings.borrow().resume_after_suspend)
+    .build();
+
+  let inhibit_check = CheckButton::builder()
+    .label("Keep the computer awake while playing")
+    .active(settings.borrow().inhibit_sleep)
+    .build();
+
+  let night_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let night_check = CheckButton::builder()
+    .label("Turn on night mode from")
+    .active(settings.borrow().night_schedule)
+    .build();
+  let night_start_spin = SpinButton::with_range(0.0, 23.0, 1.0);
+  night_start_spin.set_value(settings.borrow().night_start as f64);
+  let night_end_spin = SpinButton::with_range(0.0, 23.0, 1.0);
+  night_end_spin.set_value(settings.borrow().night_end as f64);
+  night_row.append(&night_check);
+  night_row.append(&night_start_spin);
+  night_row.append(&Label::new(Some("to")));
+  night_row.append(&night_end_spin);
+  let night_cap_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let night_cap_spin = SpinButton::with_range(0.0, 100.0, 5.0);
+  night_cap_spin.set_value((settings.borrow().night_volume_cap * 100.0).round());
+  night_cap_row.append(&Label::new(Some("Night mode volume limit (%)")));
+  night_cap_row.append(&night_cap_spin);
+
+  let acoustid_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let acoustid_entry = Entry::builder()
+    .text(settings.borrow().acoustid_key.as_deref().unwrap_or(""))
+    .hexpand(true)
+    .build();
+  acoustid_row.append(&Label::new(Some("AcoustID API key")));
+  acoustid_row.append(&acoustid_entry);
+
+  let subsonic_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let subsonic_url_entry = Entry::builder()
+    .text(settings.borrow().subsonic_url.as_deref().unwrap_or(""))
+    .placeholder_text("https://music.example.com")
+    .hexpand(true)
+    .build();
+  let subsonic_user_entry = Entry::builder()
+    .text(&settings.borrow().subsonic_user)
+    .placeholder_text("User")
+    .build();
+  let subsonic_password_entry = PasswordEntry::builder()
+    .text(&settings.borrow().subsonic_password)
+    .placeholder_text("Password")
+    .show_peek_icon(true)
+    .build();
+  subsonic_row.append(&Label::new(Some("Subsonic server")));
+  subsonic_row.append(&subsonic_url_entry);
+  subsonic_row.append(&subsonic_user_entry);
+  subsonic_row.append(&subsonic_password_entry);
 
-  let open_button = Button::builder().label("Open folder...").build();
-  let s = settings.borrow().folder.clone();
-  let textbox = Entry::builder()
-    .text(s.as_ref().unwrap_or(&"Empty".to_string()))
+  let serve_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let serve_check = CheckButton::builder()
+    .label("Serve the library to Subsonic apps on port")
+    .active(settings.borrow().serve_library)
+    .build();
+  let serve_port_spin = SpinButton::with_range(1024.0, 65535.0, 1.0);
+  serve_port_spin.set_value(settings.borrow().serve_port as f64);
+  let serve_user_entry = Entry::builder()
+    .text(&settings.borrow().serve_user)
+    .placeholder_text("User")
+    .build();
+  let serve_password_entry = PasswordEntry::builder()
+    .text(&settings.borrow().serve_password)
+    .placeholder_text("Password")
+    .show_peek_icon(true)
+    .build();
+  serve_row.append(&serve_check);
+  serve_row.append(&serve_port_spin);
+  serve_row.append(&serve_user_entry);
+  serve_row.append(&serve_password_entry);
+  serve_row.append(&Label::new(Some("(applies on next start)")));
+
+  let discord_check = CheckButton::builder()
+    .label("Show what is playing in Discord")
+    .active(settings.borrow().discord_presence)
+    .build();
+  let discord_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let discord_entry = Entry::builder()
+    .text(settings.borrow().discord_client_id.as_deref().unwrap_or(""))
     .hexpand(true)
     .build();
+  discord_row.append(&Label::new(Some("Discord application ID")));
+  discord_row.append(&discord_entry);
+
+  let search_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let labels: Vec<&str> = SearchMode::ALL.iter().map(|m| m.label()).collect();
+  let search_dropdown = DropDown::from_strings(&labels);
+  let current = settings.borrow().search_mode;
+  search_dropdown.set_selected(
+    SearchMode::ALL
+      .iter()
+      .position(|m| *m == current)
+      .unwrap_or(0) as u32,
+  );
+  search_row.append(&Label::new(Some(
+    "Default search (~ for fuzzy, /regex/ for regex)",
+  )));
+  search_row.append(&search_dropdown);
 
-  f.append(&textbox);
-  f.append(&open_button);
+  let duplicates_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let labels: Vec<&str> = DuplicatePolicy::ALL.iter().map(|p| p.label()).collect();
+  let duplicates_dropdown = DropDown::from_strings(&labels);
+  let current = settings.borrow().playlist_duplicates;
+  duplicates_dropdown.set_selected(
+    DuplicatePolicy::ALL
+      .iter()
+      .position(|p| *p == current)
+      .unwrap_or(0) as u32,
+  );
+  duplicates_row.append(&Label::new(Some(
+    "Tracks already in a playlist when adding to it",
+  )));
+  duplicates_row.append(&duplicates_dropdown);
+
+  let formats_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let formats_entry = Entry::builder()
+    .text(settings.borrow().extra_formats.join(", "))
+    .placeholder_text("e.g. psf, minipsf")
+    .hexpand(true)
+    .build();
+  formats_row.append(&Label::new(Some("More file types to read with ffprobe")));
+  formats_row.append(&formats_entry);
+
+  let checksums_check = CheckButton::builder()
+    .label("Checksum new files when scanning, to detect corruption later")
+    .active(settings.borrow().checksums)
+    .build();
+  let fingerprints_check = CheckButton::builder()
+    .label("Fingerprint untagged files when scanning, to identify them faster (needs fpcalc)")
+    .active(settings.borrow().fingerprints)
+    .build();
+
+  let catalog_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let template_entry = Entry::builder()
+    .text(settings.borrow().catalog_template.as_deref().unwrap_or(""))
+    .placeholder_text("HTML file with {{catalog}} in it, or empty for the default")
+    .hexpand(true)
+    .build();
+  let export_button = Button::builder().label("Export catalog...").build();
+  catalog_row.append(&Label::new(Some("Catalog template")));
+  catalog_row.append(&template_entry);
+  catalog_row.append(&export_button);
+
+  let separators_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let separators_entry = Entry::builder()
+    .text(settings.borrow().artist_separators.join(" "))
+    .hexpand(true)
+    .build();
+  separators_row.append(&Label::new(Some(
+    "Split artists on (applies on next start)",
+  )));
+  separators_row.append(&separators_entry);
+
+  folder_row.append(&path_entry);
+  folder_row.append(&add_path_button);
+  folder_row.append(&open_button);
+  f.append(&Label::new(Some("Library folders")));
+  f.append(&folder_list);
+  f.append(&folder_row);
+  f.append(&inbox_row);
+  f.append(&embed_check);
+  f.append(&focus_check);
+  f.append(&resume_check);
+  f.append(&suspend_check);
+  f.append(&inhibit_check);
+  f.append(&night_row);
+  f.append(&night_cap_row);
+  f.append(&acoustid_row);
+  f.append(&subsonic_row);
+  f.append(&serve_row);
+  f.append(&discord_check);
+  f.append(&discord_row);
+  f.append(&search_row);
+  f.append(&duplicates_row);
+  f.append(&formats_row);
+  f.append(&separators_row);
+  f.append(&checksums_check);
+  f.append(&fingerprints_check);
+  f.append(&catalog_row);
+  let diagnostics_button = Button::builder().label("Diagnostics...").build();
+  f.append(&diagnostics_button);
+  let verify_button = Button::builder().label("Verify files...").build();
+  f.append(&verify_button);
   let preferences_dialog = gtk::Window::builder()
     .transient_for(&*wnd)
     .modal(true)
@@ -31,26 +368,357 @@ pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, settings: Rc<RefCell<FmlSet
     #[weak]
     wnd,
     #[weak]
-    textbox,
+    folder_list,
     #[weak]
     settings,
     move |_| {
       let dialog = FileDialog::builder()
-        .title("Open File")
-        .accept_label("Open")
+        .title("Add Folders")
+        .accept_label("Add")
         .build();
 
-      dialog.open(Some(&*wnd), gio::Cancellable::NONE, move |file| {
-        if let Ok(file) = file {
-          let p = file.path().expect("Couldn't get file path");
-          let folder = &p.to_string_lossy();
-          textbox.set_text(folder);
-          let mut s = settings.borrow_mut();
-          s.folder = Some(folder.to_string());
-          write_settings(&s).expect("Failed to write");
+      dialog.select_multiple_folders(Some(&*wnd), gio::Cancellable::NONE, move |files| {
+        if let Ok(files) = files {
+          let folders = (0..files.n_items())
+            .filter_map(|i| files.item(i).and_downcast::<gio::File>())
+            .filter_map(|file| file.path())
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+          add_folders(&folder_list, &settings, folders);
         }
       });
     }
   ));
+  let add_path = glib::clone!(
+    #[weak]
+    folder_list,
+    #[weak]
+    path_entry,
+    #[weak]
+    settings,
+    move || {
+      let text = path_entry.text();
+      let path = text.trim();
+      if path.is_empty() {
+        return;
+      }
+      if !Path::new(path).is_dir() {
+        show_toast(&path_entry, &format!("Not a folder: {}", path));
+        return;
+      }
+      add_folders(&folder_list, &settings, vec![path.to_string()]);
+      path_entry.set_text("");
+    }
+  );
+  let add_path = Rc::new(add_path);
+  let add_path_rc = add_path.clone();
+  path_entry.connect_activate(move |_| add_path_rc());
+  add_path_button.connect_clicked(move |_| add_path());
+  embed_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.embed_fetched_art = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  focus_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.focus_on_play = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  resume_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.resume_playback = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  suspend_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.resume_after_suspend = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  inhibit_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.inhibit_sleep = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  night_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.night_schedule = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  night_start_spin.connect_value_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |spin| {
+      let mut s = settings.borrow_mut();
+      s.night_start = spin.value_as_int() as u32;
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  night_end_spin.connect_value_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |spin| {
+      let mut s = settings.borrow_mut();
+      s.night_end = spin.value_as_int() as u32;
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  night_cap_spin.connect_value_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |spin| {
+      let mut s = settings.borrow_mut();
+      s.night_volume_cap = spin.value() / 100.0;
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  inbox_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      let folder = e.text();
+      s.inbox_folder = if folder.is_empty() {
+        None
+      } else {
+        Some(folder.to_string())
+      };
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  acoustid_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      let key = e.text();
+      s.acoustid_key = if key.is_empty() {
+        None
+      } else {
+        Some(key.to_string())
+      };
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  subsonic_url_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      let url = e.text();
+      s.subsonic_url = if url.is_empty() {
+        None
+      } else {
+        Some(url.to_string())
+      };
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  subsonic_user_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      s.subsonic_user = e.text().to_string();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  subsonic_password_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      s.subsonic_password = e.text().to_string();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  serve_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.serve_library = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  serve_port_spin.connect_value_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |spin| {
+      let mut s = settings.borrow_mut();
+      s.serve_port = spin.value() as u16;
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  serve_user_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      s.serve_user = e.text().to_string();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  serve_password_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      s.serve_password = e.text().to_string();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  discord_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.discord_presence = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  discord_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      let id = e.text();
+      s.discord_client_id = if id.is_empty() {
+        None
+      } else {
+        Some(id.to_string())
+      };
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  search_dropdown.connect_selected_notify(glib::clone!(
+    #[weak]
+    settings,
+    move |d| {
+      let mut s = settings.borrow_mut();
+      s.search_mode = SearchMode::ALL[d.selected() as usize];
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  duplicates_dropdown.connect_selected_notify(glib::clone!(
+    #[weak]
+    settings,
+    move |d| {
+      let mut s = settings.borrow_mut();
+      s.playlist_duplicates = DuplicatePolicy::ALL[d.selected() as usize];
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  formats_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      s.extra_formats = e
+        .text()
+        .split(',')
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty())
+        .collect();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  separators_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      // space separated, so "," and "&" can be separators too
+      s.artist_separators = e.text().split_whitespace().map(str::to_string).collect();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  template_entry.connect_changed(glib::clone!(
+    #[weak]
+    settings,
+    move |e| {
+      let mut s = settings.borrow_mut();
+      let path = e.text();
+      s.catalog_template = if path.is_empty() {
+        None
+      } else {
+        Some(path.to_string())
+      };
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  export_button.connect_clicked(glib::clone!(
+    #[weak]
+    wnd,
+    #[weak]
+    settings,
+    move |button| {
+      let dialog = FileDialog::builder()
+        .title("Export Catalog To")
+        .accept_label("Export")
+        .build();
+      let template = settings.borrow().catalog_template.clone();
+      let button = button.clone();
+      dialog.select_folder(Some(&*wnd), gio::Cancellable::NONE, move |file| {
+        if let Some(dir) = file.ok().and_then(|f| f.path()) {
+          MainContext::default().spawn_local(export_catalog_to(button, template, dir));
+        }
+      });
+    }
+  ));
+  checksums_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.checksums = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  fingerprints_check.connect_toggled(glib::clone!(
+    #[weak]
+    settings,
+    move |b| {
+      let mut s = settings.borrow_mut();
+      s.fingerprints = b.is_active();
+      write_settings(&s).expect("Failed to write");
+    }
+  ));
+  let preferences_dialog = Rc::new(preferences_dialog);
+  let preferences_dialog_rc = preferences_dialog.clone();
+  diagnostics_button.connect_clicked(move |_| {
+    MainContext::default().spawn_local(crate::diagnostics_dialog::dialog(Rc::clone(
+      &preferences_dialog_rc,
+    )));
+  });
+  let preferences_dialog_rc = preferences_dialog.clone();
+  verify_button.connect_clicked(move |_| {
+    MainContext::default().spawn_local(crate::verify_dialog::dialog(Rc::clone(
+      &preferences_dialog_rc,
+    )));
+  });
   preferences_dialog.present();
 }
@@ -0,0 +1,60 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+static START: OnceLock<Instant> = OnceLock::new();
+static TIMINGS: Mutex<Vec<(String, Duration)>> = Mutex::new(Vec::new());
+
+// Marks process start, which "since start" timings are measured from
+pub fn start() {
+  START.get_or_init(Instant::now);
+}
+
+pub fn since_start() -> Duration {
+  START.get_or_init(Instant::now).elapsed()
+}
+
+// Only the first timing under each name is kept, since that is the one from
+// startup, e.g. connect_db runs for every query afterwards
+pub fn record_timing(name: &str, elapsed: Duration) {
+  let mut timings = TIMINGS.lock().unwrap();
+  if !timings.iter().any(|(n, _)| n == name) {
+    timings.push((name.to_string(), elapsed));
+  }
+}
+
+pub fn timings() -> Vec<(String, Duration)> {
+  TIMINGS.lock().unwrap().clone()
+}
+
+pub fn format_timings() -> String {
+  timings()
+    .iter()
+    .map(|(name, elapsed)| format!("{}: {:.2?}", name, elapsed))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+struct Entered(Instant);
+
+// Times every span from creation to close and records it by name
+pub struct SpanTimings;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanTimings {
+  fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+    if let Some(span) = ctx.span(id) {
+      span.extensions_mut().insert(Entered(Instant::now()));
+    }
+  }
+
+  fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+    if let Some(span) = ctx.span(&id) {
+      if let Some(entered) = span.extensions().get::<Entered>() {
+        record_timing(span.name(), entered.0.elapsed());
+      }
+    }
+  }
+}
@@ -0,0 +1,226 @@
+use crate::connect_db;
+use crate::schema::{recently_played, saved_chapters, tracks};
+use crate::writer::write_db;
+use chrono::{DateTime, Local, NaiveDateTime};
+use diesel::prelude::*;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::ItemKey;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{error, warn};
+
+pub struct Chapter {
+  pub title: String,
+  pub start: Duration,
+}
+
+fn format_size(bytes: u64) -> String {
+  let mb = bytes as f64 / (1024.0 * 1024.0);
+  format!("{:.1} MB ({} bytes)", mb, bytes)
+}
+
+fn format_duration(ms: u128) -> String {
+  let secs = ms / 1000;
+  format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+fn key_name(key: &ItemKey) -> String {
+  match key {
+    ItemKey::Unknown(name) => name.clone(),
+    key => format!("{:?}", key),
+  }
+}
+
+// Everything known about a file as label/value pairs: file system info,
+// audio properties, database fields and then every tag item, for the
+// properties dialog. Blocking, so call it off the main thread
+pub fn read_properties(filename: &str) -> Vec<(String, String)> {
+  let mut props = vec![("Path".to_string(), filename.to_string())];
+
+  if let Ok(meta) = std::fs::metadata(filename) {
+    props.push(("Size".to_string(), format_size(meta.len())));
+    if let Ok(modified) = meta.modified() {
+      let modified: DateTime<Local> = modified.into();
+      props.push((
+        "Modified".to_string(),
+        modified.format("%Y-%m-%d %H:%M:%S").to_string(),
+      ));
+    }
+  }
+
+  let conn = &mut connect_db();
+  let added: Option<NaiveDateTime> = tracks::table
+    .filter(tracks::filename.eq(filename))
+    .select(tracks::added)
+    .first(conn)
+    .ok()
+    .flatten();
+  if let Some(added) = added {
+    props.push(("Added".to_string(), added.to_string()));
+  }
+  let last_played: Option<NaiveDateTime> = recently_played::table
+    .filter(recently_played::filename.eq(filename))
+    .select(recently_played::timestamp)
+    .first(conn)
+    .ok()
+    .flatten();
+  props.push((
+    "Last played".to_string(),
+    last_played.map_or("Never".to_string(), |t| t.to_string()),
+  ));
+
+  let tagged_file = match Probe::open(filename).and_then(|p| p.read()) {
+    Ok(tagged_file) => tagged_file,
+    Err(e) => {
+      warn!("Failed to read {}: {}", filename, e);
+      return props;
+    }
+  };
+  let audio = tagged_file.properties();
+  props.push((
+    "Codec".to_string(),
+    format!("{:?}", tagged_file.file_type()),
+  ));
+  props.push((
+    "Duration".to_string(),
+    format_duration(audio.duration().as_millis()),
+  ));
+  if let Some(bitrate) = audio.audio_bitrate() {
+    props.push(("Bitrate".to_string(), format!("{} kbps", bitrate)));
+  }
+  if let Some(sample_rate) = audio.sample_rate() {
+    props.push(("Sample rate".to_string(), format!("{} Hz", sample_rate)));
+  }
+  if let Some(bit_depth) = audio.bit_depth() {
+    props.push(("Bit depth".to_string(), format!("{} bit", bit_depth)));
+  }
+  if let Some(channels) = audio.channels() {
+    props.push(("Channels".to_string(), channels.to_string()));
+  }
+
+  for tag in tagged_file.tags() {
+    for item in tag.items() {
+      if let Some(text) = item.value().text() {
+        props.push((key_name(item.key()), text.to_string()));
+      }
+    }
+  }
+  props
+}
+
+// Unsynced lyrics from the file's tags, if it has any
+pub fn read_lyrics(filename: &str) -> Option<String> {
+  let tagged_file = Probe::open(filename).and_then(|p| p.read()).ok()?;
+  let tag = tagged_file
+    .primary_tag()
+    .or_else(|| tagged_file.first_tag())?;
+  tag.get_string(&ItemKey::Lyrics).map(|s| s.to_string())
+}
+
+// "01:02:03.500" as used by Vorbis comment chapters, and "1:30" as typed in
+// the properties dialog
+pub fn parse_time(s: &str) -> Option<Duration> {
+  let mut parts = s.trim().rsplitn(3, ':');
+  let secs = parts
+    .next()?
+    .parse::<f64>()
+    .ok()
+    .filter(|s| s.is_finite() && *s >= 0.0)?;
+  let mins: u64 = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+  let hours: u64 = parts.next().map_or(Some(0), |h| h.parse().ok())?;
+  Some(Duration::from_secs(hours * 3600 + mins * 60) + Duration::from_secs_f64(secs))
+}
+
+// Chapters in the Vorbis comment style, CHAPTER001=00:00:00.000 with
+// CHAPTER001NAME=Intro, as audiobooks and DJ mixes in Ogg, Opus and FLAC
+// carry them. Sorted by start time
+pub fn read_chapters(filename: &str) -> Vec<Chapter> {
+  let Some(tagged_file) = Probe::open(filename).and_then(|p| p.read()).ok() else {
+    return Vec::new();
+  };
+  let Some(tag) = tagged_file
+    .primary_tag()
+    .or_else(|| tagged_file.first_tag())
+  else {
+    return Vec::new();
+  };
+  let mut starts = BTreeMap::new();
+  let mut names = BTreeMap::new();
+  for item in tag.items() {
+    let (ItemKey::Unknown(key), Some(value)) = (item.key(), item.value().text()) else {
+      continue;
+    };
+    let Some(rest) = key
+      .to_uppercase()
+      .strip_prefix("CHAPTER")
+      .map(|r| r.to_string())
+    else {
+      continue;
+    };
+    match rest.strip_suffix("NAME") {
+      Some(n) => names.insert(n.to_string(), value.to_string()),
+      None => starts.insert(rest, value.to_string()),
+    };
+  }
+  let mut chapters: Vec<Chapter> = starts
+    .into_iter()
+    .filter_map(|(n, start)| {
+      Some(Chapter {
+        start: parse_time(&start)?,
+        title: names.remove(&n).unwrap_or_else(|| format!("Chapter {}", n)),
+      })
+    })
+    .collect();
+  chapters.sort_by_key(|c| c.start);
+  chapters
+}
+
+// Chapters kept in the library rather than the file, e.g. track boundaries
+// found in a mix that came without any
+pub fn load_saved_chapters(filename: &str) -> Vec<Chapter> {
+  let conn = &mut connect_db();
+  saved_chapters::table
+    .filter(saved_chapters::filename.eq(filename))
+    .order(saved_chapters::start_ms.asc())
+    .select((saved_chapters::start_ms, saved_chapters::title))
+    .load::<(i32, String)>(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to load chapters of {}: {}", filename, e);
+      Vec::new()
+    })
+    .into_iter()
+    .map(|(start_ms, title)| Chapter {
+      title,
+      start: Duration::from_millis(start_ms.max(0) as u64),
+    })
+    .collect()
+}
+
+// Replaces the saved chapters of the file, none removes them
+pub fn save_chapters(filename: &str, chapters: &[Chapter]) -> QueryResult<()> {
+  write_db(|conn| {
+    diesel::delete(saved_chapters::table.filter(saved_chapters::filename.eq(filename)))
+      .execute(conn)?;
+    for chapter in chapters {
+      diesel::replace_into(saved_chapters::table)
+        .values((
+          saved_chapters::filename.eq(filename),
+          saved_chapters::start_ms.eq(chapter.start.as_millis() as i32),
+          saved_chapters::title.eq(&chapter.title),
+        ))
+        .execute(conn)?;
+    }
+    Ok(())
+  })
+}
+
+// The file's own chapters, or the saved ones when it has none
+pub fn chapters_of(filename: &str) -> Vec<Chapter> {
+  let chapters = read_chapters(filename);
+  if chapters.is_empty() {
+    load_saved_chapters(filename)
+  } else {
+    chapters
+  }
+}
@@ -0,0 +1,149 @@
+use crate::gtk_helpers::show_toast;
+use crate::player::Player;
+use adw::prelude::*;
+use fml9000::offsets::{load_offsets, save_offsets, Offsets};
+use fml9000::properties::{parse_time, read_properties};
+use gtk::gio;
+use gtk::glib;
+use gtk::{Align, Button, Entry, Grid, Label, ScrolledWindow};
+use std::rc::Rc;
+use std::time::Duration;
+
+// m:ss.s, which parse_time reads back
+fn format_offset(d: Duration) -> String {
+  let secs = d.as_secs_f64();
+  let mins = (secs / 60.0).floor();
+  format!("{}:{:04.1}", mins, secs - mins * 60.0)
+}
+
+// An empty entry is None, anything unreadable an error
+fn parse_offset(entry: &Entry) -> Result<Option<Duration>, String> {
+  let text = entry.text();
+  if text.trim().is_empty() {
+    return Ok(None);
+  }
+  parse_time(&text)
+    .map(Some)
+    .ok_or_else(|| format!("Not a time: {}", text))
+}
+
+// Start and end entries, each with a button that fills in the playing
+// position while this file is the one playing
+fn attach_offsets(grid: &Grid, row: i32, filename: &str, offsets: Offsets, player: &Rc<Player>) {
+  let playing = player
+    .current
+    .borrow()
+    .as_ref()
+    .is_some_and(|t| t.filename == filename);
+  let start_entry = Entry::builder()
+    .text(format_offset(offsets.start))
+    .hexpand(true)
+    .build();
+  let end_entry = Entry::builder()
+    .text(offsets.end.map(format_offset).unwrap_or_default())
+    .placeholder_text("End of track")
+    .hexpand(true)
+    .build();
+  for (i, (label, entry)) in [("Start at", &start_entry), ("Stop at", &end_entry)]
+    .into_iter()
+    .enumerate()
+  {
+    let row = row + i as i32;
+    let current_button = Button::builder()
+      .label("Use current position")
+      .sensitive(playing)
+      .build();
+    current_button.connect_clicked(glib::clone!(
+      #[weak]
+      entry,
+      #[weak]
+      player,
+      move |_| entry.set_text(&format_offset(player.position()))
+    ));
+    grid.attach(
+      &Label::builder().label(label).halign(Align::End).build(),
+      0,
+      row,
+      1,
+      1,
+    );
+    grid.attach(entry, 1, row, 1, 1);
+    grid.attach(&current_button, 2, row, 1, 1);
+  }
+
+  let save_button = Button::builder()
+    .label("Save offsets")
+    .halign(Align::Start)
+    .build();
+  let filename = filename.to_string();
+  save_button.connect_clicked(glib::clone!(
+    #[weak]
+    player,
+    move |button| {
+      let offsets = match (parse_offset(&start_entry), parse_offset(&end_entry)) {
+        (Ok(start), Ok(end)) => Offsets {
+          start: start.unwrap_or_default(),
+          end,
+        },
+        (Err(e), _) | (_, Err(e)) => {
+          show_toast(button, &e);
+          return;
+        }
+      };
+      match save_offsets(&filename, &offsets) {
+        Ok(()) => {
+          player.offsets_changed(&filename, &offsets);
+          show_toast(button, "Saved offsets");
+        }
+        Err(e) => show_toast(button, &format!("Failed to save offsets: {}", e)),
+      }
+    }
+  ));
+  grid.attach(&save_button, 1, row + 2, 1, 1);
+}
+
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, filename: String, player: Rc<Player>) {
+  let title = format!("Properties // {}", filename);
+  let f = filename.clone();
+  let (props, offsets) =
+    match gio::spawn_blocking(move || (read_properties(&f), load_offsets(&f))).await {
+      Ok(result) => result,
+      Err(_) => return,
+    };
+
+  let grid = Grid::builder()
+    .row_spacing(4)
+    .column_spacing(12)
+    .margin_start(8)
+    .margin_end(8)
+    .margin_top(8)
+    .margin_bottom(8)
+    .build();
+  let rows = props.len() as i32;
+  for (row, (key, value)) in props.into_iter().enumerate() {
+    let key_label = Label::builder().label(&key).halign(Align::End).build();
+    let value_label = Label::builder()
+      .label(&value)
+      .halign(Align::Start)
+      .hexpand(true)
+      .selectable(true)
+      .wrap(true)
+      .build();
+    let copy_button = Button::builder().label("Copy").build();
+    copy_button.connect_clicked(move |button| button.clipboard().set_text(&value));
+    grid.attach(&key_label, 0, row as i32, 1, 1);
+    grid.attach(&value_label, 1, row as i32, 1, 1);
+    grid.attach(&copy_button, 2, row as i32, 1, 1);
+  }
+  attach_offsets(&grid, rows, &filename, offsets, &player);
+
+  let properties_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(700)
+    .default_height(500)
+    .title(title)
+    .child(&ScrolledWindow::builder().child(&grid).build())
+    .build();
+  properties_dialog.present();
+}
@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+// Queue entries get an Rc of their own, so a track queued twice is two
+// entries that can be told apart with Rc::ptr_eq even after the queue has
+// moved on
+pub fn entries<T: Clone>(items: impl IntoIterator<Item = Rc<T>>) -> Vec<Rc<T>> {
+  items.into_iter().map(|t| Rc::new(T::clone(&t))).collect()
+}
+
+// Removes the given entry wherever it is now. False once it has left the
+// queue, e.g. auto-advance already played it
+pub fn remove_entry<T>(queue: &mut VecDeque<Rc<T>>, entry: &Rc<T>) -> bool {
+  match queue.iter().position(|e| Rc::ptr_eq(e, entry)) {
+    Some(i) => {
+      queue.remove(i);
+      true
+    }
+    None => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // The library hands out one Rc per track, so a repeated name is the same
+  // Rc twice before it goes through entries
+  fn queue(items: &[&str]) -> VecDeque<Rc<String>> {
+    let library: Vec<Rc<String>> = items.iter().map(|s| Rc::new(s.to_string())).collect();
+    let picked = items
+      .iter()
+      .map(|s| library[items.iter().position(|t| t == s).unwrap()].clone());
+    entries(picked).into()
+  }
+
+  fn names(queue: &VecDeque<Rc<String>>) -> Vec<&str> {
+    queue.iter().map(|e| e.as_str()).collect()
+  }
+
+  #[test]
+  fn removes_the_chosen_copy_of_a_duplicate() {
+    let mut q = queue(&["a", "b", "a"]);
+    let second_a = q[2].clone();
+    assert!(remove_entry(&mut q, &second_a));
+    assert_eq!(names(&q), ["a", "b"]);
+  }
+
+  #[test]
+  fn finds_the_entry_after_the_queue_advanced() {
+    let mut q = queue(&["a", "b", "a"]);
+    let second_a = q[2].clone();
+    q.pop_front();
+    assert!(remove_entry(&mut q, &second_a));
+    assert_eq!(names(&q), ["b"]);
+  }
+
+  #[test]
+  fn played_entry_is_not_removed_again() {
+    let mut q = queue(&["a", "a"]);
+    let first_a = q[0].clone();
+    q.pop_front();
+    assert!(!remove_entry(&mut q, &first_a));
+    assert_eq!(names(&q), ["a"]);
+  }
+}
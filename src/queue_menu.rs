@@ -0,0 +1,171 @@
+use crate::gtk_helpers::{format_total_duration, str_or_unknown};
+use crate::player::Player;
+use adw::prelude::*;
+use chrono::Local;
+use gtk::gio::ListStore;
+use gtk::glib::{self, MainContext};
+use gtk::{ApplicationWindow, Button, Label, MenuButton, Orientation, Popover};
+use std::rc::Rc;
+
+// e.g. "3 queued (12 m), 47 m left, ends at 23:42". Read when the list is
+// filled, so it is as of the last change
+fn summary(player: &Player) -> String {
+  let queued_ms: i64 = player
+    .queue
+    .borrow()
+    .iter()
+    .filter_map(|t| t.duration_ms)
+    .map(i64::from)
+    .sum();
+  let queued = format!(
+    "{} queued ({})",
+    player.queue.borrow().len(),
+    format_total_duration(queued_ms)
+  );
+  match player.remaining() {
+    Some(left) => {
+      let ends = Local::now() + left;
+      format!(
+        "{}, {} left, ends at {}",
+        queued,
+        format_total_duration(left.as_millis() as i64),
+        ends.format("%H:%M")
+      )
+    }
+    None => format!("{}, plays on while shuffling or repeating", queued),
+  }
+}
+
+fn fill_queue_list(list: &gtk::Box, header: &Label, player: &Rc<Player>) {
+  while let Some(child) = list.first_child() {
+    list.remove(&child);
+  }
+  header.set_text(&summary(player));
+  let queue = player.queue.borrow();
+  if queue.is_empty() {
+    list.append(&Label::new(Some("The queue is empty")));
+  }
+  for (i, track) in queue.iter().enumerate() {
+    let row = gtk::Box::new(Orientation::Horizontal, 0);
+    let label = Label::builder()
+      .label(format!(
+        "{}. {} - {}",
+        i + 1,
+        str_or_unknown(&track.artist),
+        str_or_unknown(&track.title)
+      ))
+      .xalign(0.0)
+      .hexpand(true)
+      .build();
+    let remove_button = Button::builder()
+      .icon_name("list-remove-symbolic")
+      .tooltip_text("Remove from queue")
+      .css_classes(["flat"])
+      .build();
+    let entry = track.clone();
+    remove_button.connect_clicked(glib::clone!(
+      #[weak]
+      list,
+      #[weak]
+      header,
+      #[strong]
+      player,
+      move |_| {
+        player.remove_from_queue(&entry);
+        fill_queue_list(&list, &header, &player);
+      }
+    ));
+    row.append(&label);
+    row.append(&remove_button);
+    list.append(&row);
+  }
+}
+
+pub fn create_queue_button(
+  player: &Rc<Player>,
+  playlist_mgr_store: &ListStore,
+  wnd: &Rc<ApplicationWindow>,
+) -> MenuButton {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let header = Label::builder().xalign(0.0).build();
+  let queue_list = gtk::Box::new(Orientation::Vertical, 0);
+  let button_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let save_button = Button::builder().label("Save queue as playlist...").build();
+  let clear_button = Button::builder().label("Clear").build();
+
+  button_row.append(&save_button);
+  button_row.append(&clear_button);
+  f.append(&header);
+  f.append(&queue_list);
+  f.append(&button_row);
+
+  let popover = Popover::builder().child(&f).build();
+  let queue_btn = MenuButton::builder()
+    .label("Queue")
+    .popover(&popover)
+    .build();
+
+  popover.connect_show(glib::clone!(
+    #[weak]
+    queue_list,
+    #[weak]
+    header,
+    #[strong]
+    player,
+    move |_| fill_queue_list(&queue_list, &header, &player)
+  ));
+  // auto-advance takes the first entry while the list may be open
+  let player_weak = Rc::downgrade(player);
+  player.connect_track_changed(glib::clone!(
+    #[weak]
+    popover,
+    #[weak]
+    queue_list,
+    #[weak]
+    header,
+    move |_| {
+      if let (true, Some(player)) = (popover.is_visible(), player_weak.upgrade()) {
+        fill_queue_list(&queue_list, &header, &player);
+      }
+    }
+  ));
+
+  clear_button.connect_clicked(glib::clone!(
+    #[weak]
+    queue_list,
+    #[weak]
+    header,
+    #[strong]
+    player,
+    move |_| {
+      player.queue.borrow_mut().clear();
+      fill_queue_list(&queue_list, &header, &player);
+    }
+  ));
+
+  save_button.connect_clicked(glib::clone!(
+    #[weak]
+    popover,
+    #[weak]
+    playlist_mgr_store,
+    #[strong]
+    player,
+    #[strong]
+    wnd,
+    move |_| {
+      let filenames = player
+        .queue
+        .borrow()
+        .iter()
+        .map(|t| t.filename.clone())
+        .collect();
+      popover.popdown();
+      MainContext::default().spawn_local(crate::new_playlist_dialog::dialog(
+        Rc::clone(&wnd),
+        filenames,
+        playlist_mgr_store,
+      ));
+    }
+  ));
+  queue_btn
+}
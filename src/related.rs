@@ -0,0 +1,226 @@
+use crate::interned::Interned;
+use crate::library_index::LibraryIndex;
+use crate::models::Track;
+use crate::plays;
+use crate::schema::recently_played;
+use crate::{connect_db, query_tracks};
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::prelude::*;
+use gtk::glib;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use tracing::error;
+
+const SECTION_LIMIT: usize = 8;
+// genre picks skip anything played within this many days
+const RECENT_DAYS: i64 = 30;
+// how many tracks "more like this" queues, and how many of those can be by
+// one artist so the seed's artist doesn't take over
+const MORE_LIMIT: usize = 25;
+const PER_ARTIST: usize = 3;
+
+pub struct Related {
+  pub same_album: Vec<Rc<Track>>,
+  pub same_artist: Vec<Rc<Track>>,
+  pub same_genre: Vec<Rc<Track>>,
+}
+
+fn artist_of(track: &Track) -> Option<&str> {
+  track.album_artist.as_deref().or(track.artist.as_deref())
+}
+
+fn played_since(since: NaiveDateTime) -> HashSet<String> {
+  let conn = &mut connect_db();
+  match recently_played::table
+    .filter(recently_played::timestamp.ge(since))
+    .select(recently_played::filename)
+    .load::<String>(conn)
+  {
+    Ok(filenames) => filenames.into_iter().collect(),
+    Err(e) => {
+      error!("Failed to load recently played: {}", e);
+      HashSet::new()
+    }
+  }
+}
+
+// From plays, which has a row for every time a track was played
+fn play_counts() -> HashMap<String, i64> {
+  plays::play_counts(&mut connect_db()).unwrap_or_else(|e| {
+    error!("Failed to load play counts: {}", e);
+    HashMap::new()
+  })
+}
+
+// What a track has in common with the seed: genre counts most, then
+// artist, then being from around the same years
+fn similarity(seed: &Track, track: &Track) -> f64 {
+  let mut score = 0.0;
+  if seed.genre.is_some() && track.genre == seed.genre {
+    score += 3.0;
+  }
+  if artist_of(seed).is_some() && artist_of(track) == artist_of(seed) {
+    score += 2.0;
+  }
+  match (seed.year, track.year) {
+    (Some(a), Some(b)) if (a - b).abs() <= 3 => score += 2.0,
+    (Some(a), Some(b)) if (a - b).abs() <= 10 => score += 1.0,
+    _ => (),
+  }
+  score
+}
+
+// None for tracks with too little in common with the seed
+fn score(
+  seed: &Track,
+  track: &Track,
+  plays: &HashMap<String, i64>,
+  recent: &HashSet<String>,
+) -> Option<f64> {
+  let score = similarity(seed, track);
+  if score < 2.0 {
+    return None;
+  }
+  let played = *plays.get(&track.filename).unwrap_or(&0) as f64;
+  let penalty = if recent.contains(&track.filename) {
+    2.0
+  } else {
+    0.0
+  };
+  Some(score + 0.5 * played.ln_1p() - penalty)
+}
+
+// A playlist built from the seed track. Tracks need a genre or artist in
+// common with it, or to be from close to the same year. Often played tracks
+// rank higher and ones played lately lower, with some randomness so asking
+// twice gives a different list. Reads the library from the database, so it
+// can run on a worker thread
+pub fn more_like_this(seed: &Track) -> Vec<Track> {
+  let plays = play_counts();
+  let recent = played_since((Local::now() - Duration::days(RECENT_DAYS)).naive_local());
+  let mut scored: Vec<(f64, Track)> = query_tracks()
+    .into_iter()
+    .filter(|t| t.filename != seed.filename)
+    .filter_map(|t| {
+      let score = score(seed, &t, &plays, &recent)?;
+      Some((score + glib::random_double(), t))
+    })
+    .collect();
+  scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+  let mut per_artist: HashMap<Option<String>, usize> = HashMap::new();
+  scored
+    .into_iter()
+    .filter(|(_, t)| {
+      let n = per_artist
+        .entry(artist_of(t).map(str::to_string))
+        .or_insert(0);
+      *n += 1;
+      *n <= PER_ARTIST
+    })
+    .take(MORE_LIMIT)
+    .map(|(_, t)| t)
+    .collect()
+}
+
+// Suggestions for the now playing track: the rest of its album, other albums
+// by the same artist, and a random handful of the same genre by other
+// artists that haven't been played lately
+pub fn related_tracks(library: &LibraryIndex, current: &Track) -> Related {
+  let artist = artist_of(current);
+  let not_current = |t: &&Rc<Track>| t.filename != current.filename;
+
+  let same_album = match current.album {
+    Some(_) => library
+      .by_album(&artist.map(Interned::from), &current.album)
+      .iter()
+      .filter(not_current)
+      .take(SECTION_LIMIT)
+      .cloned()
+      .collect(),
+    None => Vec::new(),
+  };
+  let same_artist = match artist {
+    Some(artist) => library
+      .by_artist(artist)
+      .iter()
+      .filter(|t| t.album != current.album)
+      .take(SECTION_LIMIT)
+      .cloned()
+      .collect(),
+    None => Vec::new(),
+  };
+
+  let mut same_genre = Vec::new();
+  if current.genre.is_some() {
+    let recent = played_since((Local::now() - Duration::days(RECENT_DAYS)).naive_local());
+    let candidates: Vec<&Rc<Track>> = library
+      .tracks()
+      .iter()
+      .filter(not_current)
+      .filter(|t| t.genre == current.genre && artist_of(t) != artist)
+      .filter(|t| !recent.contains(&t.filename))
+      .collect();
+    // start at a random spot so the picks change from play to play
+    if !candidates.is_empty() {
+      let start = glib::random_int_range(0, candidates.len() as i32) as usize;
+      same_genre = candidates
+        .iter()
+        .cycle()
+        .skip(start)
+        .take(SECTION_LIMIT.min(candidates.len()))
+        .map(|t| Rc::clone(t))
+        .collect();
+    }
+  }
+
+  Related {
+    same_album,
+    same_artist,
+    same_genre,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn track(filename: &str) -> Track {
+    Track {
+      filename: filename.to_string(),
+      artist: Some("Artist".into()),
+      title: None,
+      album: None,
+      genre: Some("Jazz".into()),
+      album_artist: None,
+      track: None,
+      added: None,
+      year: Some(1960),
+      duration_ms: None,
+      scan_session_id: None,
+    }
+  }
+
+  #[test]
+  fn often_played_outranks_the_shuffle() {
+    let seed = track("seed.mp3");
+    let plays = HashMap::from([("often.mp3".to_string(), 20)]);
+    let recent = HashSet::from(["lately.mp3".to_string()]);
+    let score = |f| score(&seed, &track(f), &plays, &recent).unwrap();
+    // more than the up to 1.0 of randomness added on top
+    assert!(score("often.mp3") - score("never.mp3") > 1.0);
+    assert!(score("never.mp3") - score("lately.mp3") > 1.0);
+  }
+
+  #[test]
+  fn unrelated_tracks_are_left_out() {
+    let seed = track("seed.mp3");
+    let other = Track {
+      artist: Some("Other".into()),
+      genre: Some("Metal".into()),
+      year: Some(2010),
+      ..track("other.mp3")
+    };
+    assert!(score(&seed, &other, &HashMap::new(), &HashSet::new()).is_none());
+  }
+}
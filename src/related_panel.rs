@@ -0,0 +1,85 @@
+use crate::gtk_helpers::str_or_unknown;
+use crate::player::Player;
+use adw::prelude::*;
+use fml9000::library_index::LibraryIndex;
+use fml9000::models::Track;
+use fml9000::related::related_tracks;
+use gtk::{Align, Button, Label, Orientation, ScrolledWindow};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn section(panel: &gtk::Box, title: &str, tracks: Vec<Rc<Track>>, player: &Rc<Player>) {
+  if tracks.is_empty() {
+    return;
+  }
+  panel.append(
+    &Label::builder()
+      .label(title)
+      .halign(Align::Start)
+      .css_classes(["heading"])
+      .build(),
+  );
+  for track in tracks {
+    let row = gtk::Box::new(Orientation::Horizontal, 4);
+    row.append(
+      &Label::builder()
+        .label(format!(
+          "{} — {}",
+          str_or_unknown(&track.title),
+          str_or_unknown(&track.artist)
+        ))
+        .halign(Align::Start)
+        .hexpand(true)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .build(),
+    );
+    let queue_btn = Button::builder()
+      .icon_name("list-add-symbolic")
+      .tooltip_text("Add to queue")
+      .build();
+    let player_rc = player.clone();
+    queue_btn.connect_clicked(move |_| player_rc.enqueue([track.clone()]));
+    row.append(&queue_btn);
+    panel.append(&row);
+  }
+}
+
+// Suggestions for the now playing track, refreshed whenever it changes
+pub fn create_related_panel(
+  player: &Rc<Player>,
+  library: &Rc<RefCell<LibraryIndex>>,
+) -> ScrolledWindow {
+  let panel = gtk::Box::builder()
+    .orientation(Orientation::Vertical)
+    .spacing(2)
+    .margin_start(4)
+    .margin_end(4)
+    .build();
+  panel.append(
+    &Label::builder()
+      .label("Related tracks show up here once something is playing")
+      .wrap(true)
+      .build(),
+  );
+
+  let panel_rc = panel.clone();
+  let player_rc = Rc::downgrade(player);
+  let library = library.clone();
+  player.connect_track_changed(move |current| {
+    let Some(player) = player_rc.upgrade() else {
+      return;
+    };
+    while let Some(child) = panel_rc.first_child() {
+      panel_rc.remove(&child);
+    }
+    let related = related_tracks(&library.borrow(), current);
+    section(&panel_rc, "From this album", related.same_album, &player);
+    section(&panel_rc, "By this artist", related.same_artist, &player);
+    section(&panel_rc, "Same genre", related.same_genre, &player);
+  });
+
+  ScrolledWindow::builder()
+    .child(&panel)
+    .vexpand(true)
+    .build()
+}
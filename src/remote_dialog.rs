@@ -0,0 +1,204 @@
+use crate::gtk_helpers::str_or_unknown;
+use crate::player::Player;
+use adw::prelude::*;
+use fml9000::subsonic::{RemoteAlbum, Server, Song};
+use gtk::gio;
+use gtk::glib::{MainContext, Propagation};
+use gtk::{Button, Label, ListBox, Orientation, ScrolledWindow, SearchEntry};
+use std::rc::Rc;
+use std::sync::Arc;
+
+// Everything a result row needs to reach back into the dialog
+#[derive(Clone)]
+struct Remote {
+  server: Arc<Server>,
+  player: Rc<Player>,
+  results: ListBox,
+  status: Label,
+}
+
+fn clear(list: &ListBox) {
+  while let Some(child) = list.first_child() {
+    list.remove(&child);
+  }
+}
+
+// Downloads the song to the cache, then plays it now or queues it
+async fn fetch(remote: Remote, song: Song, play: bool) {
+  remote
+    .status
+    .set_text(&format!("Downloading {}...", str_or_unknown(&song.title)));
+  let server = remote.server.clone();
+  let song1 = song.clone();
+  let path = match gio::spawn_blocking(move || server.fetch_song(&song1)).await {
+    Ok(Ok(path)) => path,
+    Ok(Err(e)) => {
+      remote.status.set_text(&e);
+      return;
+    }
+    Err(_) => return,
+  };
+  let track = Rc::new(song.to_track(path.display().to_string()));
+  if play {
+    remote.player.play_track(&track);
+    let server = remote.server.clone();
+    let _ = gio::spawn_blocking(move || server.scrobble(&song)).await;
+    remote.status.set_text("");
+  } else {
+    remote.player.enqueue([track]);
+    remote.status.set_text("Queued");
+  }
+}
+
+fn song_row(remote: &Remote, song: Song) -> gtk::Box {
+  let row = gtk::Box::new(Orientation::Horizontal, 6);
+  let label = Label::builder()
+    .label(format!(
+      "{} - {} - {}",
+      str_or_unknown(&song.artist),
+      str_or_unknown(&song.album),
+      str_or_unknown(&song.title)
+    ))
+    .hexpand(true)
+    .xalign(0.0)
+    .build();
+  let play_button = Button::from_icon_name("media-playback-start-symbolic");
+  play_button.set_tooltip_text(Some("Play"));
+  let queue_button = Button::from_icon_name("list-add-symbolic");
+  queue_button.set_tooltip_text(Some("Queue"));
+  row.append(&label);
+  row.append(&play_button);
+  row.append(&queue_button);
+
+  let remote1 = remote.clone();
+  let song1 = song.clone();
+  play_button.connect_clicked(move |_| {
+    MainContext::default().spawn_local(fetch(remote1.clone(), song1.clone(), true));
+  });
+  let remote1 = remote.clone();
+  queue_button.connect_clicked(move |_| {
+    MainContext::default().spawn_local(fetch(remote1.clone(), song.clone(), false));
+  });
+  row
+}
+
+fn show_songs(remote: &Remote, songs: Vec<Song>) {
+  clear(&remote.results);
+  remote.status.set_text(&format!("{} songs", songs.len()));
+  for song in songs {
+    remote.results.append(&song_row(remote, song));
+  }
+}
+
+// Runs a server call off the main thread and shows what it returns
+async fn load<T: Send + 'static>(
+  remote: Remote,
+  call: impl FnOnce(&Server) -> Result<T, String> + Send + 'static,
+  show: impl FnOnce(&Remote, T),
+) {
+  remote.status.set_text("Loading...");
+  let server = remote.server.clone();
+  match gio::spawn_blocking(move || call(&server)).await {
+    Ok(Ok(result)) => show(&remote, result),
+    Ok(Err(e)) => remote.status.set_text(&e),
+    Err(_) => (),
+  }
+}
+
+fn album_row(remote: &Remote, album: RemoteAlbum) -> Button {
+  let year = album.year.map_or(String::new(), |y| format!(" ({})", y));
+  let button = Button::builder()
+    .label(format!(
+      "{} - {}{}",
+      str_or_unknown(&album.artist),
+      str_or_unknown(&album.name),
+      year
+    ))
+    .build();
+  button.add_css_class("flat");
+  let remote = remote.clone();
+  button.connect_clicked(move |_| {
+    let id = album.id.clone();
+    MainContext::default().spawn_local(load(
+      remote.clone(),
+      move |server| server.album_songs(&id),
+      show_songs,
+    ));
+  });
+  button
+}
+
+fn show_albums(remote: &Remote, albums: Vec<RemoteAlbum>) {
+  clear(&remote.results);
+  remote.status.set_text(&format!("{} albums", albums.len()));
+  for album in albums {
+    remote.results.append(&album_row(remote, album));
+  }
+}
+
+// Browses and searches a Subsonic compatible server. Songs are downloaded
+// to the cache when played, then play like any local file
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, player: Rc<Player>, server: Server) {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let search_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let search_entry = SearchEntry::builder()
+    .placeholder_text("Search the server")
+    .hexpand(true)
+    .build();
+  let newest_button = Button::builder().label("Newest albums").build();
+  let remote = Remote {
+    server: Arc::new(server),
+    player,
+    results: ListBox::new(),
+    status: Label::new(None),
+  };
+
+  search_row.append(&search_entry);
+  search_row.append(&newest_button);
+  f.append(&search_row);
+  f.append(&remote.status);
+  f.append(
+    &ScrolledWindow::builder()
+      .child(&remote.results)
+      .vexpand(true)
+      .build(),
+  );
+  let remote_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .default_width(800)
+    .default_height(600)
+    .title(format!("Remote library: {}", remote.server.url))
+    .child(&f)
+    .build();
+
+  let remote1 = remote.clone();
+  search_entry.connect_activate(move |entry| {
+    let query = entry.text().to_string();
+    if query.is_empty() {
+      return;
+    }
+    MainContext::default().spawn_local(load(
+      remote1.clone(),
+      move |server| server.search(&query),
+      show_songs,
+    ));
+  });
+  let remote1 = remote.clone();
+  newest_button.connect_clicked(move |_| {
+    MainContext::default().spawn_local(load(remote1.clone(), Server::newest_albums, show_albums));
+  });
+  // rows hold on to the list through their buttons, so drop them on close
+  let results = remote.results.clone();
+  remote_dialog.connect_close_request(move |_| {
+    clear(&results);
+    Propagation::Proceed
+  });
+  remote_dialog.present();
+
+  load(remote, Server::ping, |remote, ()| {
+    remote
+      .status
+      .set_text("Connected, search or browse the newest albums")
+  })
+  .await;
+}
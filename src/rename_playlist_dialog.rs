@@ -0,0 +1,57 @@
+use crate::gtk_helpers::show_toast;
+use crate::playlist_manager::load_playlist_mgr_store;
+use adw::prelude::*;
+use fml9000::playlists::rename_playlist;
+use gtk::gio::ListStore;
+use gtk::glib;
+use gtk::{Button, Entry, Orientation};
+use std::rc::Rc;
+
+pub async fn dialog<W: IsA<gtk::Window>>(
+  wnd: Rc<W>,
+  playlist_id: i32,
+  name: String,
+  playlist_mgr_store: ListStore,
+) {
+  let f = gtk::Box::new(Orientation::Horizontal, 0);
+
+  let rename_button = Button::builder().label("Rename").build();
+  let textbox = Entry::builder()
+    .text(&name)
+    .hexpand(true)
+    .activates_default(true)
+    .build();
+
+  f.append(&textbox);
+  f.append(&rename_button);
+  let rename_playlist_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(400)
+    .title("Rename playlist")
+    .child(&f)
+    .default_widget(&rename_button)
+    .build();
+
+  rename_button.connect_clicked(glib::clone!(
+    #[weak]
+    textbox,
+    #[weak]
+    rename_playlist_dialog,
+    move |_| {
+      let name = textbox.text();
+      if name.is_empty() {
+        return;
+      }
+      match rename_playlist(playlist_id, &name) {
+        Ok(_) => load_playlist_mgr_store(&playlist_mgr_store),
+        Err(e) => show_toast(
+          &textbox,
+          &format!("Failed to rename playlist to {}: {}", name, e),
+        ),
+      }
+      rename_playlist_dialog.close();
+    }
+  ));
+  rename_playlist_dialog.present();
+}
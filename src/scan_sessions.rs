@@ -0,0 +1,93 @@
+use crate::connect_db;
+use crate::models::NewScanSession;
+use crate::schema::{scan_sessions, tracks};
+use chrono::NaiveDateTime;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use tracing::error;
+
+// A batch of files that were added to the library by one scan
+pub struct ScanSession {
+  pub id: i32,
+  pub folder: String,
+  pub started: Option<NaiveDateTime>,
+  pub count: i64,
+}
+
+impl ScanSession {
+  // e.g. "Added Jan 14 — 125 tracks from /music/new"
+  pub fn describe(&self) -> String {
+    let date = self
+      .started
+      .map_or("recently".to_string(), |d| d.format("%b %-d").to_string());
+    format!(
+      "Added {} — {} tracks from {}",
+      date, self.count, self.folder
+    )
+  }
+}
+
+// Inside write_db, so the id read back is the one just inserted
+pub fn start_session(conn: &mut SqliteConnection, folder: &str) -> QueryResult<i32> {
+  diesel::insert_into(scan_sessions::table)
+    .values(NewScanSession { folder })
+    .execute(conn)?;
+  scan_sessions::table
+    .select(scan_sessions::id)
+    .order(scan_sessions::id.desc())
+    .first(conn)
+}
+
+// Scans that found nothing new are not worth listing
+pub fn discard_session(conn: &mut SqliteConnection, session_id: i32) -> QueryResult<usize> {
+  diesel::delete(scan_sessions::table.filter(scan_sessions::id.eq(session_id))).execute(conn)
+}
+
+// Most recent first
+pub fn recent_sessions(limit: i64) -> Vec<ScanSession> {
+  let conn = &mut connect_db();
+  scan_sessions::table
+    .inner_join(tracks::table)
+    .group_by((
+      scan_sessions::id,
+      scan_sessions::folder,
+      scan_sessions::started,
+    ))
+    .select((
+      scan_sessions::id,
+      scan_sessions::folder,
+      scan_sessions::started,
+      count_star(),
+    ))
+    .order(scan_sessions::id.desc())
+    .limit(limit)
+    .load::<(i32, String, Option<NaiveDateTime>, i64)>(conn)
+    .map(|rows| {
+      rows
+        .into_iter()
+        .map(|(id, folder, started, count)| ScanSession {
+          id,
+          folder,
+          started,
+          count,
+        })
+        .collect()
+    })
+    .unwrap_or_else(|e| {
+      error!("Failed to load scan sessions: {}", e);
+      Vec::new()
+    })
+}
+
+pub fn session_filenames(session_id: i32) -> Vec<String> {
+  let conn = &mut connect_db();
+  tracks::table
+    .filter(tracks::scan_session_id.eq(session_id))
+    .order(tracks::filename)
+    .select(tracks::filename)
+    .load(conn)
+    .unwrap_or_else(|e| {
+      error!("Failed to load scan session {}: {}", session_id, e);
+      Vec::new()
+    })
+}
@@ -0,0 +1,589 @@
+use crate::chunked_iterator::ChunkedIterator;
+use crate::fingerprint::{self, Fingerprint};
+use crate::interned::Interned;
+use crate::metadata::{self, Metadata, MetadataProvider};
+use crate::models::{NewTrack, Track};
+use crate::schema::tracks;
+use crate::writer::Writer;
+use crate::{checksums, continuous, folders, platform, scan_sessions};
+use chrono::{DateTime, Local, NaiveDateTime};
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Instant;
+use tracing::{error, info, info_span};
+use walkdir::{DirEntry, WalkDir};
+
+const TRANSACTION_SIZE: usize = 20;
+
+// The slow extras, both off unless turned on in preferences. Only new files
+// get them
+#[derive(Clone, Copy, Default)]
+pub struct ScanOptions {
+  // sha256 of each file, for verify_dialog
+  pub checksums: bool,
+  // chromaprint fingerprints of untagged files, for identify_dialog
+  pub fingerprints: bool,
+}
+
+// What scanning one folder found
+#[derive(Default)]
+pub struct ScanReport {
+  pub seen: usize,
+  pub added: usize,
+  // already in the library, read again since the file changed after the
+  // last scan or its row lacks what older versions didn't store
+  pub updated: usize,
+  // in the library under the folder but gone from disk. They are kept, like
+  // tracks under disabled folders, in case the drive is only unmounted
+  pub stale: Vec<String>,
+  // the session the new files were recorded under, if there were any
+  pub session_id: Option<i32>,
+}
+
+// A file as the scanner read it, before anything is written
+struct ScannedFile {
+  filename: String,
+  metadata: Metadata,
+  // sha256 and modification time, when checksums are on
+  checksum: Option<(String, i64)>,
+  fingerprint: Option<Fingerprint>,
+  // already has a row, which is updated rather than added
+  known: bool,
+}
+
+fn read_file(
+  path: &Path,
+  filename: String,
+  providers: &[Box<dyn MetadataProvider>],
+  options: ScanOptions,
+  known: bool,
+) -> Option<ScannedFile> {
+  let metadata = metadata::read_metadata(providers, path)?;
+  let checksum = if options.checksums && !known {
+    checksums::file_checksum(path)
+  } else {
+    None
+  };
+  let fingerprint = if options.fingerprints && !known && fingerprint::is_untagged(&metadata) {
+    fingerprint::scan_fingerprint(path)
+  } else {
+    None
+  };
+  Some(ScannedFile {
+    filename,
+    metadata,
+    checksum,
+    fingerprint,
+    known,
+  })
+}
+
+// Run inside a write. Any failure rolls back the whole batch
+fn save_scanned(
+  conn: &mut SqliteConnection,
+  files: &[ScannedFile],
+  session_id: Option<i32>,
+) -> QueryResult<usize> {
+  let mut added = 0;
+  for file in files {
+    let m = &file.metadata;
+    if file.known {
+      diesel::update(tracks::table.filter(tracks::filename.eq(&file.filename)))
+        .set((
+          tracks::artist.eq(m.artist.as_deref()),
+          tracks::album.eq(m.album.as_deref()),
+          tracks::album_artist.eq(m.album_artist.as_deref()),
+          tracks::title.eq(m.title.as_deref()),
+          tracks::track.eq(m.track.as_deref()),
+          tracks::genre.eq(m.genre.as_deref()),
+          tracks::year.eq(m.year),
+          tracks::duration_ms.eq(m.duration_ms),
+        ))
+        .execute(conn)?;
+    } else {
+      // or ignore, since two files whose names aren't UTF-8 can end up
+      // under the same library path
+      added += diesel::insert_or_ignore_into(tracks::table)
+        .values(NewTrack {
+          filename: &file.filename,
+          artist: m.artist.as_deref(),
+          album: m.album.as_deref(),
+          album_artist: m.album_artist.as_deref(),
+          title: m.title.as_deref(),
+          track: m.track.as_deref(),
+          genre: m.genre.as_deref(),
+          year: m.year,
+          duration_ms: m.duration_ms,
+          scan_session_id: session_id,
+        })
+        .execute(conn)?;
+    }
+    if let Some((sha256, modified_ms)) = &file.checksum {
+      checksums::store(conn, &file.filename, sha256, *modified_ms)?;
+    }
+    if let Some(fp) = &file.fingerprint {
+      fingerprint::store(conn, &file.filename, fp)?;
+    }
+    if m.gapless {
+      let key = (
+        m.album_artist
+          .as_deref()
+          .or(m.artist.as_deref())
+          .map(Interned::from),
+        m.album.as_deref().map(Interned::from),
+      );
+      continuous::mark_continuous(conn, &key)?;
+    }
+  }
+  Ok(added)
+}
+
+// The library's files, and whether each has everything the scanner stores.
+// Rows from before years and lengths were stored are read again. Lengths
+// came later, so a missing one covers both
+fn known_files(rows: &[Track]) -> HashMap<&str, bool> {
+  rows
+    .iter()
+    .map(|t| (t.filename.as_str(), t.duration_ms.is_some()))
+    .collect()
+}
+
+fn modified_since(entry: &DirEntry, since: Option<NaiveDateTime>) -> bool {
+  let Some(since) = since else {
+    return false;
+  };
+  entry
+    .metadata()
+    .ok()
+    .and_then(|m| m.modified().ok())
+    .is_some_and(|t| DateTime::<Local>::from(t).naive_local() > since)
+}
+
+// Adds new files under the folder and reads changed ones again. rows is the
+// library as it was before the scan, and last_scan when the folder was last
+// scanned, both read by the caller
+pub fn scan_folder(
+  writer: &Writer,
+  folder: &str,
+  rows: &[Track],
+  last_scan: Option<NaiveDateTime>,
+  providers: &[Box<dyn MetadataProvider>],
+  options: ScanOptions,
+) -> ScanReport {
+  let _span = info_span!("scan").entered();
+  let started = Instant::now();
+  let scanned_at = Local::now().naive_local();
+  let known = known_files(rows);
+  let mut report = ScanReport {
+    session_id: match writer.write(|conn| scan_sessions::start_session(conn, folder)) {
+      Ok(id) => Some(id),
+      Err(e) => {
+        error!("Failed to record scan session: {}", e);
+        None
+      }
+    },
+    ..Default::default()
+  };
+  let mut present = HashSet::new();
+
+  for chunk in ChunkedIterator::new(
+    WalkDir::new(folder).into_iter().filter_map(|e| e.ok()),
+    TRANSACTION_SIZE,
+  ) {
+    // tags, checksums and fingerprints are read before taking the write
+    // lock, which is only held for the inserts
+    let files: Vec<ScannedFile> = chunk
+      .iter()
+      .filter(|file| file.file_type().is_file())
+      .filter_map(|file| {
+        report.seen += 1;
+        let filename = platform::library_path(file.path());
+        let known = match known.get(filename.as_str()) {
+          None => false,
+          Some(complete) if !complete || modified_since(file, last_scan) => true,
+          Some(_) => {
+            present.insert(filename);
+            return None;
+          }
+        };
+        present.insert(filename.clone());
+        read_file(file.path(), filename, providers, options, known)
+      })
+      .collect();
+    if files.is_empty() {
+      continue;
+    }
+    // one transaction per chunk, so an interrupted scan never leaves a
+    // chunk half written, and sqlite is much faster that way
+    match writer.write(|conn| save_scanned(conn, &files, report.session_id)) {
+      Ok(n) => {
+        report.added += n;
+        report.updated += files.iter().filter(|f| f.known).count();
+      }
+      Err(e) => error!("Failed to save scanned files: {}", e),
+    }
+  }
+
+  report.stale = rows
+    .iter()
+    .filter(|t| folders::contains(folder, &t.filename) && !present.contains(&t.filename))
+    .map(|t| t.filename.clone())
+    .collect();
+  if let Err(e) = writer.write(|conn| folders::record_scan(conn, folder, scanned_at)) {
+    error!("Failed to record scan of {}: {}", folder, e);
+  }
+  // files per second is the baseline for scanner changes, see benches/scan.rs
+  let elapsed = started.elapsed();
+  info!(
+    "Scanned {} files under {} in {:.2?} ({:.0} files/s), {} new, {} updated, {} missing",
+    report.seen,
+    folder,
+    elapsed,
+    report.seen as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+    report.added,
+    report.updated,
+    report.stale.len()
+  );
+
+  if let (Some(session_id), 0) = (report.session_id, report.added) {
+    if let Err(e) = writer.write(|conn| scan_sessions::discard_session(conn, session_id)) {
+      error!("Failed to discard scan session: {}", e);
+    }
+    report.session_id = None;
+  }
+  report
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory_db;
+  use crate::metadata::default_providers;
+  use crate::models::TagUpdate;
+  use crate::schema::{continuous_albums, file_checksums, fingerprints};
+  use crate::tag_writer::write_file_tags;
+  use std::fs::File;
+  use std::io::Write;
+  use std::path::PathBuf;
+  use std::time::{Duration, SystemTime};
+  use tempfile::TempDir;
+
+  fn scanned(filename: &str, gapless: bool) -> ScannedFile {
+    ScannedFile {
+      filename: filename.to_string(),
+      metadata: Metadata {
+        artist: Some("Artist".to_string()),
+        album: Some("Album".to_string()),
+        gapless,
+        ..Default::default()
+      },
+      checksum: Some(("abc".to_string(), 1)),
+      fingerprint: None,
+      known: false,
+    }
+  }
+
+  fn count(conn: &mut SqliteConnection) -> (i64, i64, i64) {
+    (
+      tracks::table.count().get_result(conn).unwrap(),
+      file_checksums::table.count().get_result(conn).unwrap(),
+      continuous_albums::table.count().get_result(conn).unwrap(),
+    )
+  }
+
+  // A second of 8 kHz mono silence
+  fn write_wav(path: &Path) {
+    let samples = 8000u32;
+    let data_len = samples * 2;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&8000u32.to_le_bytes());
+    wav.extend_from_slice(&16000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.resize(wav.len() + data_len as usize, 0);
+    File::create(path).unwrap().write_all(&wav).unwrap();
+  }
+
+  fn tag(path: &Path, title: &str) {
+    let update = TagUpdate {
+      artist: Some("Artist".to_string()),
+      title: Some(title.to_string()),
+      album: Some("Album".to_string()),
+    };
+    write_file_tags(path.to_str().unwrap(), &update).unwrap();
+  }
+
+  fn fixture(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    write_wav(&path);
+    tag(&path, name);
+    path
+  }
+
+  struct Library {
+    dir: TempDir,
+    writer: Writer,
+  }
+
+  impl Library {
+    fn new() -> Self {
+      Library {
+        dir: TempDir::new().unwrap(),
+        writer: Writer::new(memory_db()),
+      }
+    }
+
+    fn folder(&self) -> &str {
+      self.dir.path().to_str().unwrap()
+    }
+
+    fn tracks(&self) -> Vec<Track> {
+      self.writer.write(|conn| tracks::table.load(conn)).unwrap()
+    }
+
+    fn last_scan(&self) -> Option<NaiveDateTime> {
+      self
+        .writer
+        .write(|conn| folders::last_scan(conn, self.folder()))
+        .unwrap()
+    }
+
+    fn scan(&self) -> ScanReport {
+      let rows = self.tracks();
+      scan_folder(
+        &self.writer,
+        self.folder(),
+        &rows,
+        self.last_scan(),
+        &default_providers(&[]),
+        ScanOptions::default(),
+      )
+    }
+  }
+
+  #[test]
+  fn saves_a_whole_batch() {
+    let conn = &mut memory_db();
+    let session_id = scan_sessions::start_session(conn, "/music").unwrap();
+    let files = [
+      scanned("/music/a.flac", true),
+      scanned("/music/b.flac", false),
+    ];
+    let added = conn
+      .immediate_transaction(|conn| save_scanned(conn, &files, Some(session_id)))
+      .unwrap();
+    assert_eq!(added, 2);
+    assert_eq!(count(conn), (2, 2, 1));
+  }
+
+  #[test]
+  fn failed_batch_saves_nothing() {
+    let conn = &mut memory_db();
+    // the second file fails, since there is no such scan session
+    let files = [
+      scanned("/music/a.flac", true),
+      scanned("/music/b.flac", true),
+    ];
+    let result = conn.immediate_transaction(|conn| {
+      save_scanned(conn, &files[..1], None)?;
+      save_scanned(conn, &files[1..], Some(42))
+    });
+    assert!(result.is_err());
+    assert_eq!(count(conn), (0, 0, 0));
+  }
+
+  #[test]
+  fn fills_in_missing_lengths() {
+    let conn = &mut memory_db();
+    diesel::insert_into(tracks::table)
+      .values(tracks::filename.eq("/music/old.flac"))
+      .execute(conn)
+      .unwrap();
+    let mut file = scanned("/music/old.flac", false);
+    file.metadata.duration_ms = Some(183_000);
+    file.metadata.year = Some(1997);
+    file.known = true;
+    let added = conn
+      .immediate_transaction(|conn| save_scanned(conn, &[file], None))
+      .unwrap();
+    assert_eq!(added, 0);
+    let row: (Option<i32>, Option<i32>) = tracks::table
+      .select((tracks::duration_ms, tracks::year))
+      .first(conn)
+      .unwrap();
+    assert_eq!(row, (Some(183_000), Some(1997)));
+  }
+
+  #[test]
+  fn saves_fingerprints_with_the_batch() {
+    let conn = &mut memory_db();
+    let mut file = scanned("/music/untagged.flac", false);
+    file.fingerprint = Some(Fingerprint {
+      duration: 183,
+      fingerprint: "AQAA".to_string(),
+    });
+    conn
+      .immediate_transaction(|conn| save_scanned(conn, &[file], None))
+      .unwrap();
+    let row: (String, i32) = fingerprints::table
+      .select((fingerprints::filename, fingerprints::duration))
+      .first(conn)
+      .unwrap();
+    assert_eq!(row, ("/music/untagged.flac".to_string(), 183));
+  }
+
+  #[test]
+  fn same_path_twice_is_added_once() {
+    let conn = &mut memory_db();
+    let files = [
+      scanned("/music/a.flac", false),
+      scanned("/music/a.flac", false),
+    ];
+    let added = conn
+      .immediate_transaction(|conn| save_scanned(conn, &files, None))
+      .unwrap();
+    assert_eq!(added, 1);
+  }
+
+  #[test]
+  fn adds_new_files() {
+    let library = Library::new();
+    fixture(library.dir.path(), "a.wav");
+    fixture(library.dir.path(), "b.wav");
+    let report = library.scan();
+    assert_eq!((report.seen, report.added, report.updated), (2, 2, 0));
+    assert!(report.session_id.is_some());
+    let tracks = library.tracks();
+    assert!(tracks
+      .iter()
+      .all(|t| t.artist.as_deref() == Some("Artist") && t.duration_ms == Some(1000)));
+
+    // nothing changed, so nothing is read again
+    let report = library.scan();
+    assert_eq!((report.seen, report.added, report.updated), (2, 0, 0));
+    assert!(report.session_id.is_none());
+  }
+
+  #[test]
+  fn reads_changed_files_again() {
+    let library = Library::new();
+    let a = fixture(library.dir.path(), "a.wav");
+    fixture(library.dir.path(), "b.wav");
+    library.scan();
+    tag(&a, "Retitled");
+    // later than the scan, whatever the filesystem's timestamp resolution
+    File::options()
+      .write(true)
+      .open(&a)
+      .unwrap()
+      .set_modified(SystemTime::now() + Duration::from_secs(60))
+      .unwrap();
+    let report = library.scan();
+    assert_eq!((report.added, report.updated), (0, 1));
+    let titles: HashSet<Option<String>> = library.tracks().into_iter().map(|t| t.title).collect();
+    assert!(titles.contains(&Some("Retitled".to_string())));
+    assert!(titles.contains(&Some("b.wav".to_string())));
+  }
+
+  #[test]
+  fn reports_stale_files() {
+    let library = Library::new();
+    let a = fixture(library.dir.path(), "a.wav");
+    fixture(library.dir.path(), "b.wav");
+    library.scan();
+    std::fs::remove_file(&a).unwrap();
+    let report = library.scan();
+    assert_eq!(report.stale, vec![platform::library_path(&a)]);
+    // kept until the user removes them
+    assert_eq!(library.tracks().len(), 2);
+  }
+
+  #[test]
+  fn skips_unreadable_files() {
+    let library = Library::new();
+    fixture(library.dir.path(), "a.wav");
+    std::fs::write(library.dir.path().join("broken.mp3"), b"not an mp3").unwrap();
+    std::fs::write(library.dir.path().join("cover.jpg"), b"not a jpeg").unwrap();
+    let report = library.scan();
+    assert_eq!((report.seen, report.added), (3, 1));
+  }
+
+  // fpcalc stand-in, first on the PATH, so the test doesn't need chromaprint
+  #[cfg(unix)]
+  fn fake_fpcalc() -> TempDir {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = TempDir::new().unwrap();
+    let script = dir.path().join("fpcalc");
+    std::fs::write(
+      &script,
+      "#!/bin/sh\necho DURATION=1\necho FINGERPRINT=AQAA\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let paths = std::iter::once(dir.path().to_path_buf()).chain(std::env::split_paths(&path));
+    std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+    dir
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn fingerprints_and_adds_untagged_files() {
+    let _fpcalc = fake_fpcalc();
+    let library = Library::new();
+    write_wav(&library.dir.path().join("untagged.wav"));
+    let rows = library.tracks();
+    let options = ScanOptions {
+      fingerprints: true,
+      ..Default::default()
+    };
+    let report = scan_folder(
+      &library.writer,
+      library.folder(),
+      &rows,
+      library.last_scan(),
+      &default_providers(&[]),
+      options,
+    );
+    assert_eq!(report.added, 1);
+    let tracks = library.tracks();
+    assert!(tracks[0].title.is_none() && tracks[0].duration_ms == Some(1000));
+    let fingerprinted: Vec<String> = library
+      .writer
+      .write(|conn| {
+        fingerprints::table
+          .select(fingerprints::filename)
+          .load(conn)
+      })
+      .unwrap();
+    assert_eq!(fingerprinted, [tracks[0].filename.clone()]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn adds_files_whose_names_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let library = Library::new();
+    let tagged = fixture(library.dir.path(), "tagged.wav");
+    let name = library.dir.path().join(OsStr::from_bytes(b"caf\xe9.wav"));
+    std::fs::rename(&tagged, &name).unwrap();
+    let report = library.scan();
+    assert_eq!(report.added, 1);
+    assert_eq!(library.tracks()[0].filename, platform::library_path(&name));
+
+    let report = library.scan();
+    assert_eq!((report.added, report.updated), (0, 0));
+    assert!(report.stale.is_empty());
+  }
+}
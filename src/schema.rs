@@ -1,5 +1,59 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    continuous_albums (artist, album) {
+        artist -> Text,
+        album -> Text,
+    }
+}
+
+diesel::table! {
+    file_checksums (filename) {
+        filename -> Text,
+        sha256 -> Text,
+        modified_ms -> BigInt,
+    }
+}
+
+diesel::table! {
+    fingerprints (filename) {
+        filename -> Text,
+        duration -> Integer,
+        fingerprint -> Text,
+    }
+}
+
+diesel::table! {
+    folder_scans (folder) {
+        folder -> Text,
+        scanned -> Timestamp,
+    }
+}
+
+diesel::table! {
+    playlist_tracks (id) {
+        id -> Integer,
+        playlist_id -> Integer,
+        filename -> Text,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    playlists (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    plays (id) {
+        id -> Integer,
+        filename -> Text,
+        timestamp -> Timestamp,
+    }
+}
+
 diesel::table! {
     recently_played (filename) {
         filename -> Text,
@@ -7,6 +61,44 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    saved_chapters (filename, start_ms) {
+        filename -> Text,
+        start_ms -> Integer,
+        title -> Text,
+    }
+}
+
+diesel::table! {
+    scan_sessions (id) {
+        id -> Integer,
+        folder -> Text,
+        started -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    track_bpm (filename) {
+        filename -> Text,
+        bpm -> Float,
+    }
+}
+
+diesel::table! {
+    track_key (filename) {
+        filename -> Text,
+        musical_key -> Integer,
+    }
+}
+
+diesel::table! {
+    track_offsets (filename) {
+        filename -> Text,
+        start_ms -> Integer,
+        end_ms -> Nullable<Integer>,
+    }
+}
+
 diesel::table! {
     tracks (filename) {
         filename -> Text,
@@ -17,10 +109,28 @@ diesel::table! {
         album_artist -> Nullable<Text>,
         track -> Nullable<Text>,
         added -> Nullable<Timestamp>,
+        year -> Nullable<Integer>,
+        duration_ms -> Nullable<Integer>,
+        scan_session_id -> Nullable<Integer>,
     }
 }
 
+diesel::joinable!(playlist_tracks -> playlists (playlist_id));
+diesel::joinable!(tracks -> scan_sessions (scan_session_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    continuous_albums,
+    file_checksums,
+    fingerprints,
+    folder_scans,
+    playlist_tracks,
+    playlists,
+    plays,
     recently_played,
+    saved_chapters,
+    scan_sessions,
+    track_bpm,
+    track_key,
+    track_offsets,
     tracks,
 );
@@ -0,0 +1,114 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::ops::Range;
+
+// each field after the first is worth this much less, so e.g. a title match
+// outranks the same match in the filename
+const FIELD_PENALTY: i64 = 20;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SearchMode {
+  #[default]
+  Substring,
+  Fuzzy,
+  Regex,
+}
+
+impl SearchMode {
+  pub const ALL: [SearchMode; 3] = [SearchMode::Substring, SearchMode::Fuzzy, SearchMode::Regex];
+
+  pub fn label(self) -> &'static str {
+    match self {
+      SearchMode::Substring => "Substring",
+      SearchMode::Fuzzy => "Fuzzy",
+      SearchMode::Regex => "Regex",
+    }
+  }
+}
+
+enum Matcher {
+  // an escaped, case insensitive regex, which keeps match offsets right
+  // where lowercasing would change byte lengths
+  Substring(Regex),
+  Fuzzy(Box<SkimMatcherV2>, String),
+  Regex(Regex),
+}
+
+pub struct Query {
+  matcher: Matcher,
+}
+
+impl Query {
+  // The mode can be picked per search: "~query" is fuzzy and "/regex/" a
+  // case insensitive regex, anything else uses the default mode
+  pub fn parse(text: &str, default: SearchMode) -> Result<Query, regex::Error> {
+    let (mode, text) = if let Some(rest) = text.strip_prefix('~') {
+      (SearchMode::Fuzzy, rest)
+    } else if let Some(re) = text
+      .strip_prefix('/')
+      .and_then(|rest| rest.strip_suffix('/'))
+    {
+      (SearchMode::Regex, re)
+    } else {
+      (default, text)
+    };
+    let matcher = match mode {
+      SearchMode::Substring => {
+        Matcher::Substring(Regex::new(&format!("(?i){}", regex::escape(text)))?)
+      }
+      SearchMode::Fuzzy => Matcher::Fuzzy(
+        Box::new(SkimMatcherV2::default().ignore_case()),
+        text.to_string(),
+      ),
+      SearchMode::Regex => Matcher::Regex(Regex::new(&format!("(?i){}", text))?),
+    };
+    Ok(Query { matcher })
+  }
+
+  // Whether results should be ordered by score rather than left as they are
+  pub fn is_ranked(&self) -> bool {
+    matches!(self.matcher, Matcher::Fuzzy(..))
+  }
+
+  fn score(&self, text: &str) -> Option<i64> {
+    match &self.matcher {
+      Matcher::Substring(re) | Matcher::Regex(re) => re.is_match(text).then_some(0),
+      Matcher::Fuzzy(matcher, pattern) => matcher.fuzzy_match(text, pattern),
+    }
+  }
+
+  // Byte ranges of text that the query matched, for highlighting
+  pub fn match_ranges(&self, text: &str) -> Vec<Range<usize>> {
+    match &self.matcher {
+      Matcher::Substring(re) | Matcher::Regex(re) => re
+        .find_iter(text)
+        .filter(|m| !m.is_empty())
+        .map(|m| m.range())
+        .collect(),
+      Matcher::Fuzzy(matcher, pattern) => {
+        let Some((_, indices)) = matcher.fuzzy_indices(text, pattern) else {
+          return Vec::new();
+        };
+        // fuzzy matches come back as char positions
+        text
+          .char_indices()
+          .enumerate()
+          .filter(|(i, _)| indices.contains(i))
+          .map(|(_, (start, c))| start..start + c.len_utf8())
+          .collect()
+      }
+    }
+  }
+
+  // Scores the best matching field, with fields given most important first.
+  // None when nothing matches
+  pub fn score_fields(&self, fields: &[Option<&str>]) -> Option<i64> {
+    fields
+      .iter()
+      .enumerate()
+      .filter_map(|(i, field)| Some(self.score((*field)?)? - i as i64 * FIELD_PENALTY))
+      .max()
+  }
+}
@@ -0,0 +1,103 @@
+use directories::ProjectDirs;
+use serde_derive::{Deserialize, Serialize};
+use std::io::Write;
+use tracing::warn;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Session {
+  pub name: String,
+  pub filenames: Vec<String>,
+  pub current: Option<String>,
+  #[serde(default)]
+  pub position: f64,
+  #[serde(default)]
+  pub queue: Vec<String>,
+  // playlist view scroll offset, only kept for the last session
+  #[serde(default)]
+  pub scroll: f64,
+  // false while the app is running, so a last session left that way was
+  // autosaved before a crash
+  #[serde(default)]
+  pub clean_exit: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SessionFile {
+  #[serde(default)]
+  sessions: Vec<Session>,
+}
+
+pub fn read_sessions() -> Vec<Session> {
+  let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
+  let path = proj_dirs.config_dir().join("sessions.toml");
+
+  match std::fs::read_to_string(&path) {
+    Ok(conf) => match toml::from_str::<SessionFile>(&conf) {
+      Ok(file) => file.sessions,
+      Err(e) => {
+        warn!("Failed to parse {}: {}", path.display(), e);
+        Vec::new()
+      }
+    },
+    Err(_) => Vec::new(),
+  }
+}
+
+fn write_sessions(sessions: Vec<Session>) -> std::io::Result<()> {
+  let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
+  let path = proj_dirs.config_dir();
+
+  std::fs::create_dir_all(path)?;
+
+  let toml = toml::to_string(&SessionFile { sessions }).unwrap();
+  let mut f = std::fs::OpenOptions::new()
+    .create(true)
+    .truncate(true)
+    .write(true)
+    .open(path.join("sessions.toml"))?;
+  write!(f, "{}", toml)
+}
+
+// Saving under an existing name replaces that session
+pub fn save_session(session: Session) -> std::io::Result<()> {
+  let mut sessions = read_sessions();
+  sessions.retain(|s| s.name != session.name);
+  sessions.push(session);
+  write_sessions(sessions)
+}
+
+pub fn delete_session(name: &str) -> std::io::Result<()> {
+  let mut sessions = read_sessions();
+  sessions.retain(|s| s.name != name);
+  write_sessions(sessions)
+}
+
+// The state the app was closed in, restored on the next start
+pub fn read_last_session() -> Option<Session> {
+  let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
+  let path = proj_dirs.config_dir().join("last_session.toml");
+
+  let conf = std::fs::read_to_string(&path).ok()?;
+  match toml::from_str::<Session>(&conf) {
+    Ok(session) => Some(session),
+    Err(e) => {
+      warn!("Failed to parse {}: {}", path.display(), e);
+      None
+    }
+  }
+}
+
+pub fn save_last_session(session: &Session) -> std::io::Result<()> {
+  let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
+  let path = proj_dirs.config_dir();
+
+  std::fs::create_dir_all(path)?;
+
+  let toml = toml::to_string(session).unwrap();
+  let mut f = std::fs::OpenOptions::new()
+    .create(true)
+    .truncate(true)
+    .write(true)
+    .open(path.join("last_session.toml"))?;
+  write!(f, "{}", toml)
+}
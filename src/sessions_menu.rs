@@ -0,0 +1,222 @@
+use crate::player::Player;
+use adw::prelude::*;
+use fml9000::library_index::LibraryIndex;
+use fml9000::load_playlist_store;
+use fml9000::models::Track;
+use fml9000::sessions::{delete_session, read_sessions, save_last_session, save_session, Session};
+use gtk::gio::{self, ListStore};
+use gtk::glib::{self, BoxedAnyObject};
+use gtk::{Button, Entry, MenuButton, Orientation, Popover, ScrolledWindow};
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+use tracing::warn;
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+pub fn current_session(name: &str, player: &Player, playlist_store: &ListStore) -> Session {
+  let filenames = (0..playlist_store.n_items())
+    .filter_map(|i| playlist_store.item(i))
+    .map(|obj| {
+      let r: Ref<Rc<Track>> = obj.downcast_ref::<BoxedAnyObject>().unwrap().borrow();
+      r.filename.clone()
+    })
+    .collect();
+  Session {
+    name: name.to_string(),
+    filenames,
+    current: player.current.borrow().as_ref().map(|t| t.filename.clone()),
+    position: player.position().as_secs_f64(),
+    queue: player
+      .queue
+      .borrow()
+      .iter()
+      .map(|t| t.filename.clone())
+      .collect(),
+    scroll: 0.0,
+    clean_exit: false,
+  }
+}
+
+fn autosave(player: &Player, playlist_store: &ListStore, playlist_wnd: &ScrolledWindow) {
+  let session = Session {
+    scroll: playlist_wnd.vadjustment().value(),
+    ..current_session("Last session", player, playlist_store)
+  };
+  // the whole list gets written out, which is slow for a big library
+  gio::spawn_blocking(move || {
+    if let Err(e) = save_last_session(&session) {
+      warn!("Failed to autosave session: {}", e);
+    }
+  });
+}
+
+// Keeps the last session current on every track change and on a timer, so
+// a crash loses little more than the position within a track
+pub fn add_session_autosave(
+  player: &Rc<Player>,
+  playlist_store: &ListStore,
+  playlist_wnd: &ScrolledWindow,
+) {
+  let player_rc = Rc::downgrade(player);
+  player.connect_track_changed(glib::clone!(
+    #[weak]
+    playlist_store,
+    #[weak]
+    playlist_wnd,
+    move |_| {
+      if let Some(player) = player_rc.upgrade() {
+        autosave(&player, &playlist_store, &playlist_wnd);
+      }
+    }
+  ));
+  let player_rc = Rc::downgrade(player);
+  glib::timeout_add_local(
+    AUTOSAVE_INTERVAL,
+    glib::clone!(
+      #[weak]
+      playlist_store,
+      #[weak]
+      playlist_wnd,
+      #[upgrade_or]
+      glib::ControlFlow::Break,
+      move || {
+        let Some(player) = player_rc.upgrade() else {
+          return glib::ControlFlow::Break;
+        };
+        autosave(&player, &playlist_store, &playlist_wnd);
+        glib::ControlFlow::Continue
+      }
+    ),
+  );
+}
+
+// Brings back the view and queue, and with resume also picks the playing
+// track back up where it was
+pub fn restore_session(
+  session: &Session,
+  player: &Player,
+  playlist_store: &ListStore,
+  library: &LibraryIndex,
+  resume: bool,
+) {
+  playlist_store.remove_all();
+  load_playlist_store(
+    session.filenames.iter().filter_map(|f| library.get(f)),
+    playlist_store,
+  );
+  player.take_context();
+  player.queue.borrow_mut().clear();
+  player.enqueue(library.by_filenames(&session.queue));
+  if !resume {
+    return;
+  }
+  if let Some(track) = session.current.as_ref().and_then(|f| library.get(f)) {
+    player.play_track(track);
+    player.seek(Duration::from_secs_f64(session.position));
+  }
+}
+
+fn fill_session_list(
+  list: &gtk::Box,
+  popover: &Popover,
+  player: &Rc<Player>,
+  playlist_store: &ListStore,
+  library: &Rc<RefCell<LibraryIndex>>,
+) {
+  while let Some(child) = list.first_child() {
+    list.remove(&child);
+  }
+  for session in read_sessions() {
+    let row = gtk::Box::new(Orientation::Horizontal, 0);
+    let restore_button = Button::builder().label(&session.name).hexpand(true).build();
+    let delete_button = Button::builder().label("x").build();
+    row.append(&restore_button);
+    row.append(&delete_button);
+    list.append(&row);
+
+    let name = session.name.clone();
+    delete_button.connect_clicked(glib::clone!(
+      #[weak]
+      list,
+      #[weak]
+      row,
+      move |_| {
+        delete_session(&name).expect("Failed to write");
+        list.remove(&row);
+      }
+    ));
+    restore_button.connect_clicked(glib::clone!(
+      #[weak]
+      popover,
+      #[weak]
+      playlist_store,
+      #[strong]
+      player,
+      #[strong]
+      library,
+      move |_| {
+        restore_session(&session, &player, &playlist_store, &library.borrow(), true);
+        popover.popdown();
+      }
+    ));
+  }
+}
+
+pub fn create_sessions_button(
+  player: &Rc<Player>,
+  playlist_store: &ListStore,
+  library: &Rc<RefCell<LibraryIndex>>,
+) -> MenuButton {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let save_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let name_entry = Entry::builder().placeholder_text("Session name").build();
+  let save_button = Button::builder().label("Save").build();
+  let session_list = gtk::Box::new(Orientation::Vertical, 0);
+
+  save_row.append(&name_entry);
+  save_row.append(&save_button);
+  f.append(&save_row);
+  f.append(&session_list);
+
+  let popover = Popover::builder().child(&f).build();
+  let sessions_btn = MenuButton::builder()
+    .label("Sessions")
+    .popover(&popover)
+    .build();
+
+  popover.connect_show(glib::clone!(
+    #[weak]
+    session_list,
+    #[weak]
+    playlist_store,
+    #[strong]
+    player,
+    #[strong]
+    library,
+    move |popover| {
+      fill_session_list(&session_list, popover, &player, &playlist_store, &library);
+    }
+  ));
+
+  save_button.connect_clicked(glib::clone!(
+    #[weak]
+    name_entry,
+    #[weak]
+    popover,
+    #[weak]
+    playlist_store,
+    #[strong]
+    player,
+    move |_| {
+      let name = name_entry.text();
+      if name.is_empty() {
+        return;
+      }
+      save_session(current_session(&name, &player, &playlist_store)).expect("Failed to write");
+      name_entry.set_text("");
+      popover.popdown();
+    }
+  ));
+  sessions_btn
+}
@@ -1,4 +1,6 @@
 use directories::ProjectDirs;
+use fml9000::playlists::DuplicatePolicy;
+use fml9000::search::SearchMode;
 use serde_derive::{Deserialize, Serialize};
 use std::io::Write;
 
@@ -6,11 +8,132 @@ fn default_volume() -> f64 {
   1.0
 }
 
+fn default_night_start() -> u32 {
+  22
+}
+
+fn default_night_end() -> u32 {
+  7
+}
+
+fn default_night_volume_cap() -> f64 {
+  0.5
+}
+
+fn default_artist_separators() -> Vec<String> {
+  fml9000::artists::DEFAULT_SEPARATORS
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_serve_port() -> u16 {
+  4040
+}
+
+fn default_organize_pattern() -> String {
+  fml9000::organize::DEFAULT_PATTERN.to_string()
+}
+
+// An output that plays along with the main one, see zones
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ZoneSettings {
+  pub device: String,
+  #[serde(default = "default_volume")]
+  pub volume: f64,
+  #[serde(default)]
+  pub delay_ms: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct FmlSettings {
+  // the single folder older versions stored, moved into folders on load
+  #[serde(default, skip_serializing)]
   pub folder: Option<String>,
+  #[serde(default)]
+  pub folders: Vec<String>,
+  // folders kept in the list but left out of scans and views, e.g. a drive
+  // that isn't plugged in
+  #[serde(default)]
+  pub disabled_folders: Vec<String>,
   #[serde(default = "default_volume")]
   pub volume: f64,
+  #[serde(default)]
+  pub embed_fetched_art: bool,
+  #[serde(default = "default_organize_pattern")]
+  pub organize_pattern: String,
+  // new downloads land here to be tagged and filed into the library
+  #[serde(default)]
+  pub inbox_folder: Option<String>,
+  #[serde(default)]
+  pub acoustid_key: Option<String>,
+  // a Subsonic compatible server, e.g. Navidrome, to browse as a remote
+  // library. The password is kept in the settings file like the other keys
+  #[serde(default)]
+  pub subsonic_url: Option<String>,
+  #[serde(default)]
+  pub subsonic_user: String,
+  #[serde(default)]
+  pub subsonic_password: String,
+  // serve the library to Subsonic apps on the network, checked at startup
+  #[serde(default)]
+  pub serve_library: bool,
+  #[serde(default = "default_serve_port")]
+  pub serve_port: u16,
+  #[serde(default)]
+  pub serve_user: String,
+  #[serde(default)]
+  pub serve_password: String,
+  #[serde(default)]
+  pub offline: bool,
+  #[serde(default)]
+  pub night_mode: bool,
+  // turn night mode on from night_start to night_end, in local hours
+  #[serde(default)]
+  pub night_schedule: bool,
+  #[serde(default = "default_night_start")]
+  pub night_start: u32,
+  #[serde(default = "default_night_end")]
+  pub night_end: u32,
+  // the most the volume can be while night mode is on
+  #[serde(default = "default_night_volume_cap")]
+  pub night_volume_cap: f64,
+  #[serde(default)]
+  pub focus_mode: bool,
+  #[serde(default)]
+  pub focus_on_play: bool,
+  #[serde(default)]
+  pub resume_playback: bool,
+  // only playback that was paused for the suspend is resumed
+  #[serde(default)]
+  pub resume_after_suspend: bool,
+  #[serde(default)]
+  pub inhibit_sleep: bool,
+  #[serde(default)]
+  pub discord_presence: bool,
+  #[serde(default)]
+  pub discord_client_id: Option<String>,
+  #[serde(default)]
+  pub search_mode: SearchMode,
+  #[serde(default)]
+  pub playlist_duplicates: DuplicatePolicy,
+  // file extensions to read with ffprobe on top of the built in ones
+  #[serde(default)]
+  pub extra_formats: Vec<String>,
+  // checksum new files as they are scanned, for verify_dialog
+  #[serde(default)]
+  pub checksums: bool,
+  // fingerprint untagged new files as they are scanned, for identify_dialog.
+  // Needs fpcalc
+  #[serde(default)]
+  pub fingerprints: bool,
+  // an HTML file for the exported catalog, see catalog::DEFAULT_TEMPLATE
+  #[serde(default)]
+  pub catalog_template: Option<String>,
+  #[serde(default = "default_artist_separators")]
+  pub artist_separators: Vec<String>,
+  #[serde(default)]
+  pub zones: Vec<ZoneSettings>,
 }
 
 pub fn read_settings() -> FmlSettings {
@@ -19,12 +142,51 @@ pub fn read_settings() -> FmlSettings {
 
   match std::fs::read_to_string(&path) {
     Ok(conf) => {
-      let config: FmlSettings = toml::from_str(&conf).unwrap();
+      let mut config: FmlSettings = toml::from_str(&conf).unwrap();
+      if let Some(folder) = config.folder.take() {
+        if !config.folders.contains(&folder) {
+          config.folders.insert(0, folder);
+        }
+      }
       config
     }
     Err(_) => FmlSettings {
       folder: None,
+      folders: Vec::new(),
+      disabled_folders: Vec::new(),
       volume: 1.0,
+      embed_fetched_art: false,
+      organize_pattern: default_organize_pattern(),
+      inbox_folder: None,
+      acoustid_key: None,
+      subsonic_url: None,
+      subsonic_user: String::new(),
+      subsonic_password: String::new(),
+      serve_library: false,
+      serve_port: default_serve_port(),
+      serve_user: String::new(),
+      serve_password: String::new(),
+      offline: false,
+      night_mode: false,
+      night_schedule: false,
+      night_start: default_night_start(),
+      night_end: default_night_end(),
+      night_volume_cap: default_night_volume_cap(),
+      focus_mode: false,
+      focus_on_play: false,
+      resume_playback: false,
+      resume_after_suspend: false,
+      inhibit_sleep: false,
+      discord_presence: false,
+      discord_client_id: None,
+      search_mode: SearchMode::Substring,
+      playlist_duplicates: DuplicatePolicy::Allow,
+      extra_formats: Vec::new(),
+      checksums: false,
+      fingerprints: false,
+      catalog_template: None,
+      artist_separators: default_artist_separators(),
+      zones: Vec::new(),
     },
   }
 }
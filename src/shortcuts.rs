@@ -0,0 +1,152 @@
+use adw::prelude::*;
+use gtk::gdk::{Key, ModifierType};
+use gtk::gio;
+use gtk::glib::{self, Propagation};
+use gtk::{
+  EventControllerKey, PropagationPhase, ShortcutsGroup, ShortcutsSection, ShortcutsShortcut,
+  ShortcutsWindow, Widget,
+};
+
+pub struct Binding {
+  pub title: &'static str,
+  // GTK accelerator strings
+  pub accels: &'static [&'static str],
+  pub action: &'static str,
+}
+
+// The key controllers and the shortcuts window both read these, so the
+// window always lists what the keys actually do
+pub const WINDOW: &[Binding] = &[
+  Binding {
+    title: "Show keyboard shortcuts",
+    accels: &["<Control>question"],
+    action: "win.show-shortcuts",
+  },
+  Binding {
+    title: "Move focus to the next pane",
+    accels: &["F6"],
+    action: "win.next-pane",
+  },
+  Binding {
+    title: "Toggle the now playing page",
+    accels: &["F12"],
+    action: "win.focus-mode",
+  },
+];
+
+pub const PLAYLIST: &[Binding] = &[
+  Binding {
+    title: "Add to queue",
+    accels: &["<Control>Return", "<Control>KP_Enter"],
+    action: "playlist.enqueue",
+  },
+  Binding {
+    title: "Copy file paths",
+    accels: &["<Control>c"],
+    action: "playlist.copy-path",
+  },
+  Binding {
+    title: "Stop after this track",
+    accels: &["<Control>t"],
+    action: "playlist.stop-after",
+  },
+  Binding {
+    title: "Play selection only",
+    accels: &["<Control>l"],
+    action: "playlist.selection-only",
+  },
+  Binding {
+    title: "Remove from playlist",
+    accels: &["Delete", "KP_Delete"],
+    action: "playlist.remove",
+  },
+];
+
+pub const PLAYLISTS: &[Binding] = &[
+  Binding {
+    title: "Rename playlist",
+    accels: &["F2"],
+    action: "playlists.rename",
+  },
+  Binding {
+    title: "New playlist",
+    accels: &["Insert"],
+    action: "playlists.new",
+  },
+];
+
+const GROUPS: [(&str, &[Binding]); 3] = [
+  ("General", WINDOW),
+  ("Playlist view", PLAYLIST),
+  ("Playlists", PLAYLISTS),
+];
+
+// Shift is only compared when the accelerator asks for it, since it is
+// already part of keys like ? on most layouts
+fn matches(accel: &str, key: Key, modifiers: ModifierType) -> bool {
+  let Some((accel_key, accel_mods)) = gtk::accelerator_parse(accel) else {
+    return false;
+  };
+  let mut mask = ModifierType::CONTROL_MASK | ModifierType::ALT_MASK | ModifierType::SUPER_MASK;
+  if accel_mods.contains(ModifierType::SHIFT_MASK) {
+    mask |= ModifierType::SHIFT_MASK;
+  }
+  key.to_lower() == accel_key.to_lower() && modifiers & mask == accel_mods & mask
+}
+
+// Activates the matching binding's action on the widget. Capture phase, so
+// the bindings win over what the widget would do with the key itself
+pub fn add_key_bindings(widget: &impl IsA<Widget>, bindings: &'static [Binding]) {
+  let controller = EventControllerKey::new();
+  controller.set_propagation_phase(PropagationPhase::Capture);
+  controller.connect_key_pressed(move |controller, key, _, modifiers| {
+    let found = bindings
+      .iter()
+      .find(|b| b.accels.iter().any(|a| matches(a, key, modifiers)));
+    match (found, controller.widget()) {
+      (Some(binding), Some(widget)) => {
+        let _ = widget.activate_action(binding.action, None);
+        Propagation::Stop
+      }
+      _ => Propagation::Proceed,
+    }
+  });
+  widget.add_controller(controller);
+}
+
+fn create_shortcuts_window(wnd: &gtk::ApplicationWindow) -> ShortcutsWindow {
+  let section = ShortcutsSection::builder()
+    .section_name("shortcuts")
+    .build();
+  for (title, bindings) in GROUPS {
+    let group = ShortcutsGroup::builder().title(title).build();
+    for binding in bindings {
+      group.add_shortcut(
+        &ShortcutsShortcut::builder()
+          .title(binding.title)
+          .accelerator(binding.accels.join(" "))
+          .build(),
+      );
+    }
+    section.add_group(&group);
+  }
+  let shortcuts_window = ShortcutsWindow::builder()
+    .transient_for(wnd)
+    .modal(true)
+    .build();
+  shortcuts_window.add_section(&section);
+  shortcuts_window
+}
+
+// Ctrl+? and win.show-shortcuts open the cheat sheet, and the other window
+// bindings go live
+pub fn add_window_shortcuts(wnd: &gtk::ApplicationWindow) {
+  let show = gio::SimpleAction::new("show-shortcuts", None);
+  show.connect_activate(glib::clone!(
+    #[weak]
+    wnd,
+    move |_, _| create_shortcuts_window(&wnd).present()
+  ));
+  wnd.add_action(&show);
+  add_key_bindings(wnd, WINDOW);
+}
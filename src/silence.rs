@@ -0,0 +1,64 @@
+use crate::levels::to_dbfs;
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+
+// loudness is measured over windows this long
+const WINDOW: Duration = Duration::from_millis(50);
+
+// A stretch of silence between two stretches of sound
+#[derive(Clone, Copy)]
+pub struct Gap {
+  pub start: Duration,
+  pub end: Duration,
+}
+
+impl Gap {
+  pub fn length(&self) -> Duration {
+    self.end - self.start
+  }
+}
+
+// Finds where a file goes quieter than threshold_db for at least min_gap,
+// e.g. between the tracks of a mix. Silence at the very start and end of
+// the file is not a gap. Decodes the whole file, so call it off the main
+// thread
+pub fn find_gaps(filename: &str, threshold_db: f32, min_gap: Duration) -> Vec<Gap> {
+  let Ok(file) = File::open(filename) else {
+    return Vec::new();
+  };
+  let Ok(source) = Decoder::new(BufReader::new(file)) else {
+    return Vec::new();
+  };
+  let window_len =
+    (source.sample_rate() as f32 * WINDOW.as_secs_f32()) as usize * source.channels() as usize;
+  let mut gaps = Vec::new();
+  let mut silent_since = None;
+  let mut windows = 0u32;
+  let mut sum = 0.0;
+  let mut n = 0;
+  for sample in source.convert_samples::<f32>() {
+    sum += sample * sample;
+    n += 1;
+    if n < window_len {
+      continue;
+    }
+    let now = WINDOW * windows;
+    let silent = to_dbfs((sum / n as f32).sqrt()) < threshold_db;
+    match (silent, silent_since) {
+      (true, None) => silent_since = Some(now),
+      (false, Some(start)) => {
+        if start > Duration::ZERO && now - start >= min_gap {
+          gaps.push(Gap { start, end: now });
+        }
+        silent_since = None;
+      }
+      _ => (),
+    }
+    windows += 1;
+    sum = 0.0;
+    n = 0;
+  }
+  gaps
+}
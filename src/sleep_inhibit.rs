@@ -0,0 +1,71 @@
+use crate::player::Player;
+use crate::settings::FmlSettings;
+use adw::prelude::*;
+use gtk::glib;
+use gtk::{ApplicationInhibitFlags, ApplicationWindow};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+// how often pausing and preference changes are picked up
+const SYNC_INTERVAL: Duration = Duration::from_secs(2);
+
+fn sync(
+  player: &Player,
+  wnd: &ApplicationWindow,
+  settings: &RefCell<FmlSettings>,
+  cookie: &Cell<Option<u32>>,
+) {
+  let Some(app) = wnd.application() else {
+    return;
+  };
+  let playing = player.current.borrow().is_some() && !player.sink.borrow().is_paused();
+  let wanted = playing && settings.borrow().inhibit_sleep;
+  match (wanted, cookie.get()) {
+    (true, None) => {
+      let id = app.inhibit(
+        Some(wnd),
+        ApplicationInhibitFlags::SUSPEND,
+        Some("Playing music"),
+      );
+      // 0 means the desktop doesn't support inhibiting
+      cookie.set(Some(id).filter(|id| *id != 0));
+    }
+    (false, Some(id)) => {
+      app.uninhibit(id);
+      cookie.set(None);
+    }
+    _ => (),
+  }
+}
+
+// Keeps the system from suspending while something is playing, if the
+// preference is on. Released when paused or stopped
+pub fn add_sleep_inhibit(
+  player: &Rc<Player>,
+  wnd: &Rc<ApplicationWindow>,
+  settings: &Rc<RefCell<FmlSettings>>,
+) {
+  let cookie = Rc::new(Cell::new(None));
+
+  let player_rc = Rc::downgrade(player);
+  let settings_rc = settings.clone();
+  let wnd_rc = Rc::downgrade(wnd);
+  let cookie_rc = cookie.clone();
+  player.connect_track_changed(move |_| {
+    if let (Some(player), Some(wnd)) = (player_rc.upgrade(), wnd_rc.upgrade()) {
+      sync(&player, &wnd, &settings_rc, &cookie_rc);
+    }
+  });
+
+  let player_rc = Rc::downgrade(player);
+  let wnd_rc = Rc::downgrade(wnd);
+  let settings = settings.clone();
+  glib::timeout_add_local(SYNC_INTERVAL, move || {
+    let (Some(player), Some(wnd)) = (player_rc.upgrade(), wnd_rc.upgrade()) else {
+      return glib::ControlFlow::Break;
+    };
+    sync(&player, &wnd, &settings, &cookie);
+    glib::ControlFlow::Continue
+  });
+}
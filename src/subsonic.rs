@@ -0,0 +1,192 @@
+use crate::interned::Interned;
+use crate::models::Track;
+use crate::network::download;
+use directories::ProjectDirs;
+use gtk::glib::{self, Checksum, ChecksumType, Uri};
+use serde_derive::Deserialize;
+use std::path::PathBuf;
+
+const API_VERSION: &str = "1.16.1";
+const CLIENT: &str = "fml9000";
+const SEARCH_LIMIT: &str = "100";
+
+#[derive(Deserialize)]
+struct Envelope {
+  #[serde(rename = "subsonic-response")]
+  response: Response,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+  status: String,
+  error: Option<ApiError>,
+  search_result3: Option<SongList>,
+  album: Option<SongList>,
+  album_list2: Option<AlbumList>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+  message: String,
+}
+
+#[derive(Default, Deserialize)]
+struct SongList {
+  #[serde(default)]
+  song: Vec<Song>,
+}
+
+#[derive(Default, Deserialize)]
+struct AlbumList {
+  #[serde(default)]
+  album: Vec<RemoteAlbum>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Song {
+  pub id: String,
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub album: Option<String>,
+  pub track: Option<i32>,
+  pub year: Option<i32>,
+  // seconds
+  pub duration: Option<i32>,
+  pub suffix: Option<String>,
+}
+
+impl Song {
+  // The song as the player sees it once downloaded to filename
+  pub fn to_track(&self, filename: String) -> Track {
+    Track {
+      filename,
+      artist: self.artist.as_deref().map(Interned::from),
+      title: self.title.clone(),
+      album: self.album.as_deref().map(Interned::from),
+      genre: None,
+      album_artist: None,
+      track: self.track.map(|n| Interned::from(n.to_string())),
+      added: None,
+      year: self.year,
+      duration_ms: self.duration.map(|s| s * 1000),
+      scan_session_id: None,
+    }
+  }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct RemoteAlbum {
+  pub id: String,
+  pub name: Option<String>,
+  pub artist: Option<String>,
+  pub year: Option<i32>,
+}
+
+// A Subsonic compatible server, which Navidrome, Airsonic, Gonic and
+// Jellyfin (with its plugin) all are. Every call is blocking, so run them on
+// a worker thread
+pub struct Server {
+  pub url: String,
+  pub user: String,
+  pub password: String,
+}
+
+impl Server {
+  // Token auth, so the password itself never goes over the wire
+  fn endpoint(&self, method: &str, params: &[(&str, &str)]) -> String {
+    let salt = format!("{:08x}{:08x}", glib::random_int(), glib::random_int());
+    let token = Checksum::new(ChecksumType::Md5)
+      .map(|mut c| {
+        c.update(self.password.as_bytes());
+        c.update(salt.as_bytes());
+        c.string().unwrap_or_default().to_string()
+      })
+      .unwrap_or_default();
+    let mut url = format!(
+      "{}/rest/{}?u={}&t={}&s={}&v={}&c={}&f=json",
+      self.url.trim_end_matches('/'),
+      method,
+      Uri::escape_string(&self.user, None, false),
+      token,
+      salt,
+      API_VERSION,
+      CLIENT
+    );
+    for (key, value) in params {
+      url.push_str(&format!(
+        "&{}={}",
+        key,
+        Uri::escape_string(value, None, false)
+      ));
+    }
+    url
+  }
+
+  fn call(&self, method: &str, params: &[(&str, &str)]) -> Result<Response, String> {
+    let body = download(&self.endpoint(method, params))
+      .ok_or_else(|| format!("Could not reach {}", self.url))?;
+    let envelope: Envelope = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+    let response = envelope.response;
+    if response.status == "ok" {
+      Ok(response)
+    } else {
+      Err(response.error.map_or(response.status, |e| e.message))
+    }
+  }
+
+  pub fn ping(&self) -> Result<(), String> {
+    self.call("ping", &[]).map(|_| ())
+  }
+
+  pub fn newest_albums(&self) -> Result<Vec<RemoteAlbum>, String> {
+    let response = self.call("getAlbumList2", &[("type", "newest"), ("size", "50")])?;
+    Ok(response.album_list2.unwrap_or_default().album)
+  }
+
+  pub fn album_songs(&self, album_id: &str) -> Result<Vec<Song>, String> {
+    let response = self.call("getAlbum", &[("id", album_id)])?;
+    Ok(response.album.unwrap_or_default().song)
+  }
+
+  pub fn search(&self, query: &str) -> Result<Vec<Song>, String> {
+    let response = self.call(
+      "search3",
+      &[
+        ("query", query),
+        ("songCount", SEARCH_LIMIT),
+        ("albumCount", "0"),
+        ("artistCount", "0"),
+      ],
+    )?;
+    Ok(response.search_result3.unwrap_or_default().song)
+  }
+
+  // Fetches the whole file into the cache, since rodio plays from files.
+  // Songs already there are reused
+  pub fn fetch_song(&self, song: &Song) -> Result<PathBuf, String> {
+    let proj_dirs = ProjectDirs::from("com", "github", "fml9000").unwrap();
+    let dir = proj_dirs.cache_dir().join("remote");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let suffix = song.suffix.as_deref().unwrap_or("mp3");
+    // ids are opaque strings, so keep only what is safe in a filename
+    let name: String = song
+      .id
+      .chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+      .collect();
+    let path = dir.join(format!("{}.{}", name, suffix));
+    if !path.exists() {
+      let data = download(&self.endpoint("download", &[("id", &song.id)]))
+        .ok_or_else(|| format!("Failed to download {}", song.id))?;
+      std::fs::write(&path, data).map_err(|e| e.to_string())?;
+    }
+    Ok(path)
+  }
+
+  pub fn scrobble(&self, song: &Song) -> Result<(), String> {
+    self
+      .call("scrobble", &[("id", &song.id), ("submission", "true")])
+      .map(|_| ())
+  }
+}
@@ -0,0 +1,885 @@
+use crate::album_art::cover_path;
+use crate::albums::{album_key, group_albums};
+use crate::interned::Interned;
+use crate::models::Track;
+use crate::transcode::{transcode_stream, Format};
+use gtk::gio::{self, prelude::*};
+use gtk::glib::{self, Checksum, ChecksumType, Uri};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+const API_VERSION: &str = "1.16.1";
+const MAX_THREADS: u32 = 8;
+// a request line and headers bigger than this aren't from a real client
+const MAX_REQUEST_BYTES: u64 = 8192;
+// how long a client may sit idle before its connection, and its worker
+// thread, are let go
+const TIMEOUT_SECS: u32 = 60;
+// when a client asks for another format without saying how big
+const DEFAULT_BITRATE: u32 = 192;
+// most results any list or search hands back at once
+const MAX_COUNT: usize = 500;
+
+// Subsonic error codes
+const ERROR_MISSING_PARAMETER: i32 = 10;
+const ERROR_AUTH: i32 = 40;
+const ERROR_NOT_FOUND: i32 = 70;
+
+type Params = HashMap<String, String>;
+type ApiResult = Result<Value, (i32, String)>;
+
+pub struct ServerConfig {
+  pub port: u16,
+  pub user: String,
+  pub password: String,
+}
+
+// albums::Album, but with its tracks as positions in Index::songs, since
+// requests are answered on worker threads and Rc can't go there
+struct ServedAlbum {
+  artist: Option<Interned>,
+  title: Option<Interned>,
+  year: Option<i32>,
+  duration_ms: i64,
+  // when the newest of its tracks was added
+  created: Option<String>,
+  art: PathBuf,
+  songs: Vec<usize>,
+}
+
+// The library as the server sees it, with lookups by id so requests don't
+// search every track
+#[derive(Default)]
+struct Index {
+  // in album order
+  songs: Vec<Track>,
+  // sorted by artist then title
+  albums: Vec<ServedAlbum>,
+  song_ids: HashMap<String, usize>,
+  album_ids: HashMap<String, usize>,
+  // each album artist's albums, in name order
+  artists: BTreeMap<Option<Interned>, Vec<usize>>,
+  artist_ids: HashMap<String, Option<Interned>>,
+}
+
+impl Index {
+  fn new(tracks: Vec<Track>) -> Self {
+    let tracks: Vec<Rc<Track>> = tracks.into_iter().map(Rc::new).collect();
+    let mut index = Index::default();
+    for album in group_albums(&tracks) {
+      let id = album_id(&album.artist, &album.title);
+      let position = index.albums.len();
+      index.album_ids.insert(id, position);
+      index
+        .artist_ids
+        .insert(artist_id(&album.artist), album.artist.clone());
+      index
+        .artists
+        .entry(album.artist.clone())
+        .or_default()
+        .push(position);
+      let mut songs = Vec::new();
+      for track in &album.tracks {
+        index.song_ids.insert(song_id(track), index.songs.len());
+        songs.push(index.songs.len());
+        index.songs.push(Track::clone(track));
+      }
+      let art = album.art();
+      index.albums.push(ServedAlbum {
+        created: album
+          .tracks
+          .iter()
+          .filter_map(|t| t.added)
+          .max()
+          .map(|d| d.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        artist: album.artist,
+        title: album.title,
+        year: album.year,
+        duration_ms: album.duration_ms,
+        art,
+        songs,
+      });
+    }
+    index
+  }
+
+  fn song(&self, id: &str) -> Option<&Track> {
+    self.song_ids.get(id).map(|&i| &self.songs[i])
+  }
+
+  fn album(&self, id: &str) -> Option<&ServedAlbum> {
+    self.album_ids.get(id).map(|&i| &self.albums[i])
+  }
+
+  fn album_songs<'a>(&'a self, album: &'a ServedAlbum) -> impl Iterator<Item = &'a Track> {
+    album.songs.iter().map(|&i| &self.songs[i])
+  }
+
+  fn artist_albums(&self, id: &str) -> Option<(&Option<Interned>, Vec<&ServedAlbum>)> {
+    let artist = self.artist_ids.get(id)?;
+    let albums = self.artists.get(artist)?;
+    Some((artist, albums.iter().map(|&i| &self.albums[i]).collect()))
+  }
+}
+
+// None while the server isn't running, so there's nothing to keep up to date
+static INDEX: RwLock<Option<Arc<Index>>> = RwLock::new(None);
+
+fn current_index() -> Arc<Index> {
+  INDEX
+    .read()
+    .map_or(None, |index| index.clone())
+    .unwrap_or_default()
+}
+
+fn replace_index(build: impl FnOnce(&Index) -> Index) {
+  if INDEX.read().map_or(true, |index| index.is_none()) {
+    return;
+  }
+  let index = Arc::new(build(&current_index()));
+  if let Ok(mut current) = INDEX.write() {
+    *current = Some(index);
+  }
+}
+
+// What the server offers, the same tracks the UI shows. Called after each
+// scan. Blocking, since it groups the whole library
+pub fn serve_tracks(tracks: &[Track]) {
+  replace_index(|_| Index::new(tracks.to_vec()));
+}
+
+// Follows files that organize moved. Blocking, like serve_tracks
+pub fn rename_tracks(renames: &HashMap<String, String>) {
+  replace_index(|index| {
+    Index::new(
+      index
+        .songs
+        .iter()
+        .map(|t| match renames.get(&t.filename) {
+          Some(to) => Track {
+            filename: to.clone(),
+            ..t.clone()
+          },
+          None => t.clone(),
+        })
+        .collect(),
+    )
+  });
+}
+
+// Drops files deleted from disk. Blocking, like serve_tracks
+pub fn forget_tracks(filenames: &HashSet<String>) {
+  replace_index(|index| {
+    Index::new(
+      index
+        .songs
+        .iter()
+        .filter(|t| !filenames.contains(&t.filename))
+        .cloned()
+        .collect(),
+    )
+  });
+}
+
+fn hex(s: &str) -> String {
+  s.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn md5(s: &str) -> String {
+  let mut checksum = Checksum::new(ChecksumType::Md5).unwrap();
+  checksum.update(s.as_bytes());
+  checksum.string().unwrap_or_default().to_string()
+}
+
+// Ids are the hex of what they stand for, so they stay the same across
+// restarts and rescans without a table to keep them in
+fn song_id(track: &Track) -> String {
+  format!("tr-{}", hex(&track.filename))
+}
+
+fn album_id(artist: &Option<Interned>, title: &Option<Interned>) -> String {
+  format!(
+    "al-{}",
+    hex(&format!(
+      "{}\n{}",
+      artist.as_deref().unwrap_or(""),
+      title.as_deref().unwrap_or("")
+    ))
+  )
+}
+
+fn artist_id(artist: &Option<Interned>) -> String {
+  format!("ar-{}", hex(artist.as_deref().unwrap_or("")))
+}
+
+fn or_unknown(s: &Option<Interned>) -> &str {
+  s.as_deref().unwrap_or("Unknown")
+}
+
+fn content_type(suffix: &str) -> &'static str {
+  match suffix {
+    "mp3" => "audio/mpeg",
+    "flac" => "audio/flac",
+    "ogg" | "oga" | "opus" => "audio/ogg",
+    "m4a" | "aac" => "audio/mp4",
+    "wav" => "audio/wav",
+    "jpg" | "jpeg" => "image/jpeg",
+    "png" => "image/png",
+    _ => "application/octet-stream",
+  }
+}
+
+fn suffix(filename: &str) -> String {
+  Path::new(filename)
+    .extension()
+    .map_or(String::new(), |e| e.to_string_lossy().to_lowercase())
+}
+
+fn album_json(album: &ServedAlbum) -> Value {
+  json!({
+    "id": album_id(&album.artist, &album.title),
+    "name": or_unknown(&album.title),
+    "artist": or_unknown(&album.artist),
+    "artistId": artist_id(&album.artist),
+    "coverArt": album_id(&album.artist, &album.title),
+    "songCount": album.songs.len(),
+    "duration": album.duration_ms / 1000,
+    "year": album.year,
+    "created": album.created,
+  })
+}
+
+// An album as a folder, for getMusicDirectory's older folder based browsing
+fn album_dir_json(album: &ServedAlbum) -> Value {
+  json!({
+    "id": album_id(&album.artist, &album.title),
+    "parent": artist_id(&album.artist),
+    "isDir": true,
+    "title": or_unknown(&album.title),
+    "album": album.title,
+    "artist": album.artist,
+    "year": album.year,
+    "coverArt": album_id(&album.artist, &album.title),
+    "created": album.created,
+  })
+}
+
+fn song_json(track: &Track) -> Value {
+  let (artist, album) = album_key(track);
+  let suffix = suffix(&track.filename);
+  // "3/12" style track numbers too
+  let number = track
+    .track
+    .as_deref()
+    .and_then(|n| n.split('/').next())
+    .and_then(|n| n.trim().parse::<i32>().ok());
+  json!({
+    "id": song_id(track),
+    "parent": album_id(&artist, &album),
+    "isDir": false,
+    "title": track.title.as_deref().unwrap_or(&track.filename),
+    "album": track.album,
+    "artist": track.artist,
+    "track": number,
+    "year": track.year,
+    "genre": track.genre,
+    "coverArt": album_id(&artist, &album),
+    "contentType": content_type(&suffix),
+    "suffix": suffix,
+    "duration": track.duration_ms.map(|ms| ms / 1000),
+    "path": track.filename,
+    "albumId": album_id(&artist, &album),
+    "artistId": artist_id(&artist),
+    "type": "music",
+    "created": track.added.map(|d| d.format("%Y-%m-%dT%H:%M:%S").to_string()),
+  })
+}
+
+fn artist_json(artist: &Option<Interned>, album_count: usize) -> Value {
+  json!({
+    "id": artist_id(artist),
+    "name": or_unknown(artist),
+    "albumCount": album_count,
+  })
+}
+
+// Artists grouped under their first letter, for getArtists and getIndexes
+fn artist_index(index: &Index) -> Vec<Value> {
+  let mut letters: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+  for (artist, albums) in &index.artists {
+    let letter = match or_unknown(artist).chars().next() {
+      Some(c) if c.is_alphabetic() => c.to_uppercase().to_string(),
+      _ => "#".to_string(),
+    };
+    letters
+      .entry(letter)
+      .or_default()
+      .push(artist_json(artist, albums.len()));
+  }
+  letters
+    .into_iter()
+    .map(|(name, artist)| json!({ "name": name, "artist": artist }))
+    .collect()
+}
+
+fn param<'a>(params: &'a Params, key: &str) -> Result<&'a str, (i32, String)> {
+  params.get(key).map(String::as_str).ok_or((
+    ERROR_MISSING_PARAMETER,
+    format!("Required parameter {} is missing", key),
+  ))
+}
+
+fn count(params: &Params, key: &str, default: usize) -> usize {
+  params
+    .get(key)
+    .and_then(|n| n.parse().ok())
+    .unwrap_or(default)
+    .min(MAX_COUNT)
+}
+
+fn page<T>(items: impl Iterator<Item = T>, params: &Params, prefix: &str) -> Vec<T> {
+  items
+    .skip(count(params, &format!("{}Offset", prefix), 0))
+    .take(count(params, &format!("{}Count", prefix), 20))
+    .collect()
+}
+
+fn not_found(what: &str) -> (i32, String) {
+  (ERROR_NOT_FOUND, format!("{} not found", what))
+}
+
+fn album_list(albums: &[ServedAlbum], params: &Params) -> Vec<Value> {
+  let mut sorted: Vec<&ServedAlbum> = albums.iter().collect();
+  match params.get("type").map(String::as_str) {
+    Some("newest") => sorted.sort_by_key(|a| std::cmp::Reverse(&a.created)),
+    Some("alphabeticalByName") => sorted.sort_by(|a, b| a.title.cmp(&b.title)),
+    Some("byYear") => sorted.sort_by_key(|a| a.year),
+    Some("random") => {
+      for i in (1..sorted.len()).rev() {
+        sorted.swap(i, glib::random_int_range(0, i as i32 + 1) as usize);
+      }
+    }
+    // group_albums already sorts by artist
+    _ => (),
+  }
+  sorted
+    .into_iter()
+    .skip(count(params, "offset", 0))
+    .take(count(params, "size", 10))
+    .map(album_json)
+    .collect()
+}
+
+fn search(index: &Index, params: &Params) -> Value {
+  // an empty query lists everything, which some clients use to sync
+  let query = params
+    .get("query")
+    .map_or(String::new(), |q| q.trim_matches('"').to_lowercase());
+  let matches = |s: Option<&str>| s.unwrap_or("").to_lowercase().contains(&query);
+  let artists = index
+    .artists
+    .iter()
+    .filter(|(artist, _)| matches(artist.as_deref()));
+  let found_albums = index
+    .albums
+    .iter()
+    .filter(|a| matches(a.title.as_deref()) || matches(a.artist.as_deref()));
+  let songs = index.songs.iter().filter(|t| {
+    matches(t.title.as_deref()) || matches(t.artist.as_deref()) || matches(t.album.as_deref())
+  });
+  let artists: Vec<Value> = page(artists, params, "artist")
+    .into_iter()
+    .map(|(artist, albums)| artist_json(artist, albums.len()))
+    .collect();
+  let found_albums: Vec<Value> = page(found_albums, params, "album")
+    .into_iter()
+    .map(album_json)
+    .collect();
+  let songs: Vec<Value> = page(songs, params, "song")
+    .into_iter()
+    .map(song_json)
+    .collect();
+  json!({
+    "searchResult3": {
+      "artist": artists,
+      "album": found_albums,
+      "song": songs,
+    }
+  })
+}
+
+fn api_call(index: &Index, method: &str, params: &Params) -> ApiResult {
+  match method {
+    "ping" | "scrobble" => Ok(json!({})),
+    "getLicense" => Ok(json!({ "license": { "valid": true } })),
+    "getMusicFolders" => Ok(json!({
+      "musicFolders": { "musicFolder": [{ "id": 1, "name": "Music" }] }
+    })),
+    "getArtists" => Ok(json!({
+      "artists": { "ignoredArticles": "", "index": artist_index(index) }
+    })),
+    "getIndexes" => Ok(json!({
+      "indexes": { "ignoredArticles": "", "lastModified": 0, "index": artist_index(index) }
+    })),
+    // artists hold albums and albums hold songs, the same ids as the
+    // browsing by tags calls
+    "getMusicDirectory" => {
+      let id = param(params, "id")?;
+      if let Some(album) = index.album(id) {
+        return Ok(json!({
+          "directory": {
+            "id": id,
+            "parent": artist_id(&album.artist),
+            "name": or_unknown(&album.title),
+            "child": index.album_songs(album).map(song_json).collect::<Vec<_>>(),
+          }
+        }));
+      }
+      let (artist, albums) = index.artist_albums(id).ok_or(not_found("Directory"))?;
+      Ok(json!({
+        "directory": {
+          "id": id,
+          "name": or_unknown(artist),
+          "child": albums.into_iter().map(album_dir_json).collect::<Vec<_>>(),
+        }
+      }))
+    }
+    "getArtist" => {
+      let (artist, albums) = index
+        .artist_albums(param(params, "id")?)
+        .ok_or(not_found("Artist"))?;
+      let mut result = artist_json(artist, albums.len());
+      result["album"] = albums.into_iter().map(album_json).collect();
+      Ok(json!({ "artist": result }))
+    }
+    "getAlbum" => {
+      let album = index
+        .album(param(params, "id")?)
+        .ok_or(not_found("Album"))?;
+      let mut result = album_json(album);
+      result["song"] = index.album_songs(album).map(song_json).collect();
+      Ok(json!({ "album": result }))
+    }
+    // the one listing with sizes, since it takes a stat of the file
+    "getSong" => {
+      let track = index.song(param(params, "id")?).ok_or(not_found("Song"))?;
+      let mut song = song_json(track);
+      song["size"] = json!(std::fs::metadata(&track.filename).map(|m| m.len()).ok());
+      Ok(json!({ "song": song }))
+    }
+    "getAlbumList2" => Ok(json!({
+      "albumList2": { "album": album_list(&index.albums, params) }
+    })),
+    "search3" => Ok(search(index, params)),
+    _ => Err((0, format!("{} is not supported", method))),
+  }
+}
+
+// The parts of the request line and headers that matter here: the target
+// and where a Range request starts
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<(String, Option<u64>)>> {
+  let mut reader = reader.take(MAX_REQUEST_BYTES);
+  let mut line = String::new();
+  reader.read_line(&mut line)?;
+  let mut parts = line.split_whitespace();
+  let target = match (parts.next(), parts.next()) {
+    (Some("GET"), Some(target)) => target.to_string(),
+    _ => return Ok(None),
+  };
+  let mut range = None;
+  loop {
+    line.clear();
+    if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+      break;
+    }
+    if let Some((name, value)) = line.split_once(':') {
+      if name.eq_ignore_ascii_case("range") {
+        range = value
+          .trim()
+          .strip_prefix("bytes=")
+          .and_then(|r| r.split('-').next())
+          .and_then(|start| start.parse().ok());
+      }
+    }
+  }
+  if reader.limit() == 0 {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "Request too large",
+    ));
+  }
+  Ok(Some((target, range)))
+}
+
+fn parse_query(query: &str) -> Params {
+  query
+    .split('&')
+    .filter_map(|pair| pair.split_once('='))
+    .filter_map(|(key, value)| {
+      let value = Uri::unescape_string(&value.replace('+', " "), None::<&str>)?;
+      Some((key.to_string(), value.to_string()))
+    })
+    .collect()
+}
+
+// Either a token, md5(password + salt), or the password itself, optionally
+// hex encoded after "enc:"
+// Looks at every byte whatever the first difference, so how long a wrong
+// guess takes doesn't give away how much of it was right
+fn same_secret(a: &str, b: &str) -> bool {
+  a.len() == b.len()
+    && a
+      .bytes()
+      .zip(b.bytes())
+      .fold(0, |diff, (x, y)| diff | (x ^ y))
+      == 0
+}
+
+fn authorized(config: &ServerConfig, params: &Params) -> bool {
+  if config.password.is_empty() || params.get("u") != Some(&config.user) {
+    return false;
+  }
+  match (params.get("t"), params.get("s"), params.get("p")) {
+    (Some(token), Some(salt), _) => same_secret(
+      &md5(&format!("{}{}", config.password, salt)),
+      &token.to_ascii_lowercase(),
+    ),
+    (_, _, Some(password)) => match password.strip_prefix("enc:") {
+      Some(encoded) => same_secret(&hex(&config.password), &encoded.to_ascii_lowercase()),
+      None => same_secret(password, &config.password),
+    },
+    _ => false,
+  }
+}
+
+fn drop_nulls(value: &mut Value) {
+  match value {
+    Value::Object(map) => {
+      map.retain(|_, v| !v.is_null());
+      map.values_mut().for_each(drop_nulls);
+    }
+    Value::Array(items) => items.iter_mut().for_each(drop_nulls),
+    _ => (),
+  }
+}
+
+fn escape_xml(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+// The JSON form of the API is derived from the XML one: scalars are
+// attributes, objects are child elements and arrays are repeated elements
+fn write_xml(xml: &mut String, name: &str, value: &Value) {
+  let Value::Object(map) = value else {
+    let text = value.as_str().map_or(value.to_string(), str::to_string);
+    xml.push_str(&format!("<{0}>{1}</{0}>", name, escape_xml(&text)));
+    return;
+  };
+  xml.push_str(&format!("<{}", name));
+  let mut children = Vec::new();
+  for (key, value) in map {
+    match value {
+      Value::Object(_) => children.push((key, value)),
+      Value::Array(items) => children.extend(items.iter().map(|item| (key, item))),
+      Value::String(s) => xml.push_str(&format!(" {}=\"{}\"", key, escape_xml(s))),
+      _ => xml.push_str(&format!(" {}=\"{}\"", key, value)),
+    }
+  }
+  if children.is_empty() {
+    xml.push_str("/>");
+    return;
+  }
+  xml.push('>');
+  for (key, child) in children {
+    write_xml(xml, key, child);
+  }
+  xml.push_str(&format!("</{}>", name));
+}
+
+fn reply(out: &mut impl Write, params: &Params, result: ApiResult) -> io::Result<()> {
+  let mut response = Map::new();
+  response.insert("status".to_string(), json!("ok"));
+  response.insert("version".to_string(), json!(API_VERSION));
+  response.insert("type".to_string(), json!("fml9000"));
+  match result {
+    Ok(Value::Object(payload)) => response.extend(payload),
+    Ok(_) => (),
+    Err((code, message)) => {
+      response.insert("status".to_string(), json!("failed"));
+      response.insert(
+        "error".to_string(),
+        json!({ "code": code, "message": message }),
+      );
+    }
+  }
+  let mut response = Value::Object(response);
+  drop_nulls(&mut response);
+  let (content_type, body) = if params.get("f").map(String::as_str) == Some("json") {
+    (
+      "application/json",
+      json!({ "subsonic-response": response }).to_string(),
+    )
+  } else {
+    response["xmlns"] = json!("http://subsonic.org/restapi");
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    write_xml(&mut xml, "subsonic-response", &response);
+    ("text/xml", xml)
+  };
+  write!(
+    out,
+    "HTTP/1.1 200 OK\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+    content_type,
+    body.len()
+  )?;
+  out.write_all(body.as_bytes())
+}
+
+// With Range support, so clients can seek without downloading everything
+fn send_file(out: &mut impl Write, path: &Path, range: Option<u64>) -> io::Result<()> {
+  let mut file = File::open(path)?;
+  let len = file.metadata()?.len();
+  let content_type = content_type(&suffix(&path.to_string_lossy()));
+  match range.filter(|&start| start < len) {
+    Some(start) => {
+      file.seek(SeekFrom::Start(start))?;
+      write!(
+        out,
+        "HTTP/1.1 206 Partial Content\r\nContent-Type: {}\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+        content_type,
+        len - start,
+        start,
+        len - 1,
+        len
+      )?;
+    }
+    None => write!(
+      out,
+      "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+      content_type, len
+    )?,
+  }
+  io::copy(&mut file, out)?;
+  Ok(())
+}
+
+// Pipes ffmpeg's output straight to the client, stopping ffmpeg if the
+// client goes away
+fn send_transcoded(
+  out: &mut impl Write,
+  filename: &str,
+  format: Format,
+  bitrate: u32,
+) -> io::Result<()> {
+  let mut child = transcode_stream(filename, format, bitrate)?;
+  let result = write!(
+    out,
+    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nConnection: close\r\n\r\n",
+    content_type(format.extension())
+  )
+  .and_then(|()| match child.stdout.as_mut() {
+    Some(stdout) => io::copy(stdout, out).map(|_| ()),
+    None => Ok(()),
+  });
+  let _ = child.kill();
+  let _ = child.wait();
+  result
+}
+
+// stream, download and getCoverArt, which answer with the file itself.
+// Only files in the library can be fetched
+fn send_media(
+  out: &mut impl Write,
+  method: &str,
+  params: &Params,
+  range: Option<u64>,
+) -> io::Result<()> {
+  let Some(id) = params.get("id") else {
+    return reply(out, params, Err(param(params, "id").unwrap_err()));
+  };
+  let index = current_index();
+  if method == "getCoverArt" {
+    let art = match (index.album(id), index.song(id)) {
+      (Some(album), _) => album.art.clone(),
+      (None, Some(track)) => cover_path(&track.filename),
+      (None, None) => return reply(out, params, Err(not_found("Cover art"))),
+    };
+    return match art.exists() {
+      true => send_file(out, &art, None),
+      false => reply(out, params, Err(not_found("Cover art"))),
+    };
+  }
+  let Some(track) = index.song(id) else {
+    return reply(out, params, Err(not_found("Song")));
+  };
+  let max_bitrate: u32 = params
+    .get("maxBitRate")
+    .and_then(|b| b.parse().ok())
+    .unwrap_or(0);
+  let format = match params.get("format").map(String::as_str) {
+    _ if method == "download" => None,
+    Some("raw") => None,
+    Some(name) => Format::from_name(name),
+    None if max_bitrate > 0 => Some(Format::Mp3),
+    None => None,
+  }
+  .filter(|f| max_bitrate > 0 || f.extension() != suffix(&track.filename));
+  match format {
+    Some(format) => {
+      let bitrate = if max_bitrate > 0 {
+        max_bitrate
+      } else {
+        DEFAULT_BITRATE
+      };
+      send_transcoded(out, &track.filename, format, bitrate)
+    }
+    None => send_file(out, Path::new(&track.filename), range),
+  }
+}
+
+// One request per connection, which every Subsonic client copes with
+fn handle(connection: &gio::SocketConnection, config: &ServerConfig) -> io::Result<()> {
+  connection.socket().set_timeout(TIMEOUT_SECS);
+  let mut reader = BufReader::new(connection.input_stream().into_read());
+  let mut out = io::BufWriter::new(connection.output_stream().into_write());
+  let Some((target, range)) = read_request(&mut reader)? else {
+    return out.write_all(b"HTTP/1.1 405 Method Not Allowed\r\nConnection: close\r\n\r\n");
+  };
+  let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+  let params = parse_query(query);
+  let method = path.trim_start_matches("/rest/").trim_end_matches(".view");
+  if !authorized(config, &params) {
+    let error = (ERROR_AUTH, "Wrong username or password".to_string());
+    return reply(&mut out, &params, Err(error));
+  }
+  match method {
+    "stream" | "download" | "getCoverArt" => send_media(&mut out, method, &params, range)?,
+    _ => reply(
+      &mut out,
+      &params,
+      api_call(&current_index(), method, &params),
+    )?,
+  }
+  out.flush()
+}
+
+// Serves the library to Subsonic apps such as DSub or Symfonium, on every
+// interface so phones on the network can reach it. Connections are handled
+// on a pool of worker threads; the server runs until the returned service
+// is stopped
+pub fn start_server(config: ServerConfig) -> Result<gio::ThreadedSocketService, glib::Error> {
+  let service = gio::ThreadedSocketService::new(Some(MAX_THREADS));
+  // empty until the app hands over the scanned library
+  if let Ok(mut index) = INDEX.write() {
+    index.get_or_insert_with(Default::default);
+  }
+  service.add_inet_port(config.port, None::<&glib::Object>)?;
+  info!("Subsonic server listening on port {}", config.port);
+  service.connect_run(move |_, connection, _| {
+    if let Err(e) = handle(connection, &config) {
+      warn!("Subsonic server: {}", e);
+    }
+    true
+  });
+  service.start();
+  Ok(service)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn track(filename: &str, album: &str) -> Track {
+    Track {
+      filename: filename.to_string(),
+      artist: Some("Artist".into()),
+      title: Some(filename.to_string()),
+      album: Some(album.into()),
+      genre: None,
+      album_artist: None,
+      track: None,
+      added: None,
+      year: None,
+      duration_ms: None,
+      scan_session_id: None,
+    }
+  }
+
+  fn library() -> Index {
+    Index::new(vec![
+      track("b.mp3", "First"),
+      track("a.mp3", "First"),
+      track("c.mp3", "Second"),
+    ])
+  }
+
+  fn ids(children: &Value) -> Vec<&str> {
+    children
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|c| c["id"].as_str().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn music_directories_go_from_artist_to_albums_to_songs() {
+    let index = library();
+    let artist = Some("Artist".into());
+    let first = album_id(&artist, &Some("First".into()));
+    let second = album_id(&artist, &Some("Second".into()));
+    let mut params = Params::new();
+
+    params.insert("id".to_string(), artist_id(&artist));
+    let dir = api_call(&index, "getMusicDirectory", &params).unwrap();
+    assert_eq!(
+      ids(&dir["directory"]["child"]),
+      [first.as_str(), second.as_str()]
+    );
+
+    params.insert("id".to_string(), first);
+    let dir = api_call(&index, "getMusicDirectory", &params).unwrap();
+    let a = song_id(&track("a.mp3", "First"));
+    let b = song_id(&track("b.mp3", "First"));
+    assert_eq!(ids(&dir["directory"]["child"]), [a.as_str(), b.as_str()]);
+
+    params.insert("id".to_string(), "ar-none".to_string());
+    let err = api_call(&index, "getMusicDirectory", &params).unwrap_err();
+    assert_eq!(err.0, ERROR_NOT_FOUND);
+  }
+
+  #[test]
+  fn oversized_requests_are_refused() {
+    let request = format!(
+      "GET /rest/ping HTTP/1.1\r\nX-Pad: {}\r\n\r\n",
+      "a".repeat(10_000)
+    );
+    assert!(read_request(&mut request.as_bytes()).is_err());
+    let request = "GET /rest/ping HTTP/1.1\r\nRange: bytes=100-\r\n\r\n";
+    let (target, range) = read_request(&mut request.as_bytes()).unwrap().unwrap();
+    assert_eq!((target.as_str(), range), ("/rest/ping", Some(100)));
+  }
+
+  #[test]
+  fn secrets_must_match_exactly() {
+    assert!(same_secret("hunter2", "hunter2"));
+    assert!(!same_secret("hunter2", "hunter3"));
+    assert!(!same_secret("hunter2", "hunter"));
+  }
+
+  #[test]
+  fn songs_are_found_by_id() {
+    let index = library();
+    let c = track("c.mp3", "Second");
+    assert_eq!(index.song(&song_id(&c)).unwrap().filename, "c.mp3");
+    assert!(index.song(&song_id(&track("gone.mp3", "First"))).is_none());
+  }
+}
@@ -0,0 +1,97 @@
+use crate::player::Player;
+use crate::settings::FmlSettings;
+use gtk::gio::{self, BusType, DBusCallFlags, DBusConnection, DBusSignalFlags, UnixFDList};
+use gtk::glib::variant::ToVariant;
+use gtk::glib::{self, VariantTy};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use tracing::warn;
+
+const LOGIND: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+
+#[derive(Default)]
+struct State {
+  // logind holds off suspending until this is dropped, so there is time to
+  // pause before the machine goes to sleep
+  delay_lock: RefCell<Option<UnixFDList>>,
+  // whether playback was paused here rather than by the user
+  paused: Cell<bool>,
+}
+
+async fn take_delay_lock(conn: &DBusConnection, state: &State) {
+  let result = conn
+    .call_with_unix_fd_list_future(
+      Some(LOGIND),
+      LOGIND_PATH,
+      LOGIND_MANAGER,
+      "Inhibit",
+      Some(&("sleep", "fml9000", "Pausing playback", "delay").to_variant()),
+      Some(VariantTy::new("(h)").unwrap()),
+      DBusCallFlags::NONE,
+      -1,
+      None::<&UnixFDList>,
+    )
+    .await;
+  match result {
+    Ok((_, fds)) => {
+      state.delay_lock.replace(Some(fds));
+    }
+    Err(e) => warn!("Failed to take a sleep delay lock: {}", e),
+  }
+}
+
+fn prepare_for_sleep(player: &Player, settings: &RefCell<FmlSettings>, state: &State) {
+  if player.current.borrow().is_some() && !player.sink.borrow().is_paused() {
+    player.pause();
+    state.paused.set(settings.borrow().resume_after_suspend);
+  }
+  state.delay_lock.replace(None);
+}
+
+fn woke(player: &Player, state: &State) {
+  if state.paused.replace(false) {
+    player.resume();
+  }
+}
+
+// Pauses playback when the system suspends, and picks it back up on wake
+// if the preference is on. Does nothing where logind isn't running
+pub fn add_suspend_pause(player: &Rc<Player>, settings: &Rc<RefCell<FmlSettings>>) {
+  let player = Rc::downgrade(player);
+  let settings = settings.clone();
+  glib::MainContext::default().spawn_local(async move {
+    let conn = match gio::bus_get_future(BusType::System).await {
+      Ok(conn) => conn,
+      Err(e) => {
+        warn!("No system bus, not pausing on suspend: {}", e);
+        return;
+      }
+    };
+    let state = Rc::new(State::default());
+    take_delay_lock(&conn, &state).await;
+    conn.signal_subscribe(
+      Some(LOGIND),
+      Some(LOGIND_MANAGER),
+      Some("PrepareForSleep"),
+      Some(LOGIND_PATH),
+      None,
+      DBusSignalFlags::NONE,
+      move |conn, _, _, _, _, params| {
+        let (Some(player), Some((sleeping,))) = (player.upgrade(), params.get::<(bool,)>()) else {
+          return;
+        };
+        if sleeping {
+          prepare_for_sleep(&player, &settings, &state);
+        } else {
+          woke(&player, &state);
+          let conn = conn.clone();
+          let state = state.clone();
+          glib::MainContext::default()
+            .spawn_local(async move { take_delay_lock(&conn, &state).await });
+        }
+      },
+    );
+  });
+}
@@ -0,0 +1,45 @@
+use crate::models::TagUpdate;
+use crate::writer::write_db;
+use diesel::prelude::*;
+use lofty::config::WriteOptions;
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use lofty::tag::{Accessor, Tag, TagExt};
+use tracing::{error, warn};
+
+pub(crate) fn write_file_tags(filename: &str, update: &TagUpdate) -> lofty::error::Result<()> {
+  let mut tagged_file = Probe::open(filename)?.read()?;
+  if tagged_file.primary_tag().is_none() {
+    let tag_type = tagged_file.primary_tag_type();
+    tagged_file.insert_tag(Tag::new(tag_type));
+  }
+  let tag = tagged_file.primary_tag_mut().unwrap();
+  if let Some(artist) = &update.artist {
+    tag.set_artist(artist.clone());
+  }
+  if let Some(title) = &update.title {
+    tag.set_title(title.clone());
+  }
+  if let Some(album) = &update.album {
+    tag.set_album(album.clone());
+  }
+  tag.save_to_path(filename, WriteOptions::default())
+}
+
+// Writes the given fields to the file and mirrors them into the database,
+// leaving fields that are None untouched
+pub fn write_tags(filename: &str, update: &TagUpdate) {
+  use crate::schema::tracks;
+
+  if let Err(e) = write_file_tags(filename, update) {
+    warn!("Failed to write tags to {}: {}", filename, e);
+    return;
+  }
+  if let Err(e) = write_db(|conn| {
+    diesel::update(tracks::table.filter(tracks::filename.eq(filename)))
+      .set(update)
+      .execute(conn)
+  }) {
+    error!("Failed to update {} in database: {}", filename, e);
+  }
+}
@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+  Mp3,
+  Opus,
+  Vorbis,
+  Aac,
+  Flac,
+}
+
+pub const FORMATS: [Format; 5] = [
+  Format::Mp3,
+  Format::Opus,
+  Format::Vorbis,
+  Format::Aac,
+  Format::Flac,
+];
+
+impl Format {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Format::Mp3 => "MP3",
+      Format::Opus => "Opus",
+      Format::Vorbis => "Ogg Vorbis",
+      Format::Aac => "AAC",
+      Format::Flac => "FLAC",
+    }
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Format::Mp3 => "mp3",
+      Format::Opus => "opus",
+      Format::Vorbis => "ogg",
+      Format::Aac => "m4a",
+      Format::Flac => "flac",
+    }
+  }
+
+  fn codec(&self) -> &'static str {
+    match self {
+      Format::Mp3 => "libmp3lame",
+      Format::Opus => "libopus",
+      Format::Vorbis => "libvorbis",
+      Format::Aac => "aac",
+      Format::Flac => "flac",
+    }
+  }
+
+  fn is_lossless(&self) -> bool {
+    *self == Format::Flac
+  }
+
+  // ffmpeg's name for the container, when writing to a pipe
+  fn muxer(&self) -> &'static str {
+    match self {
+      Format::Mp3 => "mp3",
+      Format::Opus => "opus",
+      Format::Vorbis => "ogg",
+      Format::Aac => "adts",
+      Format::Flac => "flac",
+    }
+  }
+
+  // From an extension or codec name as Subsonic clients send them
+  pub fn from_name(name: &str) -> Option<Format> {
+    match name.to_lowercase().as_str() {
+      "mp3" => Some(Format::Mp3),
+      "opus" => Some(Format::Opus),
+      "ogg" | "vorbis" | "oga" => Some(Format::Vorbis),
+      "aac" | "m4a" => Some(Format::Aac),
+      "flac" => Some(Format::Flac),
+      _ => None,
+    }
+  }
+}
+
+pub struct TranscodeJob {
+  pub input: String,
+  pub output: PathBuf,
+}
+
+// Keeps the name of the folder each file came from so albums stay together in
+// the output folder
+pub fn plan_jobs(filenames: &[String], out_dir: &Path, format: Format) -> Vec<TranscodeJob> {
+  filenames
+    .iter()
+    .map(|input| {
+      let path = Path::new(input);
+      let mut output = out_dir.to_path_buf();
+      if let Some(folder) = path.parent().and_then(|p| p.file_name()) {
+        output.push(folder);
+      }
+      output.push(path.file_stem().unwrap_or_default());
+      output.set_extension(format.extension());
+      TranscodeJob {
+        input: input.clone(),
+        output,
+      }
+    })
+    .collect()
+}
+
+// Blocking, run on a worker thread. Requires ffmpeg on the PATH
+pub fn transcode(job: &TranscodeJob, format: Format, bitrate_kbps: u32) -> std::io::Result<()> {
+  if let Some(parent) = job.output.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let mut cmd = Command::new("ffmpeg");
+  cmd
+    .args(["-nostdin", "-y", "-loglevel", "error", "-i"])
+    .arg(&job.input)
+    .args(["-vn", "-map_metadata", "0", "-c:a", format.codec()]);
+  if !format.is_lossless() {
+    cmd.arg("-b:a").arg(format!("{}k", bitrate_kbps));
+  }
+  let output = cmd.arg(&job.output).stdin(Stdio::null()).output()?;
+  if output.status.success() {
+    Ok(())
+  } else {
+    Err(std::io::Error::other(
+      String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    ))
+  }
+}
+
+// Like transcode, but the output is read from the child's stdout as ffmpeg
+// writes it, for streaming
+pub fn transcode_stream(input: &str, format: Format, bitrate_kbps: u32) -> std::io::Result<Child> {
+  let mut cmd = Command::new("ffmpeg");
+  cmd
+    .args(["-nostdin", "-loglevel", "error", "-i"])
+    .arg(input)
+    .args(["-vn", "-c:a", format.codec()]);
+  if !format.is_lossless() {
+    cmd.arg("-b:a").arg(format!("{}k", bitrate_kbps));
+  }
+  cmd
+    .args(["-f", format.muxer(), "pipe:1"])
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+}
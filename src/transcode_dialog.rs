@@ -0,0 +1,142 @@
+use crate::gtk_helpers::show_toast;
+use adw::prelude::*;
+use fml9000::transcode::{plan_jobs, transcode, Format, TranscodeJob, FORMATS};
+use gtk::gio;
+use gtk::glib::{self, MainContext};
+use gtk::{Button, DropDown, Entry, FileDialog, Label, Orientation, ProgressBar, SpinButton};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::rc::Rc;
+
+struct JobQueue {
+  jobs: RefCell<VecDeque<TranscodeJob>>,
+  done: Cell<usize>,
+  total: usize,
+}
+
+async fn worker(queue: Rc<JobQueue>, format: Format, bitrate: u32, progress_bar: ProgressBar) {
+  loop {
+    let next = queue.jobs.borrow_mut().pop_front();
+    let Some(job) = next else { break };
+    let result = gio::spawn_blocking(move || {
+      let result = transcode(&job, format, bitrate);
+      (job.input, result)
+    })
+    .await;
+    if let Ok((input, Err(e))) = result {
+      show_toast(
+        &progress_bar,
+        &format!("Failed to convert {}: {}", input, e),
+      );
+    }
+    queue.done.set(queue.done.get() + 1);
+    progress_bar.set_fraction(queue.done.get() as f64 / queue.total as f64);
+    progress_bar.set_text(Some(&format!("{} / {}", queue.done.get(), queue.total)));
+  }
+}
+
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>, filenames: Vec<String>) {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let options_row = gtk::Box::new(Orientation::Horizontal, 0);
+  let folder_row = gtk::Box::new(Orientation::Horizontal, 0);
+
+  let format_names: Vec<&str> = FORMATS.iter().map(|f| f.name()).collect();
+  let format_dropdown = DropDown::from_strings(&format_names);
+  let bitrate_spin = SpinButton::with_range(64.0, 320.0, 32.0);
+  bitrate_spin.set_value(192.0);
+  let parallel_spin = SpinButton::with_range(1.0, 16.0, 1.0);
+  parallel_spin.set_value(
+    std::thread::available_parallelism()
+      .map(|n| n.get())
+      .unwrap_or(1) as f64,
+  );
+  let folder_entry = Entry::builder()
+    .placeholder_text("Output folder")
+    .hexpand(true)
+    .build();
+  let folder_button = Button::builder().label("Choose folder...").build();
+  let progress_bar = ProgressBar::builder().show_text(true).build();
+  let start_button = Button::builder().label("Start").build();
+
+  options_row.append(&Label::new(Some("Format")));
+  options_row.append(&format_dropdown);
+  options_row.append(&Label::new(Some("Bitrate (kbps)")));
+  options_row.append(&bitrate_spin);
+  options_row.append(&Label::new(Some("Parallel jobs")));
+  options_row.append(&parallel_spin);
+  folder_row.append(&folder_entry);
+  folder_row.append(&folder_button);
+  f.append(&options_row);
+  f.append(&folder_row);
+  f.append(&progress_bar);
+  f.append(&start_button);
+  let transcode_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(600)
+    .title(format!("Convert {} files", filenames.len()))
+    .child(&f)
+    .build();
+
+  folder_button.connect_clicked(glib::clone!(
+    #[weak]
+    transcode_dialog,
+    #[weak]
+    folder_entry,
+    move |_| {
+      let dialog = FileDialog::builder()
+        .title("Choose output folder")
+        .accept_label("Select")
+        .build();
+
+      dialog.select_folder(
+        Some(&transcode_dialog),
+        gio::Cancellable::NONE,
+        move |file| {
+          if let Ok(file) = file {
+            let p = file.path().expect("Couldn't get file path");
+            folder_entry.set_text(&p.to_string_lossy());
+          }
+        },
+      );
+    }
+  ));
+
+  start_button.connect_clicked(glib::clone!(
+    #[weak]
+    format_dropdown,
+    #[weak]
+    bitrate_spin,
+    #[weak]
+    parallel_spin,
+    #[weak]
+    folder_entry,
+    #[weak]
+    progress_bar,
+    move |button| {
+      let out_dir = folder_entry.text();
+      if out_dir.is_empty() {
+        return;
+      }
+      button.set_sensitive(false);
+      let format = FORMATS[format_dropdown.selected() as usize];
+      let bitrate = bitrate_spin.value_as_int() as u32;
+      let jobs = plan_jobs(&filenames, Path::new(&out_dir), format);
+      let queue = Rc::new(JobQueue {
+        total: jobs.len(),
+        jobs: RefCell::new(VecDeque::from(jobs)),
+        done: Cell::new(0),
+      });
+      for _ in 0..parallel_spin.value_as_int() {
+        MainContext::default().spawn_local(worker(
+          queue.clone(),
+          format,
+          bitrate,
+          progress_bar.clone(),
+        ));
+      }
+    }
+  ));
+  transcode_dialog.present();
+}
@@ -0,0 +1,45 @@
+use crate::schema::{
+  file_checksums, fingerprints, playlist_tracks, plays, recently_played, saved_chapters, track_bpm,
+  track_key, track_offsets, tracks,
+};
+use crate::writer::write_db;
+use diesel::prelude::*;
+use gtk::gio;
+use gtk::prelude::*;
+use tracing::{error, warn};
+
+fn remove_from_db(conn: &mut SqliteConnection, filename: &str) -> QueryResult<()> {
+  diesel::delete(tracks::table.filter(tracks::filename.eq(filename))).execute(conn)?;
+  diesel::delete(recently_played::table.filter(recently_played::filename.eq(filename)))
+    .execute(conn)?;
+  diesel::delete(plays::table.filter(plays::filename.eq(filename))).execute(conn)?;
+  diesel::delete(playlist_tracks::table.filter(playlist_tracks::filename.eq(filename)))
+    .execute(conn)?;
+  diesel::delete(track_offsets::table.filter(track_offsets::filename.eq(filename)))
+    .execute(conn)?;
+  diesel::delete(file_checksums::table.filter(file_checksums::filename.eq(filename)))
+    .execute(conn)?;
+  diesel::delete(fingerprints::table.filter(fingerprints::filename.eq(filename))).execute(conn)?;
+  diesel::delete(track_bpm::table.filter(track_bpm::filename.eq(filename))).execute(conn)?;
+  diesel::delete(track_key::table.filter(track_key::filename.eq(filename))).execute(conn)?;
+  diesel::delete(saved_chapters::table.filter(saved_chapters::filename.eq(filename)))
+    .execute(conn)?;
+  Ok(())
+}
+
+// Moves the files to the desktop trash and forgets them in the library and
+// every playlist. Returns the files that were actually trashed
+pub fn trash_files(filenames: &[String]) -> Vec<String> {
+  let mut trashed = Vec::new();
+  for filename in filenames {
+    if let Err(e) = gio::File::for_path(filename).trash(gio::Cancellable::NONE) {
+      warn!("Failed to move {} to trash: {}", filename, e);
+      continue;
+    }
+    if let Err(e) = write_db(|conn| remove_from_db(conn, filename)) {
+      error!("Failed to remove {} from database: {}", filename, e);
+    }
+    trashed.push(filename.clone());
+  }
+  trashed
+}
@@ -0,0 +1,88 @@
+use adw::prelude::*;
+use fml9000::checksums::{verify_files, Status};
+use fml9000::query_tracks;
+use gtk::gio;
+use gtk::{Button, Label, Orientation, ProgressBar, ScrolledWindow, TextView};
+use std::cell::Cell;
+use std::rc::Rc;
+
+// files per worker call, so progress moves and cancel is noticed
+const BATCH_SIZE: usize = 20;
+
+// Checks every library file against its stored checksum, adding checksums
+// for files that have none, and lists the files that look damaged
+pub async fn dialog<W: IsA<gtk::Window>>(wnd: Rc<W>) {
+  let f = gtk::Box::new(Orientation::Vertical, 0);
+  let label = Label::builder().label("Verifying files").build();
+  let progress_bar = ProgressBar::builder().show_text(true).build();
+  let cancel_button = Button::builder().label("Cancel").build();
+  let report = TextView::builder().editable(false).monospace(true).build();
+
+  f.append(&label);
+  f.append(&progress_bar);
+  f.append(&cancel_button);
+  f.append(
+    &ScrolledWindow::builder()
+      .child(&report)
+      .vexpand(true)
+      .build(),
+  );
+  let verify_dialog = gtk::Window::builder()
+    .transient_for(&*wnd)
+    .modal(true)
+    .default_width(800)
+    .default_height(500)
+    .title("Verify files")
+    .child(&f)
+    .build();
+
+  let cancelled = Rc::new(Cell::new(false));
+  let cancelled1 = cancelled.clone();
+  cancel_button.connect_clicked(move |_| cancelled1.set(true));
+  verify_dialog.present();
+
+  let filenames: Vec<String> = match gio::spawn_blocking(query_tracks).await {
+    Ok(tracks) => tracks.into_iter().map(|t| t.filename).collect(),
+    Err(_) => return,
+  };
+  let total = filenames.len();
+  let mut checked = 0;
+  let mut added = 0;
+  let mut problems = Vec::new();
+  for batch in filenames.chunks(BATCH_SIZE) {
+    if cancelled.get() {
+      break;
+    }
+    progress_bar.set_fraction(checked as f64 / total as f64);
+    progress_bar.set_text(Some(&format!("{} / {}", checked, total)));
+    let batch = batch.to_vec();
+    let Ok((batch, statuses)) = gio::spawn_blocking(move || {
+      let statuses = verify_files(&batch);
+      (batch, statuses)
+    })
+    .await
+    else {
+      break;
+    };
+    checked += batch.len();
+    for (filename, status) in batch.into_iter().zip(statuses) {
+      if status.is_problem() {
+        problems.push(format!("{}\n  {}", filename, status.describe()));
+      } else if !matches!(status, Status::Ok) {
+        added += 1;
+      }
+    }
+  }
+
+  progress_bar.set_fraction(1.0);
+  progress_bar.set_text(Some(&format!("{} / {}", checked, total)));
+  cancel_button.set_sensitive(false);
+  label.set_text(&format!(
+    "Checked {} of {} files, {} new or updated checksums, {} problems",
+    checked,
+    total,
+    added,
+    problems.len()
+  ));
+  report.buffer().set_text(&problems.join("\n"));
+}
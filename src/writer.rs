@@ -0,0 +1,144 @@
+use crate::connect_db;
+use diesel::connection::{AnsiTransactionManager, TransactionManager};
+use diesel::result::Error;
+use diesel::sqlite::SqliteConnection;
+use std::sync::{Mutex, OnceLock};
+
+// Every change to the library goes through one connection, a transaction at
+// a time, so writers from the scan thread, the dialogs and the main loop
+// queue up here instead of racing for sqlite's lock. Reads keep using
+// connect_db, which WAL lets run alongside
+pub struct Writer {
+  conn: Mutex<SqliteConnection>,
+}
+
+impl Writer {
+  pub fn new(conn: SqliteConnection) -> Self {
+    Writer {
+      conn: Mutex::new(conn),
+    }
+  }
+
+  // Runs f in an immediate transaction, committed when it returns Ok and
+  // rolled back otherwise. Don't call write again from inside f, the
+  // connection is only released once f returns
+  pub fn write<T, E, F>(&self, f: F) -> Result<T, E>
+  where
+    F: FnOnce(&mut SqliteConnection) -> Result<T, E>,
+    E: From<Error>,
+  {
+    let mut conn = match self.conn.lock() {
+      Ok(conn) => conn,
+      // a write panicked halfway and left its transaction open
+      Err(e) => {
+        let mut conn = e.into_inner();
+        AnsiTransactionManager::rollback_transaction(&mut *conn).ok();
+        self.conn.clear_poison();
+        conn
+      }
+    };
+    conn.immediate_transaction(f)
+  }
+}
+
+static WRITER: OnceLock<Writer> = OnceLock::new();
+
+// The writer for the library database, opened on first use
+pub fn writer() -> &'static Writer {
+  WRITER.get_or_init(|| Writer::new(connect_db()))
+}
+
+pub fn write_db<T, E, F>(f: F) -> Result<T, E>
+where
+  F: FnOnce(&mut SqliteConnection) -> Result<T, E>,
+  E: From<Error>,
+{
+  writer().write(f)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::schema::playlists;
+  use crate::{open_db, MIGRATIONS};
+  use diesel::prelude::*;
+  use diesel_migrations::MigrationHarness;
+  use std::sync::Arc;
+  use std::thread;
+
+  // A database file of its own, since readers need a second connection to
+  // the same data, which :memory: doesn't allow
+  fn temp_db(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("fml9000-{}-{}.db", name, std::process::id()));
+    for suffix in ["", "-wal", "-shm"] {
+      std::fs::remove_file(format!("{}{}", path.display(), suffix)).ok();
+    }
+    let url = path.to_string_lossy().to_string();
+    open_db(&url).run_pending_migrations(MIGRATIONS).unwrap();
+    url
+  }
+
+  #[test]
+  fn writes_from_many_threads_all_land() {
+    let url = temp_db("writer-stress");
+    let writer = Arc::new(Writer::new(open_db(&url)));
+    let writers: Vec<_> = (0..4)
+      .map(|t| {
+        let writer = writer.clone();
+        thread::spawn(move || {
+          for i in 0..50 {
+            let name = format!("{} {}", t, i);
+            writer
+              .write(|conn| {
+                diesel::insert_into(playlists::table)
+                  .values(playlists::name.eq(name))
+                  .execute(conn)
+              })
+              .unwrap();
+          }
+        })
+      })
+      .collect();
+    let readers: Vec<_> = (0..4)
+      .map(|_| {
+        let url = url.clone();
+        thread::spawn(move || {
+          let conn = &mut open_db(&url);
+          let mut last = 0;
+          for _ in 0..100 {
+            let n: i64 = playlists::table.count().get_result(conn).unwrap();
+            // readers never see a write go backwards or half applied
+            assert!(n >= last);
+            last = n;
+          }
+        })
+      })
+      .collect();
+    for t in writers.into_iter().chain(readers) {
+      t.join().unwrap();
+    }
+    let n: i64 = playlists::table
+      .count()
+      .get_result(&mut open_db(&url))
+      .unwrap();
+    assert_eq!(n, 200);
+  }
+
+  #[test]
+  fn failed_write_is_rolled_back() {
+    let url = temp_db("writer-rollback");
+    let writer = Writer::new(open_db(&url));
+    let result = writer.write(|conn| {
+      diesel::insert_into(playlists::table)
+        .values(playlists::name.eq("kept?"))
+        .execute(conn)?;
+      Err::<(), _>(diesel::result::Error::RollbackTransaction)
+    });
+    assert!(result.is_err());
+    let n: i64 = playlists::table
+      .count()
+      .get_result(&mut open_db(&url))
+      .unwrap();
+    assert_eq!(n, 0);
+  }
+}
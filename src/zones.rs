@@ -0,0 +1,38 @@
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{cpal, OutputStream, Sink};
+use std::time::Duration;
+
+// Names of the sound cards and other outputs the system offers
+pub fn output_devices() -> Vec<String> {
+  match cpal::default_host().output_devices() {
+    Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+    Err(_) => Vec::new(),
+  }
+}
+
+// An extra output that plays along with the main one. delay holds it back
+// to line it up with an output that lags more, e.g. a Bluetooth speaker
+pub struct Zone {
+  pub device: String,
+  pub sink: Sink,
+  pub delay: Duration,
+  // the device stays open for as long as this does
+  _stream: OutputStream,
+}
+
+pub fn open_zone(device: &str, volume: f32, delay: Duration) -> Result<Zone, String> {
+  let output = cpal::default_host()
+    .output_devices()
+    .map_err(|e| e.to_string())?
+    .find(|d| d.name().is_ok_and(|name| name == device))
+    .ok_or_else(|| format!("{} is not connected", device))?;
+  let (stream, handle) = OutputStream::try_from_device(&output).map_err(|e| e.to_string())?;
+  let sink = Sink::try_new(&handle).map_err(|e| e.to_string())?;
+  sink.set_volume(volume);
+  Ok(Zone {
+    device: device.to_string(),
+    sink,
+    delay,
+    _stream: stream,
+  })
+}